@@ -12,6 +12,12 @@ pub struct SwsContext {
     output: PictureSettings,
 }
 
+// libswscale keeps all of its state in the context and has no thread
+// affinity, so the context may move between threads. it must never be used
+// from two threads at once, which holds as this wrapper is its sole owner:
+// it is neither Clone nor Sync, and process takes &mut self
+unsafe impl Send for SwsContext {}
+
 impl SwsContext {
     pub fn new(input: PictureSettings, output: PictureSettings) -> Self {
         let input_width: i32 = input.width.try_into().expect("input_width too large");
@@ -1,9 +1,12 @@
 use std::mem;
 
 use web_sys::MouseEvent;
-use yew::{html, Component, ComponentLink, Html, ShouldRender, Properties, Callback, Children};
+use yew::{html, Component, ComponentLink, Html, ShouldRender, Properties, Children};
 
-use crate::service::midi::{self, RangeSubscription, MidiRangeId, ConfigureTask};
+use mixlab_protocol::{ModuleId, MidiBinding, MidiControl};
+
+use crate::service::midi::{self, ConfigureTask};
+use crate::workspace::{Window, WindowMsg};
 
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub enum MidiUiMode {
@@ -11,6 +14,8 @@ pub enum MidiUiMode {
     Configure,
 }
 
+// binds a MIDI control to a numeric param of a module. bindings are applied
+// by the server, which scales the control's range onto min to max
 pub struct MidiRangeTarget {
     link: ComponentLink<Self>,
     props: MidiTargetProps,
@@ -21,13 +26,18 @@ pub struct MidiRangeTarget {
 pub enum MidiState {
     Unbound,
     Configure(ConfigureTask),
-    Bound(RangeSubscription),
+    Bound,
 }
 
 #[derive(Properties, Clone)]
 pub struct MidiTargetProps {
     pub ui_mode: MidiUiMode,
-    pub onchange: Callback<f64>,
+    pub module: ComponentLink<Window>,
+    pub id: ModuleId,
+    // as named by ModuleParams::visit_numeric:
+    pub param: String,
+    pub min: f64,
+    pub max: f64,
     #[prop_or_default]
     pub children: Children,
 }
@@ -35,9 +45,18 @@ pub struct MidiTargetProps {
 #[derive(Debug)]
 pub enum MidiTargetMsg {
     Configure,
+    Cancel,
     Unbind,
-    RangeConfigured(MidiRangeId, u8),
-    RangeChanged(u8),
+    RangeConfigured(MidiControl),
+}
+
+impl MidiRangeTarget {
+    fn bound_state(props: &MidiTargetProps) -> MidiState {
+        match midi::broker().binding(props.id, &props.param) {
+            Some(_) => MidiState::Bound,
+            None => MidiState::Unbound,
+        }
+    }
 }
 
 impl Component for MidiRangeTarget {
@@ -46,23 +65,23 @@ impl Component for MidiRangeTarget {
 
     fn create(props: MidiTargetProps, link: ComponentLink<Self>) -> Self {
         MidiRangeTarget {
+            state: Self::bound_state(&props),
             props,
             link,
-            state: MidiState::Unbound,
         }
     }
 
     fn change(&mut self, mut props: MidiTargetProps) -> ShouldRender {
         mem::swap(&mut self.props, &mut props);
 
-        if props.ui_mode != self.props.ui_mode {
-            match (&self.state, self.props.ui_mode) {
-                (MidiState::Configure(_), MidiUiMode::Normal) => {
-                    // if we're still in configure state when the UI changes
-                    // back to normal mode, return to unbound mode:
-                    self.state = MidiState::Unbound;
-                }
-                _ => { /* otherwise do nothing */ }
+        match (&self.state, self.props.ui_mode) {
+            (MidiState::Configure(_), MidiUiMode::Configure) => {
+                // still waiting on a control to bind
+            }
+            _ => {
+                // if we're still in configure state when the UI changes back
+                // to normal mode, return to whatever the server has for us:
+                self.state = Self::bound_state(&self.props);
             }
         }
 
@@ -74,42 +93,50 @@ impl Component for MidiRangeTarget {
             MidiTargetMsg::Configure => {
                 let configure = midi::broker().configure_range(self.link.callback(|result| {
                     match result {
-                        None => MidiTargetMsg::Unbind,
-                        Some((range_id, range_value)) =>
-                            MidiTargetMsg::RangeConfigured(range_id, range_value),
+                        None => MidiTargetMsg::Cancel,
+                        Some(control) => MidiTargetMsg::RangeConfigured(control),
                     }
                 }));
                 self.state = MidiState::Configure(configure);
                 true
             }
+            MidiTargetMsg::Cancel => {
+                // another target has started configuring
+                self.state = Self::bound_state(&self.props);
+                true
+            }
             MidiTargetMsg::Unbind => {
+                self.props.module.send_message(
+                    WindowMsg::UnbindMidi(self.props.param.clone()));
+
                 self.state = MidiState::Unbound;
                 true
             }
-            MidiTargetMsg::RangeConfigured(range_id, range_value) => {
+            MidiTargetMsg::RangeConfigured(control) => {
                 // only handle this message if we're still in configure state:
                 if let MidiState::Configure(_) = self.state {
-                    let subscription = midi::broker().subscribe_range(range_id,
-                        self.link.callback(MidiTargetMsg::RangeChanged));
-
-                    self.props.onchange.emit(range_value as f64 / 127.0);
-                    self.state = MidiState::Bound(subscription);
+                    self.props.module.send_message(
+                        WindowMsg::BindMidi(MidiBinding {
+                            control,
+                            module: self.props.id,
+                            param: self.props.param.clone(),
+                            min: self.props.min,
+                            max: self.props.max,
+                        }));
+
+                    self.state = MidiState::Bound;
                     true
                 } else {
                     false
                 }
             }
-            MidiTargetMsg::RangeChanged(range_value) => {
-                self.props.onchange.emit(range_value as f64 / 127.0);
-                false
-            }
         }
     }
 
     fn view(&self) -> Html {
         let overlay = match self.props.ui_mode {
             MidiUiMode::Normal => {
-                if let MidiState::Bound(_) = self.state {
+                if let MidiState::Bound = self.state {
                     html! {
                         <div class="midi-target-overlay midi-target-overlay-bound">
                             <span class="midi-target-overlay-label">{"MIDI"}</span>
@@ -123,7 +150,7 @@ impl Component for MidiRangeTarget {
                 let class = match self.state {
                     MidiState::Unbound => "midi-target-overlay midi-target-cfg-overlay midi-target-cfg-overlay-unbound",
                     MidiState::Configure(_) => "midi-target-overlay midi-target-cfg-overlay midi-target-cfg-overlay-configure",
-                    MidiState::Bound(_) => "midi-target-overlay midi-target-cfg-overlay midi-target-cfg-overlay-bound",
+                    MidiState::Bound => "midi-target-overlay midi-target-cfg-overlay midi-target-cfg-overlay-bound",
                 };
 
                 html! {
@@ -1,5 +1,6 @@
 pub mod fader;
 pub mod rotary;
+pub mod unit;
 
 pub use fader::Fader;
 pub use rotary::Rotary;
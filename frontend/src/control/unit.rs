@@ -0,0 +1,149 @@
+use std::fmt::{self, Display};
+
+use mixlab_protocol::{COMPRESSOR_MAX_RATIO, GATE_MAX_RATIO};
+
+// values for controls to display with their unit. the protocol keeps these
+// as plain f64 params, Decibel aside
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Millis(pub f64);
+
+impl Display for Millis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:.1} ms", self.0)
+    }
+}
+
+impl From<f64> for Millis {
+    fn from(ms: f64) -> Millis {
+        Millis(ms)
+    }
+}
+
+impl From<Millis> for f64 {
+    fn from(ms: Millis) -> f64 {
+        ms.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ratio(pub f64);
+
+impl Display for Ratio {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // the compressor limits at its highest ratio:
+        if self.0 >= COMPRESSOR_MAX_RATIO {
+            write!(f, "Limit")
+        } else {
+            write!(f, "{:.1}:1", self.0)
+        }
+    }
+}
+
+impl From<f64> for Ratio {
+    fn from(ratio: f64) -> Ratio {
+        Ratio(ratio)
+    }
+}
+
+impl From<Ratio> for f64 {
+    fn from(ratio: Ratio) -> f64 {
+        ratio.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExpanderRatio(pub f64);
+
+impl Display for ExpanderRatio {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // the gate closes fully at its highest ratio:
+        if self.0 >= GATE_MAX_RATIO {
+            write!(f, "Gate")
+        } else {
+            write!(f, "1:{:.1}", self.0)
+        }
+    }
+}
+
+impl From<f64> for ExpanderRatio {
+    fn from(ratio: f64) -> ExpanderRatio {
+        ExpanderRatio(ratio)
+    }
+}
+
+impl From<ExpanderRatio> for f64 {
+    fn from(ratio: ExpanderRatio) -> f64 {
+        ratio.0
+    }
+}
+
+// frequencies sweep logarithmically, so controls are positioned by the log of
+// the frequency
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hertz(pub f64);
+
+impl Display for Hertz {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0 >= 1000.0 {
+            write!(f, "{:.2} kHz", self.0 / 1000.0)
+        } else {
+            write!(f, "{:.0} Hz", self.0)
+        }
+    }
+}
+
+impl From<f64> for Hertz {
+    fn from(position: f64) -> Hertz {
+        Hertz(f64::powf(10.0, position))
+    }
+}
+
+impl From<Hertz> for f64 {
+    fn from(hz: Hertz) -> f64 {
+        hz.0.log10()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Q(pub f64);
+
+impl Display for Q {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Q {:.2}", self.0)
+    }
+}
+
+impl From<f64> for Q {
+    fn from(q: f64) -> Q {
+        Q(q)
+    }
+}
+
+impl From<Q> for f64 {
+    fn from(q: Q) -> f64 {
+        q.0
+    }
+}
+
+// a fraction from 0 to 1, shown as a percentage
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Percent(pub f64);
+
+impl Display for Percent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:.0}%", self.0 * 100.0)
+    }
+}
+
+impl From<f64> for Percent {
+    fn from(fraction: f64) -> Percent {
+        Percent(fraction)
+    }
+}
+
+impl From<Percent> for f64 {
+    fn from(percent: Percent) -> f64 {
+        percent.0
+    }
+}
@@ -4,22 +4,27 @@ mod component;
 mod control;
 mod library;
 mod module;
+mod patch;
 mod service;
+mod scenes;
 mod session;
 mod sidebar;
+mod transport;
 mod util;
 mod workspace;
 
+use std::cell::RefCell;
 use std::fmt::Display;
+use std::rc::Rc;
 
 use derive_more::Display;
 use wasm_bindgen::prelude::*;
 use yew::{html, Component, ComponentLink, Html, ShouldRender, Callback, Properties};
 
-use mixlab_protocol::WorkspaceOp;
+use mixlab_protocol::{ModuleId, ModuleParams, Indication, WorkspaceOp};
 
 use library::MediaLibrary;
-use session::{Session, SessionRef};
+use session::{Session, SessionRef, WorkspaceStateRef};
 use sidebar::Sidebar;
 use util::{notify, Sequence};
 use workspace::Workspace;
@@ -28,6 +33,8 @@ pub struct App {
     link: ComponentLink<Self>,
     session: SessionRef,
     selected_tab: Tab,
+    // sub-patch module open for editing in the workspace tab:
+    open_patch: Option<ModuleId>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Display)]
@@ -42,6 +49,7 @@ pub enum Tab {
 pub enum AppMsg {
     ClientUpdate(WorkspaceOp),
     ChangeTab(Tab),
+    OpenPatch(Option<ModuleId>),
 }
 
 impl Component for App {
@@ -49,10 +57,22 @@ impl Component for App {
     type Properties = ();
 
     fn create(_: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let session = Session::new();
+
+        // bound MIDI controls attached to this browser drive the top level
+        // workspace, whichever patch is open:
+        service::midi::broker().forward_to(Callback::from({
+            let session = session.clone();
+            move |(control, value)| {
+                session.update_workspace(WorkspaceOp::MidiInput(control, value));
+            }
+        }));
+
         App {
             link,
-            session: Session::new(),
+            session,
             selected_tab: Tab::Workspace,
+            open_patch: None,
         }
     }
 
@@ -63,13 +83,22 @@ impl Component for App {
     fn update(&mut self, msg: Self::Message) -> ShouldRender {
         match msg {
             AppMsg::ClientUpdate(op) => {
-                self.session.update_workspace(op);
-                false
+                match self.open_patch {
+                    Some(patch_id) => self.update_patch(patch_id, op),
+                    None => {
+                        self.session.update_workspace(op);
+                        false
+                    }
+                }
             }
             AppMsg::ChangeTab(tab) => {
                 self.selected_tab = tab;
                 true
             }
+            AppMsg::OpenPatch(patch_id) => {
+                self.open_patch = patch_id;
+                true
+            }
         }
     }
 
@@ -93,6 +122,7 @@ impl Component for App {
                             <WorkspaceContainer
                                 app={self.link.clone()}
                                 session={self.session.clone()}
+                                patch={self.open_patch}
                             />
                         },
                         Tab::MediaLibrary => html! {
@@ -105,6 +135,45 @@ impl Component for App {
     }
 }
 
+impl App {
+    // ops from the workspace view of an open sub-patch are applied to the
+    // sub-patch's params, which are then sent as a single params update
+    fn update_patch(&mut self, patch_id: ModuleId, op: WorkspaceOp) -> ShouldRender {
+        if let WorkspaceOp::Undo | WorkspaceOp::Redo = op {
+            // undo history is kept for the whole workspace
+            self.session.update_workspace(op);
+            return false;
+        }
+
+        let state = match self.session.workspace() {
+            Some(state) => state,
+            None => return false,
+        };
+
+        let params = match state.borrow_mut().modules.get_mut(&patch_id) {
+            Some(ModuleParams::SubPatch(params)) => {
+                patch::apply_op(&mut params.patch, op);
+                Some(params.clone())
+            }
+            _ => None,
+        };
+
+        match params {
+            Some(params) => {
+                self.session.update_workspace(
+                    WorkspaceOp::UpdateModuleParams(patch_id, ModuleParams::SubPatch(params)));
+
+                false
+            }
+            None => {
+                // the sub-patch has been deleted out from under us
+                self.open_patch = None;
+                true
+            }
+        }
+    }
+}
+
 pub struct WorkspaceContainer {
     _notify: notify::Handle,
     props: WorkspaceContainerProps,
@@ -114,6 +183,7 @@ pub struct WorkspaceContainer {
 pub struct WorkspaceContainerProps {
     app: ComponentLink<App>,
     session: SessionRef,
+    patch: Option<ModuleId>,
 }
 
 impl Component for WorkspaceContainer {
@@ -140,17 +210,69 @@ impl Component for WorkspaceContainer {
     }
 
     fn view(&self) -> Html {
-        if let Some(state) = self.props.session.workspace() {
-            html! {
+        let state = match self.props.session.workspace() {
+            Some(state) => state,
+            None => return html! {},
+        };
+
+        if let Some(patch_id) = self.props.patch {
+            if let Some(html) = self.view_patch(patch_id, &state) {
+                return html;
+            }
+        }
+
+        html! {
+            <Workspace
+                app={self.props.app.clone()}
+                state={state.clone()}
+                session={self.props.session.clone()}
+                patch={None}
+            />
+        }
+    }
+}
+
+impl WorkspaceContainer {
+    fn view_patch(&self, patch_id: ModuleId, state: &WorkspaceStateRef) -> Option<Html> {
+        let state = state.borrow();
+
+        let params = match state.modules.get(&patch_id) {
+            Some(ModuleParams::SubPatch(params)) => params,
+            _ => return None,
+        };
+
+        let indication = match state.indications.get(&patch_id) {
+            Some(Indication::SubPatch(indication)) => Some(indication),
+            _ => None,
+        };
+
+        let patch_state = patch::workspace_state(&params.patch, indication);
+
+        let name = if params.name.is_empty() {
+            "Sub-patch"
+        } else {
+            params.name.as_str()
+        };
+
+        Some(html! {
+            <>
+                <div class="patch-bar">
+                    <div class="patch-bar-button"
+                        onclick={self.props.app.callback(|_| AppMsg::OpenPatch(None))}
+                    >
+                        {"Workspace"}
+                    </div>
+                    <div class="patch-bar-name">{format!("/ {}", name)}</div>
+                </div>
+
                 <Workspace
                     app={self.props.app.clone()}
-                    state={state.clone()}
+                    state={Rc::new(RefCell::new(patch_state))}
                     session={self.props.session.clone()}
+                    patch={Some(patch_id)}
                 />
-            }
-        } else {
-            html! {}
-        }
+            </>
+        })
     }
 }
 
@@ -0,0 +1,116 @@
+use std::fmt::Display;
+
+use yew::{html, Component, ComponentLink, Html, ShouldRender, Properties};
+
+use mixlab_protocol::{ModuleId, ModuleParams, CompressorParams, CompressorIndication, Decibel, COMPRESSOR_MAX_RATIO, COMPRESSOR_MAX_LOOKAHEAD_MS};
+
+use crate::component::midi_target::{MidiRangeTarget, MidiUiMode};
+use crate::control::Rotary;
+use crate::control::unit::{Millis, Ratio};
+use crate::workspace::{Window, WindowMsg};
+
+// gain reduction meter scale:
+const METER_MAX_DB: f64 = 24.0;
+
+#[derive(Properties, Clone)]
+pub struct CompressorProps {
+    pub id: ModuleId,
+    pub module: ComponentLink<Window>,
+    pub params: CompressorParams,
+    pub indication: CompressorIndication,
+    pub midi_mode: MidiUiMode,
+}
+
+pub struct Compressor {
+    props: CompressorProps,
+}
+
+impl Component for Compressor {
+    type Properties = CompressorProps;
+    type Message = ();
+
+    fn create(props: Self::Properties, _: ComponentLink<Self>) -> Self {
+        Self { props }
+    }
+
+    fn update(&mut self, _msg: Self::Message) -> ShouldRender {
+        false
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        self.props = props;
+        true
+    }
+
+    fn view(&self) -> Html {
+        let params = &self.props.params;
+        let reduction = self.props.indication.gain_reduction;
+        let meter_percent = (reduction.0 / METER_MAX_DB).max(0.0).min(1.0) * 100.0;
+
+        html! {
+            <div class="dynamics">
+                <div class="dynamics-controls">
+                    { self.rotary("THRESH", "threshold", Decibel(-60.0), Decibel(0.0), Decibel(-18.0), params.threshold,
+                        |params, threshold| CompressorParams { threshold, ..params }) }
+                    { self.rotary("RATIO", "ratio", Ratio(1.0), Ratio(COMPRESSOR_MAX_RATIO), Ratio(4.0), Ratio(params.ratio),
+                        |params, ratio: Ratio| CompressorParams { ratio: ratio.0, ..params }) }
+                    { self.rotary("KNEE", "knee", Decibel(0.0), Decibel(24.0), Decibel(6.0), params.knee,
+                        |params, knee| CompressorParams { knee, ..params }) }
+                    { self.rotary("MAKEUP", "makeup", Decibel(0.0), Decibel(24.0), Decibel(0.0), params.makeup,
+                        |params, makeup| CompressorParams { makeup, ..params }) }
+                    { self.rotary("ATTACK", "attack_ms", Millis(0.0), Millis(200.0), Millis(10.0), Millis(params.attack_ms),
+                        |params, attack: Millis| CompressorParams { attack_ms: attack.0, ..params }) }
+                    { self.rotary("RELEASE", "release_ms", Millis(5.0), Millis(2000.0), Millis(100.0), Millis(params.release_ms),
+                        |params, release: Millis| CompressorParams { release_ms: release.0, ..params }) }
+                    { self.rotary("LOOKAHEAD", "lookahead_ms", Millis(0.0), Millis(COMPRESSOR_MAX_LOOKAHEAD_MS), Millis(0.0), Millis(params.lookahead_ms),
+                        |params, lookahead: Millis| CompressorParams { lookahead_ms: lookahead.0, ..params }) }
+                </div>
+                <div class="gain-reduction-meter">
+                    <div class="gain-reduction-meter-bar" style={format!("width:{}%", meter_percent)} />
+                </div>
+                <div class="control-rotary-label">{format!("GR {}", reduction)}</div>
+            </div>
+        }
+    }
+}
+
+impl Compressor {
+    fn rotary<T>(
+        &self,
+        label: &str,
+        param: &str,
+        min: T,
+        max: T,
+        default: T,
+        value: T,
+        f: impl Fn(CompressorParams, T) -> CompressorParams + 'static,
+    ) -> Html
+        where T: Into<f64> + From<f64> + Clone + Copy + Display + PartialEq + 'static
+    {
+        let params = self.props.params.clone();
+
+        html! {
+            <div class="dynamics-control">
+                <MidiRangeTarget
+                    ui_mode={self.props.midi_mode}
+                    module={self.props.module.clone()}
+                    id={self.props.id}
+                    param={param}
+                    min={min.into()}
+                    max={max.into()}
+                >
+                    <Rotary<T>
+                        value={value}
+                        min={min}
+                        max={max}
+                        default={default}
+                        onchange={self.props.module.callback(move |value| {
+                            WindowMsg::UpdateParams(ModuleParams::Compressor(f(params.clone(), value)))
+                        })}
+                    />
+                </MidiRangeTarget>
+                <div class="control-rotary-label">{label}</div>
+            </div>
+        }
+    }
+}
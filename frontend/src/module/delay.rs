@@ -0,0 +1,182 @@
+use std::fmt::{self, Display};
+
+use yew::{html, Callback, Component, ComponentLink, Html, ShouldRender, Properties};
+use yew_components::Select;
+
+use mixlab_protocol::{ModuleId, ModuleParams, DelayParams, NoteValue, DELAY_MAX_MS};
+
+use crate::component::midi_target::{MidiRangeTarget, MidiUiMode};
+use crate::control::Rotary;
+use crate::control::unit::{Hertz, Millis, Percent};
+use crate::workspace::{Window, WindowMsg};
+
+const FILTER_MIN: f64 = 200.0;
+const FILTER_MAX: f64 = 20000.0;
+
+#[derive(Properties, Clone)]
+pub struct DelayProps {
+    pub id: ModuleId,
+    pub module: ComponentLink<Window>,
+    pub params: DelayParams,
+    pub midi_mode: MidiUiMode,
+}
+
+pub struct Delay {
+    props: DelayProps,
+}
+
+impl Component for Delay {
+    type Properties = DelayProps;
+    type Message = ();
+
+    fn create(props: Self::Properties, _: ComponentLink<Self>) -> Self {
+        Self { props }
+    }
+
+    fn update(&mut self, _msg: Self::Message) -> ShouldRender {
+        false
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        self.props = props;
+        true
+    }
+
+    fn view(&self) -> Html {
+        let params = &self.props.params;
+
+        // a synced delay takes its time from the tempo, so there's no time
+        // control to show:
+        let time = if params.sync.is_none() {
+            self.rotary("TIME", "time_ms", Millis(1.0), Millis(DELAY_MAX_MS), Millis(375.0), Millis(params.time_ms),
+                |params, time: Millis| DelayParams { time_ms: time.0, ..params })
+        } else {
+            html! {}
+        };
+
+        html! {
+            <div class="dynamics">
+                <div class="dynamics-controls">
+                    { time }
+                    { self.rotary("FEEDBACK", "feedback", Percent(0.0), Percent(0.99), Percent(0.4), Percent(params.feedback),
+                        |params, feedback: Percent| DelayParams { feedback: feedback.0, ..params }) }
+                    { self.rotary("MIX", "mix", Percent(0.0), Percent(1.0), Percent(0.3), Percent(params.mix),
+                        |params, mix: Percent| DelayParams { mix: mix.0, ..params }) }
+                    <div class="dynamics-control">
+                        <MidiRangeTarget
+                            ui_mode={self.props.midi_mode}
+                            module={self.props.module.clone()}
+                            id={self.props.id}
+                            param={"filter_freq"}
+                            min={FILTER_MIN}
+                            max={FILTER_MAX}
+                        >
+                            <Rotary<Hertz>
+                                value={Hertz(params.filter_freq)}
+                                min={Hertz(FILTER_MIN)}
+                                max={Hertz(FILTER_MAX)}
+                                default={Hertz(8000.0)}
+                                onchange={self.update_params(|params, freq: Hertz| DelayParams { filter_freq: freq.0, ..params })}
+                            />
+                        </MidiRangeTarget>
+                        <div class="control-rotary-label">{"FILTER"}</div>
+                    </div>
+                </div>
+                <label class="form-field">
+                    <span class="form-field-label">{"Sync"}</span>
+                    <Select<SyncNote>
+                        selected={Some(SyncNote(params.sync))}
+                        options={Some(None).into_iter().chain(NoteValue::ALL.iter().copied().map(Some)).map(SyncNote).collect::<Vec<_>>()}
+                        on_change={self.update_params(|params, sync: SyncNote| DelayParams { sync: sync.0, ..params })}
+                    />
+                </label>
+                <label class="form-field">
+                    <span class="form-field-label">{"Mode"}</span>
+                    <Select<Mode>
+                        selected={Some(Mode(params.ping_pong))}
+                        options={vec![Mode(false), Mode(true)]}
+                        on_change={self.update_params(|params, mode: Mode| DelayParams { ping_pong: mode.0, ..params })}
+                    />
+                </label>
+            </div>
+        }
+    }
+}
+
+impl Delay {
+    fn rotary<T>(
+        &self,
+        label: &str,
+        param: &str,
+        min: T,
+        max: T,
+        default: T,
+        value: T,
+        f: impl Fn(DelayParams, T) -> DelayParams + 'static,
+    ) -> Html
+        where T: Into<f64> + From<f64> + Clone + Copy + Display + PartialEq + 'static
+    {
+        html! {
+            <div class="dynamics-control">
+                <MidiRangeTarget
+                    ui_mode={self.props.midi_mode}
+                    module={self.props.module.clone()}
+                    id={self.props.id}
+                    param={param}
+                    min={min.into()}
+                    max={max.into()}
+                >
+                    <Rotary<T>
+                        value={value}
+                        min={min}
+                        max={max}
+                        default={default}
+                        onchange={self.update_params(f)}
+                    />
+                </MidiRangeTarget>
+                <div class="control-rotary-label">{label}</div>
+            </div>
+        }
+    }
+
+    fn update_params<Ev>(&self, f: impl Fn(DelayParams, Ev) -> DelayParams + 'static) -> Callback<Ev> {
+        let params = self.props.params.clone();
+
+        self.props.module.callback(move |ev| {
+            WindowMsg::UpdateParams(ModuleParams::Delay(f(params.clone(), ev)))
+        })
+    }
+}
+
+#[derive(PartialEq, Clone)]
+struct SyncNote(Option<NoteValue>);
+
+impl Display for SyncNote {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            None => write!(f, "Free"),
+            Some(NoteValue::Whole) => write!(f, "1/1"),
+            Some(NoteValue::Half) => write!(f, "1/2"),
+            Some(NoteValue::Quarter) => write!(f, "1/4"),
+            Some(NoteValue::DottedQuarter) => write!(f, "1/4 dotted"),
+            Some(NoteValue::TripletQuarter) => write!(f, "1/4 triplet"),
+            Some(NoteValue::Eighth) => write!(f, "1/8"),
+            Some(NoteValue::DottedEighth) => write!(f, "1/8 dotted"),
+            Some(NoteValue::TripletEighth) => write!(f, "1/8 triplet"),
+            Some(NoteValue::Sixteenth) => write!(f, "1/16"),
+        }
+    }
+}
+
+#[derive(PartialEq, Clone)]
+struct Mode(bool);
+
+impl Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0 {
+            write!(f, "Ping-pong")
+        } else {
+            write!(f, "Stereo")
+        }
+    }
+}
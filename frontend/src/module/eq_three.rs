@@ -10,15 +10,17 @@ use crate::workspace::{Window, WindowMsg};
 pub type EqThree = Pure<EqThreeParams>;
 
 impl PureModule for EqThreeParams {
-    fn view(&self, _: ModuleId, module: ComponentLink<Window>, midi_mode: MidiUiMode) -> Html {
-
+    fn view(&self, id: ModuleId, module: ComponentLink<Window>, midi_mode: MidiUiMode) -> Html {
         html! {
             <>
                 <div>{"HI"}</div>
                 <MidiRangeTarget
                     ui_mode={midi_mode}
-                    onchange={module.callback(wrap_decibel(update_params(self,
-                        |params, value| EqThreeParams { gain_hi: value, ..params })))}
+                    module={module.clone()}
+                    id={id}
+                    param={"gain_hi"}
+                    min={-24.0}
+                    max={6.0}
                 >
                     <Rotary<Decibel>
                         value={self.gain_hi}
@@ -33,8 +35,11 @@ impl PureModule for EqThreeParams {
                 <div>{"MID"}</div>
                 <MidiRangeTarget
                     ui_mode={midi_mode}
-                    onchange={module.callback(wrap_decibel(update_params(self,
-                        |params, value| EqThreeParams { gain_mid: value, ..params })))}
+                    module={module.clone()}
+                    id={id}
+                    param={"gain_mid"}
+                    min={-24.0}
+                    max={6.0}
                 >
                     <Rotary<Decibel>
                         value={self.gain_mid}
@@ -49,8 +54,11 @@ impl PureModule for EqThreeParams {
                 <div>{"LO"}</div>
                 <MidiRangeTarget
                     ui_mode={midi_mode}
-                    onchange={module.callback(wrap_decibel(update_params(self,
-                        |params, value| EqThreeParams { gain_lo: value, ..params })))}
+                    module={module.clone()}
+                    id={id}
+                    param={"gain_lo"}
+                    min={-24.0}
+                    max={6.0}
                 >
                     <Rotary<Decibel>
                         value={self.gain_lo}
@@ -73,7 +81,3 @@ fn update_params(params: &EqThreeParams, f: impl Fn(EqThreeParams, Decibel) -> E
         WindowMsg::UpdateParams(ModuleParams::EqThree(params))
     }
 }
-
-fn wrap_decibel<'a, T>(f: impl Fn(Decibel) -> T + 'a) -> impl Fn(f64) -> T + 'a {
-    move |gain| f(Decibel(gain * 30.0 - 24.0))
-}
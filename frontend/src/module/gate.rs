@@ -0,0 +1,143 @@
+use std::fmt::{self, Display};
+
+use yew::{html, Component, ComponentLink, Html, ShouldRender, Properties};
+use yew_components::Select;
+
+use mixlab_protocol::{ModuleId, ModuleParams, GateParams, GateIndication, Decibel, GATE_MAX_RATIO};
+
+use crate::component::midi_target::{MidiRangeTarget, MidiUiMode};
+use crate::control::Rotary;
+use crate::control::unit::{Millis, ExpanderRatio};
+use crate::workspace::{Window, WindowMsg};
+
+#[derive(Properties, Clone)]
+pub struct GateProps {
+    pub id: ModuleId,
+    pub module: ComponentLink<Window>,
+    pub params: GateParams,
+    pub indication: GateIndication,
+    pub midi_mode: MidiUiMode,
+}
+
+pub struct Gate {
+    props: GateProps,
+}
+
+impl Component for Gate {
+    type Properties = GateProps;
+    type Message = ();
+
+    fn create(props: Self::Properties, _: ComponentLink<Self>) -> Self {
+        Self { props }
+    }
+
+    fn update(&mut self, _msg: Self::Message) -> ShouldRender {
+        false
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        self.props = props;
+        true
+    }
+
+    fn view(&self) -> Html {
+        let params = &self.props.params;
+
+        let open_class = if self.props.indication.open {
+            "status-light status-light-green-active"
+        } else {
+            "status-light"
+        };
+
+        html! {
+            <div class="dynamics">
+                <div class="status-light-bar">
+                    <div class={open_class}>{"OPEN"}</div>
+                </div>
+                <div class="dynamics-controls">
+                    { self.rotary("THRESH", "threshold", Decibel(-80.0), Decibel(0.0), Decibel(-40.0), params.threshold,
+                        |params, threshold| GateParams { threshold, ..params }) }
+                    { self.rotary("HYST", "hysteresis", Decibel(0.0), Decibel(24.0), Decibel(6.0), params.hysteresis,
+                        |params, hysteresis| GateParams { hysteresis, ..params }) }
+                    { self.rotary("RANGE", "range", Decibel(-80.0), Decibel(0.0), Decibel(-80.0), params.range,
+                        |params, range| GateParams { range, ..params }) }
+                    { self.rotary("RATIO", "ratio", ExpanderRatio(1.0), ExpanderRatio(GATE_MAX_RATIO), ExpanderRatio(GATE_MAX_RATIO), ExpanderRatio(params.ratio),
+                        |params, ratio: ExpanderRatio| GateParams { ratio: ratio.0, ..params }) }
+                    { self.rotary("HOLD", "hold_ms", Millis(0.0), Millis(500.0), Millis(50.0), Millis(params.hold_ms),
+                        |params, hold: Millis| GateParams { hold_ms: hold.0, ..params }) }
+                    { self.rotary("ATTACK", "attack_ms", Millis(0.0), Millis(50.0), Millis(1.0), Millis(params.attack_ms),
+                        |params, attack: Millis| GateParams { attack_ms: attack.0, ..params }) }
+                    { self.rotary("RELEASE", "release_ms", Millis(5.0), Millis(2000.0), Millis(100.0), Millis(params.release_ms),
+                        |params, release: Millis| GateParams { release_ms: release.0, ..params }) }
+                </div>
+                <label class="form-field">
+                    <span class="form-field-label">{"Channels"}</span>
+                    <Select<Channels>
+                        selected={Some(Channels(params.stereo))}
+                        options={vec![Channels(false), Channels(true)]}
+                        on_change={self.props.module.callback({
+                            let params = params.clone();
+                            move |channels: Channels| {
+                                WindowMsg::UpdateParams(ModuleParams::Gate(GateParams { stereo: channels.0, ..params.clone() }))
+                            }
+                        })}
+                    />
+                </label>
+            </div>
+        }
+    }
+}
+
+impl Gate {
+    fn rotary<T>(
+        &self,
+        label: &str,
+        param: &str,
+        min: T,
+        max: T,
+        default: T,
+        value: T,
+        f: impl Fn(GateParams, T) -> GateParams + 'static,
+    ) -> Html
+        where T: Into<f64> + From<f64> + Clone + Copy + Display + PartialEq + 'static
+    {
+        let params = self.props.params.clone();
+
+        html! {
+            <div class="dynamics-control">
+                <MidiRangeTarget
+                    ui_mode={self.props.midi_mode}
+                    module={self.props.module.clone()}
+                    id={self.props.id}
+                    param={param}
+                    min={min.into()}
+                    max={max.into()}
+                >
+                    <Rotary<T>
+                        value={value}
+                        min={min}
+                        max={max}
+                        default={default}
+                        onchange={self.props.module.callback(move |value| {
+                            WindowMsg::UpdateParams(ModuleParams::Gate(f(params.clone(), value)))
+                        })}
+                    />
+                </MidiRangeTarget>
+                <div class="control-rotary-label">{label}</div>
+            </div>
+        }
+    }
+}
+
+#[derive(PartialEq, Clone)]
+struct Channels(bool);
+
+impl Display for Channels {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0 {
+            write!(f, "Stereo")
+        } else {
+            write!(f, "Mono")
+        }
+    }
+}
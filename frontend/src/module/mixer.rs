@@ -57,6 +57,9 @@ impl Component for Mixer {
                     .map(|(idx, channel)| {
                         html! {
                             <Channel
+                                id={self.props.id}
+                                module={self.props.module.clone()}
+                                index={idx}
                                 params={channel}
                                 onchange={self.link.callback(move |params|
                                     MixerMsg::ChannelChanged(idx, params))}
@@ -83,6 +86,9 @@ pub enum ChannelMsg {
 
 #[derive(Properties, Clone)]
 pub struct ChannelProps {
+    pub id: ModuleId,
+    pub module: ComponentLink<Window>,
+    pub index: usize,
     pub params: MixerChannelParams,
     pub onchange: Callback<MixerChannelParams>,
     pub midi_mode: MidiUiMode,
@@ -139,9 +145,11 @@ impl Component for Channel {
             <div class="mixer-channel">
                 <MidiRangeTarget
                     ui_mode={self.props.midi_mode}
-                    onchange={self.link.callback(|gain| {
-                        ChannelMsg::GainChanged(Decibel(gain * 30.0 - 24.0))
-                    })}
+                    module={self.props.module.clone()}
+                    id={self.props.id}
+                    param={format!("channel{}.gain", self.props.index + 1)}
+                    min={-24.0}
+                    max={6.0}
                 >
                     <Rotary<Decibel>
                         value={self.props.params.gain}
@@ -156,7 +164,11 @@ impl Component for Channel {
                 </div>
                 <MidiRangeTarget
                     ui_mode={self.props.midi_mode}
-                    onchange={self.link.callback(ChannelMsg::FaderChanged)}
+                    module={self.props.module.clone()}
+                    id={self.props.id}
+                    param={format!("channel{}.fader", self.props.index + 1)}
+                    min={0.0}
+                    max={1.0}
                 >
                     <Fader
                         value={self.props.params.fader}
@@ -1,14 +1,22 @@
 pub mod amplifier;
+pub mod compressor;
+pub mod delay;
 pub mod envelope;
 pub mod eq_three;
 pub mod fm_sine;
+pub mod gate;
 pub mod media_source;
 pub mod mixer;
 pub mod monitor;
 pub mod oscillator;
 pub mod output_device;
+pub mod parametric_eq;
+pub mod patch_terminal;
 pub mod plotter;
+pub mod reverb;
 pub mod stream_input;
 pub mod stream_output;
+pub mod sub_patch;
+pub mod surround;
 pub mod trigger;
 pub mod video_mixer;
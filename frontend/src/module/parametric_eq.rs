@@ -0,0 +1,216 @@
+use std::fmt::{self, Display};
+
+use plotters::prelude::*;
+use web_sys::HtmlCanvasElement;
+use yew::{html, Callback, Component, ComponentLink, Html, ShouldRender, Properties, NodeRef};
+use yew_components::Select;
+
+use mixlab_protocol::{ModuleId, ModuleParams, ParametricEqParams, ParametricEqIndication, EqBand, FilterType, Decibel};
+
+use crate::component::midi_target::{MidiRangeTarget, MidiUiMode};
+use crate::control::Rotary;
+use crate::control::unit::{Hertz, Q};
+use crate::workspace::{Window, WindowMsg};
+
+const MAX_BANDS: usize = 8;
+
+const FREQ_MIN: f64 = 20.0;
+const FREQ_MAX: f64 = 20000.0;
+const GAIN_MIN: f64 = -24.0;
+const GAIN_MAX: f64 = 24.0;
+
+#[derive(Properties, Clone)]
+pub struct ParametricEqProps {
+    pub id: ModuleId,
+    pub module: ComponentLink<Window>,
+    pub params: ParametricEqParams,
+    pub indication: ParametricEqIndication,
+    pub midi_mode: MidiUiMode,
+}
+
+pub struct ParametricEq {
+    props: ParametricEqProps,
+    canvas: NodeRef,
+}
+
+impl Component for ParametricEq {
+    type Properties = ParametricEqProps;
+    type Message = ();
+
+    fn create(props: Self::Properties, _: ComponentLink<Self>) -> Self {
+        ParametricEq { props, canvas: NodeRef::default() }
+    }
+
+    fn update(&mut self, _msg: Self::Message) -> ShouldRender {
+        false
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        self.props = props;
+        true
+    }
+
+    fn rendered(&mut self, _first_render: bool) {
+        if let Some(canvas) = self.canvas.cast::<HtmlCanvasElement>() {
+            render_response(canvas, &self.props.indication.response);
+        }
+    }
+
+    fn view(&self) -> Html {
+        let bands = &self.props.params.bands;
+
+        html! {
+            <div class="parametric-eq">
+                <canvas ref={self.canvas.clone()} width={300} height={150} />
+                { for bands.iter().enumerate().map(|(index, band)| self.view_band(index, band)) }
+                { if bands.len() < MAX_BANDS {
+                    html! {
+                        <button onclick={self.update_params(|mut params, _| {
+                            params.bands.push(EqBand { filter: FilterType::Peak, freq: 1000.0, q: 1.0, gain: Decibel(0.0) });
+                            params
+                        })}>
+                            {"Add band"}
+                        </button>
+                    }
+                } else {
+                    html! {}
+                } }
+            </div>
+        }
+    }
+}
+
+impl ParametricEq {
+    fn view_band(&self, index: usize, band: &EqBand) -> Html {
+        let id = self.props.id;
+        let module = &self.props.module;
+        let midi_mode = self.props.midi_mode;
+
+        html! {
+            <div class="parametric-eq-band">
+                <Select<DisplayFilterType>
+                    selected={Some(DisplayFilterType(band.filter))}
+                    options={FilterType::ALL.iter().copied().map(DisplayFilterType).collect::<Vec<_>>()}
+                    on_change={self.update_params(move |mut params, filter: DisplayFilterType| {
+                        params.bands[index].filter = filter.0;
+                        params
+                    })}
+                />
+                <MidiRangeTarget
+                    ui_mode={midi_mode}
+                    module={module.clone()}
+                    id={id}
+                    param={format!("band{}.freq", index + 1)}
+                    min={FREQ_MIN}
+                    max={FREQ_MAX}
+                >
+                    <Rotary<Hertz>
+                        value={Hertz(band.freq)}
+                        min={Hertz(FREQ_MIN)}
+                        max={Hertz(FREQ_MAX)}
+                        default={Hertz(1000.0)}
+                        onchange={self.update_params(move |mut params, freq: Hertz| {
+                            params.bands[index].freq = freq.0;
+                            params
+                        })}
+                    />
+                </MidiRangeTarget>
+                <MidiRangeTarget
+                    ui_mode={midi_mode}
+                    module={module.clone()}
+                    id={id}
+                    param={format!("band{}.q", index + 1)}
+                    min={0.1}
+                    max={10.0}
+                >
+                    <Rotary<Q>
+                        value={Q(band.q)}
+                        min={Q(0.1)}
+                        max={Q(10.0)}
+                        default={Q(0.707)}
+                        onchange={self.update_params(move |mut params, q: Q| {
+                            params.bands[index].q = q.0;
+                            params
+                        })}
+                    />
+                </MidiRangeTarget>
+                <MidiRangeTarget
+                    ui_mode={midi_mode}
+                    module={module.clone()}
+                    id={id}
+                    param={format!("band{}.gain", index + 1)}
+                    min={GAIN_MIN}
+                    max={GAIN_MAX}
+                >
+                    <Rotary<Decibel>
+                        value={band.gain}
+                        min={Decibel(GAIN_MIN)}
+                        max={Decibel(GAIN_MAX)}
+                        default={Decibel(0.0)}
+                        onchange={self.update_params(move |mut params, gain: Decibel| {
+                            params.bands[index].gain = gain;
+                            params
+                        })}
+                    />
+                </MidiRangeTarget>
+                <button onclick={self.update_params(move |mut params, _| {
+                    params.bands.remove(index);
+                    params
+                })}>
+                    {"Remove"}
+                </button>
+            </div>
+        }
+    }
+
+    fn update_params<Ev>(&self, f: impl Fn(ParametricEqParams, Ev) -> ParametricEqParams + 'static) -> Callback<Ev> {
+        let params = self.props.params.clone();
+
+        self.props.module.callback(move |ev| {
+            WindowMsg::UpdateParams(ModuleParams::ParametricEq(f(params.clone(), ev)))
+        })
+    }
+}
+
+fn render_response(canvas: HtmlCanvasElement, response: &[(f64, Decibel)]) {
+    let backend = CanvasBackend::with_canvas_object(canvas).unwrap();
+    let root = backend.into_drawing_area();
+    root.fill(&WHITE).unwrap();
+
+    // frequency is plotted on a log scale:
+    let mut chart = ChartBuilder::on(&root)
+        .x_label_area_size(20)
+        .y_label_area_size(45)
+        .build_ranged(FREQ_MIN.log10()..FREQ_MAX.log10(), GAIN_MIN..GAIN_MAX).unwrap();
+    chart.configure_mesh()
+        .x_labels(4)
+        .x_label_formatter(&|x| format!("{}", Hertz(f64::powf(10.0, *x))))
+        .y_labels(5)
+        .y_desc("Gain (dB)")
+        .draw()
+        .unwrap();
+
+    let series = response
+        .iter()
+        .map(|(freq, gain)| (freq.log10(), gain.0.max(GAIN_MIN).min(GAIN_MAX)))
+        .collect::<Vec<(f64, f64)>>();
+    chart.draw_series(LineSeries::new(series, &RED)).unwrap();
+
+    root.present().unwrap();
+}
+
+#[derive(PartialEq, Clone)]
+pub struct DisplayFilterType(FilterType);
+
+impl Display for DisplayFilterType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            FilterType::Peak => write!(f, "Peak"),
+            FilterType::LowShelf => write!(f, "Low shelf"),
+            FilterType::HighShelf => write!(f, "High shelf"),
+            FilterType::LowPass => write!(f, "Low pass"),
+            FilterType::HighPass => write!(f, "High pass"),
+            FilterType::Notch => write!(f, "Notch"),
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use std::fmt::{self, Display};
+
+use yew::{html, Component, ComponentLink, Html, ShouldRender, Properties, Callback};
+use yew::events::ChangeData;
+use yew_components::Select;
+
+use mixlab_protocol::{ModuleId, ModuleParams, PatchTerminalParams, LineType, ChannelLayout};
+
+use crate::workspace::{Window, WindowMsg};
+
+// shared by the PatchInput and PatchOutput modules, which differ only in
+// which side of the sub-patch they expose
+#[derive(Properties, Clone, Debug)]
+pub struct PatchTerminalProps {
+    pub id: ModuleId,
+    pub module: ComponentLink<Window>,
+    pub params: PatchTerminalParams,
+    pub output: bool,
+}
+
+pub struct PatchTerminal {
+    props: PatchTerminalProps,
+}
+
+impl Component for PatchTerminal {
+    type Properties = PatchTerminalProps;
+    type Message = ();
+
+    fn create(props: Self::Properties, _: ComponentLink<Self>) -> Self {
+        Self { props }
+    }
+
+    fn update(&mut self, _msg: Self::Message) -> ShouldRender {
+        false
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        self.props = props;
+        true
+    }
+
+    fn view(&self) -> Html {
+        html! {
+            <>
+                <label class="form-field">
+                    <span class="form-field-label">{"Label"}</span>
+                    <input type="text"
+                        onchange={self.callback(|change, params| {
+                            if let ChangeData::Value(label) = change {
+                                PatchTerminalParams { label, ..params }
+                            } else {
+                                unreachable!()
+                            }
+                        })}
+                        value={&self.props.params.label}
+                    />
+                </label>
+
+                <label class="form-field">
+                    <span class="form-field-label">{"Type"}</span>
+                    <Select<DisplayLineType>
+                        selected={Some(DisplayLineType(self.props.params.line_type))}
+                        options={vec![
+                            DisplayLineType(LineType::Mono),
+                            DisplayLineType(LineType::Stereo),
+                            DisplayLineType(ChannelLayout::Quad.line_type()),
+                            DisplayLineType(ChannelLayout::Surround51.line_type()),
+                            DisplayLineType(ChannelLayout::Surround71.line_type()),
+                            DisplayLineType(LineType::Video),
+                            DisplayLineType(LineType::Event),
+                        ]}
+                        on_change={self.callback(|line_type: DisplayLineType, params| {
+                            PatchTerminalParams { line_type: line_type.0, ..params }
+                        })}
+                    />
+                </label>
+            </>
+        }
+    }
+}
+
+impl PatchTerminal {
+    fn callback<Ev>(&self, f: impl Fn(Ev, PatchTerminalParams) -> PatchTerminalParams + 'static)
+        -> Callback<Ev>
+    {
+        let params = self.props.params.clone();
+        let output = self.props.output;
+
+        self.props.module.callback(move |ev| {
+            let params = f(ev, params.clone());
+
+            WindowMsg::UpdateParams(if output {
+                ModuleParams::PatchOutput(params)
+            } else {
+                ModuleParams::PatchInput(params)
+            })
+        })
+    }
+}
+
+#[derive(PartialEq, Clone)]
+pub struct DisplayLineType(LineType);
+
+impl Display for DisplayLineType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            LineType::Mono => write!(f, "Mono"),
+            LineType::Stereo => write!(f, "Stereo"),
+            LineType::Multi(channels) => write!(f, "{} channel", channels),
+            LineType::Video => write!(f, "Video"),
+            LineType::Event => write!(f, "Events"),
+        }
+    }
+}
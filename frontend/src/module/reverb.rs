@@ -0,0 +1,102 @@
+use std::fmt::Display;
+
+use yew::{html, Component, ComponentLink, Html, ShouldRender, Properties};
+
+use mixlab_protocol::{ModuleId, ModuleParams, ReverbParams, REVERB_MAX_PRE_DELAY_MS};
+
+use crate::component::midi_target::{MidiRangeTarget, MidiUiMode};
+use crate::control::Rotary;
+use crate::control::unit::{Millis, Percent};
+use crate::workspace::{Window, WindowMsg};
+
+#[derive(Properties, Clone)]
+pub struct ReverbProps {
+    pub id: ModuleId,
+    pub module: ComponentLink<Window>,
+    pub params: ReverbParams,
+    pub midi_mode: MidiUiMode,
+}
+
+pub struct Reverb {
+    props: ReverbProps,
+}
+
+impl Component for Reverb {
+    type Properties = ReverbProps;
+    type Message = ();
+
+    fn create(props: Self::Properties, _: ComponentLink<Self>) -> Self {
+        Self { props }
+    }
+
+    fn update(&mut self, _msg: Self::Message) -> ShouldRender {
+        false
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        self.props = props;
+        true
+    }
+
+    fn view(&self) -> Html {
+        let params = &self.props.params;
+
+        html! {
+            <div class="dynamics">
+                <div class="dynamics-controls">
+                    { self.rotary("ROOM", "room_size", Percent(0.0), Percent(1.0), Percent(0.5), Percent(params.room_size),
+                        |params, room: Percent| ReverbParams { room_size: room.0, ..params }) }
+                    { self.rotary("DAMP", "damping", Percent(0.0), Percent(1.0), Percent(0.5), Percent(params.damping),
+                        |params, damping: Percent| ReverbParams { damping: damping.0, ..params }) }
+                    { self.rotary("PRE-DELAY", "pre_delay_ms", Millis(0.0), Millis(REVERB_MAX_PRE_DELAY_MS), Millis(10.0), Millis(params.pre_delay_ms),
+                        |params, pre_delay: Millis| ReverbParams { pre_delay_ms: pre_delay.0, ..params }) }
+                    { self.rotary("WIDTH", "width", Percent(0.0), Percent(1.0), Percent(1.0), Percent(params.width),
+                        |params, width: Percent| ReverbParams { width: width.0, ..params }) }
+                    { self.rotary("MIX", "mix", Percent(0.0), Percent(1.0), Percent(0.25), Percent(params.mix),
+                        |params, mix: Percent| ReverbParams { mix: mix.0, ..params }) }
+                </div>
+            </div>
+        }
+    }
+}
+
+impl Reverb {
+    fn rotary<T>(
+        &self,
+        label: &str,
+        param: &str,
+        min: T,
+        max: T,
+        default: T,
+        value: T,
+        f: impl Fn(ReverbParams, T) -> ReverbParams + 'static,
+    ) -> Html
+        where T: Into<f64> + From<f64> + Clone + Copy + Display + PartialEq + 'static
+    {
+        let params = self.props.params.clone();
+
+        html! {
+            <div class="dynamics-control">
+                <MidiRangeTarget
+                    ui_mode={self.props.midi_mode}
+                    module={self.props.module.clone()}
+                    id={self.props.id}
+                    param={param}
+                    min={min.into()}
+                    max={max.into()}
+                >
+                    <Rotary<T>
+                        value={value}
+                        min={min}
+                        max={max}
+                        default={default}
+                        onchange={self.props.module.callback(move |value| {
+                            WindowMsg::UpdateParams(ModuleParams::Reverb(f(params.clone(), value)))
+                        })}
+                    />
+                </MidiRangeTarget>
+                <div class="control-rotary-label">{label}</div>
+            </div>
+        }
+    }
+}
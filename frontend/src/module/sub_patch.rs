@@ -0,0 +1,70 @@
+use yew::{html, Component, ComponentLink, Html, ShouldRender, Properties};
+use yew::events::ChangeData;
+
+use mixlab_protocol::{ModuleId, ModuleParams, SubPatchParams};
+
+use crate::workspace::{Window, WindowMsg};
+
+#[derive(Properties, Clone, Debug)]
+pub struct SubPatchProps {
+    pub id: ModuleId,
+    pub module: ComponentLink<Window>,
+    pub params: SubPatchParams,
+}
+
+pub struct SubPatch {
+    props: SubPatchProps,
+}
+
+impl Component for SubPatch {
+    type Properties = SubPatchProps;
+    type Message = ();
+
+    fn create(props: Self::Properties, _: ComponentLink<Self>) -> Self {
+        Self { props }
+    }
+
+    fn update(&mut self, _msg: Self::Message) -> ShouldRender {
+        false
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        self.props = props;
+        true
+    }
+
+    fn view(&self) -> Html {
+        html! {
+            <>
+                <label class="form-field">
+                    <span class="form-field-label">{"Name"}</span>
+                    <input type="text"
+                        onchange={self.props.module.callback({
+                            let params = self.props.params.clone();
+                            move |change| {
+                                if let ChangeData::Value(name) = change {
+                                    WindowMsg::UpdateParams(
+                                        ModuleParams::SubPatch(SubPatchParams {
+                                            name,
+                                            ..params.clone()
+                                        }))
+                                } else {
+                                    unreachable!()
+                                }
+                            }
+                        })}
+                        value={&self.props.params.name}
+                    />
+                </label>
+
+                <button onclick={self.props.module.callback(|_| WindowMsg::OpenPatch)}>
+                    {"Open"}
+                </button>
+
+                <button onclick={self.props.module.callback(|_| WindowMsg::Duplicate)}>
+                    {"Duplicate"}
+                </button>
+            </>
+        }
+    }
+}
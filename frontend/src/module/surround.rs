@@ -0,0 +1,106 @@
+use std::fmt::{self, Display};
+
+use yew::{html, Callback, ComponentLink, Html};
+use yew::events::ChangeData;
+use yew_components::Select;
+
+use mixlab_protocol::{ModuleId, ModuleParams, ChannelLayout, UpmixParams, DownmixParams};
+
+use crate::component::pure_module::{Pure, PureModule};
+use crate::component::midi_target::MidiUiMode;
+use crate::workspace::{Window, WindowMsg};
+
+pub type Upmix = Pure<UpmixParams>;
+pub type Downmix = Pure<DownmixParams>;
+
+impl PureModule for UpmixParams {
+    fn view(&self, id: ModuleId, module: ComponentLink<Window>, _: MidiUiMode) -> Html {
+        let layout_params = self.clone();
+
+        let centre_id = format!("w{}-centre", id.0);
+        let centre_params = self.clone();
+
+        let surround_id = format!("w{}-surround", id.0);
+        let surround_params = self.clone();
+
+        html! {
+            <>
+                { view_layout(self.layout, module.callback(move |layout| {
+                    WindowMsg::UpdateParams(
+                        ModuleParams::Upmix(UpmixParams { layout, ..layout_params.clone() }))
+                })) }
+                <label for={&centre_id}>{"Centre"}</label>
+                <input type="range"
+                    id={&centre_id}
+                    min={0}
+                    max={1}
+                    step={0.01}
+                    onchange={module.callback(move |ev| {
+                        if let ChangeData::Value(centre_str) = ev {
+                            let centre = centre_str.parse().unwrap_or(0.0);
+                            let params = UpmixParams { centre, ..centre_params.clone() };
+                            WindowMsg::UpdateParams(
+                                ModuleParams::Upmix(params))
+                        } else {
+                            unreachable!()
+                        }
+                    })}
+                    value={self.centre}
+                />
+                <label for={&surround_id}>{"Surround"}</label>
+                <input type="range"
+                    id={&surround_id}
+                    min={0}
+                    max={1}
+                    step={0.01}
+                    onchange={module.callback(move |ev| {
+                        if let ChangeData::Value(surround_str) = ev {
+                            let surround = surround_str.parse().unwrap_or(0.0);
+                            let params = UpmixParams { surround, ..surround_params.clone() };
+                            WindowMsg::UpdateParams(
+                                ModuleParams::Upmix(params))
+                        } else {
+                            unreachable!()
+                        }
+                    })}
+                    value={self.surround}
+                />
+            </>
+        }
+    }
+}
+
+impl PureModule for DownmixParams {
+    fn view(&self, _: ModuleId, module: ComponentLink<Window>, _: MidiUiMode) -> Html {
+        view_layout(self.layout, module.callback(|layout| {
+            WindowMsg::UpdateParams(
+                ModuleParams::Downmix(DownmixParams { layout }))
+        }))
+    }
+}
+
+fn view_layout(layout: ChannelLayout, on_change: Callback<ChannelLayout>) -> Html {
+    html! {
+        <label class="form-field">
+            <span class="form-field-label">{"Layout"}</span>
+            <Select<DisplayLayout>
+                selected={Some(DisplayLayout(layout))}
+                options={ChannelLayout::ALL.iter().copied().map(DisplayLayout).collect::<Vec<_>>()}
+                on_change={on_change.reform(|layout: DisplayLayout| layout.0)}
+            />
+        </label>
+    }
+}
+
+#[derive(PartialEq, Clone)]
+pub struct DisplayLayout(pub ChannelLayout);
+
+impl Display for DisplayLayout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            ChannelLayout::Quad => write!(f, "Quad"),
+            ChannelLayout::Surround51 => write!(f, "5.1"),
+            ChannelLayout::Surround71 => write!(f, "7.1"),
+        }
+    }
+}
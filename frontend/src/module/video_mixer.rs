@@ -11,7 +11,7 @@ use crate::util::prevent_default;
 pub type VideoMixer = Pure<VideoMixerParams>;
 
 impl PureModule for VideoMixerParams {
-    fn view(&self, _: ModuleId, module: ComponentLink<Window>, midi_mode: MidiUiMode) -> Html {
+    fn view(&self, id: ModuleId, module: ComponentLink<Window>, midi_mode: MidiUiMode) -> Html {
         html! {
             <>
                 <div class="video-mixer">
@@ -31,9 +31,11 @@ impl PureModule for VideoMixerParams {
                     <div class="video-mixer-fader">
                         <MidiRangeTarget
                             ui_mode={midi_mode}
-                            onchange={module.callback(
-                                update_params(self, move |params, fader|
-                                    VideoMixerParams { fader, ..params }))}
+                            module={module.clone()}
+                            id={id}
+                            param={"fader"}
+                            min={0.0}
+                            max={1.0}
                         >
                             <Fader
                                 value={self.fader}
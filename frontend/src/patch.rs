@@ -0,0 +1,154 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::num::NonZeroUsize;
+
+use mixlab_protocol::{ModuleId, InputId, OutputId, SavedWorkspace, SavedModule, SubPatchIndication, WorkspaceOp};
+
+use crate::session::WorkspaceState;
+
+// Sub-patches are edited in the same workspace view as the top level
+// workspace. Their contents live in the params of the sub-patch module, so the
+// view's state is derived from those params, and the view's ops are applied
+// to them before being sent on as a params update.
+
+pub fn workspace_state(patch: &SavedWorkspace, indication: Option<&SubPatchIndication>) -> WorkspaceState {
+    let mut state = WorkspaceState {
+        modules: BTreeMap::new(),
+        geometry: HashMap::new(),
+        connections: HashMap::new(),
+        feedback: HashSet::new(),
+        indications: HashMap::new(),
+        inputs: HashMap::new(),
+        outputs: HashMap::new(),
+        // automation lanes are only kept for top level modules:
+        automation: HashMap::new(),
+        // as are scenes:
+        scenes: Vec::new(),
+        cv: HashMap::new(),
+    };
+
+    for (module_id, module) in &patch.modules {
+        state.modules.insert(*module_id, module.params.clone());
+        state.geometry.insert(*module_id, module.geometry.clone());
+
+        if !module.cv.is_empty() {
+            state.cv.insert(*module_id, module.cv.clone());
+        }
+
+        for (index, output) in module.inputs.iter().enumerate() {
+            if let Some(output) = output {
+                let input = InputId(*module_id, index);
+                state.connections.insert(input, *output);
+
+                if module.feedback.contains(&index) {
+                    state.feedback.insert(input);
+                }
+            }
+        }
+    }
+
+    // terminals and indications are only known to the server, modules appear
+    // once it has reported them
+    if let Some(indication) = indication {
+        for module in &indication.modules {
+            if patch.modules.contains_key(&module.id) {
+                state.indications.insert(module.id, module.indication.clone());
+                state.inputs.insert(module.id, module.inputs.clone());
+                state.outputs.insert(module.id, module.outputs.clone());
+            }
+        }
+    }
+
+    state
+}
+
+// applies an op from the sub-patch's workspace view to the patch. returns
+// false if the op has no meaning within a patch
+pub fn apply_op(patch: &mut SavedWorkspace, op: WorkspaceOp) -> bool {
+    match op {
+        WorkspaceOp::CreateModule(params, geometry) => {
+            patch.module_seq += 1;
+
+            let id = ModuleId(NonZeroUsize::new(patch.module_seq).unwrap());
+
+            patch.modules.insert(id, SavedModule {
+                params,
+                geometry,
+                inputs: Vec::new(),
+                feedback: Vec::new(),
+                cv: Vec::new(),
+            });
+        }
+        WorkspaceOp::UpdateModuleParams(module_id, params) => {
+            if let Some(module) = patch.modules.get_mut(&module_id) {
+                module.params = params;
+            }
+        }
+        WorkspaceOp::UpdateWindowGeometry(module_id, geometry) => {
+            if let Some(module) = patch.modules.get_mut(&module_id) {
+                module.geometry = geometry;
+            }
+        }
+        WorkspaceOp::DeleteModule(module_id) => {
+            patch.modules.remove(&module_id);
+
+            for module in patch.modules.values_mut() {
+                let SavedModule { inputs, feedback, .. } = module;
+
+                for output in inputs.iter_mut() {
+                    if output.map(|output| output.module_id()) == Some(module_id) {
+                        *output = None;
+                    }
+                }
+
+                feedback.retain(|index| inputs.get(*index).map_or(false, Option::is_some));
+            }
+        }
+        WorkspaceOp::CreateConnection(input, output) => {
+            connect(patch, input, Some(output), false);
+        }
+        WorkspaceOp::CreateFeedbackConnection(input, output) => {
+            connect(patch, input, Some(output), true);
+        }
+        WorkspaceOp::DeleteConnection(input) => {
+            connect(patch, input, None, false);
+        }
+        WorkspaceOp::SetModuleCv(module_id, cv) => {
+            // the module's own input count is only known to the server, so
+            // unlike at the top level, connections to cv inputs are kept by
+            // index when bindings change
+            if let Some(module) = patch.modules.get_mut(&module_id) {
+                module.cv = cv;
+            }
+        }
+        WorkspaceOp::Undo |
+        WorkspaceOp::Redo |
+        WorkspaceOp::Transport(_) |
+        WorkspaceOp::SetAutomationMode(..) |
+        WorkspaceOp::ClearAutomation(_) |
+        WorkspaceOp::CaptureScene(_) |
+        WorkspaceOp::RecallScene(..) |
+        WorkspaceOp::DeleteScene(_) |
+        WorkspaceOp::MidiInput(..) |
+        WorkspaceOp::BindMidi(_) |
+        WorkspaceOp::UnbindMidi(..) => {
+            return false;
+        }
+    }
+
+    true
+}
+
+fn connect(patch: &mut SavedWorkspace, input: InputId, output: Option<OutputId>, feedback: bool) {
+    if let Some(module) = patch.modules.get_mut(&input.module_id()) {
+        if module.inputs.len() <= input.index() {
+            module.inputs.resize(input.index() + 1, None);
+        }
+
+        module.inputs[input.index()] = output;
+        module.feedback.retain(|index| *index != input.index());
+
+        if feedback {
+            module.feedback.push(input.index());
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use yew::{html, Component, ComponentLink, Html, ShouldRender, Properties};
+use yew::events::ChangeData;
+
+use mixlab_protocol::WorkspaceOp;
+
+use crate::session::{SessionRef, WorkspaceStateRef};
+
+pub struct Scenes {
+    link: ComponentLink<Self>,
+    props: ScenesProps,
+    name: String,
+    fade_ms: u64,
+}
+
+#[derive(Properties, Clone, Debug)]
+pub struct ScenesProps {
+    pub session: SessionRef,
+    pub workspace: WorkspaceStateRef,
+}
+
+pub enum ScenesMsg {
+    SetName(String),
+    SetFade(u64),
+    Capture,
+    Op(WorkspaceOp),
+}
+
+impl Component for Scenes {
+    type Properties = ScenesProps;
+    type Message = ScenesMsg;
+
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        Scenes {
+            link,
+            props,
+            name: String::new(),
+            fade_ms: 1000,
+        }
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        self.props = props;
+        true
+    }
+
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        match msg {
+            ScenesMsg::SetName(name) => {
+                self.name = name;
+                false
+            }
+            ScenesMsg::SetFade(fade_ms) => {
+                self.fade_ms = fade_ms;
+                true
+            }
+            ScenesMsg::Capture => {
+                let name = self.name.trim().to_owned();
+
+                if name.is_empty() {
+                    return false;
+                }
+
+                self.props.session.update_workspace(WorkspaceOp::CaptureScene(name));
+                self.name = String::new();
+                true
+            }
+            ScenesMsg::Op(op) => {
+                self.props.session.update_workspace(op);
+                false
+            }
+        }
+    }
+
+    fn view(&self) -> Html {
+        let workspace = self.props.workspace.borrow();
+        let fade_ms = self.fade_ms;
+
+        html! {
+            <div class="scenes">
+                <div class="scenes-capture">
+                    <input type="text"
+                        placeholder="Scene name"
+                        value={&self.name}
+                        onchange={self.link.callback(|change| {
+                            match change {
+                                ChangeData::Value(name) => ScenesMsg::SetName(name),
+                                _ => unreachable!(),
+                            }
+                        })}
+                    />
+                    <button onclick={self.link.callback(|_| ScenesMsg::Capture)}>
+                        {"Capture"}
+                    </button>
+                </div>
+                <label class="form-field">
+                    <span class="form-field-label">{"Fade ms"}</span>
+                    <input type="number"
+                        min={0}
+                        value={fade_ms}
+                        onchange={self.link.callback(move |change| {
+                            match change {
+                                ChangeData::Value(value) => ScenesMsg::SetFade(value.parse().unwrap_or(fade_ms)),
+                                _ => unreachable!(),
+                            }
+                        })}
+                    />
+                </label>
+                { for workspace.scenes.iter().map(|name| {
+                    let recall = name.clone();
+                    let fade = name.clone();
+                    let delete = name.clone();
+
+                    html! {
+                        <div class="scenes-scene">
+                            <span class="scenes-scene-name">{name}</span>
+                            <button onclick={self.link.callback(move |_| ScenesMsg::Op(WorkspaceOp::RecallScene(recall.clone(), 0)))}>
+                                {"Recall"}
+                            </button>
+                            <button onclick={self.link.callback(move |_| ScenesMsg::Op(WorkspaceOp::RecallScene(fade.clone(), fade_ms)))}>
+                                {"Fade"}
+                            </button>
+                            <button onclick={self.link.callback(move |_| ScenesMsg::Op(WorkspaceOp::DeleteScene(delete.clone())))}>
+                                {"×"}
+                            </button>
+                        </div>
+                    }
+                }) }
+            </div>
+        }
+    }
+}
@@ -1,9 +1,7 @@
 use std::cell::RefCell;
-use std::collections::{HashMap, BTreeMap};
+use std::collections::HashMap;
 use std::fmt::{self, Debug};
-use std::num::NonZeroUsize;
 use std::rc::Rc;
-use std::usize;
 
 use derive_more::From;
 use gloo_events::EventListener;
@@ -13,39 +11,25 @@ use wasm_bindgen_futures::JsFuture;
 use web_sys::{MidiInput, MidiMessageEvent, MidiConnectionEvent};
 use yew::Callback;
 
-use crate::util::Sequence;
+use mixlab_protocol::{ModuleId, MidiBinding, MidiControl, MidiControlKind};
 
+// MIDI devices attached to this browser are read here, but bindings of their
+// controls to module params are kept and applied by the server. the broker
+// forwards changes to bound controls on to the server, and picks up controls
+// to bind while configuring
 struct MidiBroker {
     state_change_listener: Option<EventListener>,
     inputs: HashMap<MidiInputId, MidiInput>,
     listeners: HashMap<MidiInputId, EventListener>,
     configuring: Option<ConfigureKind>,
-    id_seq: Sequence,
-    range_subscribers: BTreeMap<(MidiRangeId, SubscriptionId), Callback<u8>>,
+    // as last received from the server:
+    bindings: Vec<MidiBinding>,
+    forward: Option<Callback<(MidiControl, u8)>>,
 }
 
 #[derive(Clone)]
 pub struct MidiBrokerRef(Rc<RefCell<MidiBroker>>);
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
-pub struct MidiRangeId(MidiInputId, u8);
-
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
-pub struct MidiNoteId(MidiInputId, u8);
-
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
-struct SubscriptionId(NonZeroUsize);
-
-impl SubscriptionId {
-    fn min() -> SubscriptionId {
-        SubscriptionId(NonZeroUsize::new(1).unwrap())
-    }
-
-    fn max() -> SubscriptionId {
-        SubscriptionId(NonZeroUsize::new(usize::MAX).unwrap())
-    }
-}
-
 type MidiInputId = Rc<String>;
 
 thread_local! {
@@ -57,7 +41,7 @@ pub fn broker() -> MidiBrokerRef {
 }
 
 impl MidiBrokerRef {
-    pub fn configure_range(&self, callback: Callback<Option<(MidiRangeId, u8)>>) -> ConfigureTask {
+    pub fn configure_range(&self, callback: Callback<Option<MidiControl>>) -> ConfigureTask {
         let configure = ConfigureKind::Range(callback);
 
         self.0.borrow_mut().configure(configure.clone());
@@ -68,55 +52,73 @@ impl MidiBrokerRef {
         }
     }
 
-    pub fn subscribe_range(&self, range_id: MidiRangeId, callback: Callback<u8>) -> RangeSubscription {
-        let key = {
-            let mut broker = self.0.borrow_mut();
-            let subscription_id = SubscriptionId(broker.id_seq.next());
-            let key = (range_id, subscription_id);
-            broker.range_subscribers.insert(key.clone(), callback);
-            key
-        };
+    // changes to bound controls are passed to the callback, to be sent on to
+    // the server
+    pub fn forward_to(&self, callback: Callback<(MidiControl, u8)>) {
+        self.0.borrow_mut().forward = Some(callback);
+    }
 
-        RangeSubscription {
-            broker: self.clone(),
-            key,
-        }
+    pub fn set_bindings(&self, bindings: Vec<MidiBinding>) {
+        self.0.borrow_mut().bindings = bindings;
+    }
+
+    pub fn binding(&self, module: ModuleId, param: &str) -> Option<MidiBinding> {
+        self.0.borrow().bindings.iter()
+            .find(|binding| binding.module == module && binding.param == param)
+            .cloned()
     }
 
     fn on_message(&self, input_id: MidiInputId, event: &MidiMessageEvent) {
         let data = event.data().expect("MidiMessageEvent::data");
 
-        // MIDI controller (range) change message
-        if data.len() == 3 && (data[0] & 0xf0) == 0xb0 {
-            let range_id = MidiRangeId(input_id, data[1] & 0x7f);
-            let value = data[2] & 0x7f;
+        if data.len() != 3 {
+            return;
+        }
 
-            let min_key = (range_id.clone(), SubscriptionId::min());
-            let max_key = (range_id.clone(), SubscriptionId::max());
+        let channel = data[0] & 0x0f;
+        let number = data[1] & 0x7f;
 
-            let mut subscribers = Vec::new();
-            let mut configuring = None;
+        let (kind, value) = match data[0] & 0xf0 {
+            // controller (range) change
+            0xb0 => (MidiControlKind::Cc(number), data[2] & 0x7f),
+            // note on, which is note off if velocity is zero
+            0x90 if data[2] > 0 => (MidiControlKind::Note(number), 127),
+            0x90 | 0x80 => (MidiControlKind::Note(number), 0),
+            _ => { return; }
+        };
 
-            {
-                let mut broker = self.0.borrow_mut();
+        let mut configuring = None;
+        let mut forward = None;
 
-                for (_, callback) in broker.range_subscribers.range(min_key..=max_key) {
-                    subscribers.push(callback.clone());
-                }
+        let control = {
+            let mut broker = self.0.borrow_mut();
+
+            // devices are identified by name rather than by id, as ids are
+            // specific to the browser the device is attached to
+            let device = broker.inputs.get(&input_id)
+                .and_then(|input| input.name())
+                .unwrap_or_else(|| input_id.to_string());
+
+            let control = MidiControl { device, channel, kind };
 
-                if let Some(ConfigureKind::Range(callback)) = &broker.configuring {
+            if let Some(ConfigureKind::Range(callback)) = &broker.configuring {
+                // note off would immediately follow the note on which
+                // selected the control, leave it be
+                if value > 0 || matches!(kind, MidiControlKind::Cc(_)) {
                     configuring = Some(callback.clone());
                     broker.configuring = None;
                 }
+            } else if broker.bindings.iter().any(|binding| binding.control == control) {
+                forward = broker.forward.clone();
             }
 
-            for callback in subscribers {
-                callback.emit(value);
-            }
+            control
+        };
 
-            if let Some(callback) = configuring {
-                callback.emit(Some((range_id, value)));
-            }
+        if let Some(callback) = configuring {
+            callback.emit(Some(control));
+        } else if let Some(callback) = forward {
+            callback.emit((control, value));
         }
     }
 }
@@ -128,8 +130,8 @@ impl MidiBroker {
             inputs: HashMap::new(),
             listeners: HashMap::new(),
             configuring: None,
-            id_seq: Sequence::new(),
-            range_subscribers: BTreeMap::new(),
+            bindings: Vec::new(),
+            forward: None,
         })));
 
         wasm_bindgen_futures::spawn_local({
@@ -161,24 +163,6 @@ impl MidiBroker {
     }
 }
 
-#[must_use = "subscription only lives as long as this object"]
-pub struct RangeSubscription {
-    broker: MidiBrokerRef,
-    key: (MidiRangeId, SubscriptionId),
-}
-
-impl Debug for RangeSubscription {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "RangeSubscription {{ key: {:?}, broker: .. }}", self.key)
-    }
-}
-
-impl Drop for RangeSubscription {
-    fn drop(&mut self) {
-        self.broker.0.borrow_mut().range_subscribers.remove(&self.key);
-    }
-}
-
 #[must_use = "configure callback will never fire after this is dropped"]
 pub struct ConfigureTask {
     broker: MidiBrokerRef,
@@ -203,8 +187,7 @@ impl Drop for ConfigureTask {
 
 #[derive(Debug, Clone, PartialEq)]
 enum ConfigureKind {
-    Range(Callback<Option<(MidiRangeId, u8)>>),
-    // Note(Callback<Option<MidiNoteId>>),
+    Range(Callback<Option<MidiControl>>),
 }
 
 #[derive(Debug, From)]
@@ -1,13 +1,14 @@
 use std::cell::RefCell;
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::rc::Rc;
 
 use yew::services::websocket::{WebSocketService, WebSocketStatus, WebSocketTask};
 use yew::format::Binary;
 use yew::Callback;
 
-use mixlab_protocol::{ServerMessage, ServerUpdate, ClientMessage, ClientSequence, ModuleId, ModuleParams, WindowGeometry, InputId, OutputId, Indication, Terminal, WorkspaceOp, WorkspaceMessage};
+use mixlab_protocol::{ServerMessage, ServerUpdate, ClientMessage, ClientSequence, ModuleId, ModuleParams, WindowGeometry, InputId, OutputId, Indication, Terminal, WorkspaceOp, WorkspaceMessage, TransportState, AutomationStatus, CvBinding};
 
+use crate::service::midi;
 use crate::util;
 use crate::util::notify::{self, Notify};
 use crate::Sequence;
@@ -31,6 +32,7 @@ struct Notifiers {
     workspace: Notify<()>,
     performance: Notify<Rc<mixlab_protocol::PerformanceInfo>>,
     media: Notify<Rc<mixlab_protocol::MediaLibrary>>,
+    transport: Notify<TransportState>,
 }
 
 pub type SessionRef = Rc<Session>;
@@ -48,6 +50,7 @@ impl Session {
                 workspace: Notify::new(),
                 performance: Notify::new(),
                 media: Notify::new(),
+                transport: Notify::new(),
             },
         });
 
@@ -110,9 +113,16 @@ impl Session {
     fn on_server_message(&self, msg: ServerMessage) {
         match msg {
             ServerMessage::WorkspaceState(state) => {
+                self.notify.transport.broadcast(state.transport);
+                midi::broker().set_bindings(state.midi.clone());
                 *self.state.borrow_mut() = Some(Rc::new(RefCell::new(state.into())));
                 self.notify.workspace.broadcast(());
             }
+            ServerMessage::Update(ServerUpdate::UpdateTransport(transport)) => {
+                // transport is kept apart from the workspace state so that
+                // its frequent updates don't re-render the workspace
+                self.notify.transport.broadcast(transport);
+            }
             ServerMessage::Sync(seq) => {
                 self.sync(seq);
 
@@ -150,7 +160,16 @@ impl Session {
                         }
                         ServerUpdate::UpdateModuleIndication(id, new_indication) => {
                             if let Some(indication) = state.indications.get_mut(&id) {
-                                *indication = new_indication;
+                                indication.apply(new_indication);
+                            }
+                        }
+                        ServerUpdate::UpdateModuleTerminals(id, new_inputs, new_outputs) => {
+                            if let Some(inputs) = state.inputs.get_mut(&id) {
+                                *inputs = new_inputs;
+                            }
+
+                            if let Some(outputs) = state.outputs.get_mut(&id) {
+                                *outputs = new_outputs;
                             }
                         }
                         ServerUpdate::DeleteModule(id) => {
@@ -159,12 +178,41 @@ impl Session {
                             state.indications.remove(&id);
                             state.inputs.remove(&id);
                             state.outputs.remove(&id);
+                            state.cv.remove(&id);
+                            state.automation.remove(&id);
                         }
                         ServerUpdate::CreateConnection(input, output) => {
                             state.connections.insert(input, output);
+                            state.feedback.remove(&input);
+                        }
+                        ServerUpdate::CreateFeedbackConnection(input, output) => {
+                            state.connections.insert(input, output);
+                            state.feedback.insert(input);
                         }
                         ServerUpdate::DeleteConnection(input) => {
                             state.connections.remove(&input);
+                            state.feedback.remove(&input);
+                        }
+                        ServerUpdate::UpdateTransport(_) => {
+                            // handled above
+                        }
+                        ServerUpdate::UpdateAutomation(id, status) => {
+                            state.automation.insert(id, status);
+                        }
+                        ServerUpdate::UpdateScenes(names) => {
+                            state.scenes = names;
+                        }
+                        ServerUpdate::UpdateModuleCv(id, cv) => {
+                            if cv.is_empty() {
+                                state.cv.remove(&id);
+                            } else {
+                                state.cv.insert(id, cv);
+                            }
+                        }
+                        ServerUpdate::UpdateMidiBindings(bindings) => {
+                            // bindings are kept with the midi service, which
+                            // forwards changes to bound controls
+                            midi::broker().set_bindings(bindings);
                         }
                     }
                 }
@@ -207,6 +255,10 @@ impl Session {
         self.notify.performance.subscribe(callback)
     }
 
+    pub fn listen_transport(&self, callback: Callback<TransportState>) -> notify::Handle {
+        self.notify.transport.subscribe(callback)
+    }
+
     pub fn listen_media(&self, callback: Callback<Rc<mixlab_protocol::MediaLibrary>>) -> notify::Handle {
         self.notify.media.subscribe(callback)
     }
@@ -230,9 +282,43 @@ pub struct WorkspaceState {
     pub modules: BTreeMap<ModuleId, ModuleParams>,
     pub geometry: HashMap<ModuleId, WindowGeometry>,
     pub connections: HashMap<InputId, OutputId>,
+    // inputs in connections which are connected via feedback connections:
+    pub feedback: HashSet<InputId>,
     pub indications: HashMap<ModuleId, Indication>,
     pub inputs: HashMap<ModuleId, Vec<Terminal>>,
     pub outputs: HashMap<ModuleId, Vec<Terminal>>,
+    pub automation: HashMap<ModuleId, AutomationStatus>,
+    // scene names in order:
+    pub scenes: Vec<String>,
+    // only modules with cv bindings are present:
+    pub cv: HashMap<ModuleId, Vec<CvBinding>>,
+}
+
+impl WorkspaceState {
+    // returns true if module is fed, directly or indirectly, by the output of
+    // dependency. feedback connections are not followed
+    pub fn depends_on(&self, module: ModuleId, dependency: ModuleId) -> bool {
+        let mut seen = HashSet::new();
+        let mut stack = vec![module];
+
+        while let Some(module) = stack.pop() {
+            if module == dependency {
+                return true;
+            }
+
+            if !seen.insert(module) {
+                continue;
+            }
+
+            for (input, output) in &self.connections {
+                if input.module_id() == module && !self.feedback.contains(input) {
+                    stack.push(output.module_id());
+                }
+            }
+        }
+
+        false
+    }
 }
 
 impl From<mixlab_protocol::WorkspaceState> for WorkspaceState {
@@ -242,8 +328,12 @@ impl From<mixlab_protocol::WorkspaceState> for WorkspaceState {
             geometry: wstate.geometry.into_iter().collect(),
             indications: wstate.indications.into_iter().collect(),
             connections: wstate.connections.into_iter().collect(),
+            feedback: wstate.feedback.into_iter().collect(),
             inputs: wstate.inputs.into_iter().collect(),
             outputs: wstate.outputs.into_iter().collect(),
+            automation: wstate.automation.into_iter().collect(),
+            scenes: wstate.scenes,
+            cv: wstate.cv.into_iter().collect(),
         }
     }
 }
@@ -4,7 +4,9 @@ use yew::{html, Component, ComponentLink, Html, ShouldRender, Properties};
 
 use mixlab_protocol::{PerformanceInfo, PerformanceAccount, TemporalWarningStatus, ModuleId};
 
+use crate::scenes::Scenes;
 use crate::session::{SessionRef, WorkspaceStateRef};
+use crate::transport::Transport;
 use crate::util::notify;
 
 pub struct Sidebar {
@@ -55,6 +57,8 @@ impl Component for Sidebar {
         html! {
             <div class="sidebar">
                 <div class="sidebar-title">{"Mixlab"}</div>
+                <Transport session={self.props.session.clone()} />
+                <Scenes session={self.props.session.clone()} workspace={self.props.workspace.clone()} />
                 {self.view_perf_info()}
             </div>
         }
@@ -106,9 +110,13 @@ impl Sidebar {
                     <div class="perf-info-tick-util">
                         {format!("{:2.1}%", total_tick_percent)}
                     </div>
+                    <div class="perf-info-overruns">
+                        {format!("{} overruns", perf_info.overruns)}
+                    </div>
                     <table class="perf-info-accounts-table">
                         { for sorted_accounts.iter().map(|(account, metric)| {
                             let percent = (metric.last.0 as f64 / tick_budget) * 100.0;
+                            let p99_percent = (metric.p99.0 as f64 / tick_budget) * 100.0;
 
                             html! {
                                 <tr>
@@ -122,6 +130,7 @@ impl Sidebar {
                                         }
                                     } }
                                     <td class="perf-info-metric">{format!("{:2.1}%", percent)}</td>
+                                    <td class="perf-info-metric perf-info-metric-p99" title="99th percentile">{format!("{:2.1}%", p99_percent)}</td>
                                 </tr>
                             }
                         }) }
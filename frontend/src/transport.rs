@@ -0,0 +1,138 @@
+use yew::{html, Component, ComponentLink, Html, ShouldRender, Properties};
+use yew::events::ChangeData;
+
+use mixlab_protocol::{TransportState, TransportOp, Tempo, WorkspaceOp};
+
+use crate::session::SessionRef;
+use crate::util::notify;
+
+pub struct Transport {
+    link: ComponentLink<Self>,
+    props: TransportProps,
+    state: Option<TransportState>,
+    _notify: notify::Handle,
+}
+
+#[derive(Properties, Clone, Debug)]
+pub struct TransportProps {
+    pub session: SessionRef,
+}
+
+pub enum TransportMsg {
+    State(TransportState),
+    Op(TransportOp),
+}
+
+impl Component for Transport {
+    type Properties = TransportProps;
+    type Message = TransportMsg;
+
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let notify = props.session.listen_transport(link.callback(TransportMsg::State));
+
+        Transport {
+            link,
+            props,
+            state: None,
+            _notify: notify,
+        }
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        self.props = props;
+        true
+    }
+
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        match msg {
+            TransportMsg::State(state) => {
+                self.state = Some(state);
+                true
+            }
+            TransportMsg::Op(op) => {
+                self.props.session.update_workspace(WorkspaceOp::Transport(op));
+                false
+            }
+        }
+    }
+
+    fn view(&self) -> Html {
+        let state = match self.state {
+            Some(state) => state,
+            None => return html! {},
+        };
+
+        let (bar, beat) = state.bar_beat();
+        let tempo = state.tempo;
+
+        let play_op = if state.playing { TransportOp::Pause } else { TransportOp::Play };
+        let play_label = if state.playing { "Pause" } else { "Play" };
+
+        html! {
+            <div class="transport">
+                <div class="transport-position">
+                    {format!("{}.{}", bar + 1, beat.floor() as u64 + 1)}
+                </div>
+                <div class="transport-buttons">
+                    <button onclick={self.link.callback(move |_| TransportMsg::Op(play_op))}>
+                        {play_label}
+                    </button>
+                    <button onclick={self.link.callback(|_| TransportMsg::Op(TransportOp::Stop))}>
+                        {"Stop"}
+                    </button>
+                </div>
+                <label class="form-field">
+                    <span class="form-field-label">{"BPM"}</span>
+                    <input type="number"
+                        min={Tempo::MIN_BPM}
+                        max={Tempo::MAX_BPM}
+                        value={tempo.bpm}
+                        onchange={self.tempo_callback(move |value| {
+                            value.parse().ok().map(|bpm| Tempo { bpm, ..tempo })
+                        })}
+                    />
+                </label>
+                <label class="form-field">
+                    <span class="form-field-label">{"Time"}</span>
+                    <div class="transport-time-signature">
+                        <input type="number"
+                            min={1}
+                            value={tempo.beats_per_bar}
+                            onchange={self.tempo_callback(move |value| {
+                                value.parse().ok().map(|beats_per_bar| Tempo { beats_per_bar, ..tempo })
+                            })}
+                        />
+                        {"/"}
+                        <input type="number"
+                            min={1}
+                            value={tempo.beat_unit}
+                            onchange={self.tempo_callback(move |value| {
+                                value.parse().ok().map(|beat_unit| Tempo { beat_unit, ..tempo })
+                            })}
+                        />
+                    </div>
+                </label>
+            </div>
+        }
+    }
+}
+
+impl Transport {
+    fn tempo_callback(&self, f: impl Fn(&str) -> Option<Tempo> + 'static) -> yew::Callback<ChangeData> {
+        let state = self.state;
+
+        self.link.callback(move |change| {
+            let tempo = match change {
+                ChangeData::Value(value) => f(&value).filter(Tempo::is_valid),
+                _ => None,
+            };
+
+            match (tempo, state) {
+                (Some(tempo), _) => TransportMsg::Op(TransportOp::SetTempo(tempo)),
+                // re-render with the current tempo to reset the field:
+                (None, Some(state)) => TransportMsg::State(state),
+                (None, None) => unreachable!(),
+            }
+        })
+    }
+}
@@ -1,25 +1,36 @@
 use std::collections::{BTreeMap, HashSet};
 use std::mem;
+use std::rc::Rc;
 
-use wasm_bindgen::JsCast;
-use web_sys::{CanvasRenderingContext2d, HtmlElement, HtmlCanvasElement, MouseEvent, Element};
+use gloo_events::EventListener;
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{CanvasRenderingContext2d, HtmlElement, HtmlCanvasElement, MouseEvent, KeyboardEvent, Element};
 use yew::{html, Callback, Component, ComponentLink, Html, ShouldRender, Properties, NodeRef};
+use yew::events::ChangeData;
 
-use mixlab_protocol::{ModuleId, TerminalId, InputId, OutputId, ModuleParams, OscillatorParams, Waveform, WorkspaceOp, WindowGeometry, Coords, Indication, OutputDeviceParams, FmSineParams, AmplifierParams, GateState, LineType, EnvelopeParams, MixerParams, StreamInputParams, EqThreeParams, StreamOutputParams, VideoMixerParams, MediaSourceParams};
+use mixlab_protocol::{ModuleId, TerminalId, InputId, OutputId, ModuleParams, OscillatorParams, Waveform, WorkspaceOp, WindowGeometry, Coords, Indication, OutputDeviceParams, FmSineParams, AmplifierParams, GateState, LineType, EnvelopeParams, MixerParams, StreamInputParams, EqThreeParams, StreamOutputParams, VideoMixerParams, MediaSourceParams, SubPatchParams, PatchTerminalParams, UpmixParams, DownmixParams, CompressorParams, GateParams, DelayParams, ReverbParams, ParametricEqParams, AutomationMode, AutomationStatus, CvBinding, MidiBinding};
 
 use crate::component::midi_target::MidiUiMode;
 use crate::module::amplifier::Amplifier;
+use crate::module::compressor::Compressor;
+use crate::module::delay::Delay;
 use crate::module::envelope::Envelope;
 use crate::module::eq_three::EqThree;
 use crate::module::fm_sine::FmSine;
+use crate::module::gate::Gate;
 use crate::module::media_source::MediaSource;
 use crate::module::mixer::Mixer;
 use crate::module::monitor::Monitor;
 use crate::module::oscillator::Oscillator;
 use crate::module::output_device::OutputDevice;
+use crate::module::parametric_eq::ParametricEq;
+use crate::module::patch_terminal::PatchTerminal;
 use crate::module::plotter::Plotter;
+use crate::module::reverb::Reverb;
 use crate::module::stream_input::StreamInput;
 use crate::module::stream_output::StreamOutput;
+use crate::module::sub_patch::SubPatch;
+use crate::module::surround::{Upmix, Downmix};
 use crate::module::trigger::Trigger;
 use crate::module::video_mixer::VideoMixer;
 use crate::util::{self, stop_propagation, prevent_default, Sequence};
@@ -33,6 +44,7 @@ pub struct Workspace {
     gen_z_index: Sequence,
     mouse: MouseMode,
     window_refs: BTreeMap<ModuleId, WindowRef>,
+    _keydown: EventListener,
 }
 
 #[derive(Properties, Clone)]
@@ -40,6 +52,8 @@ pub struct WorkspaceProps {
     pub app: ComponentLink<App>,
     pub state: WorkspaceStateRef,
     pub session: SessionRef,
+    // the sub-patch module being edited, if not the top level workspace:
+    pub patch: Option<ModuleId>,
 }
 
 pub enum MouseMode {
@@ -66,6 +80,14 @@ pub enum WorkspaceMsg {
     DeleteWindow(ModuleId),
     UpdateModuleParams(ModuleId, ModuleParams),
     CreateModule(ModuleParams, Coords),
+    OpenPatch(ModuleId),
+    SetAutomationMode(ModuleId, AutomationMode),
+    ClearAutomation(ModuleId),
+    SetModuleCv(ModuleId, Vec<CvBinding>),
+    BindMidi(MidiBinding),
+    UnbindMidi(ModuleId, String),
+    Undo,
+    Redo,
 }
 
 impl Component for Workspace {
@@ -73,6 +95,20 @@ impl Component for Workspace {
     type Properties = WorkspaceProps;
 
     fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let window = web_sys::window().expect("web_sys::window");
+
+        let keydown = EventListener::new(&window, "keydown", {
+            let link = link.clone();
+            move |ev| {
+                if let Some(ev) = ev.dyn_ref::<KeyboardEvent>() {
+                    if let Some(msg) = keyboard_shortcut(ev) {
+                        ev.prevent_default();
+                        link.send_message(msg);
+                    }
+                }
+            }
+        });
+
         let mut workspace = Workspace {
             link,
             props,
@@ -80,6 +116,7 @@ impl Component for Workspace {
             gen_z_index: Sequence::new(),
             mouse: MouseMode::Normal,
             window_refs: BTreeMap::new(),
+            _keydown: keydown,
         };
 
         workspace.update_state();
@@ -88,6 +125,22 @@ impl Component for Workspace {
     }
 
     fn change(&mut self, new_props: Self::Properties) -> ShouldRender {
+        if let MouseMode::Drag(drag) = &self.mouse {
+            // sub-patch state is rebuilt from the server's on every render,
+            // hold on to the position of the window being dragged
+            if !Rc::ptr_eq(&self.props.state, &new_props.state) {
+                if let Some(geometry) = self.props.state.borrow().geometry.get(&drag.module) {
+                    new_props.state.borrow_mut().geometry.insert(drag.module, geometry.clone());
+                }
+            }
+        }
+
+        if new_props.patch != self.props.patch {
+            // module ids are only unique within a patch
+            self.window_refs.clear();
+            self.mouse = MouseMode::Normal;
+        }
+
         self.props = new_props;
         self.update_state();
         true
@@ -195,13 +248,23 @@ impl Component for Workspace {
                                 let mut state = self.props.state.borrow_mut();
 
                                 if terminal_ref.line_type == other_terminal_ref.line_type {
+                                    // a connection completing a cycle becomes a
+                                    // feedback connection, reading the output
+                                    // from the previous tick
+                                    let op = if state.depends_on(output.module_id(), input.module_id()) {
+                                        state.feedback.insert(input);
+                                        WorkspaceOp::CreateFeedbackConnection(input, output)
+                                    } else {
+                                        state.feedback.remove(&input);
+                                        WorkspaceOp::CreateConnection(input, output)
+                                    };
+
                                     state.connections.insert(input, output);
 
                                     self.mouse = MouseMode::Normal;
 
                                     self.props.app.send_message(
-                                        AppMsg::ClientUpdate(
-                                            WorkspaceOp::CreateConnection(input, output)));
+                                        AppMsg::ClientUpdate(op));
 
                                     true
                                 } else {
@@ -222,9 +285,9 @@ impl Component for Workspace {
             WorkspaceMsg::ClearTerminal(terminal) => {
                 match terminal {
                     TerminalId::Input(input) => {
-                        self.props.state.borrow_mut()
-                            .connections
-                            .remove(&input);
+                        let mut state = self.props.state.borrow_mut();
+                        state.connections.remove(&input);
+                        state.feedback.remove(&input);
 
                         self.props.app.send_message(
                             AppMsg::ClientUpdate(
@@ -235,16 +298,20 @@ impl Component for Workspace {
 
                         let mut state = self.props.state.borrow_mut();
 
+                        let mut deleted = Vec::new();
+
                         for (in_, out_) in &state.connections {
                             if *out_ == output {
+                                deleted.push(*in_);
                                 msgs.push(AppMsg::ClientUpdate(
                                     WorkspaceOp::DeleteConnection(*in_)));
                             }
                         }
 
-                        // yeah, this is just doing the same loop as the loop above
-                        // but it's good enough for now
-                        state.connections.retain(|_, out| output != *out);
+                        for in_ in deleted {
+                            state.connections.remove(&in_);
+                            state.feedback.remove(&in_);
+                        }
 
                         self.props.app.send_message_batch(msgs);
                     }
@@ -258,6 +325,8 @@ impl Component for Workspace {
                 state.connections.retain(|input, output| {
                     output.module_id() != module && input.module_id() != module
                 });
+                let WorkspaceState { connections, feedback, .. } = &mut *state;
+                feedback.retain(|input| connections.contains_key(input));
 
                 self.props.app.send_message(
                     AppMsg::ClientUpdate(
@@ -300,6 +369,72 @@ impl Component for Workspace {
 
                 true
             }
+            WorkspaceMsg::OpenPatch(module) => {
+                self.props.app.send_message(AppMsg::OpenPatch(Some(module)));
+                false
+            }
+            WorkspaceMsg::SetAutomationMode(module, mode) => {
+                self.props.state.borrow_mut().automation.entry(module).or_default().mode = mode;
+
+                self.props.app.send_message(
+                    AppMsg::ClientUpdate(
+                        WorkspaceOp::SetAutomationMode(module, mode)));
+
+                true
+            }
+            WorkspaceMsg::ClearAutomation(module) => {
+                if let Some(status) = self.props.state.borrow_mut().automation.get_mut(&module) {
+                    status.points = 0;
+                }
+
+                self.props.app.send_message(
+                    AppMsg::ClientUpdate(
+                        WorkspaceOp::ClearAutomation(module)));
+
+                true
+            }
+            WorkspaceMsg::SetModuleCv(module, cv) => {
+                // the server follows up with the module's new terminals
+                {
+                    let mut state = self.props.state.borrow_mut();
+
+                    if cv.is_empty() {
+                        state.cv.remove(&module);
+                    } else {
+                        state.cv.insert(module, cv.clone());
+                    }
+                }
+
+                self.props.app.send_message(
+                    AppMsg::ClientUpdate(
+                        WorkspaceOp::SetModuleCv(module, cv)));
+
+                true
+            }
+            WorkspaceMsg::BindMidi(binding) => {
+                self.props.app.send_message(
+                    AppMsg::ClientUpdate(
+                        WorkspaceOp::BindMidi(binding)));
+
+                false
+            }
+            WorkspaceMsg::UnbindMidi(module, param) => {
+                self.props.app.send_message(
+                    AppMsg::ClientUpdate(
+                        WorkspaceOp::UnbindMidi(module, param)));
+
+                false
+            }
+            WorkspaceMsg::Undo => {
+                self.mouse = MouseMode::Normal;
+                self.props.app.send_message(AppMsg::ClientUpdate(WorkspaceOp::Undo));
+                true
+            }
+            WorkspaceMsg::Redo => {
+                self.mouse = MouseMode::Normal;
+                self.props.app.send_message(AppMsg::ClientUpdate(WorkspaceOp::Redo));
+                true
+            }
         };
 
         fn drag_event(state: &mut WorkspaceState, window_refs: &BTreeMap<ModuleId, WindowRef>, drag: &mut Drag, ev: MouseEvent) -> ShouldRender {
@@ -328,24 +463,30 @@ impl Component for Workspace {
     }
 
     fn view(&self) -> Html {
-        let mut connections: Vec<(Coords, Coords)> = vec![];
+        let mut connections: Vec<ConnectionLine> = vec![];
 
-        for (input, output) in &self.props.state.borrow().connections {
+        let state = self.props.state.borrow();
+
+        for (input, output) in &state.connections {
             if let Some(input_coords) = self.screen_coords_for_terminal(TerminalId::Input(*input)) {
                 if let Some(output_coords) = self.screen_coords_for_terminal(TerminalId::Output(*output)) {
-                    connections.push((output_coords, input_coords));
+                    connections.push(ConnectionLine {
+                        start: output_coords,
+                        end: input_coords,
+                        feedback: state.feedback.contains(input),
+                    });
                 }
             }
         }
 
         if let MouseMode::Connect(terminal_id, _, Some(to_coords)) = &self.mouse {
             if let Some(start_coords) = self.screen_coords_for_terminal(*terminal_id) {
-                let pair = match terminal_id {
+                let (start, end) = match terminal_id {
                     TerminalId::Input(_) => (*to_coords, start_coords),
                     TerminalId::Output(_) => (start_coords, *to_coords),
                 };
 
-                connections.push(pair);
+                connections.push(ConnectionLine { start, end, feedback: false });
             }
         }
 
@@ -366,9 +507,19 @@ impl Component for Workspace {
                     let geometry = state.geometry.get(id);
                     let workspace = self.link.clone();
                     let indication = state.indications.get(id);
+                    let cv = state.cv.get(id).cloned().unwrap_or_default();
+
+                    // automation lanes are only kept for top level modules:
+                    let automation = match self.props.patch {
+                        None => Some(state.automation.get(id).copied().unwrap_or_default()),
+                        Some(_) => None,
+                    };
 
                     if let (Some(module), Some(geometry)) = (module, geometry) {
-                        let name = format!("{:?}", module).chars().take_while(|c| c.is_alphanumeric()).collect::<String>();
+                        let name = match module {
+                            ModuleParams::SubPatch(params) if !params.name.is_empty() => params.name.clone(),
+                            _ => format!("{:?}", module).chars().take_while(|c| c.is_alphanumeric()).collect::<String>(),
+                        };
                         html! { <Window
                             id={id}
                             module={module}
@@ -377,6 +528,8 @@ impl Component for Workspace {
                             workspace={workspace}
                             geometry={geometry}
                             indication={indication.cloned()}
+                            automation={automation}
+                            cv={cv}
                             session={self.props.session.clone()}
                         /> }
                     } else {
@@ -407,13 +560,21 @@ impl Workspace {
         let state = self.props.state.borrow();
 
         for id in state.modules.keys() {
-            if deleted_windows.remove(id) {
+            let inputs = state.inputs.get(id);
+            let outputs = state.outputs.get(id);
+
+            let unchanged = match (self.window_refs.get(id), inputs, outputs) {
+                (Some(refs), Some(inputs), Some(outputs)) => {
+                    terminals_match(&refs.inputs, inputs) && terminals_match(&refs.outputs, outputs)
+                }
+                _ => false,
+            };
+
+            if deleted_windows.remove(id) && unchanged {
                 // cool, nothing changes with this module
             } else {
-                // this module was not present before, create a window ref for it
-                let inputs = state.inputs.get(id);
-                let outputs = state.outputs.get(id);
-
+                // this module was not present before or its terminals have
+                // changed, create a window ref for it
                 if let (Some(inputs), Some(outputs)) = (inputs, outputs) {
                     let refs = WindowRef {
                         module: NodeRef::default(),
@@ -441,6 +602,13 @@ impl Workspace {
         for deleted_window in deleted_windows {
             self.window_refs.remove(&deleted_window);
         }
+
+        fn terminals_match(refs: &[TerminalRef], terminals: &[mixlab_protocol::Terminal]) -> bool {
+            refs.len() == terminals.len() && refs.iter().zip(terminals).all(|(terminal_ref, terminal)| {
+                terminal_ref.label.as_deref() == terminal.label()
+                    && terminal_ref.line_type == terminal.line_type()
+            })
+        }
     }
 
     fn screen_coords_for_terminal(&self, terminal_id: TerminalId) -> Option<Coords> {
@@ -465,7 +633,7 @@ impl Workspace {
             _ => return html! {},
         };
 
-        let items = &[
+        let mut items = vec![
             ("Oscillator", ModuleParams::Oscillator(OscillatorParams { freq: 100.0, waveform: Waveform::Sine })),
             ("Mixer (2 channel)", ModuleParams::Mixer(MixerParams::with_channels(2))),
             ("Mixer (4 channel)", ModuleParams::Mixer(MixerParams::with_channels(4))),
@@ -478,19 +646,43 @@ impl Workspace {
             ("Envelope", ModuleParams::Envelope(EnvelopeParams::default())),
             ("Stereo Panner", ModuleParams::StereoPanner(())),
             ("Stereo Splitter", ModuleParams::StereoSplitter(())),
+            ("Upmix", ModuleParams::Upmix(UpmixParams::default())),
+            ("Downmix", ModuleParams::Downmix(DownmixParams::default())),
             ("Stream Input", ModuleParams::StreamInput(StreamInputParams::default())),
             ("Stream Output", ModuleParams::StreamOutput(StreamOutputParams::default())),
             ("EQ Three", ModuleParams::EqThree(EqThreeParams::default())),
+            ("Parametric EQ", ModuleParams::ParametricEq(ParametricEqParams::default())),
+            ("Compressor", ModuleParams::Compressor(CompressorParams::default())),
+            ("Gate", ModuleParams::Gate(GateParams::default())),
+            ("Delay", ModuleParams::Delay(DelayParams::default())),
+            ("Reverb", ModuleParams::Reverb(ReverbParams::default())),
             ("Monitor", ModuleParams::Monitor(())),
             ("Video Mixer", ModuleParams::VideoMixer(VideoMixerParams::default())),
             ("Media Source", ModuleParams::MediaSource(MediaSourceParams::default())),
+            ("Sub-patch", ModuleParams::SubPatch(SubPatchParams::default())),
         ];
 
+        if self.props.patch.is_some() {
+            items.push(("Patch Input", ModuleParams::PatchInput(PatchTerminalParams::default())));
+            items.push(("Patch Output", ModuleParams::PatchOutput(PatchTerminalParams::default())));
+        }
+
         html! {
             <div class="context-menu"
                 style={format!("left:{}px; top:{}px;", coords.x, coords.y)}
                 onmousedown={stop_propagation()}
             >
+                <div class="context-menu-heading">{"Edit"}</div>
+                <div class="context-menu-item"
+                    onmousedown={self.link.callback(|_| WorkspaceMsg::Undo)}
+                >
+                    {"Undo"}
+                </div>
+                <div class="context-menu-item"
+                    onmousedown={self.link.callback(|_| WorkspaceMsg::Redo)}
+                >
+                    {"Redo"}
+                </div>
                 <div class="context-menu-heading">{"Add module"}</div>
                 { for items.iter().map(|(label, params)| {
                     let params = params.clone();
@@ -513,6 +705,8 @@ pub struct Window {
     link: ComponentLink<Self>,
     props: WindowProps,
     midi_mode: MidiUiMode,
+    // showing cv bindings in place of the module's params:
+    cv_mode: bool,
 }
 
 pub enum WindowMsg {
@@ -521,6 +715,14 @@ pub enum WindowMsg {
     Delete,
     UpdateParams(ModuleParams),
     SetMidiMode(MidiUiMode),
+    OpenPatch,
+    Duplicate,
+    SetAutomationMode(AutomationMode),
+    ClearAutomation,
+    ToggleCvMode,
+    SetCv(Vec<CvBinding>),
+    BindMidi(MidiBinding),
+    UnbindMidi(String),
 }
 
 #[derive(Properties, Clone, Debug)]
@@ -532,6 +734,8 @@ pub struct WindowProps {
     pub workspace: ComponentLink<Workspace>,
     pub refs: WindowRef,
     pub indication: Option<Indication>,
+    pub automation: Option<AutomationStatus>,
+    pub cv: Vec<CvBinding>,
     pub session: SessionRef,
 }
 
@@ -574,6 +778,7 @@ impl Component for Window {
             link,
             props,
             midi_mode: MidiUiMode::Normal,
+            cv_mode: false,
         }
     }
 
@@ -614,6 +819,54 @@ impl Component for Window {
                 self.midi_mode = new_midi_mode;
                 true
             }
+            WindowMsg::OpenPatch => {
+                self.props.workspace.send_message(
+                    WorkspaceMsg::OpenPatch(self.props.id));
+
+                false
+            }
+            WindowMsg::SetAutomationMode(mode) => {
+                self.props.workspace.send_message(
+                    WorkspaceMsg::SetAutomationMode(self.props.id, mode));
+
+                false
+            }
+            WindowMsg::ClearAutomation => {
+                self.props.workspace.send_message(
+                    WorkspaceMsg::ClearAutomation(self.props.id));
+
+                false
+            }
+            WindowMsg::ToggleCvMode => {
+                self.cv_mode = !self.cv_mode;
+                true
+            }
+            WindowMsg::SetCv(cv) => {
+                self.props.workspace.send_message(
+                    WorkspaceMsg::SetModuleCv(self.props.id, cv));
+
+                false
+            }
+            WindowMsg::BindMidi(binding) => {
+                self.props.workspace.send_message(
+                    WorkspaceMsg::BindMidi(binding));
+
+                false
+            }
+            WindowMsg::UnbindMidi(param) => {
+                self.props.workspace.send_message(
+                    WorkspaceMsg::UnbindMidi(self.props.id, param));
+
+                false
+            }
+            WindowMsg::Duplicate => {
+                let coords = self.props.geometry.position.add(Coords { x: 20, y: 20 });
+
+                self.props.workspace.send_message(
+                    WorkspaceMsg::CreateModule(self.props.module.clone(), coords));
+
+                false
+            }
         }
     }
 
@@ -642,6 +895,8 @@ impl Component for Window {
                     <div class="module-window-title-label">
                         {&self.props.name}
                     </div>
+                    {self.view_automation_buttons()}
+                    {self.view_cv_button()}
                     {self.view_custom_title_buttons()}
                     <div class="module-window-title-button module-window-title-delete" onmousedown={self.link.callback(|_| WindowMsg::Delete)}>
                         {"×"}
@@ -664,6 +919,141 @@ impl Component for Window {
 }
 
 impl Window {
+    fn view_automation_buttons(&self) -> Html {
+        let status = match self.props.automation {
+            Some(status) => status,
+            None => return html! {},
+        };
+
+        // cycles off -> record -> play -> off:
+        let (label, class, next_mode) = match status.mode {
+            AutomationMode::Off => ("AUTO",
+                "module-window-title-button",
+                AutomationMode::Record),
+            AutomationMode::Record => ("REC",
+                "module-window-title-button module-window-title-automation-record",
+                AutomationMode::Play),
+            AutomationMode::Play => ("PLAY",
+                "module-window-title-button module-window-title-automation-play",
+                AutomationMode::Off),
+        };
+
+        html! {
+            <>
+                <div class={class}
+                    title={format!("Automation: {} points", status.points)}
+                    onmousedown={self.link.callback(move |_| WindowMsg::SetAutomationMode(next_mode))}
+                >
+                    {label}
+                </div>
+                { if status.points > 0 {
+                    html! {
+                        <div class="module-window-title-button"
+                            title="Clear automation"
+                            onmousedown={self.link.callback(|_| WindowMsg::ClearAutomation)}
+                        >
+                            {"CLR"}
+                        </div>
+                    }
+                } else {
+                    html! {}
+                } }
+            </>
+        }
+    }
+
+    fn view_cv_button(&self) -> Html {
+        if self.props.module.numeric_params().is_empty() {
+            return html! {};
+        }
+
+        let class = if self.cv_mode {
+            "module-window-title-button module-window-title-cv-btn module-window-title-cv-btn-active"
+        } else {
+            "module-window-title-button module-window-title-cv-btn"
+        };
+
+        html! {
+            <div class={class}
+                title={format!("CV: {} bound", self.props.cv.len())}
+                onmousedown={self.link.callback(|_| WindowMsg::ToggleCvMode)}
+            >
+                {"CV"}
+            </div>
+        }
+    }
+
+    fn view_cv(&self) -> Html {
+        html! {
+            <div class="module-cv">
+                { for self.props.module.numeric_params().into_iter().map(|(param, value)| {
+                    let cv = self.props.cv.clone();
+                    let binding = cv.iter().find(|binding| binding.param == param).cloned();
+
+                    let toggled = match &binding {
+                        Some(_) => cv.iter().filter(|binding| binding.param != param).cloned().collect(),
+                        // new bindings leave the param where it is while the cv is at zero:
+                        None => cv.iter().cloned().chain(Some(CvBinding { param: param.clone(), depth: 1.0, offset: value })).collect::<Vec<_>>(),
+                    };
+
+                    let class = if binding.is_some() {
+                        "module-cv-param module-cv-param-bound"
+                    } else {
+                        "module-cv-param"
+                    };
+
+                    html! {
+                        <div class={class}>
+                            <button onclick={self.link.callback(move |_| WindowMsg::SetCv(toggled.clone()))}>
+                                {&param}
+                            </button>
+                            { match binding {
+                                Some(binding) => html! {
+                                    <>
+                                        {self.view_cv_field("Depth", &param, binding.depth, |binding, value| binding.depth = value)}
+                                        {self.view_cv_field("Offset", &param, binding.offset, |binding, value| binding.offset = value)}
+                                    </>
+                                },
+                                None => html! {},
+                            } }
+                        </div>
+                    }
+                }) }
+            </div>
+        }
+    }
+
+    fn view_cv_field(&self, label: &str, param: &str, current: f64, set: fn(&mut CvBinding, f64)) -> Html {
+        let cv = self.props.cv.clone();
+        let param = param.to_owned();
+
+        html! {
+            <label class="form-field">
+                <span class="form-field-label">{label}</span>
+                <input type="number"
+                    step="any"
+                    value={current}
+                    onchange={self.link.callback(move |change| {
+                        let value = match change {
+                            ChangeData::Value(value) => value.parse().unwrap_or(current),
+                            _ => unreachable!(),
+                        };
+
+                        let cv = cv.iter().cloned().map(|mut binding| {
+                            if binding.param == param {
+                                set(&mut binding, value);
+                            }
+
+                            binding
+                        }).collect();
+
+                        WindowMsg::SetCv(cv)
+                    })}
+                />
+            </label>
+        }
+    }
+
     fn view_custom_title_buttons(&self) -> Html {
         match &self.props.module {
             ModuleParams::EqThree(..) |
@@ -724,6 +1114,20 @@ impl Window {
     }
 
     fn view_params(&self) -> Html {
+        if let Some(Indication::Fault(fault)) = &self.props.indication {
+            return html! {
+                <div class="module-fault">
+                    <div class="module-fault-title">{"Module faulted"}</div>
+                    <div class="module-fault-message">{&fault.message}</div>
+                    <div class="module-fault-hint">{"Delete and undo to restart"}</div>
+                </div>
+            };
+        }
+
+        if self.cv_mode {
+            return self.view_cv();
+        }
+
         match &self.props.module {
             ModuleParams::Oscillator(params) => {
                 html! { <Oscillator id={self.props.id} module={self.link.clone()} params={params} /> }
@@ -752,6 +1156,12 @@ impl Window {
             ModuleParams::Amplifier(params) => {
                 html! { <Amplifier id={self.props.id} module={self.link.clone()} params={params} midi_mode={self.midi_mode} /> }
             }
+            ModuleParams::Upmix(params) => {
+                html! { <Upmix id={self.props.id} module={self.link.clone()} params={params} midi_mode={self.midi_mode} /> }
+            }
+            ModuleParams::Downmix(params) => {
+                html! { <Downmix id={self.props.id} module={self.link.clone()} params={params} midi_mode={self.midi_mode} /> }
+            }
             ModuleParams::Trigger(params) => {
                 html! { <Trigger id={self.props.id} module={self.link.clone()} params={params} /> }
             }
@@ -774,6 +1184,33 @@ impl Window {
             ModuleParams::EqThree(params) => {
                 html! { <EqThree id={self.props.id} module={self.link.clone()} params={params} midi_mode={self.midi_mode} /> }
             }
+            ModuleParams::ParametricEq(params) => {
+                if let Some(Indication::ParametricEq(indication)) = &self.props.indication {
+                    html! { <ParametricEq id={self.props.id} module={self.link.clone()} params={params} indication={indication} midi_mode={self.midi_mode} /> }
+                } else {
+                    unreachable!()
+                }
+            }
+            ModuleParams::Compressor(params) => {
+                if let Some(Indication::Compressor(indication)) = &self.props.indication {
+                    html! { <Compressor id={self.props.id} module={self.link.clone()} params={params} indication={indication} midi_mode={self.midi_mode} /> }
+                } else {
+                    unreachable!()
+                }
+            }
+            ModuleParams::Gate(params) => {
+                if let Some(Indication::Gate(indication)) = &self.props.indication {
+                    html! { <Gate id={self.props.id} module={self.link.clone()} params={params} indication={indication} midi_mode={self.midi_mode} /> }
+                } else {
+                    unreachable!()
+                }
+            }
+            ModuleParams::Delay(params) => {
+                html! { <Delay id={self.props.id} module={self.link.clone()} params={params} midi_mode={self.midi_mode} /> }
+            }
+            ModuleParams::Reverb(params) => {
+                html! { <Reverb id={self.props.id} module={self.link.clone()} params={params} midi_mode={self.midi_mode} /> }
+            }
             ModuleParams::Monitor(()) => {
                 if let Some(Indication::Monitor(indication)) = &self.props.indication {
                     html! { <Monitor id={self.props.id} indication={indication} /> }
@@ -787,6 +1224,15 @@ impl Window {
             ModuleParams::MediaSource(params) => {
                 html! { <MediaSource id={self.props.id} module={self.link.clone()} params={params} session={self.props.session.clone()} /> }
             }
+            ModuleParams::SubPatch(params) => {
+                html! { <SubPatch id={self.props.id} module={self.link.clone()} params={params} /> }
+            }
+            ModuleParams::PatchInput(params) => {
+                html! { <PatchTerminal id={self.props.id} module={self.link.clone()} params={params} output={false} /> }
+            }
+            ModuleParams::PatchOutput(params) => {
+                html! { <PatchTerminal id={self.props.id} module={self.link.clone()} params={params} output={true} /> }
+            }
         }
     }
 }
@@ -845,9 +1291,15 @@ impl Component for Terminal {
                         LineType::Stereo => html! {
                             <polygon points="0,16 16,16 16,0" fill={ if self.hover { "#f0b5b3" } else { "#e0a5a3" } } />
                         },
+                        LineType::Multi(_) => html! {
+                            <polygon points="0,16 16,16 16,0" fill={ if self.hover { "#b3c5f0" } else { "#a3b5e0" } } />
+                        },
                         LineType::Video => html! {
                             <rect width="16" height="16" fill={ if self.hover { "#fef8e1" } else { "#fdf1bf" } } />
-                        }
+                        },
+                        LineType::Event => html! {
+                            <circle cx="8" cy="8" r="6" fill={ if self.hover { "#c3e8b8" } else { "#b3d8a8" } } />
+                        },
                     } }
                 </svg>
             </div>
@@ -855,6 +1307,30 @@ impl Component for Terminal {
     }
 }
 
+fn keyboard_shortcut(ev: &KeyboardEvent) -> Option<WorkspaceMsg> {
+    // leave text fields alone so they keep their own undo behaviour:
+    let target = ev.target().and_then(|target| target.dyn_into::<Element>().ok());
+
+    if let Some(target) = target {
+        match target.tag_name().as_str() {
+            "INPUT" | "TEXTAREA" | "SELECT" => return None,
+            _ => {}
+        }
+    }
+
+    if !(ev.ctrl_key() || ev.meta_key()) {
+        return None;
+    }
+
+    match ev.key().as_str() {
+        "z" if ev.shift_key() => Some(WorkspaceMsg::Redo),
+        "Z" => Some(WorkspaceMsg::Redo),
+        "z" => Some(WorkspaceMsg::Undo),
+        "y" => Some(WorkspaceMsg::Redo),
+        _ => None,
+    }
+}
+
 pub struct Connections {
     canvas: NodeRef,
     props: ConnectionsProps,
@@ -862,7 +1338,15 @@ pub struct Connections {
 
 #[derive(Properties, Clone, PartialEq, Eq)]
 pub struct ConnectionsProps {
-    connections: Vec<(Coords, Coords)>,
+    connections: Vec<ConnectionLine>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct ConnectionLine {
+    start: Coords,
+    end: Coords,
+    // feedback connections are drawn dashed:
+    feedback: bool,
 }
 
 impl Component for Connections {
@@ -912,12 +1396,12 @@ impl Component for Connections {
 
             // plan multi-segment lines for all connections
             let lines = self.props.connections.iter()
-                .map(|(a, b)| plan_line_points(*a, *b))
+                .map(|line| (plan_line_points(line.start, line.end), line.feedback))
                 .collect::<Vec<_>>();
 
             // calculate required canvas size for all points
             let Coords { x: width, y: height } = lines.iter()
-                .flat_map(|segments| segments)
+                .flat_map(|(segments, _)| segments)
                 .fold(Coords { x: 0, y: 0 }, |area, point| {
                     Coords {
                         x: max(area.x, point.x),
@@ -931,7 +1415,15 @@ impl Component for Connections {
             // draw lines
             ctx.clear_rect(0f64, 0f64, width as f64, height as f64);
 
-            for points in lines {
+            for (points, feedback) in lines {
+                let dash = if feedback {
+                    js_sys::Array::of2(&JsValue::from_f64(6.0), &JsValue::from_f64(4.0))
+                } else {
+                    js_sys::Array::new()
+                };
+
+                let _ = ctx.set_line_dash(&dash);
+
                 ctx.begin_path();
 
                 ctx.move_to(points[0].x as f64, points[0].y as f64);
@@ -40,6 +40,7 @@ pub enum TrackData {
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Mp4Params<'a> {
     pub timescale: u32,
+    pub sample_rate: u32,
     pub width: u32,
     pub height: u32,
     pub dcr: Cow<'a, [u8]>,
@@ -75,6 +76,24 @@ fn to_bytes(segment: impl WriteTo) -> Bytes {
     bytes.freeze()
 }
 
+fn sampling_frequency(sample_rate: u32) -> SamplingFrequency {
+    match sample_rate {
+        96000 => SamplingFrequency::Hz96000,
+        88200 => SamplingFrequency::Hz88200,
+        64000 => SamplingFrequency::Hz64000,
+        48000 => SamplingFrequency::Hz48000,
+        44100 => SamplingFrequency::Hz44100,
+        32000 => SamplingFrequency::Hz32000,
+        24000 => SamplingFrequency::Hz24000,
+        22050 => SamplingFrequency::Hz22050,
+        16000 => SamplingFrequency::Hz16000,
+        12000 => SamplingFrequency::Hz12000,
+        11025 => SamplingFrequency::Hz11025,
+        8000 => SamplingFrequency::Hz8000,
+        _ => panic!("unsupported aac sample rate: {}", sample_rate),
+    }
+}
+
 const AUDIO_TRACK: u32 = 1;
 const VIDEO_TRACK: u32 = 2;
 
@@ -136,7 +155,7 @@ fn make_init_segment(
                                             esds_box: Mpeg4EsDescriptorBox {
                                                 // TODO set these from ADTS header - or are they always constant?
                                                 profile: AacProfile::Lc,
-                                                frequency: SamplingFrequency::Hz44100,
+                                                frequency: sampling_frequency(params.sample_rate),
                                                 channel_configuration: ChannelConfiguration::TwoChannels,
                                             },
                                         }),
@@ -1,6 +1,7 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::fmt;
 use std::num::NonZeroUsize;
-use std::borrow::Cow;
 
 use serde_derive::{Deserialize, Serialize};
 use uuid::Uuid;
@@ -25,8 +26,14 @@ pub struct WorkspaceState {
     pub geometry: Vec<(ModuleId, WindowGeometry)>,
     pub indications: Vec<(ModuleId, Indication)>,
     pub connections: Vec<(InputId, OutputId)>,
+    pub feedback: Vec<InputId>,
     pub inputs: Vec<(ModuleId, Vec<Terminal>)>,
     pub outputs: Vec<(ModuleId, Vec<Terminal>)>,
+    pub transport: TransportState,
+    pub automation: Vec<(ModuleId, AutomationStatus)>,
+    pub scenes: Vec<String>,
+    pub cv: Vec<(ModuleId, Vec<CvBinding>)>,
+    pub midi: Vec<MidiBinding>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -35,7 +42,12 @@ pub struct PerformanceInfo {
     pub lag: Option<TemporalWarningStatus>,
     pub tick_rate: usize,
     pub tick_budget: Microseconds,
+    // ticks which have run over budget since the engine started:
+    pub overruns: u64,
     pub accounts: Vec<(PerformanceAccount, PerformanceMetric)>,
+    pub lagged_clients: u64,
+    pub lagged_messages: u64,
+    pub encoder_queues: Vec<EncoderQueueInfo>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
@@ -53,6 +65,17 @@ pub enum PerformanceAccount {
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PerformanceMetric {
     pub last: Microseconds,
+    // over a rolling window of recent ticks:
+    pub p50: Microseconds,
+    pub p99: Microseconds,
+    pub max: Microseconds,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EncoderQueueInfo {
+    pub name: String,
+    pub depth: usize,
+    pub dropped: u64,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialOrd, Ord, PartialEq, Eq)]
@@ -95,7 +118,32 @@ pub enum WorkspaceOp {
     UpdateWindowGeometry(ModuleId, WindowGeometry),
     DeleteModule(ModuleId),
     CreateConnection(InputId, OutputId),
+    CreateFeedbackConnection(InputId, OutputId),
     DeleteConnection(InputId),
+    // undo and redo apply to this session's own history only:
+    Undo,
+    Redo,
+    // transport changes are not part of undo history:
+    Transport(TransportOp),
+    SetAutomationMode(ModuleId, AutomationMode),
+    ClearAutomation(ModuleId),
+    // captures the params of all modules under a name, replacing any scene
+    // already of that name:
+    CaptureScene(String),
+    // recalls a scene, fading numeric params over the given number of
+    // milliseconds. recall is immediate if zero:
+    RecallScene(String, u64),
+    DeleteScene(String),
+    // replaces the module's cv bindings. each binding adds a mono input after
+    // the module's own inputs, in binding order:
+    SetModuleCv(ModuleId, Vec<CvBinding>),
+    // a control on a MIDI device attached to the client has changed to the
+    // given value, from 0 to 127. applied to any params bound to it as
+    // though they had been changed by hand:
+    MidiInput(MidiControl, u8),
+    // replaces any binding for the same module param:
+    BindMidi(MidiBinding),
+    UnbindMidi(ModuleId, String),
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -111,9 +159,22 @@ pub enum ServerUpdate {
     UpdateModuleParams(ModuleId, ModuleParams),
     UpdateWindowGeometry(ModuleId, WindowGeometry),
     UpdateModuleIndication(ModuleId, Indication),
+    // modules with params dependent terminals (eg. sub-patches) can change
+    // their inputs and outputs on params update:
+    UpdateModuleTerminals(ModuleId, Vec<Terminal>, Vec<Terminal>),
     DeleteModule(ModuleId),
     CreateConnection(InputId, OutputId),
+    CreateFeedbackConnection(InputId, OutputId),
     DeleteConnection(InputId),
+    // sent on every transport change, and periodically while playing so that
+    // clients can keep their displayed position in step:
+    UpdateTransport(TransportState),
+    UpdateAutomation(ModuleId, AutomationStatus),
+    UpdateScenes(Vec<String>),
+    // followed by UpdateModuleTerminals when the bindings change which
+    // inputs the module has:
+    UpdateModuleCv(ModuleId, Vec<CvBinding>),
+    UpdateMidiBindings(Vec<MidiBinding>),
 }
 
 #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialOrd, Ord, PartialEq, Eq, Hash)]
@@ -177,9 +238,16 @@ impl Terminal {
 pub enum LineType {
     Mono,
     Stereo,
+    // interleaved audio with the given number of channels, up to
+    // MAX_CHANNELS. only lines with the same number of channels connect
+    Multi(u8),
     Video,
+    // timestamped discrete messages: notes, bangs and midi cc
+    Event,
 }
 
+pub const MAX_CHANNELS: usize = 16;
+
 impl LineType {
     pub fn labeled(self, label: &str) -> Terminal {
         Terminal(Some(label.to_string()), self)
@@ -188,46 +256,296 @@ impl LineType {
     pub fn unlabeled(self) -> Terminal {
         Terminal(None, self)
     }
+
+    // number of audio channels carried by the line, None for video and events
+    pub fn channels(self) -> Option<usize> {
+        match self {
+            LineType::Mono => Some(1),
+            LineType::Stereo => Some(2),
+            LineType::Multi(channels) => Some(channels as usize),
+            LineType::Video |
+            LineType::Event => None,
+        }
+    }
+}
+
+// speaker arrangements of multi-channel lines, with channels in the same
+// order as in WAV files
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelLayout {
+    Quad,
+    Surround51,
+    Surround71,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Speaker {
+    Left,
+    Right,
+    Centre,
+    Lfe,
+    SurroundLeft,
+    SurroundRight,
+    RearLeft,
+    RearRight,
+}
+
+impl ChannelLayout {
+    pub const ALL: &'static [ChannelLayout] = &[
+        ChannelLayout::Quad,
+        ChannelLayout::Surround51,
+        ChannelLayout::Surround71,
+    ];
+
+    pub fn speakers(self) -> &'static [Speaker] {
+        use Speaker::*;
+
+        match self {
+            ChannelLayout::Quad => &[Left, Right, SurroundLeft, SurroundRight],
+            ChannelLayout::Surround51 => &[Left, Right, Centre, Lfe, SurroundLeft, SurroundRight],
+            ChannelLayout::Surround71 => &[Left, Right, Centre, Lfe, RearLeft, RearRight, SurroundLeft, SurroundRight],
+        }
+    }
+
+    pub fn channels(self) -> usize {
+        self.speakers().len()
+    }
+
+    pub fn line_type(self) -> LineType {
+        LineType::Multi(self.channels() as u8)
+    }
+
+    pub fn from_channels(channels: usize) -> Option<ChannelLayout> {
+        ChannelLayout::ALL.iter()
+            .copied()
+            .find(|layout| layout.channels() == channels)
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum ModuleParams {
     Amplifier(AmplifierParams),
+    Compressor(CompressorParams),
+    Delay(DelayParams),
+    Downmix(DownmixParams),
     Envelope(EnvelopeParams),
     EqThree(EqThreeParams),
     FmSine(FmSineParams),
+    Gate(GateParams),
     MediaSource(MediaSourceParams),
     Mixer(MixerParams),
     Monitor(()),
     Oscillator(OscillatorParams),
     OutputDevice(OutputDeviceParams),
+    ParametricEq(ParametricEqParams),
+    PatchInput(PatchTerminalParams),
+    PatchOutput(PatchTerminalParams),
     Plotter(()),
+    Reverb(ReverbParams),
     StereoPanner(()),
     StereoSplitter(()),
     StreamInput(StreamInputParams),
     StreamOutput(StreamOutputParams),
+    SubPatch(SubPatchParams),
     Trigger(GateState),
+    Upmix(UpmixParams),
     VideoMixer(VideoMixerParams),
 }
 
+impl ModuleParams {
+    // visits each param which takes a value from a continuous range, such as
+    // faders, gains and frequencies, by name. these are the params which can
+    // be faded between scenes or addressed by name from outside the UI
+    pub fn visit_numeric(&mut self, mut f: impl FnMut(&str, &mut f64)) {
+        match self {
+            ModuleParams::Amplifier(params) => {
+                f("amplitude", &mut params.amplitude);
+                f("mod_depth", &mut params.mod_depth);
+            }
+            ModuleParams::Compressor(params) => {
+                f("threshold", &mut params.threshold.0);
+                f("ratio", &mut params.ratio);
+                f("attack_ms", &mut params.attack_ms);
+                f("release_ms", &mut params.release_ms);
+                f("knee", &mut params.knee.0);
+                f("makeup", &mut params.makeup.0);
+                f("lookahead_ms", &mut params.lookahead_ms);
+            }
+            ModuleParams::Delay(params) => {
+                f("time_ms", &mut params.time_ms);
+                f("feedback", &mut params.feedback);
+                f("mix", &mut params.mix);
+                f("filter_freq", &mut params.filter_freq);
+            }
+            ModuleParams::Envelope(params) => {
+                f("attack_ms", &mut params.attack_ms);
+                f("decay_ms", &mut params.decay_ms);
+                f("sustain_amplitude", &mut params.sustain_amplitude);
+                f("release_ms", &mut params.release_ms);
+            }
+            ModuleParams::EqThree(params) => {
+                f("gain_lo", &mut params.gain_lo.0);
+                f("gain_mid", &mut params.gain_mid.0);
+                f("gain_hi", &mut params.gain_hi.0);
+            }
+            ModuleParams::FmSine(params) => {
+                f("freq_lo", &mut params.freq_lo);
+                f("freq_hi", &mut params.freq_hi);
+            }
+            ModuleParams::Gate(params) => {
+                f("threshold", &mut params.threshold.0);
+                f("hysteresis", &mut params.hysteresis.0);
+                f("hold_ms", &mut params.hold_ms);
+                f("attack_ms", &mut params.attack_ms);
+                f("release_ms", &mut params.release_ms);
+                f("range", &mut params.range.0);
+                f("ratio", &mut params.ratio);
+            }
+            ModuleParams::Mixer(params) => {
+                for (index, channel) in params.channels.iter_mut().enumerate() {
+                    f(&format!("channel{}.gain", index + 1), &mut channel.gain.0);
+                    f(&format!("channel{}.fader", index + 1), &mut channel.fader);
+                }
+            }
+            ModuleParams::Oscillator(params) => {
+                f("freq", &mut params.freq);
+            }
+            ModuleParams::ParametricEq(params) => {
+                for (index, band) in params.bands.iter_mut().enumerate() {
+                    f(&format!("band{}.freq", index + 1), &mut band.freq);
+                    f(&format!("band{}.q", index + 1), &mut band.q);
+                    f(&format!("band{}.gain", index + 1), &mut band.gain.0);
+                }
+            }
+            ModuleParams::Reverb(params) => {
+                f("room_size", &mut params.room_size);
+                f("damping", &mut params.damping);
+                f("pre_delay_ms", &mut params.pre_delay_ms);
+                f("width", &mut params.width);
+                f("mix", &mut params.mix);
+            }
+            ModuleParams::Upmix(params) => {
+                f("centre", &mut params.centre);
+                f("surround", &mut params.surround);
+            }
+            ModuleParams::VideoMixer(params) => {
+                f("fader", &mut params.fader);
+            }
+            ModuleParams::Downmix(_) |
+            ModuleParams::MediaSource(_) |
+            ModuleParams::Monitor(_) |
+            ModuleParams::OutputDevice(_) |
+            ModuleParams::PatchInput(_) |
+            ModuleParams::PatchOutput(_) |
+            ModuleParams::Plotter(_) |
+            ModuleParams::StereoPanner(_) |
+            ModuleParams::StereoSplitter(_) |
+            ModuleParams::StreamInput(_) |
+            ModuleParams::StreamOutput(_) |
+            ModuleParams::SubPatch(_) |
+            ModuleParams::Trigger(_) => {}
+        }
+    }
+
+    pub fn numeric_params(&self) -> Vec<(String, f64)> {
+        let mut params = Vec::new();
+
+        self.clone().visit_numeric(|name, value| {
+            params.push((name.to_owned(), *value));
+        });
+
+        params
+    }
+
+    pub fn numeric_param(&self, name: &str) -> Option<f64> {
+        self.numeric_params().into_iter()
+            .find(|(param, _)| param == name)
+            .map(|(_, value)| value)
+    }
+
+    // returns false if there is no numeric param by that name
+    pub fn set_numeric_param(&mut self, name: &str, new_value: f64) -> bool {
+        let mut found = false;
+
+        self.visit_numeric(|param, value| {
+            if param == name {
+                *value = new_value;
+                found = true;
+            }
+        });
+
+        found
+    }
+
+    // params part way between two sets of params for the same module, with
+    // `t` running from 0 to 1. numeric params are interpolated linearly,
+    // everything else takes its value from `to`
+    pub fn interpolate(from: &ModuleParams, to: &ModuleParams, t: f64) -> ModuleParams {
+        let from_values = from.numeric_params().into_iter().collect::<HashMap<_, _>>();
+        let mut params = to.clone();
+
+        params.visit_numeric(|name, value| {
+            if let Some(from_value) = from_values.get(name) {
+                *value = from_value + (*value - from_value) * t;
+            }
+        });
+
+        params
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum Indication {
     Amplifier(()),
+    Compressor(CompressorIndication),
+    Delay(()),
+    Downmix(()),
     Envelope(()),
     EqThree(()),
     FmSine(()),
+    Gate(GateIndication),
     MediaSource(()),
     Mixer(()),
     Monitor(MonitorIndication),
     Oscillator(()),
     OutputDevice(OutputDeviceIndication),
+    ParametricEq(ParametricEqIndication),
+    PatchInput(()),
+    PatchOutput(()),
     Plotter(PlotterIndication),
+    Reverb(()),
     StereoPanner(()),
     StereoSplitter(()),
     StreamInput(()),
     StreamOutput(StreamOutputIndication),
+    SubPatch(SubPatchIndication),
     Trigger(()),
+    Upmix(()),
     VideoMixer(()),
+    // replaces a module's own indication once it has faulted. the module is
+    // no longer run, and its outputs are silent
+    Fault(ModuleFault),
+}
+
+impl Indication {
+    // folds an indication update into the module's last known indication.
+    // most updates replace it outright, but running sub-patches only send
+    // the modules within them which changed
+    pub fn apply(&mut self, update: Indication) {
+        match (self, update) {
+            (Indication::SubPatch(current), Indication::SubPatch(update)) if !update.complete => {
+                current.merge(update);
+            }
+            (current, update) => {
+                *current = update;
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ModuleFault {
+    pub message: String,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, Copy, PartialEq)]
@@ -277,6 +595,24 @@ pub struct OutputDeviceIndication {
     pub devices: Option<Vec<(String, usize)>>,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CompressorIndication {
+    // the most gain reduction applied since the last indication:
+    pub gain_reduction: Decibel,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GateIndication {
+    pub open: bool,
+}
+
+// magnitude response of all bands together, as (frequency, gain) pairs at
+// log spaced frequencies across the audible range
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ParametricEqIndication {
+    pub response: Vec<(f64, Decibel)>,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct PlotterIndication {
     pub inputs: Vec<Vec<Sample>>,
@@ -301,7 +637,7 @@ pub struct AmplifierParams {
     pub mod_depth: f64,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub enum GateState {
     Open,
     Closed
@@ -346,6 +682,54 @@ pub struct MixerChannelParams {
     pub cue: bool,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ParametricEqParams {
+    pub bands: Vec<EqBand>,
+}
+
+impl Default for ParametricEqParams {
+    fn default() -> Self {
+        ParametricEqParams {
+            bands: vec![
+                EqBand { filter: FilterType::LowShelf, freq: 100.0, q: 0.707, gain: Decibel(0.0) },
+                EqBand { filter: FilterType::Peak, freq: 500.0, q: 1.0, gain: Decibel(0.0) },
+                EqBand { filter: FilterType::Peak, freq: 2500.0, q: 1.0, gain: Decibel(0.0) },
+                EqBand { filter: FilterType::HighShelf, freq: 8000.0, q: 0.707, gain: Decibel(0.0) },
+            ],
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct EqBand {
+    pub filter: FilterType,
+    pub freq: f64,
+    pub q: f64,
+    // ignored by pass and notch filters:
+    pub gain: Decibel,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FilterType {
+    Peak,
+    LowShelf,
+    HighShelf,
+    LowPass,
+    HighPass,
+    Notch,
+}
+
+impl FilterType {
+    pub const ALL: &'static [FilterType] = &[
+        FilterType::Peak,
+        FilterType::LowShelf,
+        FilterType::HighShelf,
+        FilterType::LowPass,
+        FilterType::HighPass,
+        FilterType::Notch,
+    ];
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct StreamInputParams {
     pub protocol: Option<StreamProtocol>,
@@ -400,6 +784,218 @@ pub struct StreamSource {
     pub kbps: usize,
 }
 
+// spreads a stereo line over a multi-channel layout. left and right go to the
+// front speakers as they are, with optional feeds to the centre and surrounds
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct UpmixParams {
+    pub layout: ChannelLayout,
+    // level of the mono sum of left and right sent to the centre speaker:
+    pub centre: f64,
+    // level of left and right sent to the surround and rear speakers on their
+    // side:
+    pub surround: f64,
+}
+
+impl Default for UpmixParams {
+    fn default() -> Self {
+        UpmixParams {
+            layout: ChannelLayout::Surround51,
+            centre: 0.0,
+            surround: 0.0,
+        }
+    }
+}
+
+// folds a multi-channel layout down to stereo, as in ITU-R BS.775
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DownmixParams {
+    pub layout: ChannelLayout,
+}
+
+impl Default for DownmixParams {
+    fn default() -> Self {
+        DownmixParams {
+            layout: ChannelLayout::Surround51,
+        }
+    }
+}
+
+// stereo compressor, levels above threshold are reduced by ratio. ratios of
+// COMPRESSOR_MAX_RATIO and up limit instead
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CompressorParams {
+    pub threshold: Decibel,
+    pub ratio: f64,
+    pub attack_ms: f64,
+    pub release_ms: f64,
+    // width of the soft knee, centred on the threshold:
+    pub knee: Decibel,
+    pub makeup: Decibel,
+    // delays the signal so that gain reduction can begin before the peaks
+    // that cause it, up to COMPRESSOR_MAX_LOOKAHEAD_MS:
+    pub lookahead_ms: f64,
+}
+
+pub const COMPRESSOR_MAX_RATIO: f64 = 20.0;
+pub const COMPRESSOR_MAX_LOOKAHEAD_MS: f64 = 10.0;
+
+impl Default for CompressorParams {
+    fn default() -> Self {
+        CompressorParams {
+            threshold: Decibel(-18.0),
+            ratio: 4.0,
+            attack_ms: 10.0,
+            release_ms: 100.0,
+            knee: Decibel(6.0),
+            makeup: Decibel(0.0),
+            lookahead_ms: 0.0,
+        }
+    }
+}
+
+// noise gate. opens when the level reaches threshold, and closes once it has
+// stayed below threshold less hysteresis for the hold time. while closed,
+// levels below threshold are expanded downwards by ratio, and ratios of
+// GATE_MAX_RATIO and up gate fully instead. either way the signal is never
+// attenuated by more than range
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct GateParams {
+    pub threshold: Decibel,
+    pub hysteresis: Decibel,
+    pub hold_ms: f64,
+    pub attack_ms: f64,
+    pub release_ms: f64,
+    pub range: Decibel,
+    // projects saved before the gate could expand always gated fully:
+    #[serde(default = "gate_max_ratio")]
+    pub ratio: f64,
+    pub stereo: bool,
+}
+
+pub const GATE_MAX_RATIO: f64 = 20.0;
+
+fn gate_max_ratio() -> f64 {
+    GATE_MAX_RATIO
+}
+
+impl Default for GateParams {
+    fn default() -> Self {
+        GateParams {
+            threshold: Decibel(-40.0),
+            hysteresis: Decibel(6.0),
+            hold_ms: 50.0,
+            attack_ms: 1.0,
+            release_ms: 100.0,
+            range: Decibel(-80.0),
+            ratio: GATE_MAX_RATIO,
+            stereo: false,
+        }
+    }
+}
+
+// stereo echo. repeats are fed back through a low pass filter, and in ping
+// pong mode alternate between left and right
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DelayParams {
+    // delay time when not synced to the transport's tempo:
+    pub time_ms: f64,
+    pub sync: Option<NoteValue>,
+    pub feedback: f64,
+    pub ping_pong: bool,
+    // 0 is all dry, 1 all wet:
+    pub mix: f64,
+    // cutoff of the low pass filter in the feedback path:
+    pub filter_freq: f64,
+}
+
+pub const DELAY_MAX_MS: f64 = 4000.0;
+
+impl Default for DelayParams {
+    fn default() -> Self {
+        DelayParams {
+            time_ms: 375.0,
+            sync: None,
+            feedback: 0.4,
+            ping_pong: false,
+            mix: 0.3,
+            filter_freq: 8000.0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NoteValue {
+    Whole,
+    Half,
+    Quarter,
+    DottedQuarter,
+    TripletQuarter,
+    Eighth,
+    DottedEighth,
+    TripletEighth,
+    Sixteenth,
+}
+
+impl NoteValue {
+    pub const ALL: &'static [NoteValue] = &[
+        NoteValue::Whole,
+        NoteValue::Half,
+        NoteValue::Quarter,
+        NoteValue::DottedQuarter,
+        NoteValue::TripletQuarter,
+        NoteValue::Eighth,
+        NoteValue::DottedEighth,
+        NoteValue::TripletEighth,
+        NoteValue::Sixteenth,
+    ];
+
+    pub fn whole_notes(self) -> f64 {
+        match self {
+            NoteValue::Whole => 1.0,
+            NoteValue::Half => 1.0 / 2.0,
+            NoteValue::Quarter => 1.0 / 4.0,
+            NoteValue::DottedQuarter => 1.5 / 4.0,
+            NoteValue::TripletQuarter => 1.0 / 6.0,
+            NoteValue::Eighth => 1.0 / 8.0,
+            NoteValue::DottedEighth => 1.5 / 8.0,
+            NoteValue::TripletEighth => 1.0 / 12.0,
+            NoteValue::Sixteenth => 1.0 / 16.0,
+        }
+    }
+
+    // length in beats, which are 1/beat_unit of a whole note
+    pub fn beats(self, tempo: &Tempo) -> f64 {
+        self.whole_notes() * tempo.beat_unit as f64
+    }
+}
+
+// stereo room ambience. room size and damping are fractions from 0 to 1
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ReverbParams {
+    pub room_size: f64,
+    // how quickly high frequencies die away relative to low:
+    pub damping: f64,
+    pub pre_delay_ms: f64,
+    // 0 is a mono tail, 1 fully decorrelated left and right:
+    pub width: f64,
+    // 0 is all dry, 1 all wet:
+    pub mix: f64,
+}
+
+pub const REVERB_MAX_PRE_DELAY_MS: f64 = 200.0;
+
+impl Default for ReverbParams {
+    fn default() -> Self {
+        ReverbParams {
+            room_size: 0.5,
+            damping: 0.5,
+            pre_delay_ms: 10.0,
+            width: 1.0,
+            mix: 0.25,
+        }
+    }
+}
+
 pub const VIDEO_MIXER_CHANNELS: usize = 4;
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
@@ -424,6 +1020,250 @@ pub struct MediaSourceParams {
     pub media_id: Option<MediaId>,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Tempo {
+    pub bpm: f64,
+    pub beats_per_bar: u32,
+    // note value of one beat, eg. 4 for quarter notes:
+    pub beat_unit: u32,
+}
+
+impl Default for Tempo {
+    fn default() -> Self {
+        Tempo {
+            bpm: 120.0,
+            beats_per_bar: 4,
+            beat_unit: 4,
+        }
+    }
+}
+
+impl Tempo {
+    pub const MIN_BPM: f64 = 20.0;
+    pub const MAX_BPM: f64 = 999.0;
+
+    pub fn is_valid(&self) -> bool {
+        self.bpm >= Tempo::MIN_BPM && self.bpm <= Tempo::MAX_BPM
+            && self.beats_per_bar > 0
+            && self.beat_unit > 0
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub struct TransportState {
+    pub tempo: Tempo,
+    pub playing: bool,
+    // musical position in beats since the start:
+    pub position: f64,
+}
+
+impl TransportState {
+    // zero based bar and beat within bar of the current position
+    pub fn bar_beat(&self) -> (u64, f64) {
+        let beats_per_bar = self.tempo.beats_per_bar as f64;
+        let bar = (self.position / beats_per_bar).floor();
+        (bar as u64, self.position - bar * beats_per_bar)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub enum TransportOp {
+    Play,
+    Pause,
+    // pauses and returns to the start:
+    Stop,
+    // moves to a position in beats:
+    Locate(f64),
+    SetTempo(Tempo),
+}
+
+// Automation lanes record a module's params changes against the transport
+// position while the transport is playing, and replay them on later passes.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AutomationMode {
+    Off,
+    Record,
+    Play,
+}
+
+impl Default for AutomationMode {
+    fn default() -> Self {
+        AutomationMode::Off
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct AutomationLane {
+    pub mode: AutomationMode,
+    // ordered by position:
+    pub points: Vec<AutomationPoint>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AutomationPoint {
+    // transport position in beats:
+    pub position: f64,
+    pub params: ModuleParams,
+}
+
+// what clients are told about a lane, the points themselves stay on the server
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub struct AutomationStatus {
+    pub mode: AutomationMode,
+    pub points: usize,
+}
+
+impl AutomationLane {
+    pub fn status(&self) -> AutomationStatus {
+        AutomationStatus {
+            mode: self.mode,
+            points: self.points.len(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Scene {
+    pub modules: HashMap<ModuleId, ModuleParams>,
+}
+
+// a module graph in its persisted form. the top level workspace is saved in
+// this shape, and sub-patches carry one in their params
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SavedWorkspace {
+    pub module_seq: usize,
+    pub modules: HashMap<ModuleId, SavedModule>,
+    // only set on the top level workspace, sub-patches follow it:
+    #[serde(default)]
+    pub tempo: Option<Tempo>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SavedModule {
+    pub params: ModuleParams,
+    pub geometry: WindowGeometry,
+    pub inputs: Vec<Option<OutputId>>,
+    // indices of inputs connected via feedback connections:
+    #[serde(default)]
+    pub feedback: Vec<usize>,
+    #[serde(default)]
+    pub cv: Vec<CvBinding>,
+}
+
+// modulates a numeric param (as named by ModuleParams::visit_numeric) from a
+// control input. the param is set to `offset + depth * cv` each tick while the
+// input is connected, and returns to its own value when it is not
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CvBinding {
+    pub param: String,
+    pub depth: f64,
+    pub offset: f64,
+}
+
+// identifies a control on a MIDI device by the name of the device, as seen by
+// the client it is attached to
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MidiControl {
+    pub device: String,
+    // 0 to 15, as encoded in the message status byte:
+    pub channel: u8,
+    pub kind: MidiControlKind,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MidiControlKind {
+    // control change, by controller number
+    Cc(u8),
+    // note on and off, by note number. note on is taken as a value of 127 and
+    // note off as 0
+    Note(u8),
+}
+
+// maps a MIDI control onto a numeric param (as named by
+// ModuleParams::visit_numeric), scaling its range of 0 to 127 onto min to max
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MidiBinding {
+    pub control: MidiControl,
+    pub module: ModuleId,
+    pub param: String,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl MidiBinding {
+    pub fn value(&self, midi_value: u8) -> f64 {
+        let t = midi_value.min(127) as f64 / 127.0;
+        self.min + (self.max - self.min) * t
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct SubPatchParams {
+    pub name: String,
+    // the sub-patch exposes an input for each PatchInput module and an
+    // output for each PatchOutput module in the patch, in module id order
+    pub patch: SavedWorkspace,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PatchTerminalParams {
+    pub label: String,
+    pub line_type: LineType,
+}
+
+impl PatchTerminalParams {
+    pub fn terminal(&self) -> Terminal {
+        let line_type = match self.line_type {
+            LineType::Multi(channels) => LineType::Multi(channels.max(1).min(MAX_CHANNELS as u8)),
+            line_type => line_type,
+        };
+
+        if self.label.is_empty() {
+            line_type.unlabeled()
+        } else {
+            line_type.labeled(&self.label)
+        }
+    }
+}
+
+impl Default for PatchTerminalParams {
+    fn default() -> Self {
+        PatchTerminalParams {
+            label: String::new(),
+            line_type: LineType::Stereo,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct SubPatchIndication {
+    pub modules: Vec<SubPatchModule>,
+    // true when modules covers the whole patch. indications sent as the
+    // patch runs only carry the modules which changed, see Indication::apply
+    pub complete: bool,
+}
+
+impl SubPatchIndication {
+    fn merge(&mut self, update: SubPatchIndication) {
+        for module in update.modules {
+            match self.modules.iter_mut().find(|existing| existing.id == module.id) {
+                Some(existing) => *existing = module,
+                None => self.modules.push(module),
+            }
+        }
+    }
+}
+
+// live state of a module within a sub-patch, so that it can be shown when
+// editing the sub-patch
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SubPatchModule {
+    pub id: ModuleId,
+    pub indication: Indication,
+    pub inputs: Vec<Terminal>,
+    pub outputs: Vec<Terminal>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub struct Coords {
     pub x: i32,
@@ -0,0 +1,664 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::convert::Infallible;
+use std::mem;
+use std::num::NonZeroUsize;
+
+use derive_more::From;
+use serde::{Serialize, Deserialize};
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio::task;
+use warp::Filter;
+use warp::http::StatusCode;
+use warp::reply::{self, Reply, Response};
+
+use mixlab_protocol::{ModuleId, InputId, OutputId, ModuleParams, WindowGeometry, Indication, Terminal, CvBinding, WorkspaceState, WorkspaceOp, WorkspaceMessage, ClientSequence, ServerUpdate};
+
+use crate::engine::{EngineEvent, EngineError};
+use crate::project::ProjectHandle;
+use crate::util::Sequence;
+
+// JSON API for scripting the workspace, served under /api. All requests go
+// through one engine session, so changes made through the API are seen by
+// every connected client but can't be undone from the UI.
+//
+// A task follows the engine's updates to keep a copy of the workspace, which
+// requests which only read are answered from. Requests which change the
+// workspace are applied as workspace ops, and respond with what the engine
+// did in response to them.
+
+const MAX_BODY: u64 = 1024 * 1024;
+
+#[derive(Serialize, Clone)]
+struct Module {
+    id: ModuleId,
+    params: ModuleParams,
+    geometry: WindowGeometry,
+    indication: Option<Indication>,
+    inputs: Vec<Terminal>,
+    outputs: Vec<Terminal>,
+    cv: Vec<CvBinding>,
+}
+
+#[derive(Deserialize)]
+struct CreateModule {
+    params: ModuleParams,
+    #[serde(default)]
+    geometry: WindowGeometry,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+struct Connection {
+    input: InputId,
+    output: OutputId,
+    #[serde(default)]
+    feedback: bool,
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    error: String,
+}
+
+#[derive(From, Debug)]
+enum ApiError {
+    Engine(EngineError),
+    #[from(ignore)]
+    NoSuchModule(ModuleId),
+    #[from(ignore)]
+    NoSuchConnection(InputId),
+    // params given for a different kind of module:
+    ParamsMismatch,
+    ConnectionRejected,
+    // the engine's updates were dropped before the response could be read
+    // from them, and the workspace has since changed too much to tell what
+    // the request did
+    Lagged,
+}
+
+impl ApiError {
+    fn into_response(self) -> Response {
+        let (status, message) = match self {
+            ApiError::Engine(EngineError::Busy) => (StatusCode::SERVICE_UNAVAILABLE, "engine busy".to_owned()),
+            ApiError::Engine(EngineError::Stopped) => (StatusCode::SERVICE_UNAVAILABLE, "engine stopped".to_owned()),
+            ApiError::NoSuchModule(module_id) => (StatusCode::NOT_FOUND, format!("no such module: {}", module_id.0)),
+            ApiError::NoSuchConnection(input) => (StatusCode::NOT_FOUND, format!("nothing connected to input {} of module {}", input.index(), input.module_id().0)),
+            ApiError::ParamsMismatch => (StatusCode::BAD_REQUEST, "params are for a different kind of module".to_owned()),
+            ApiError::ConnectionRejected => (StatusCode::CONFLICT, "connection rejected: terminals missing, of different types, or forming a cycle without feedback".to_owned()),
+            ApiError::Lagged => (StatusCode::SERVICE_UNAVAILABLE, "lost track of engine updates, request may have been applied".to_owned()),
+        };
+
+        json_status(&ErrorBody { error: message }, status)
+    }
+}
+
+pub fn routes(project: ProjectHandle) -> impl Filter<Extract = (impl Reply,), Error = warp::Rejection> + Clone {
+    let api = Api::start(project);
+    let api = warp::any().map(move || api.clone());
+
+    let list_modules = warp::get()
+        .and(warp::path!("api" / "modules"))
+        .and(api.clone())
+        .and_then(|api: Api| respond(list_modules(api)));
+
+    let create_module = warp::post()
+        .and(warp::path!("api" / "modules"))
+        .and(api.clone())
+        .and(json_body())
+        .and_then(|api: Api, body: CreateModule| respond(create_module(api, body)));
+
+    let get_module = warp::get()
+        .and(warp::path!("api" / "modules" / NonZeroUsize))
+        .and(api.clone())
+        .and_then(|id: NonZeroUsize, api: Api| respond(get_module(api, ModuleId(id))));
+
+    let delete_module = warp::delete()
+        .and(warp::path!("api" / "modules" / NonZeroUsize))
+        .and(api.clone())
+        .and_then(|id: NonZeroUsize, api: Api| respond(delete_module(api, ModuleId(id))));
+
+    let get_params = warp::get()
+        .and(warp::path!("api" / "modules" / NonZeroUsize / "params"))
+        .and(api.clone())
+        .and_then(|id: NonZeroUsize, api: Api| respond(get_params(api, ModuleId(id))));
+
+    let update_params = warp::put()
+        .and(warp::path!("api" / "modules" / NonZeroUsize / "params"))
+        .and(api.clone())
+        .and(json_body())
+        .and_then(|id: NonZeroUsize, api: Api, params: ModuleParams| respond(update_params(api, ModuleId(id), params)));
+
+    let get_indication = warp::get()
+        .and(warp::path!("api" / "modules" / NonZeroUsize / "indication"))
+        .and(api.clone())
+        .and_then(|id: NonZeroUsize, api: Api| respond(get_indication(api, ModuleId(id))));
+
+    let list_connections = warp::get()
+        .and(warp::path!("api" / "connections"))
+        .and(api.clone())
+        .and_then(|api: Api| respond(list_connections(api)));
+
+    let create_connection = warp::post()
+        .and(warp::path!("api" / "connections"))
+        .and(api.clone())
+        .and(json_body())
+        .and_then(|api: Api, body: Connection| respond(create_connection(api, body)));
+
+    let delete_connection = warp::delete()
+        .and(warp::path!("api" / "connections" / NonZeroUsize / usize))
+        .and(api.clone())
+        .and_then(|id: NonZeroUsize, index: usize, api: Api| respond(delete_connection(api, InputId(ModuleId(id), index))));
+
+    list_modules
+        .or(create_module)
+        .or(get_module)
+        .or(delete_module)
+        .or(get_params)
+        .or(update_params)
+        .or(get_indication)
+        .or(list_connections)
+        .or(create_connection)
+        .or(delete_connection)
+}
+
+fn json_body<T: serde::de::DeserializeOwned + Send>() -> impl Filter<Extract = (T,), Error = warp::Rejection> + Clone {
+    warp::body::content_length_limit(MAX_BODY)
+        .and(warp::body::json())
+}
+
+async fn respond(handler: impl std::future::Future<Output = Result<Response, ApiError>>) -> Result<Response, Infallible> {
+    Ok(handler.await.unwrap_or_else(ApiError::into_response))
+}
+
+fn json_status(body: &impl Serialize, status: StatusCode) -> Response {
+    reply::with_status(reply::json(body), status).into_response()
+}
+
+async fn list_modules(api: Api) -> Result<Response, ApiError> {
+    let workspace = api.snapshot().await?;
+    Ok(reply::json(&workspace.modules.values().collect::<Vec<_>>()).into_response())
+}
+
+async fn get_module(api: Api, module_id: ModuleId) -> Result<Response, ApiError> {
+    let workspace = api.snapshot().await?;
+
+    let module = workspace.modules.get(&module_id)
+        .ok_or(ApiError::NoSuchModule(module_id))?;
+
+    Ok(reply::json(module).into_response())
+}
+
+async fn create_module(api: Api, body: CreateModule) -> Result<Response, ApiError> {
+    let applied = api.apply(WorkspaceOp::CreateModule(body.params, body.geometry)).await?;
+
+    let module_id = match &applied.updates {
+        Some(updates) => {
+            updates.iter().find_map(|update| match update {
+                ServerUpdate::CreateModule { id, .. } => Some(*id),
+                _ => None,
+            })
+        }
+        // ids are handed out in order, so ours is the newest:
+        None => {
+            applied.after.modules.keys()
+                .filter(|id| !applied.before.modules.contains_key(id))
+                .max()
+                .copied()
+        }
+    };
+
+    let module = module_id
+        .and_then(|id| applied.after.modules.get(&id))
+        .ok_or(ApiError::Lagged)?;
+
+    Ok(json_status(module, StatusCode::CREATED))
+}
+
+async fn delete_module(api: Api, module_id: ModuleId) -> Result<Response, ApiError> {
+    let applied = api.apply(WorkspaceOp::DeleteModule(module_id)).await?;
+
+    let deleted = applied.before.modules.contains_key(&module_id)
+        && !applied.after.modules.contains_key(&module_id);
+
+    if deleted {
+        Ok(StatusCode::NO_CONTENT.into_response())
+    } else {
+        Err(ApiError::NoSuchModule(module_id))
+    }
+}
+
+async fn get_params(api: Api, module_id: ModuleId) -> Result<Response, ApiError> {
+    let workspace = api.snapshot().await?;
+
+    let module = workspace.modules.get(&module_id)
+        .ok_or(ApiError::NoSuchModule(module_id))?;
+
+    Ok(reply::json(&module.params).into_response())
+}
+
+async fn update_params(api: Api, module_id: ModuleId, params: ModuleParams) -> Result<Response, ApiError> {
+    let workspace = api.snapshot().await?;
+
+    let current = workspace.modules.get(&module_id)
+        .ok_or(ApiError::NoSuchModule(module_id))?;
+
+    // the engine quarantines a module given params of the wrong kind
+    if mem::discriminant(&current.params) != mem::discriminant(&params) {
+        return Err(ApiError::ParamsMismatch);
+    }
+
+    let applied = api.apply(WorkspaceOp::UpdateModuleParams(module_id, params)).await?;
+
+    let module = applied.after.modules.get(&module_id)
+        .ok_or(ApiError::NoSuchModule(module_id))?;
+
+    Ok(reply::json(&module.params).into_response())
+}
+
+async fn get_indication(api: Api, module_id: ModuleId) -> Result<Response, ApiError> {
+    let workspace = api.snapshot().await?;
+
+    let module = workspace.modules.get(&module_id)
+        .ok_or(ApiError::NoSuchModule(module_id))?;
+
+    // modules without an indication yet respond with null
+    Ok(reply::json(&module.indication).into_response())
+}
+
+async fn list_connections(api: Api) -> Result<Response, ApiError> {
+    let workspace = api.snapshot().await?;
+    Ok(reply::json(&workspace.connections.values().collect::<Vec<_>>()).into_response())
+}
+
+async fn create_connection(api: Api, body: Connection) -> Result<Response, ApiError> {
+    let op = if body.feedback {
+        WorkspaceOp::CreateFeedbackConnection(body.input, body.output)
+    } else {
+        WorkspaceOp::CreateConnection(body.input, body.output)
+    };
+
+    let applied = api.apply(op).await?;
+
+    if applied.after.connections.get(&body.input) == Some(&body) {
+        Ok(json_status(&body, StatusCode::CREATED))
+    } else {
+        Err(ApiError::ConnectionRejected)
+    }
+}
+
+async fn delete_connection(api: Api, input: InputId) -> Result<Response, ApiError> {
+    let applied = api.apply(WorkspaceOp::DeleteConnection(input)).await?;
+
+    let deleted = applied.before.connections.contains_key(&input)
+        && !applied.after.connections.contains_key(&input);
+
+    if deleted {
+        Ok(StatusCode::NO_CONTENT.into_response())
+    } else {
+        Err(ApiError::NoSuchConnection(input))
+    }
+}
+
+// the workspace as last seen by the api's session
+#[derive(Clone, Default)]
+struct Workspace {
+    modules: BTreeMap<ModuleId, Module>,
+    connections: BTreeMap<InputId, Connection>,
+}
+
+impl Workspace {
+    fn from_state(state: WorkspaceState) -> Self {
+        let mut geometry = state.geometry.into_iter().collect::<HashMap<_, _>>();
+        let mut indications = state.indications.into_iter().collect::<HashMap<_, _>>();
+        let mut inputs = state.inputs.into_iter().collect::<HashMap<_, _>>();
+        let mut outputs = state.outputs.into_iter().collect::<HashMap<_, _>>();
+        let mut cv = state.cv.into_iter().collect::<HashMap<_, _>>();
+
+        let modules = state.modules.into_iter()
+            .map(|(id, params)| (id, Module {
+                id,
+                params,
+                geometry: geometry.remove(&id).unwrap_or_default(),
+                indication: indications.remove(&id),
+                inputs: inputs.remove(&id).unwrap_or_default(),
+                outputs: outputs.remove(&id).unwrap_or_default(),
+                cv: cv.remove(&id).unwrap_or_default(),
+            }))
+            .collect();
+
+        let connections = state.connections.iter()
+            .map(|(input, output)| (*input, Connection {
+                input: *input,
+                output: *output,
+                feedback: state.feedback.contains(input),
+            }))
+            .collect();
+
+        Workspace { modules, connections }
+    }
+
+    fn update(&mut self, update: &ServerUpdate) {
+        match update {
+            ServerUpdate::CreateModule { id, params, geometry, indication, inputs, outputs } => {
+                self.modules.insert(*id, Module {
+                    id: *id,
+                    params: params.clone(),
+                    geometry: geometry.clone(),
+                    indication: Some(indication.clone()),
+                    inputs: inputs.clone(),
+                    outputs: outputs.clone(),
+                    cv: Vec::new(),
+                });
+            }
+            ServerUpdate::UpdateModuleParams(id, params) => {
+                if let Some(module) = self.modules.get_mut(id) {
+                    module.params = params.clone();
+                }
+            }
+            ServerUpdate::UpdateWindowGeometry(id, geometry) => {
+                if let Some(module) = self.modules.get_mut(id) {
+                    module.geometry = geometry.clone();
+                }
+            }
+            ServerUpdate::UpdateModuleIndication(id, update) => {
+                if let Some(indication) = self.modules.get_mut(id).and_then(|module| module.indication.as_mut()) {
+                    indication.apply(update.clone());
+                }
+            }
+            ServerUpdate::UpdateModuleTerminals(id, inputs, outputs) => {
+                if let Some(module) = self.modules.get_mut(id) {
+                    module.inputs = inputs.clone();
+                    module.outputs = outputs.clone();
+                }
+            }
+            ServerUpdate::UpdateModuleCv(id, cv) => {
+                if let Some(module) = self.modules.get_mut(id) {
+                    module.cv = cv.clone();
+                }
+            }
+            ServerUpdate::DeleteModule(id) => {
+                self.modules.remove(id);
+            }
+            ServerUpdate::CreateConnection(input, output) => {
+                self.connections.insert(*input, Connection { input: *input, output: *output, feedback: false });
+            }
+            ServerUpdate::CreateFeedbackConnection(input, output) => {
+                self.connections.insert(*input, Connection { input: *input, output: *output, feedback: true });
+            }
+            ServerUpdate::DeleteConnection(input) => {
+                self.connections.remove(input);
+            }
+            ServerUpdate::UpdateTransport(_) |
+            ServerUpdate::UpdateAutomation(..) |
+            ServerUpdate::UpdateScenes(_) |
+            ServerUpdate::UpdateMidiBindings(_) => {}
+        }
+    }
+}
+
+// what became of an op
+struct Applied {
+    before: Workspace,
+    after: Workspace,
+    // the updates the engine sent out in applying the op, or None if they
+    // were dropped and the workspace had to be resynced
+    updates: Option<Vec<ServerUpdate>>,
+}
+
+enum Request {
+    Snapshot(oneshot::Sender<Workspace>),
+    Apply(WorkspaceOp, oneshot::Sender<Result<Applied, ApiError>>),
+}
+
+// an op sent to the engine, waiting for its sync
+struct Pending {
+    sequence: ClientSequence,
+    before: Workspace,
+    reply: oneshot::Sender<Result<Applied, ApiError>>,
+}
+
+#[derive(Clone)]
+struct Api {
+    requests: mpsc::Sender<Request>,
+}
+
+impl Api {
+    fn start(project: ProjectHandle) -> Self {
+        let (requests_tx, requests_rx) = mpsc::channel(64);
+        tokio::spawn(run(project, requests_rx));
+        Api { requests: requests_tx }
+    }
+
+    async fn snapshot(&self) -> Result<Workspace, ApiError> {
+        let (tx, rx) = oneshot::channel();
+        self.request(Request::Snapshot(tx)).await?;
+        rx.await.map_err(|_| ApiError::Engine(EngineError::Stopped))
+    }
+
+    async fn apply(&self, op: WorkspaceOp) -> Result<Applied, ApiError> {
+        let (tx, rx) = oneshot::channel();
+        self.request(Request::Apply(op, tx)).await?;
+        rx.await.map_err(|_| ApiError::Engine(EngineError::Stopped))?
+    }
+
+    async fn request(&self, request: Request) -> Result<(), ApiError> {
+        self.requests.clone().send(request).await
+            .map_err(|_| ApiError::Engine(EngineError::Stopped))
+    }
+}
+
+enum Event {
+    Request(Option<Request>),
+    Engine(Result<EngineEvent, broadcast::RecvError>),
+}
+
+async fn run(project: ProjectHandle, mut requests: mpsc::Receiver<Request>) {
+    // ops still waiting for their sync when the session lost track of the
+    // engine's updates. they are answered from the resynced workspace
+    let mut resyncing = Vec::<Pending>::new();
+
+    loop {
+        let (state, mut events, session) = match project.connect_engine().await {
+            Ok(connection) => connection,
+            Err(EngineError::Busy) => {
+                // the engine's command queue is full, give it a moment
+                task::yield_now().await;
+                continue;
+            }
+            Err(EngineError::Stopped) => {
+                return;
+            }
+        };
+
+        let mut workspace = Workspace::from_state(state);
+        let mut sequence = Sequence::new();
+
+        // ops sent before connecting were all applied before the engine
+        // dumped its state for us
+        for pending in resyncing.drain(..) {
+            let _ = pending.reply.send(Ok(Applied {
+                before: pending.before,
+                after: workspace.clone(),
+                updates: None,
+            }));
+        }
+
+        let mut pending = VecDeque::<Pending>::new();
+        // updates since the last sync, which belong to the op it is for:
+        let mut since_sync = Vec::new();
+
+        loop {
+            let event = tokio::select! {
+                request = requests.recv() => Event::Request(request),
+                event = events.recv() => Event::Engine(event),
+            };
+
+            match event {
+                Event::Request(Some(Request::Snapshot(reply))) => {
+                    let _ = reply.send(workspace.clone());
+                }
+                Event::Request(Some(Request::Apply(op, reply))) => {
+                    let sequence = ClientSequence(sequence.next());
+
+                    match session.update(WorkspaceMessage { sequence, op }) {
+                        Ok(()) => {
+                            pending.push_back(Pending { sequence, before: workspace.clone(), reply });
+                        }
+                        Err(e) => {
+                            let _ = reply.send(Err(ApiError::Engine(e)));
+                        }
+                    }
+                }
+                Event::Request(None) => {
+                    // routes dropped, the server is shutting down
+                    return;
+                }
+                Event::Engine(Ok(EngineEvent::ServerUpdate(update))) => {
+                    workspace.update(&update);
+
+                    if !pending.is_empty() {
+                        since_sync.push(update);
+                    }
+                }
+                Event::Engine(Ok(EngineEvent::Sync(clock))) => {
+                    let updates = mem::replace(&mut since_sync, Vec::new());
+
+                    if clock.0 != session.session_id() {
+                        continue;
+                    }
+
+                    if let Some(op) = pending.pop_front() {
+                        debug_assert!(op.sequence == clock.1);
+
+                        let _ = op.reply.send(Ok(Applied {
+                            before: op.before,
+                            after: workspace.clone(),
+                            updates: Some(updates),
+                        }));
+                    }
+                }
+                Event::Engine(Err(broadcast::RecvError::Lagged(_))) => {
+                    // missed updates, reconnect to resync with the engine
+                    resyncing.extend(pending);
+                    break;
+                }
+                Event::Engine(Err(broadcast::RecvError::Closed)) => {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use bytes::Bytes;
+    use serde_json::{json, Value};
+    use warp::http::{Response, StatusCode};
+    use warp::test::request;
+
+    use crate::engine::ConfigOverride;
+    use crate::project;
+    use super::routes;
+
+    fn body(res: &Response<Bytes>) -> Value {
+        serde_json::from_slice(res.body()).expect("json response")
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn edits_workspace() {
+        let path = env::temp_dir().join(format!("mixlab-api-{}", std::process::id()));
+        let project = project::open_or_create(path.clone(), ConfigOverride::default()).await
+            .expect("open project");
+
+        let api = routes(project);
+
+        // modules
+
+        let res = request().method("POST").path("/api/modules")
+            .json(&json!({ "params": { "Oscillator": { "freq": 440.0, "waveform": "Sine" } } }))
+            .reply(&api).await;
+
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let oscillator = body(&res)["id"].as_u64().expect("oscillator id");
+
+        let res = request().method("POST").path("/api/modules")
+            .json(&json!({ "params": { "Amplifier": { "amplitude": 1.0, "mod_depth": 0.0 } } }))
+            .reply(&api).await;
+
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let amplifier = body(&res)["id"].as_u64().expect("amplifier id");
+
+        let res = request().path("/api/modules").reply(&api).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(body(&res).as_array().map(Vec::len), Some(2));
+
+        // params
+
+        let res = request().method("PUT").path(&format!("/api/modules/{}/params", amplifier))
+            .json(&json!({ "Oscillator": { "freq": 220.0, "waveform": "Sine" } }))
+            .reply(&api).await;
+
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        let res = request().method("PUT").path(&format!("/api/modules/{}/params", amplifier))
+            .json(&json!({ "Amplifier": { "amplitude": 0.25, "mod_depth": 0.0 } }))
+            .reply(&api).await;
+
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(body(&res)["Amplifier"]["amplitude"], json!(0.25));
+
+        let res = request().path(&format!("/api/modules/{}/params", amplifier)).reply(&api).await;
+        assert_eq!(body(&res)["Amplifier"]["amplitude"], json!(0.25));
+
+        // indications
+
+        let res = request().path(&format!("/api/modules/{}/indication", amplifier)).reply(&api).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(body(&res), json!({ "Amplifier": null }));
+
+        // connections
+
+        let res = request().method("POST").path("/api/connections")
+            .json(&json!({ "input": [amplifier, 0], "output": [oscillator, 1] }))
+            .reply(&api).await;
+
+        assert_eq!(res.status(), StatusCode::CREATED);
+
+        // stereo output into the mono control input:
+        let res = request().method("POST").path("/api/connections")
+            .json(&json!({ "input": [amplifier, 1], "output": [oscillator, 1] }))
+            .reply(&api).await;
+
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+
+        let res = request().path("/api/connections").reply(&api).await;
+        assert_eq!(body(&res), json!([{ "input": [amplifier, 0], "output": [oscillator, 1], "feedback": false }]));
+
+        let res = request().method("DELETE").path(&format!("/api/connections/{}/0", amplifier)).reply(&api).await;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+        let res = request().method("DELETE").path(&format!("/api/connections/{}/0", amplifier)).reply(&api).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        // deleting
+
+        let res = request().method("DELETE").path(&format!("/api/modules/{}", oscillator)).reply(&api).await;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+        let res = request().path(&format!("/api/modules/{}", oscillator)).reply(&api).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        let res = request().path(&format!("/api/modules/{}/indication", oscillator)).reply(&api).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        let res = request().method("DELETE").path(&format!("/api/modules/{}", oscillator)).reply(&api).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        let mut database = path.clone();
+        database.set_extension("mixlab");
+        let _ = fs::remove_file(database);
+    }
+}
@@ -2,4 +2,8 @@ pub static MIGRATIONS: &'static [(i64, &'static str)] = &[
     (0, include_str!("migrations/0_init.sql")),
     (20200804, include_str!("migrations/20200804_create_media_tables.sql")),
     (20200805, include_str!("migrations/20200805_create_workspace_table.sql")),
+    (20201013, include_str!("migrations/20201013_create_config_table.sql")),
+    (20201020, include_str!("migrations/20201020_create_automation_table.sql")),
+    (20201021, include_str!("migrations/20201021_create_scenes_table.sql")),
+    (20201022, include_str!("migrations/20201022_create_midi_bindings_table.sql")),
 ];
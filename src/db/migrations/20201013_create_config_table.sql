@@ -0,0 +1 @@
+CREATE TABLE config (serialized TEXT NOT NULL);
@@ -0,0 +1 @@
+CREATE TABLE automation (serialized TEXT NOT NULL);
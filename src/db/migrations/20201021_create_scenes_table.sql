@@ -0,0 +1 @@
+CREATE TABLE scenes (serialized TEXT NOT NULL);
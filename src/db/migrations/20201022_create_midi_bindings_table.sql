@@ -0,0 +1 @@
+CREATE TABLE midi_bindings (serialized TEXT NOT NULL);
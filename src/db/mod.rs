@@ -1,6 +1,6 @@
 use std::path::PathBuf;
 
-use rusqlite::{self, Connection, Row};
+use rusqlite::{self, Connection, OpenFlags, Row};
 use tokio::task;
 
 mod migrations;
@@ -58,7 +58,34 @@ fn attach_blocking(path: PathBuf) -> Result<Connection, rusqlite::Error> {
     Ok(conn)
 }
 
+// a fresh database with the current schema, for tests
+#[cfg(test)]
+pub fn attach_in_memory() -> Result<Connection, rusqlite::Error> {
+    attach_blocking(PathBuf::from(":memory:"))
+}
+
 pub async fn attach(path: PathBuf) -> Result<Connection, rusqlite::Error> {
     task::spawn_blocking(|| attach_blocking(path)).await
         .expect("join blocking task")
 }
+
+fn attach_read_only_blocking(path: PathBuf) -> Result<Option<Connection>, rusqlite::Error> {
+    let conn = Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+
+    let latest_version = migrations::MIGRATIONS.iter()
+        .map(|(ver, _)| *ver)
+        .max();
+
+    if schema_version(&conn)? != latest_version {
+        return Ok(None);
+    }
+
+    Ok(Some(conn))
+}
+
+// opens an existing database without writing to it. migrations are never
+// run, so databases with any other schema than the current one give None
+pub async fn attach_read_only(path: PathBuf) -> Result<Option<Connection>, rusqlite::Error> {
+    task::spawn_blocking(|| attach_read_only_blocking(path)).await
+        .expect("join blocking task")
+}
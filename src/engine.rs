@@ -1,6 +1,7 @@
-use std::cmp::Ordering;
+use std::cmp::{self, Ordering};
 use std::collections::{HashMap, HashSet};
 use std::f32;
+use std::mem;
 use std::num::NonZeroUsize;
 use std::sync::Arc;
 use std::sync::mpsc::{self, SyncSender, Receiver, RecvTimeoutError, TrySendError, TryRecvError};
@@ -9,29 +10,52 @@ use std::time::{Instant, Duration};
 
 use futures::future;
 use futures::stream::{Stream, StreamExt};
-use tokio::runtime;
+use rayon::prelude::*;
 use tokio::sync::{oneshot, broadcast, watch};
 
-use mixlab_protocol::{ModuleId, InputId, OutputId, WorkspaceState, ServerUpdate, Indication, ClientSequence, WorkspaceMessage, WorkspaceOp, PerformanceInfo};
+use mixlab_protocol::{ModuleId, InputId, OutputId, WorkspaceState, ServerUpdate, Indication, ClientSequence, WorkspaceMessage, WorkspaceOp, PerformanceInfo, ModuleParams, Terminal, TransportOp, Scene, CvBinding, MidiControl, MidiBinding};
 
 use crate::project::ProjectBaseRef;
 use crate::util::Sequence;
 
+mod automation;
+mod config;
+mod cv;
+mod dsp;
+#[cfg(test)]
+pub mod harness;
+mod history;
 mod io;
+mod midi;
 mod module;
+mod nested;
+mod offline;
+mod scenes;
+mod smooth;
 mod timing;
+mod transport;
 mod workspace;
 
+use history::{History, Edit, Coalesce};
 use timing::{EngineStat, TickStat};
-use workspace::SyncWorkspace;
+use workspace::{SyncWorkspace, Workspace, ConnectError};
 
-pub use io::{InputRef, OutputRef, Output, VideoFrame};
+pub use automation::Automation;
+pub use config::{EngineConfig, ConfigError, ConfigOverride};
+pub use dsp::{level_db, time_coefficient};
+pub use io::{InputRef, OutputRef, Output, VideoFrame, TimedEvent, EventMessage};
+pub use midi::MidiBindings;
 pub use module::{ModuleCtx, DynModuleHost};
+pub use nested::NestedWorkspace;
+pub use offline::OfflineEngine;
+pub use scenes::Scenes;
+pub use smooth::Smoothed;
+pub use transport::{Transport, TransportRef, TransportTime};
 pub use workspace::WorkspaceEmbryo;
 
 pub type Sample = f32;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct SessionId(NonZeroUsize);
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -50,13 +74,11 @@ impl PartialOrd for OpClock {
 }
 
 pub const CHANNELS: usize = 2;
-pub const SAMPLE_RATE: usize = 44100;
-pub const TICKS_PER_SECOND: usize = 60;
-pub const SAMPLES_PER_TICK: usize = SAMPLE_RATE / TICKS_PER_SECOND;
 
 pub enum EngineMessage {
     ConnectSession(oneshot::Sender<(SessionId, WorkspaceState, EngineEvents)>),
     Workspace(SessionId, WorkspaceMessage),
+    DisconnectSession(SessionId),
 }
 
 #[derive(Clone)]
@@ -70,11 +92,13 @@ pub struct EngineSession {
     cmd_tx: SyncSender<EngineMessage>,
 }
 
-pub fn start(tokio_runtime: runtime::Handle, workspace: WorkspaceEmbryo, base: ProjectBaseRef) -> EngineHandle {
+pub fn start(workspace: WorkspaceEmbryo, automation: Automation, scenes: Scenes, midi: MidiBindings, base: ProjectBaseRef) -> EngineHandle {
     let (cmd_tx, cmd_rx) = mpsc::sync_channel(8);
     let (log_tx, _) = broadcast::channel(64);
     let (perf_tx, perf_rx) = watch::channel(None);
 
+    let tokio_runtime = base.runtime();
+
     thread::spawn(move || {
         // enter the tokio runtime context for the engine thread
         // this allows modules to spawn async tasks
@@ -85,6 +109,13 @@ pub fn start(tokio_runtime: runtime::Handle, workspace: WorkspaceEmbryo, base: P
                 perf_tx,
                 session_seq: Sequence::new(),
                 workspace: workspace.spawn(base.clone()),
+                history: History::default(),
+                automation,
+                scenes,
+                midi,
+                config: base.config(),
+                transport: base.transport(),
+                tick_start: Instant::now(),
                 base,
             };
 
@@ -135,6 +166,10 @@ impl EngineHandle {
     pub fn performance_info(&self) -> impl Stream<Item = Arc<PerformanceInfo>> {
         self.perf_rx.clone().filter_map(|info| future::ready(info))
     }
+
+    pub fn latest_performance_info(&self) -> Option<Arc<PerformanceInfo>> {
+        self.perf_rx.borrow().clone()
+    }
 }
 
 impl EngineSession {
@@ -152,41 +187,77 @@ impl EngineSession {
     }
 }
 
+impl Drop for EngineSession {
+    fn drop(&mut self) {
+        // best effort, if the engine is busy the session's undo history is
+        // left behind, but it is bounded in size
+        let _ = self.send_message(EngineMessage::DisconnectSession(self.session_id));
+    }
+}
+
 pub struct Engine {
     cmd_rx: Receiver<EngineMessage>,
     log_tx: broadcast::Sender<EngineEvent>,
     perf_tx: watch::Sender<Option<Arc<PerformanceInfo>>>,
     session_seq: Sequence,
     workspace: SyncWorkspace,
+    history: History,
+    automation: Automation,
+    scenes: Scenes,
+    midi: MidiBindings,
+    config: EngineConfig,
+    transport: TransportRef,
+    // when the tick about to run was scheduled to start:
+    tick_start: Instant,
     base: ProjectBaseRef,
 }
 
 impl Engine {
     fn run(&mut self) {
         let start = Instant::now();
-        let mut stat = EngineStat::new();
+        let mut stat = EngineStat::new(self.config);
         let mut tick = 0;
+        let ticks_per_second = self.config.ticks_per_second as u64;
+        // report performance metrics twice a second:
+        let perf_interval = cmp::max(ticks_per_second / 2, 1);
 
         loop {
             let this_tick = tick;
             tick += 1;
 
             // we don't simply calculate `tick * TICK_BUDGET` here to prevent loss of precision over time:
-            let scheduled_tick_end = start + Duration::from_millis((tick * 1_000) / TICKS_PER_SECOND as u64);
+            let scheduled_tick_end = start + Duration::from_micros((tick * 1_000_000) / ticks_per_second);
+
+            // commands are processed after this tick has run, and apply to the next:
+            self.tick_start = start + Duration::from_micros((this_tick * 1_000_000) / ticks_per_second);
+
+            self.play_automation();
+            self.step_scene_fade();
 
             // run tick
             let indications = stat.record_tick(scheduled_tick_end,
                 |tick_stat| self.run_tick(this_tick, tick_stat));
 
+            self.transport.advance(self.config.samples_per_tick());
+
             // send out indication updates
             for (module_id, indication) in indications {
-                self.workspace.indications_mut().insert(module_id, indication.clone());
+                if let Some(current) = self.workspace.indications_mut().get_mut(&module_id) {
+                    current.apply(indication.clone());
+                }
+
                 self.log_op(ServerUpdate::UpdateModuleIndication(module_id, indication));
             }
 
             // send out performance metrics
-            if (this_tick % (TICKS_PER_SECOND as u64 / 2)) == 0 {
+            if (this_tick % perf_interval) == 0 {
                 let _ = self.perf_tx.broadcast(Some(Arc::new(stat.report())));
+
+                let transport = self.transport.state();
+
+                if transport.playing {
+                    self.log_op(ServerUpdate::UpdateTransport(transport));
+                }
             }
 
             // process all waiting commands immediately
@@ -223,6 +294,9 @@ impl Engine {
             EngineMessage::Workspace(session, msg) => {
                 self.client_update(session, msg, stat);
             }
+            EngineMessage::DisconnectSession(session) => {
+                self.history.remove_session(session);
+            }
         }
     }
 
@@ -239,14 +313,25 @@ impl Engine {
             geometry: Vec::new(),
             indications: Vec::new(),
             connections: Vec::new(),
+            feedback: Vec::new(),
             inputs: Vec::new(),
             outputs: Vec::new(),
+            transport: self.transport.state(),
+            automation: self.automation.status(),
+            scenes: self.scenes.names(),
+            cv: Vec::new(),
+            midi: self.midi.bindings().to_vec(),
         };
 
         let workspace = self.workspace.borrow();
 
         for (module_id, module) in &workspace.modules {
             state.modules.push((*module_id, module.params()));
+
+            if !module.cv().is_empty() {
+                state.cv.push((*module_id, module.cv().to_vec()));
+            }
+
             state.inputs.push((*module_id, module.inputs().to_vec()));
             state.outputs.push((*module_id, module.outputs().to_vec()));
         }
@@ -263,6 +348,8 @@ impl Engine {
             state.connections.push((*input, *output));
         }
 
+        state.feedback.extend(workspace.feedback.iter().copied());
+
         state
     }
 
@@ -282,9 +369,224 @@ impl Engine {
                 // TODO - the audio engine is not actually concerned with
                 // window geometry and so should not own this data and force
                 // all accesses to it to go via the live audio thread
+                let id = ModuleId(self.workspace.borrow_mut().module_seq.next());
+                let inverse = self.apply_edits(vec![Edit::CreateModule { id, params, geometry }], stat);
+                self.history.record(session_id, inverse, None);
+            }
+            WorkspaceOp::UpdateModuleParams(module_id, params) => {
+                self.update_params_by_hand(session_id, module_id, params, stat);
+            }
+            WorkspaceOp::UpdateWindowGeometry(module_id, geometry) => {
+                let inverse = self.apply_edits(vec![Edit::UpdateWindowGeometry(module_id, geometry)], stat);
+                self.history.record(session_id, inverse, Some(Coalesce::WindowGeometry(module_id)));
+            }
+            WorkspaceOp::DeleteModule(module_id) => {
+                let inverse = self.apply_edits(vec![Edit::DeleteModule(module_id)], stat);
+                self.history.record(session_id, inverse, None);
+            }
+            WorkspaceOp::CreateConnection(input, output) => {
+                match self.apply_edit(Edit::Connect { input, output, feedback: false }, stat) {
+                    Ok(inverse) => {
+                        self.history.record(session_id, inverse, None);
+                    }
+                    Err(ConnectError::Cycle) => {
+                        // client should have made this a feedback connection.
+                        // it will have already applied the connection locally,
+                        // so tell it what this input is really connected to
+                        let op = {
+                            let workspace = self.workspace.borrow();
+
+                            match workspace.connections.get(&input) {
+                                Some(current) if workspace.feedback.contains(&input) => {
+                                    ServerUpdate::CreateFeedbackConnection(input, *current)
+                                }
+                                Some(current) => ServerUpdate::CreateConnection(input, *current),
+                                None => ServerUpdate::DeleteConnection(input),
+                            }
+                        };
+
+                        self.log_op(op);
+                    }
+                    Err(_) => {
+                        // client should have guarded against a type mismatched
+                        // connection, just drop
+                    }
+                }
+            }
+            WorkspaceOp::CreateFeedbackConnection(input, output) => {
+                let inverse = self.apply_edits(vec![Edit::Connect { input, output, feedback: true }], stat);
+                self.history.record(session_id, inverse, None);
+            }
+            WorkspaceOp::DeleteConnection(input) => {
+                let inverse = self.apply_edits(vec![Edit::Disconnect(input)], stat);
+                self.history.record(session_id, inverse, None);
+            }
+            WorkspaceOp::Undo => {
+                if let Some(edits) = self.history.take_undo(session_id) {
+                    let inverse = self.apply_edits(edits, stat);
+                    self.history.push_redo(session_id, inverse);
+                }
+            }
+            WorkspaceOp::Redo => {
+                if let Some(edits) = self.history.take_redo(session_id) {
+                    let inverse = self.apply_edits(edits, stat);
+                    self.history.push_undo(session_id, inverse);
+                }
+            }
+            WorkspaceOp::Transport(op) => {
+                let transport = self.transport.apply(op);
+
+                if let TransportOp::SetTempo(_) = op {
+                    // tempo is saved with the workspace
+                    self.workspace.borrow_mut().tempo = Some(transport.tempo);
+                }
+
+                self.log_op(ServerUpdate::UpdateTransport(transport));
+            }
+            WorkspaceOp::SetAutomationMode(module_id, mode) => {
+                let status = self.automation.set_mode(module_id, mode);
+                self.log_op(ServerUpdate::UpdateAutomation(module_id, status));
+            }
+            WorkspaceOp::ClearAutomation(module_id) => {
+                let status = self.automation.clear(module_id);
+                self.log_op(ServerUpdate::UpdateAutomation(module_id, status));
+            }
+            WorkspaceOp::CaptureScene(name) => {
+                if !name.trim().is_empty() {
+                    let scene = {
+                        let workspace = self.workspace.borrow();
+
+                        let modules = workspace.modules.iter()
+                            .map(|(module_id, module)| (*module_id, module.params()))
+                            .filter(|(_, params)| scenes::in_scene(params))
+                            .collect();
+
+                        Scene { modules }
+                    };
+
+                    self.scenes.capture(name, scene);
+
+                    let names = self.scenes.names();
+                    self.log_op(ServerUpdate::UpdateScenes(names));
+                }
+            }
+            WorkspaceOp::RecallScene(name, fade_ms) => {
+                if let Some(scene) = self.scenes.get(&name).cloned() {
+                    self.recall_scene(session_id, scene, fade_ms, stat);
+                }
+            }
+            WorkspaceOp::DeleteScene(name) => {
+                if self.scenes.delete(&name) {
+                    let names = self.scenes.names();
+                    self.log_op(ServerUpdate::UpdateScenes(names));
+                }
+            }
+            WorkspaceOp::SetModuleCv(module_id, cv) => {
+                let inverse = self.apply_edits(vec![Edit::SetCv(module_id, cv)], stat);
+                self.history.record(session_id, inverse, None);
+            }
+            WorkspaceOp::MidiInput(control, value) => {
+                self.midi_input(session_id, control, value, stat);
+            }
+            WorkspaceOp::BindMidi(binding) => {
+                if self.bind_midi(binding) {
+                    self.log_op(ServerUpdate::UpdateMidiBindings(self.midi.bindings().to_vec()));
+                }
+            }
+            WorkspaceOp::UnbindMidi(module_id, param) => {
+                if self.midi.unbind(module_id, &param) {
+                    self.log_op(ServerUpdate::UpdateMidiBindings(self.midi.bindings().to_vec()));
+                }
+            }
+        }
+
+        return self.sync_log(clock);
+    }
+
+    fn update_params_by_hand(&mut self, session_id: SessionId, module_id: ModuleId, params: ModuleParams, stat: &mut EngineStat) {
+        // edits within a sub-patch arrive as params updates, but each
+        // should be undoable on its own
+        let coalesce = match params {
+            ModuleParams::SubPatch(_) => None,
+            _ => Some(Coalesce::ModuleParams(module_id)),
+        };
+
+        let status = self.automation.record(module_id, &self.transport.state(), &params);
+
+        // a scene fade in progress lets go of modules changed by hand
+        self.scenes.cancel_fade(module_id);
+
+        let inverse = self.apply_edits(vec![Edit::UpdateModuleParams(module_id, params)], stat);
+        self.history.record(session_id, inverse, coalesce);
+
+        if let Some(status) = status {
+            self.log_op(ServerUpdate::UpdateAutomation(module_id, status));
+        }
+    }
+
+    fn midi_input(&mut self, session_id: SessionId, control: MidiControl, value: u8, stat: &mut EngineStat) {
+        // several params of one module may be bound to the same control:
+        let mut modules = HashMap::<ModuleId, ModuleParams>::new();
+
+        {
+            let workspace = self.workspace.borrow();
+
+            for (module_id, param, value) in self.midi.map(&control, value) {
+                let module = match workspace.modules.get(&module_id) {
+                    Some(module) => module,
+                    None => continue,
+                };
+
+                modules.entry(module_id)
+                    .or_insert_with(|| module.params())
+                    .set_numeric_param(param, value);
+            }
+        }
+
+        for (module_id, params) in modules {
+            self.update_params_by_hand(session_id, module_id, params, stat);
+        }
+    }
+
+    // only numeric params of top level modules can be bound
+    fn bind_midi(&mut self, binding: MidiBinding) -> bool {
+        let bindable = self.workspace.borrow().modules.get(&binding.module)
+            .map(|module| module.params().numeric_param(&binding.param).is_some())
+            .unwrap_or(false);
+
+        if bindable {
+            self.midi.bind(binding);
+        }
+
+        bindable
+    }
+
+    // applies edits in order, returning the edits which revert all of them.
+    // edits which no longer apply (eg. because another session deleted the
+    // module in the meantime) are skipped
+    fn apply_edits(&mut self, edits: Vec<Edit>, stat: &mut EngineStat) -> Vec<Edit> {
+        let mut inverses = Vec::new();
+
+        for edit in edits {
+            match self.apply_edit(edit, stat) {
+                Ok(inverse) => inverses.push(inverse),
+                Err(_) => {}
+            }
+        }
+
+        inverses.into_iter().rev().flatten().collect()
+    }
+
+    fn apply_edit(&mut self, edit: Edit, stat: &mut EngineStat) -> Result<Vec<Edit>, ConnectError> {
+        match edit {
+            Edit::CreateModule { id, params, geometry } => {
                 let op = {
                     let mut workspace = self.workspace.borrow_mut();
-                    let id = ModuleId(workspace.module_seq.next());
+
+                    if workspace.modules.contains_key(&id) {
+                        return Ok(vec![]);
+                    }
+
                     let (module, indication) = module::host(params.clone(), self.base.clone());
                     let inputs = module.inputs().to_vec();
                     let outputs = module.outputs().to_vec();
@@ -303,41 +605,66 @@ impl Engine {
                 };
 
                 self.log_op(op);
+
+                Ok(vec![Edit::DeleteModule(id)])
             }
-            WorkspaceOp::UpdateModuleParams(module_id, params) => {
-                let op = {
-                    let mut workspace = self.workspace.borrow_mut();
+            Edit::UpdateModuleParams(module_id, params) => {
+                let offset = self.param_offset();
+                let result = update_module_params(&mut self.workspace.borrow_mut(), module_id, params, offset);
 
-                    workspace.modules.get_mut(&module_id).map(|module| {
-                        module.update(params.clone());
-                        ServerUpdate::UpdateModuleParams(module_id, module.params())
-                    })
-                };
+                match result {
+                    Some((operations, inverse)) => {
+                        for op in operations {
+                            self.log_op(op);
+                        }
 
-                if let Some(op) = op {
-                    self.log_op(op);
+                        Ok(inverse)
+                    }
+                    None => Ok(vec![]),
                 }
             }
-            WorkspaceOp::UpdateWindowGeometry(module_id, geometry) => {
-                let op = {
+            Edit::SetCv(module_id, cv) => {
+                let result = set_module_cv(&mut self.workspace.borrow_mut(), module_id, cv);
+
+                match result {
+                    Some((operations, inverse)) => {
+                        for op in operations {
+                            self.log_op(op);
+                        }
+
+                        Ok(inverse)
+                    }
+                    None => Ok(vec![]),
+                }
+            }
+            Edit::UpdateWindowGeometry(module_id, geometry) => {
+                let old_geometry = {
                     let mut workspace = self.workspace.borrow_mut();
 
                     workspace.geometry.get_mut(&module_id).map(|geom| {
-                        *geom = geometry.clone();
-                        ServerUpdate::UpdateWindowGeometry(module_id, geometry)
+                        mem::replace(geom, geometry.clone())
                     })
                 };
 
-                if let Some(op) = op {
-                    self.log_op(op);
+                match old_geometry {
+                    Some(old_geometry) => {
+                        self.log_op(ServerUpdate::UpdateWindowGeometry(module_id, geometry));
+                        Ok(vec![Edit::UpdateWindowGeometry(module_id, old_geometry)])
+                    }
+                    None => Ok(vec![]),
                 }
             }
-            WorkspaceOp::DeleteModule(module_id) => {
+            Edit::DeleteModule(module_id) => {
                 let mut operations = Vec::new();
+                let mut inverse = Vec::new();
 
                 {
                     let mut workspace = self.workspace.borrow_mut();
 
+                    if !workspace.modules.contains_key(&module_id) {
+                        return Ok(vec![]);
+                    }
+
                     // find any connections connected to this module's inputs or
                     // outputs and delete them, generating oplog entries
 
@@ -345,20 +672,35 @@ impl Engine {
 
                     for (input, output) in &workspace.connections {
                         if input.module_id() == module_id || output.module_id() == module_id {
-                            deleted_connections.push(*input);
+                            deleted_connections.push((*input, *output, workspace.feedback.contains(input)));
                         }
                     }
 
-                    for deleted_connection in deleted_connections {
-                        workspace.connections.remove(&deleted_connection);
-                        operations.push(ServerUpdate::DeleteConnection(deleted_connection));
+                    for (input, output, feedback) in deleted_connections {
+                        workspace.disconnect(input);
+                        operations.push(ServerUpdate::DeleteConnection(input));
+                        inverse.push(Edit::Connect { input, output, feedback });
                     }
 
                     // finally, delete the module:
 
-                    if workspace.modules.contains_key(&module_id) {
-                        workspace.modules.remove(&module_id);
+                    if let Some(module) = workspace.modules.remove(&module_id) {
+                        let geometry = workspace.geometry.remove(&module_id)
+                            .unwrap_or_default();
+
                         operations.push(ServerUpdate::DeleteModule(module_id));
+
+                        // module and its cv inputs must be recreated before
+                        // its connections:
+                        if !module.cv().is_empty() {
+                            inverse.insert(0, Edit::SetCv(module_id, module.cv().to_vec()));
+                        }
+
+                        inverse.insert(0, Edit::CreateModule {
+                            id: module_id,
+                            params: module.params(),
+                            geometry,
+                        });
                     }
                 }
 
@@ -366,35 +708,159 @@ impl Engine {
                     self.log_op(op);
                 }
 
+                self.automation.remove(module_id);
                 stat.remove_module(module_id);
+
+                Ok(inverse)
             }
-            WorkspaceOp::CreateConnection(input_id, output_id) => {
-                let previous = self.workspace.borrow_mut().connect(input_id, output_id);
+            Edit::Connect { input, output, feedback } => {
+                let (previous, was_feedback) = {
+                    let mut workspace = self.workspace.borrow_mut();
+                    let was_feedback = workspace.feedback.contains(&input);
+
+                    let previous = if feedback {
+                        workspace.connect_feedback(input, output)?
+                    } else {
+                        workspace.connect(input, output)?
+                    };
+
+                    (previous, was_feedback)
+                };
+
+                if let Some(_) = previous {
+                    self.log_op(ServerUpdate::DeleteConnection(input));
+                }
+
+                if feedback {
+                    self.log_op(ServerUpdate::CreateFeedbackConnection(input, output));
+                } else {
+                    self.log_op(ServerUpdate::CreateConnection(input, output));
+                }
 
                 match previous {
-                    Ok(old_output) => {
-                        if let Some(_) = old_output {
-                            self.log_op(ServerUpdate::DeleteConnection(input_id));
-                        }
+                    Some(previous) => Ok(vec![Edit::Connect { input, output: previous, feedback: was_feedback }]),
+                    None => Ok(vec![Edit::Disconnect(input)]),
+                }
+            }
+            Edit::Disconnect(input) => {
+                let (previous, was_feedback) = {
+                    let mut workspace = self.workspace.borrow_mut();
+                    let was_feedback = workspace.feedback.contains(&input);
+                    (workspace.disconnect(input), was_feedback)
+                };
 
-                        self.log_op(ServerUpdate::CreateConnection(input_id, output_id));
-                    }
-                    Err(_) => {
-                        // client should have guarded against a type mismatched
-                        // connection, just drop
+                match previous {
+                    Some(output) => {
+                        self.log_op(ServerUpdate::DeleteConnection(input));
+                        Ok(vec![Edit::Connect { input, output, feedback: was_feedback }])
                     }
+                    None => Ok(vec![]),
                 }
             }
-            WorkspaceOp::DeleteConnection(input_id) => {
-                let previous = self.workspace.borrow_mut().disconnect(input_id);
+        }
+    }
 
-                if let Some(_) = previous {
-                    self.log_op(ServerUpdate::DeleteConnection(input_id));
+    // params updates from clients take effect one tick after they arrive, at
+    // the same offset into the tick as they arrived at. this delays them by a
+    // constant amount rather than quantising them to tick boundaries
+    fn param_offset(&self) -> usize {
+        let samples_per_tick = self.config.samples_per_tick();
+        let elapsed = Instant::now().saturating_duration_since(self.tick_start);
+        let fraction = elapsed.as_secs_f64() / self.config.tick_budget().as_secs_f64();
+        let offset = (fraction * samples_per_tick as f64) as usize;
+
+        cmp::min(offset, samples_per_tick - 1)
+    }
+
+    fn play_automation(&mut self) {
+        let now = self.transport.now();
+        let samples_per_tick = self.config.samples_per_tick();
+        let tick_beats = now.beat_at(samples_per_tick) - now.state.position;
+
+        for (module_id, params, position) in self.automation.playback(&now.state, tick_beats) {
+            // automation playback is not persisted. the workspace is saved
+            // with the automated params on the next edit
+            let workspace = self.workspace.borrow_mut_without_sync();
+            let offset = now.offset_of(position, samples_per_tick);
+
+            if let Some((operations, _)) = update_module_params(workspace, module_id, params, offset) {
+                for op in operations {
+                    self.log_op(op);
                 }
             }
         }
+    }
 
-        return self.sync_log(clock);
+    fn recall_scene(&mut self, session_id: SessionId, scene: Scene, fade_ms: u64, stat: &mut EngineStat) {
+        // modules deleted since the scene was captured are skipped
+        let mut modules = {
+            let workspace = self.workspace.borrow();
+
+            scene.modules.into_iter()
+                .filter_map(|(module_id, params)| {
+                    let current = workspace.modules.get(&module_id)?.params();
+
+                    if scenes::same_module_kind(&current, &params) {
+                        Some((module_id, current, params))
+                    } else {
+                        None
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+
+        modules.sort_by_key(|(module_id, _, _)| *module_id);
+
+        let fade_ticks = fade_ms * self.config.ticks_per_second as u64 / 1000;
+
+        // modules without numeric params have nothing to fade, so they are
+        // recalled immediately either way
+        let (fade, immediate): (Vec<_>, Vec<_>) = modules.into_iter()
+            .partition(|(_, _, params)| fade_ticks > 0 && !params.numeric_params().is_empty());
+
+        let edits = immediate.into_iter()
+            .map(|(module_id, _, params)| Edit::UpdateModuleParams(module_id, params))
+            .collect();
+
+        let mut inverse = self.apply_edits(edits, stat);
+
+        // undoing a fade reverts to where it started from, however far it got
+        inverse.extend(fade.iter()
+            .map(|(module_id, current, _)| Edit::UpdateModuleParams(*module_id, current.clone())));
+
+        self.history.record(session_id, inverse, None);
+
+        if !fade.is_empty() {
+            let fade = fade.into_iter()
+                .map(|(module_id, current, params)| (module_id, (current, params)))
+                .collect();
+
+            self.scenes.start_fade(fade, fade_ticks);
+        }
+    }
+
+    fn step_scene_fade(&mut self) {
+        let (steps, finished) = match self.scenes.step_fade() {
+            Some(step) => step,
+            None => { return; }
+        };
+
+        for (module_id, params) in steps {
+            let workspace = self.workspace.borrow_mut_without_sync();
+
+            // fades are stepped once per tick, each module smooths the steps
+            if let Some((operations, _)) = update_module_params(workspace, module_id, params, 0) {
+                for op in operations {
+                    self.log_op(op);
+                }
+            }
+        }
+
+        if finished {
+            // only the params the fade ends on are persisted, which happens
+            // when the mutable borrow is released
+            drop(self.workspace.borrow_mut());
+        }
     }
 
     fn run_tick(&mut self, tick: u64, stat: &mut TickStat) -> Vec<(ModuleId, Indication)> {
@@ -402,110 +868,555 @@ impl Engine {
         // module params or connections
         let workspace = self.workspace.borrow_mut_without_sync();
 
-        // find terminal modules - modules which do not send their output to
-        // the input of any other module
+        run_tick(workspace, self.config, tick, stat, &HashMap::new()).indications
+    }
+}
 
-        let mut terminal_modules = HashSet::new();
+// updates a module's params from `offset` samples into the next tick,
+// returning the server updates to send out and the edits which revert the update
+fn update_module_params(workspace: &mut Workspace, module_id: ModuleId, params: ModuleParams, offset: usize) -> Option<(Vec<ServerUpdate>, Vec<Edit>)> {
+    let mut operations = Vec::new();
+    let mut inverse = Vec::new();
 
-        for (id, _) in &workspace.modules {
-            terminal_modules.insert(*id);
-        }
+    let module = workspace.modules.get_mut(&module_id)?;
+
+    let old_params = module.params();
+    let old_inputs = module.inputs().to_vec();
+    let old_outputs = module.outputs().to_vec();
+
+    let indication = module.update(params, offset);
+
+    operations.push(ServerUpdate::UpdateModuleParams(module_id, module.params()));
+    inverse.push(Edit::UpdateModuleParams(module_id, old_params));
+
+    // some modules (eg. sub-patches) derive their terminals from their
+    // params. drop any connections to terminals which have gone away or
+    // changed type
+    drop_stale_connections(workspace, module_id, &old_inputs, &old_outputs,
+        |old, new| old.line_type() == new.line_type(),
+        &mut operations, &mut inverse);
+
+    if let Some(indication) = indication {
+        workspace.indications.insert(module_id, indication.clone());
+        operations.push(ServerUpdate::UpdateModuleIndication(module_id, indication));
+    }
+
+    Some((operations, inverse))
+}
+
+// replaces a module's cv bindings, returning the server updates to send out and
+// the edits which revert the change
+fn set_module_cv(workspace: &mut Workspace, module_id: ModuleId, cv: Vec<CvBinding>) -> Option<(Vec<ServerUpdate>, Vec<Edit>)> {
+    let mut operations = Vec::new();
+    let mut inverse = Vec::new();
+
+    let module = workspace.modules.get_mut(&module_id)?;
+
+    let old_cv = module.cv().to_vec();
+    let old_inputs = module.inputs().to_vec();
+    let old_outputs = module.outputs().to_vec();
+
+    module.set_cv(cv.clone());
+
+    operations.push(ServerUpdate::UpdateModuleCv(module_id, cv));
+    inverse.push(Edit::SetCv(module_id, old_cv));
+
+    // cv inputs are labeled with their param, so a connection is dropped when
+    // the input it went to now modulates a different param
+    drop_stale_connections(workspace, module_id, &old_inputs, &old_outputs,
+        |old, new| old == new,
+        &mut operations, &mut inverse);
+
+    Some((operations, inverse))
+}
 
-        for (_, output) in &workspace.connections {
-            terminal_modules.remove(&output.module_id());
+// deletes connections to a module's terminals which have gone away, or which
+// `same` says have changed, since the module had the given terminals. sends
+// out the module's new terminals if they differ at all
+fn drop_stale_connections(
+    workspace: &mut Workspace,
+    module_id: ModuleId,
+    old_inputs: &[Terminal],
+    old_outputs: &[Terminal],
+    same: impl Fn(&Terminal, &Terminal) -> bool,
+    operations: &mut Vec<ServerUpdate>,
+    inverse: &mut Vec<Edit>,
+) {
+    let (inputs, outputs) = match workspace.modules.get(&module_id) {
+        Some(module) => (module.inputs().to_vec(), module.outputs().to_vec()),
+        None => { return; }
+    };
+
+    if inputs == old_inputs && outputs == old_outputs {
+        return;
+    }
+
+    let stale = |old: &[Terminal], new: &[Terminal], index: usize| {
+        match (old.get(index), new.get(index)) {
+            (Some(old), Some(new)) => !same(old, new),
+            _ => true,
         }
+    };
 
-        // depth-first-search modules out via their inputs, starting from
-        // terminal modules
+    let mut deleted_connections = Vec::new();
 
-        let mut topsort = Topsort {
-            modules: &workspace.modules,
-            connections: &workspace.connections,
-            run_order: Vec::new(),
-            seen: HashSet::new(),
-        };
+    for (input, output) in &workspace.connections {
+        let stale_input = input.module_id() == module_id
+            && stale(old_inputs, &inputs, input.index());
+
+        let stale_output = output.module_id() == module_id
+            && stale(old_outputs, &outputs, output.index());
 
-        for id in terminal_modules.into_iter() {
-            traverse(id, &mut topsort);
+        if stale_input || stale_output {
+            deleted_connections.push((*input, *output, workspace.feedback.contains(input)));
         }
+    }
+
+    for (input, output, feedback) in deleted_connections {
+        workspace.disconnect(input);
+        operations.push(ServerUpdate::DeleteConnection(input));
+        inverse.push(Edit::Connect { input, output, feedback });
+    }
+
+    operations.push(ServerUpdate::UpdateModuleTerminals(module_id, inputs, outputs));
+}
 
-        struct Topsort<'a> {
-            modules: &'a HashMap<ModuleId, DynModuleHost>,
-            connections: &'a HashMap<InputId, OutputId>,
-            run_order: Vec<ModuleId>,
-            seen: HashSet<ModuleId>,
+pub struct TickOutput {
+    pub indications: Vec<(ModuleId, Indication)>,
+    pub buffers: HashMap<OutputId, Output>,
+}
+
+// `external` supplies buffers from outside the workspace (eg. the inputs of an
+// enclosing sub-patch module) in place of the outputs they are keyed by
+pub(in crate::engine) fn run_tick(
+    workspace: &mut Workspace,
+    config: EngineConfig,
+    tick: u64,
+    stat: &mut TickStat,
+    external: &HashMap<OutputId, Output>,
+) -> TickOutput {
+    let samples_per_tick = config.samples_per_tick();
+
+    let terminal_modules = workspace.terminal_modules();
+
+    // depth-first-search modules out via their inputs, starting from
+    // terminal modules. feedback connections read the previous tick's
+    // buffers so they impose no ordering and are not followed
+
+    let mut topsort = Topsort {
+        modules: &workspace.modules,
+        connections: &workspace.connections,
+        feedback: &workspace.feedback,
+        run_order: Vec::new(),
+        seen: HashSet::new(),
+    };
+
+    for id in terminal_modules.into_iter() {
+        traverse(id, &mut topsort);
+    }
+
+    struct Topsort<'a> {
+        modules: &'a HashMap<ModuleId, DynModuleHost>,
+        connections: &'a HashMap<InputId, OutputId>,
+        feedback: &'a HashSet<InputId>,
+        run_order: Vec<ModuleId>,
+        seen: HashSet<ModuleId>,
+    }
+
+    fn traverse(module_id: ModuleId, state: &mut Topsort) {
+        if state.seen.contains(&module_id) {
+            return;
         }
 
-        fn traverse(module_id: ModuleId, state: &mut Topsort) {
-            if state.seen.contains(&module_id) {
-                return;
-            }
+        state.seen.insert(module_id);
 
-            state.seen.insert(module_id);
+        let module = &state.modules[&module_id];
 
-            let module = &state.modules[&module_id];
+        for i in 0..module.inputs().len() {
+            let terminal_id = InputId(module_id, i);
 
-            for i in 0..module.inputs().len() {
-                let terminal_id = InputId(module_id, i);
+            if state.feedback.contains(&terminal_id) {
+                continue;
+            }
 
-                if let Some(output_id) = state.connections.get(&terminal_id) {
-                    traverse(output_id.module_id(), state);
-                }
+            if let Some(output_id) = state.connections.get(&terminal_id) {
+                traverse(output_id.module_id(), state);
             }
+        }
 
-            state.run_order.push(module_id);
+        state.run_order.push(module_id);
+    }
+
+    // group modules into dependency levels. a module's inputs are only ever
+    // fed by modules in earlier levels, so the modules within a level are
+    // independent of each other and can run in parallel
+
+    let mut module_levels = HashMap::<ModuleId, usize>::new();
+    let mut levels = Vec::<Vec<ModuleId>>::new();
+
+    for module_id in topsort.run_order.iter() {
+        let module = &workspace.modules[module_id];
+
+        let level = (0..module.inputs().len())
+            .map(|i| InputId(*module_id, i))
+            .filter(|input_id| !workspace.feedback.contains(input_id))
+            .filter_map(|input_id| workspace.connections.get(&input_id))
+            .filter_map(|output_id| module_levels.get(&output_id.module_id()))
+            .map(|level| level + 1)
+            .max()
+            .unwrap_or(0);
+
+        module_levels.insert(*module_id, level);
+
+        if levels.len() <= level {
+            levels.resize_with(level + 1, Vec::new);
         }
 
-        // run modules in dependency order according to BFS above
+        levels[level].push(*module_id);
+    }
+
+    // run each level in turn, dispatching modules within a level to the
+    // worker pool
+
+    let t = config.tick_time(tick);
+    let mut buffers = HashMap::<OutputId, Output>::new();
+    let mut indications = Vec::new();
 
-        let mut buffers = HashMap::<OutputId, Output>::new();
-        let mut indications = Vec::new();
+    for level in levels {
+        let sources = InputSources {
+            connections: &workspace.connections,
+            feedback: &workspace.feedback,
+            buffers: &buffers,
+            feedback_buffers: &workspace.feedback_buffers,
+            external,
+        };
 
-        for module_id in topsort.run_order.iter() {
-            let module = workspace.modules.get_mut(&module_id)
+        let results = if let [module_id] = level.as_slice() {
+            // no point paying for a trip through the worker pool
+            let module = workspace.modules.get_mut(module_id)
                 .expect("module get_mut");
 
-            let connections = &workspace.connections;
+            let result = stat.record_module(*module_id, || {
+                run_module(*module_id, module, sources, samples_per_tick, t)
+            });
 
-            let mut output_buffers = module.outputs().iter()
-                .map(|output| Output::from_line_type(output.line_type()))
+            vec![(*module_id, result)]
+        } else {
+            let mut level_modules = workspace.modules.iter_mut()
+                .filter(|(module_id, _)| level.contains(module_id))
                 .collect::<Vec<_>>();
 
-            {
-                let input_refs = module.inputs().iter()
-                    .enumerate()
-                    .map(|(i, _ty)| InputId(*module_id, i))
-                    .map(|input_id| {
-                        connections.get(&input_id)
-                            .and_then(|output_id| buffers.get(output_id))
-                            .map(|output| output.as_input_ref())
-                            .unwrap_or(InputRef::Disconnected)
+            stat.record_level(|| {
+                level_modules.par_iter_mut()
+                    .map(|(module_id, module)| {
+                        let start = Instant::now();
+                        let result = run_module(**module_id, module, sources, samples_per_tick, t);
+                        (**module_id, start.elapsed(), result)
                     })
-                    .collect::<Vec<_>>();
+                    .collect()
+            })
+        };
 
-                let mut output_refs = output_buffers.iter_mut()
-                    .map(|output| output.as_output_ref())
-                    .collect::<Vec<_>>();
+        for (module_id, (indication, output_buffers)) in results {
+            if let Some(indic) = indication {
+                indications.push((module_id, indic));
+            }
 
-                let t = tick * SAMPLES_PER_TICK as u64;
+            for (i, output) in output_buffers.into_iter().enumerate() {
+                buffers.insert(OutputId(module_id, i), output);
+            }
+        }
+    }
 
-                let result = stat.record_module(*module_id, || {
-                    module.run_tick(t, &input_refs, &mut output_refs)
-                });
+    // hold on to buffers read by feedback connections for the next tick
 
-                match result {
-                    None => {}
-                    Some(indic) => {
-                        indications.push((*module_id, indic));
+    workspace.feedback_buffers = workspace.feedback.iter()
+        .filter_map(|input_id| workspace.connections.get(input_id))
+        .filter_map(|output_id| {
+            buffers.get(output_id).map(|output| (*output_id, output.clone()))
+        })
+        .collect();
+
+    TickOutput { indications, buffers }
+}
+
+#[derive(Clone, Copy)]
+struct InputSources<'a> {
+    connections: &'a HashMap<InputId, OutputId>,
+    feedback: &'a HashSet<InputId>,
+    // outputs of modules already run this tick:
+    buffers: &'a HashMap<OutputId, Output>,
+    // outputs from the previous tick, read by feedback connections:
+    feedback_buffers: &'a HashMap<OutputId, Output>,
+    external: &'a HashMap<OutputId, Output>,
+}
+
+fn run_module(
+    module_id: ModuleId,
+    module: &mut DynModuleHost,
+    sources: InputSources,
+    samples_per_tick: usize,
+    t: u64,
+) -> (Option<Indication>, Vec<Output>) {
+    let mut output_buffers = module.outputs().iter()
+        .map(|output| Output::from_line_type(output.line_type(), samples_per_tick))
+        .collect::<Vec<_>>();
+
+    let input_refs = module.inputs().iter()
+        .enumerate()
+        .map(|(i, _ty)| InputId(module_id, i))
+        .map(|input_id| {
+            let buffers = if sources.feedback.contains(&input_id) {
+                sources.feedback_buffers
+            } else {
+                sources.buffers
+            };
+
+            sources.connections.get(&input_id)
+                .and_then(|output_id| {
+                    sources.external.get(output_id)
+                        .or_else(|| buffers.get(output_id))
+                })
+                .map(|output| output.as_input_ref())
+                .unwrap_or(InputRef::Disconnected(samples_per_tick))
+        })
+        .collect::<Vec<_>>();
+
+    let mut output_refs = output_buffers.iter_mut()
+        .map(|output| output.as_output_ref())
+        .collect::<Vec<_>>();
+
+    let indication = module.run_tick(t, &input_refs, &mut output_refs);
+
+    (indication, output_buffers)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use tokio::runtime::Handle;
+    use tokio::sync::broadcast::RecvError;
+
+    use mixlab_protocol::{ModuleId, ModuleParams, InputId, OutputId, WorkspaceState, WorkspaceMessage, WorkspaceOp, ServerUpdate, ClientSequence, SavedWorkspace, OscillatorParams, Waveform, AmplifierParams, MidiBinding, MidiControl, MidiControlKind};
+
+    use crate::project::ProjectBase;
+    use crate::util::Sequence;
+    use super::{EngineConfig, EngineHandle, EngineSession, EngineEvents, EngineEvent, OpClock, WorkspaceEmbryo, Automation, Scenes, MidiBindings};
+
+    fn id(id: usize) -> ModuleId {
+        ModuleId(NonZeroUsize::new(id).unwrap())
+    }
+
+    struct Client {
+        engine: EngineHandle,
+        session: EngineSession,
+        events: EngineEvents,
+        sequence: Sequence,
+    }
+
+    impl Client {
+        async fn start() -> Client {
+            let config = EngineConfig { sample_rate: 44100, ticks_per_second: 100 };
+            let base = ProjectBase::stub(config, Handle::current());
+
+            let (workspace, _) = WorkspaceEmbryo::new(SavedWorkspace::default());
+            let (automation, _) = Automation::new(Default::default());
+            let (scenes, _) = Scenes::new(Default::default());
+            let (midi, _) = MidiBindings::new(Default::default());
+
+            let engine = super::start(workspace, automation, scenes, midi, base);
+            let (_, events, session) = engine.connect().await.expect("connect to engine");
+
+            Client { engine, session, events, sequence: Sequence::new() }
+        }
+
+        // applies an op, returning once the engine has processed it
+        async fn apply(&mut self, op: WorkspaceOp) {
+            let sequence = ClientSequence(self.sequence.next());
+            self.session.update(WorkspaceMessage { sequence, op }).expect("send op");
+
+            loop {
+                match self.events.recv().await {
+                    Ok(EngineEvent::Sync(OpClock(session_id, seq))) if session_id == self.session.session_id() && seq == sequence => {
+                        return;
                     }
+                    Ok(_) | Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => panic!("engine stopped"),
                 }
             }
+        }
 
-            for (i, output) in output_buffers.into_iter().enumerate() {
-                buffers.insert(OutputId(*module_id, i), output);
+        // the amplitudes a module is updated to as the engine runs, up to and
+        // including `until`
+        async fn amplitudes_until(&mut self, module_id: ModuleId, until: f64) -> Vec<f64> {
+            let mut amplitudes = Vec::new();
+
+            loop {
+                match self.events.recv().await {
+                    Ok(EngineEvent::ServerUpdate(ServerUpdate::UpdateModuleParams(id, ModuleParams::Amplifier(params)))) if id == module_id => {
+                        amplitudes.push(params.amplitude);
+
+                        if params.amplitude == until {
+                            return amplitudes;
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(_)) => panic!("missed updates"),
+                    Err(RecvError::Closed) => panic!("engine stopped"),
+                }
             }
         }
 
-        indications
+        async fn state(&self) -> WorkspaceState {
+            self.engine.connect().await.expect("connect to engine").0
+        }
+    }
+
+    fn amplitude(state: &WorkspaceState, module_id: ModuleId) -> Option<f64> {
+        state.modules.iter()
+            .find(|(id, _)| *id == module_id)
+            .map(|(_, params)| match params {
+                ModuleParams::Amplifier(params) => params.amplitude,
+                _ => panic!("expected amplifier"),
+            })
+    }
+
+    fn amplifier(amplitude: f64) -> ModuleParams {
+        ModuleParams::Amplifier(AmplifierParams { amplitude, mod_depth: 0.0 })
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn undoes_and_redoes_param_edits() {
+        let mut client = Client::start().await;
+
+        client.apply(WorkspaceOp::CreateModule(amplifier(1.0), Default::default())).await;
+
+        // dragging a fader is one undo step:
+        for amplitude in &[0.8, 0.6, 0.4] {
+            client.apply(WorkspaceOp::UpdateModuleParams(id(1), amplifier(*amplitude))).await;
+        }
+
+        client.apply(WorkspaceOp::Undo).await;
+        assert_eq!(amplitude(&client.state().await, id(1)), Some(1.0));
+
+        client.apply(WorkspaceOp::Redo).await;
+        assert_eq!(amplitude(&client.state().await, id(1)), Some(0.4));
+
+        // a new edit after undoing leaves nothing to redo:
+        client.apply(WorkspaceOp::Undo).await;
+        client.apply(WorkspaceOp::UpdateModuleParams(id(1), amplifier(0.2))).await;
+        client.apply(WorkspaceOp::Redo).await;
+        assert_eq!(amplitude(&client.state().await, id(1)), Some(0.2));
+
+        client.apply(WorkspaceOp::Undo).await;
+        client.apply(WorkspaceOp::Undo).await;
+        assert!(client.state().await.modules.is_empty());
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn undoing_delete_restores_connections() {
+        let mut client = Client::start().await;
+
+        // the oscillator controls the first amplifier, which feeds the
+        // second, which feeds back into the first
+        let oscillator = ModuleParams::Oscillator(OscillatorParams { freq: 440.0, waveform: Waveform::Sine });
+        client.apply(WorkspaceOp::CreateModule(oscillator, Default::default())).await;
+        client.apply(WorkspaceOp::CreateModule(amplifier(0.5), Default::default())).await;
+        client.apply(WorkspaceOp::CreateModule(amplifier(0.25), Default::default())).await;
+
+        client.apply(WorkspaceOp::CreateConnection(InputId(id(2), 1), OutputId(id(1), 0))).await;
+        client.apply(WorkspaceOp::CreateConnection(InputId(id(3), 0), OutputId(id(2), 0))).await;
+        client.apply(WorkspaceOp::CreateFeedbackConnection(InputId(id(2), 0), OutputId(id(3), 0))).await;
+
+        let before = client.state().await;
+        assert_eq!(before.connections.len(), 3);
+
+        client.apply(WorkspaceOp::DeleteModule(id(2))).await;
+
+        let deleted = client.state().await;
+        assert_eq!(deleted.modules.len(), 2);
+        assert!(deleted.connections.is_empty());
+
+        client.apply(WorkspaceOp::Undo).await;
+
+        let mut restored = client.state().await;
+        assert_eq!(amplitude(&restored, id(2)), Some(0.5));
+
+        let mut expected = before.connections.clone();
+        expected.sort();
+        restored.connections.sort();
+        assert_eq!(restored.connections, expected);
+        assert_eq!(restored.feedback, vec![InputId(id(2), 0)]);
+
+        client.apply(WorkspaceOp::Redo).await;
+        assert!(client.state().await.connections.is_empty());
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn recalls_scenes() {
+        let mut client = Client::start().await;
+
+        client.apply(WorkspaceOp::CreateModule(amplifier(1.0), Default::default())).await;
+        client.apply(WorkspaceOp::CaptureScene("loud".to_owned())).await;
+        client.apply(WorkspaceOp::UpdateModuleParams(id(1), amplifier(0.0))).await;
+
+        client.apply(WorkspaceOp::RecallScene("loud".to_owned(), 0)).await;
+        assert_eq!(amplitude(&client.state().await, id(1)), Some(1.0));
+
+        // recalling a scene is one undo step:
+        client.apply(WorkspaceOp::Undo).await;
+        assert_eq!(amplitude(&client.state().await, id(1)), Some(0.0));
+
+        // unknown scenes are ignored:
+        client.apply(WorkspaceOp::RecallScene("quiet".to_owned(), 0)).await;
+        assert_eq!(amplitude(&client.state().await, id(1)), Some(0.0));
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn fades_to_scene_over_ticks() {
+        let mut client = Client::start().await;
+
+        client.apply(WorkspaceOp::CreateModule(amplifier(1.0), Default::default())).await;
+        client.apply(WorkspaceOp::CaptureScene("loud".to_owned())).await;
+        client.apply(WorkspaceOp::UpdateModuleParams(id(1), amplifier(0.0))).await;
+
+        // 40ms is four ticks, each taking a step towards the scene:
+        client.apply(WorkspaceOp::RecallScene("loud".to_owned(), 40)).await;
+
+        let steps = client.amplitudes_until(id(1), 1.0).await;
+        assert_eq!(steps, vec![0.25, 0.5, 0.75, 1.0]);
+        assert_eq!(amplitude(&client.state().await, id(1)), Some(1.0));
+
+        // undoing goes back to where the fade started:
+        client.apply(WorkspaceOp::Undo).await;
+        assert_eq!(amplitude(&client.state().await, id(1)), Some(0.0));
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn applies_midi_input_to_bound_params() {
+        let mut client = Client::start().await;
+
+        client.apply(WorkspaceOp::CreateModule(amplifier(1.0), Default::default())).await;
+
+        let control = MidiControl { device: "nanoKONTROL".to_owned(), channel: 0, kind: MidiControlKind::Cc(7) };
+        let binding = MidiBinding { control: control.clone(), module: id(1), param: "amplitude".to_owned(), min: 0.0, max: 2.0 };
+
+        // params which don't exist can't be bound:
+        client.apply(WorkspaceOp::BindMidi(MidiBinding { param: "cutoff".to_owned(), ..binding.clone() })).await;
+        assert!(client.state().await.midi.is_empty());
+
+        client.apply(WorkspaceOp::BindMidi(binding.clone())).await;
+        assert_eq!(client.state().await.midi, vec![binding]);
+
+        client.apply(WorkspaceOp::MidiInput(control.clone(), 0)).await;
+        assert_eq!(amplitude(&client.state().await, id(1)), Some(0.0));
+
+        client.apply(WorkspaceOp::MidiInput(control.clone(), 127)).await;
+        assert_eq!(amplitude(&client.state().await, id(1)), Some(2.0));
+
+        client.apply(WorkspaceOp::UnbindMidi(id(1), "amplitude".to_owned())).await;
+        client.apply(WorkspaceOp::MidiInput(control, 0)).await;
+        assert_eq!(amplitude(&client.state().await, id(1)), Some(2.0));
     }
 }
@@ -0,0 +1,299 @@
+use std::collections::HashMap;
+
+use tokio::sync::watch;
+
+use mixlab_protocol::{ModuleId, ModuleParams, AutomationLane, AutomationMode, AutomationPoint, AutomationStatus, TransportState};
+
+use crate::persist;
+
+// positions within this many beats of each other are considered the same,
+// to absorb floating point error in the transport's position:
+const POSITION_EPSILON: f64 = 1e-6;
+
+pub struct Automation {
+    lanes: HashMap<ModuleId, AutomationLane>,
+    // end of the span of the timeline covered by the last tick played back,
+    // None if the transport was not playing:
+    played_until: Option<f64>,
+    // lanes recorded into during the current pass, with the position of the
+    // last point recorded:
+    recording: HashMap<ModuleId, f64>,
+    persist_tx: watch::Sender<persist::Automation>,
+}
+
+impl Automation {
+    pub fn new(lanes: persist::Automation) -> (Automation, watch::Receiver<persist::Automation>) {
+        let (persist_tx, persist_rx) = watch::channel(lanes.clone());
+
+        let automation = Automation {
+            lanes,
+            played_until: None,
+            recording: HashMap::new(),
+            persist_tx,
+        };
+
+        (automation, persist_rx)
+    }
+
+    pub fn status(&self) -> Vec<(ModuleId, AutomationStatus)> {
+        self.lanes.iter()
+            .map(|(module_id, lane)| (*module_id, lane.status()))
+            .collect()
+    }
+
+    pub fn set_mode(&mut self, module_id: ModuleId, mode: AutomationMode) -> AutomationStatus {
+        self.end_recording(module_id);
+
+        let lane = self.lanes.entry(module_id).or_default();
+        lane.mode = mode;
+        let status = lane.status();
+
+        self.persist();
+        status
+    }
+
+    pub fn clear(&mut self, module_id: ModuleId) -> AutomationStatus {
+        self.end_recording(module_id);
+
+        let status = match self.lanes.get_mut(&module_id) {
+            Some(lane) => {
+                lane.points.clear();
+                lane.status()
+            }
+            None => AutomationStatus::default(),
+        };
+
+        self.persist();
+        status
+    }
+
+    // drops the lane of a module which has been deleted. lanes are not part
+    // of the undo history, so undoing the delete brings the module back
+    // without its automation
+    pub fn remove(&mut self, module_id: ModuleId) {
+        self.end_recording(module_id);
+
+        if self.lanes.remove(&module_id).is_some() {
+            self.persist();
+        }
+    }
+
+    // records a params change made while the transport is playing, if the
+    // module's lane is armed. points previously recorded over the span of
+    // this pass are replaced
+    pub fn record(&mut self, module_id: ModuleId, transport: &TransportState, params: &ModuleParams) -> Option<AutomationStatus> {
+        if !transport.playing {
+            return None;
+        }
+
+        let lane = self.lanes.get_mut(&module_id)?;
+
+        if lane.mode != AutomationMode::Record {
+            return None;
+        }
+
+        let position = transport.position;
+        let last = self.recording.get(&module_id).copied();
+
+        // replaces points since the last one recorded in this pass, but not
+        // that point itself unless this one is at the same position:
+        lane.points.retain(|point| {
+            let passed = match last {
+                Some(last) => point.position > last + POSITION_EPSILON && point.position < position,
+                None => false,
+            };
+
+            !passed && (point.position - position).abs() > POSITION_EPSILON
+        });
+
+        let index = lane.points.iter()
+            .position(|point| point.position > position)
+            .unwrap_or(lane.points.len());
+
+        lane.points.insert(index, AutomationPoint {
+            position,
+            params: params.clone(),
+        });
+
+        self.recording.insert(module_id, position);
+
+        Some(lane.status())
+    }
+
+    // returns the params to apply for the tick about to run, which covers
+    // `tick_beats` beats of the timeline from the transport's position, along
+    // with the position each should take effect at within the tick
+    pub fn playback(&mut self, transport: &TransportState, tick_beats: f64) -> Vec<(ModuleId, ModuleParams, f64)> {
+        if !transport.playing {
+            self.played_until = None;
+
+            if !self.recording.is_empty() {
+                // transport stopped, the recording pass is over
+                self.recording.clear();
+                self.persist();
+            }
+
+            return Vec::new();
+        }
+
+        let from = transport.position;
+        let until = transport.position + tick_beats;
+
+        // after starting or relocating, chase each lane to the state it
+        // would be in at this position:
+        let chase = match self.played_until {
+            Some(played_until) => (played_until - from).abs() > POSITION_EPSILON,
+            None => true,
+        };
+
+        self.played_until = Some(until);
+
+        let mut params = Vec::new();
+
+        for (module_id, lane) in &self.lanes {
+            if lane.mode != AutomationMode::Play {
+                continue;
+            }
+
+            let points = lane.points.iter()
+                .take_while(|point| point.position < until);
+
+            if chase {
+                // only the latest point matters when chasing, applied from
+                // the start of the tick
+                if let Some(point) = points.last() {
+                    params.push((*module_id, point.params.clone(), point.position.max(from)));
+                }
+            } else {
+                for point in points.filter(|point| point.position >= from - POSITION_EPSILON) {
+                    params.push((*module_id, point.params.clone(), point.position.max(from)));
+                }
+            }
+        }
+
+        params
+    }
+
+    fn end_recording(&mut self, module_id: ModuleId) {
+        self.recording.remove(&module_id);
+    }
+
+    fn persist(&mut self) {
+        let _ = self.persist_tx.broadcast(self.lanes.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use mixlab_protocol::{ModuleId, ModuleParams, AmplifierParams, AutomationLane, AutomationMode, AutomationPoint, TransportState};
+
+    use super::Automation;
+
+    fn id(id: usize) -> ModuleId {
+        ModuleId(NonZeroUsize::new(id).unwrap())
+    }
+
+    fn amplifier(amplitude: f64) -> ModuleParams {
+        ModuleParams::Amplifier(AmplifierParams { amplitude, mod_depth: 0.0 })
+    }
+
+    fn playing(position: f64) -> TransportState {
+        TransportState { tempo: Default::default(), playing: true, position }
+    }
+
+    // the amplitude and position of each param change played back
+    fn played(params: Vec<(ModuleId, ModuleParams, f64)>) -> Vec<(f64, f64)> {
+        params.into_iter()
+            .map(|(_, params, position)| match params {
+                ModuleParams::Amplifier(params) => (params.amplitude, position),
+                _ => panic!("expected amplifier params"),
+            })
+            .collect()
+    }
+
+    fn lane(points: &[(f64, f64)]) -> Automation {
+        let points = points.iter()
+            .map(|(position, amplitude)| AutomationPoint { position: *position, params: amplifier(*amplitude) })
+            .collect();
+
+        let mut lanes = super::persist::Automation::new();
+        lanes.insert(id(1), AutomationLane { mode: AutomationMode::Play, points });
+
+        Automation::new(lanes).0
+    }
+
+    #[test]
+    fn plays_points_within_each_tick() {
+        let mut automation = lane(&[(1.0, 0.1), (1.25, 0.2), (2.0, 0.3)]);
+
+        assert_eq!(played(automation.playback(&playing(0.0), 0.5)), vec![]);
+        assert_eq!(played(automation.playback(&playing(0.5), 0.5)), vec![]);
+
+        // both points in this tick, each at its own position:
+        assert_eq!(played(automation.playback(&playing(1.0), 0.5)), vec![(0.1, 1.0), (0.2, 1.25)]);
+
+        // lanes step from point to point, holding the last value between:
+        assert_eq!(played(automation.playback(&playing(1.5), 0.5)), vec![]);
+        assert_eq!(played(automation.playback(&playing(2.0), 0.5)), vec![(0.3, 2.0)]);
+        assert_eq!(played(automation.playback(&playing(2.5), 0.5)), vec![]);
+    }
+
+    #[test]
+    fn chases_after_locating() {
+        let mut automation = lane(&[(1.0, 0.1), (1.25, 0.2), (2.0, 0.3)]);
+
+        // starting part way through, the lane jumps to its latest point from
+        // the start of the tick:
+        assert_eq!(played(automation.playback(&playing(1.5), 0.5)), vec![(0.2, 1.5)]);
+        assert_eq!(played(automation.playback(&playing(2.0), 0.5)), vec![(0.3, 2.0)]);
+
+        // after jumping back, only the latest point within the tick plays:
+        assert_eq!(played(automation.playback(&playing(1.1), 0.5)), vec![(0.2, 1.25)]);
+
+        // stopping and starting again chases too:
+        let stopped = TransportState { playing: false, ..playing(1.6) };
+        assert_eq!(played(automation.playback(&stopped, 0.25)), vec![]);
+        assert_eq!(played(automation.playback(&playing(1.6), 0.25)), vec![(0.2, 1.6)]);
+    }
+
+    #[test]
+    fn only_plays_lanes_in_play_mode() {
+        let mut automation = lane(&[(0.0, 0.1)]);
+        automation.set_mode(id(1), AutomationMode::Off);
+
+        assert_eq!(played(automation.playback(&playing(0.0), 0.5)), vec![]);
+    }
+
+    #[test]
+    fn records_over_previous_pass() {
+        let mut automation = lane(&[(1.0, 0.1), (2.0, 0.2), (3.0, 0.3)]);
+
+        // not armed:
+        assert!(automation.record(id(1), &playing(1.5), &amplifier(0.9)).is_none());
+
+        automation.set_mode(id(1), AutomationMode::Record);
+
+        // nor playing:
+        let stopped = TransportState { playing: false, ..playing(1.5) };
+        assert!(automation.record(id(1), &stopped, &amplifier(0.9)).is_none());
+
+        // a pass from 1.5 to 2.5 replaces the point at 2.0:
+        automation.record(id(1), &playing(1.5), &amplifier(0.4));
+        let status = automation.record(id(1), &playing(2.5), &amplifier(0.5)).unwrap();
+        assert_eq!(status.points, 4);
+
+        // stopping ends the pass:
+        automation.playback(&stopped, 0.5);
+        automation.set_mode(id(1), AutomationMode::Play);
+
+        let mut ticks = Vec::new();
+
+        for tick in 0..8 {
+            ticks.extend(played(automation.playback(&playing(tick as f64 * 0.5), 0.5)));
+        }
+
+        assert_eq!(ticks, vec![(0.1, 1.0), (0.4, 1.5), (0.5, 2.5), (0.3, 3.0)]);
+    }
+}
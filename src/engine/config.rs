@@ -0,0 +1,139 @@
+use std::time::Duration;
+
+use serde::{Serialize, Deserialize};
+
+// upper bound on tick size, this sizes the static zero buffer handed out for
+// disconnected inputs:
+pub const MAX_SAMPLES_PER_TICK: usize = 8192;
+
+// sample rates supported by our AAC encoder and decoder:
+pub const SUPPORTED_SAMPLE_RATES: &[usize] = &[32000, 44100, 48000, 88200, 96000];
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct EngineConfig {
+    pub sample_rate: usize,
+    pub ticks_per_second: usize,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    UnsupportedSampleRate(usize),
+    // ticks per second must evenly divide sample rate:
+    UnevenTick { sample_rate: usize, ticks_per_second: usize },
+    TickTooLarge { samples_per_tick: usize },
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        EngineConfig {
+            sample_rate: 44100,
+            ticks_per_second: 60,
+        }
+    }
+}
+
+impl EngineConfig {
+    pub fn validate(self) -> Result<Self, ConfigError> {
+        if !SUPPORTED_SAMPLE_RATES.contains(&self.sample_rate) {
+            return Err(ConfigError::UnsupportedSampleRate(self.sample_rate));
+        }
+
+        if self.ticks_per_second == 0 || self.sample_rate % self.ticks_per_second != 0 {
+            return Err(ConfigError::UnevenTick {
+                sample_rate: self.sample_rate,
+                ticks_per_second: self.ticks_per_second,
+            });
+        }
+
+        if self.samples_per_tick() > MAX_SAMPLES_PER_TICK {
+            return Err(ConfigError::TickTooLarge {
+                samples_per_tick: self.samples_per_tick(),
+            });
+        }
+
+        Ok(self)
+    }
+
+    pub fn samples_per_tick(&self) -> usize {
+        self.sample_rate / self.ticks_per_second
+    }
+
+    pub fn tick_budget(&self) -> Duration {
+        Duration::from_micros(1_000_000 / self.ticks_per_second as u64)
+    }
+
+    // sample timestamp at the start of the given tick
+    pub fn tick_time(&self, tick: u64) -> u64 {
+        tick * self.samples_per_tick() as u64
+    }
+}
+
+// overrides for the persisted project configuration, eg. from the command line
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ConfigOverride {
+    pub sample_rate: Option<usize>,
+    pub ticks_per_second: Option<usize>,
+}
+
+impl ConfigOverride {
+    pub fn is_empty(&self) -> bool {
+        self.sample_rate.is_none() && self.ticks_per_second.is_none()
+    }
+
+    pub fn apply(&self, config: EngineConfig) -> EngineConfig {
+        EngineConfig {
+            sample_rate: self.sample_rate.unwrap_or(config.sample_rate),
+            ticks_per_second: self.ticks_per_second.unwrap_or(config.ticks_per_second),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EngineConfig, ConfigError, SUPPORTED_SAMPLE_RATES, MAX_SAMPLES_PER_TICK};
+
+    fn config(sample_rate: usize, ticks_per_second: usize) -> EngineConfig {
+        EngineConfig { sample_rate, ticks_per_second }
+    }
+
+    #[test]
+    fn accepts_supported_rates() {
+        for sample_rate in SUPPORTED_SAMPLE_RATES {
+            let valid = config(*sample_rate, 50).validate().expect("valid config");
+            assert_eq!(valid.samples_per_tick(), sample_rate / 50);
+        }
+
+        assert!(EngineConfig::default().validate().is_ok());
+    }
+
+    #[test]
+    fn rejects_unsupported_rate() {
+        match config(22050, 50).validate() {
+            Err(ConfigError::UnsupportedSampleRate(22050)) => {}
+            result => panic!("expected unsupported sample rate, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn rejects_uneven_ticks() {
+        match config(44100, 64).validate() {
+            Err(ConfigError::UnevenTick { sample_rate: 44100, ticks_per_second: 64 }) => {}
+            result => panic!("expected uneven tick, got {:?}", result),
+        }
+
+        assert!(matches!(config(44100, 0).validate(), Err(ConfigError::UnevenTick { .. })));
+    }
+
+    #[test]
+    fn rejects_large_ticks() {
+        // 96000 / 10 = 9600 samples per tick:
+        match config(96000, 10).validate() {
+            Err(ConfigError::TickTooLarge { samples_per_tick: 9600 }) => {}
+            result => panic!("expected tick too large, got {:?}", result),
+        }
+
+        // right at the limit is fine, 32000 / 4 = 8000:
+        assert!(config(32000, 4).samples_per_tick() <= MAX_SAMPLES_PER_TICK);
+        assert!(config(32000, 4).validate().is_ok());
+    }
+}
@@ -0,0 +1,251 @@
+use mixlab_protocol::{ModuleParams, Indication, Terminal, LineType, CvBinding};
+
+use crate::engine::{InputRef, OutputRef};
+use crate::engine::module::{DynModuleHost, DynModuleHostT};
+
+// cv is read every this many samples into the tick:
+const CV_INTERVAL: usize = 64;
+
+// Binds numeric params of a module to mono control inputs, which are appended
+// after the module's own inputs. Each bound param with a connected input
+// follows `offset + depth * cv`. The cv is read every CV_INTERVAL samples, and
+// whenever the resulting values have changed they are handed to the module as
+// a params update at that offset into the tick. Steady cv costs nothing.
+//
+// The params reported and persisted are always the unmodulated ones, as last
+// set from outside.
+pub struct CvHost {
+    module: DynModuleHost,
+    bindings: Vec<CvBinding>,
+    inputs: Vec<Terminal>,
+    // set while any binding is modulating the module's params:
+    base: Option<ModuleParams>,
+    // the value each binding last applied, None where its input is
+    // disconnected. emptied to have the values applied afresh
+    applied: Vec<Option<f64>>,
+}
+
+impl CvHost {
+    pub fn new(module: DynModuleHost) -> Self {
+        let mut host = CvHost {
+            module,
+            bindings: Vec::new(),
+            inputs: Vec::new(),
+            base: None,
+            applied: Vec::new(),
+        };
+
+        host.update_inputs();
+        host
+    }
+
+    fn update_inputs(&mut self) {
+        self.inputs = self.module.inputs().to_vec();
+
+        self.inputs.extend(self.bindings.iter()
+            .map(|binding| LineType::Mono.labeled(&binding.param)));
+    }
+
+    fn modulate(&mut self, cv: &[InputRef]) -> Option<Indication> {
+        let samples = cv.iter()
+            .find(|input| input.connected())
+            .map(|input| input.expect_mono().len())
+            .unwrap_or(1);
+
+        let mut indication = None;
+
+        for offset in (0..samples).step_by(CV_INTERVAL) {
+            let values = self.bindings.iter()
+                .zip(cv)
+                .map(|(binding, input)| {
+                    if !input.connected() {
+                        return None;
+                    }
+
+                    let value = *input.expect_mono().get(offset)? as f64;
+                    Some(binding.offset + binding.depth * value)
+                })
+                .collect::<Vec<_>>();
+
+            if values != self.applied {
+                self.applied = values;
+                indication = self.apply(offset).or(indication);
+            }
+        }
+
+        indication
+    }
+
+    fn apply(&mut self, offset: usize) -> Option<Indication> {
+        if self.applied.iter().all(Option::is_none) {
+            // nothing modulating any more, put the params back as they were:
+            let base = self.base.take()?;
+            return self.module.update(base, offset);
+        }
+
+        let mut params = match &self.base {
+            Some(base) => base.clone(),
+            None => {
+                let base = self.module.params();
+                self.base = Some(base.clone());
+                base
+            }
+        };
+
+        for (binding, value) in self.bindings.iter().zip(&self.applied) {
+            if let Some(value) = value {
+                params.set_numeric_param(&binding.param, *value);
+            }
+        }
+
+        self.module.update(params, offset)
+    }
+}
+
+impl DynModuleHostT for CvHost {
+    fn params(&self) -> ModuleParams {
+        match &self.base {
+            Some(base) => base.clone(),
+            None => self.module.params(),
+        }
+    }
+
+    fn update(&mut self, new_params: ModuleParams, offset: usize) -> Option<Indication> {
+        if self.base.is_some() {
+            // modulated params are applied over the new params next tick
+            self.base = Some(new_params.clone());
+            self.applied.clear();
+        }
+
+        let indication = self.module.update(new_params, offset);
+        self.update_inputs();
+        indication
+    }
+
+    fn run_tick(&mut self, t: u64, inputs: &[InputRef], outputs: &mut [OutputRef]) -> Option<Indication> {
+        let own_inputs = self.module.inputs().len().min(inputs.len());
+        let (inputs, cv) = inputs.split_at(own_inputs);
+
+        let indication = if self.bindings.is_empty() && self.base.is_none() {
+            None
+        } else {
+            self.modulate(cv)
+        };
+
+        self.module.run_tick(t, inputs, outputs).or(indication)
+    }
+
+    fn inputs(&self) -> &[Terminal] {
+        &self.inputs
+    }
+
+    fn outputs(&self) -> &[Terminal] {
+        self.module.outputs()
+    }
+
+    fn cv(&self) -> &[CvBinding] {
+        &self.bindings
+    }
+
+    fn set_cv(&mut self, bindings: Vec<CvBinding>) {
+        self.bindings = bindings;
+        self.applied.clear();
+        self.update_inputs();
+    }
+
+    fn restore_terminals(&mut self, inputs: Vec<Terminal>, outputs: Vec<Terminal>) {
+        self.module.restore_terminals(inputs, outputs);
+        self.update_inputs();
+    }
+
+    fn finished(&self) -> Option<bool> {
+        self.module.finished()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use mixlab_protocol::{ModuleParams, AmplifierParams, Indication, Terminal, LineType, CvBinding};
+
+    use crate::engine::{InputRef, OutputRef, Sample};
+    use crate::engine::module::DynModuleHostT;
+    use super::CvHost;
+
+    // records the params updates it is given, with their offsets
+    struct Recorder {
+        params: ModuleParams,
+        updates: Arc<Mutex<Vec<(f64, usize)>>>,
+        inputs: Vec<Terminal>,
+    }
+
+    impl DynModuleHostT for Recorder {
+        fn params(&self) -> ModuleParams {
+            self.params.clone()
+        }
+
+        fn update(&mut self, params: ModuleParams, offset: usize) -> Option<Indication> {
+            if let ModuleParams::Amplifier(amplifier) = &params {
+                self.updates.lock().unwrap().push((amplifier.amplitude, offset));
+            }
+
+            self.params = params;
+            None
+        }
+
+        fn run_tick(&mut self, _: u64, _: &[InputRef], _: &mut [OutputRef]) -> Option<Indication> {
+            None
+        }
+
+        fn inputs(&self) -> &[Terminal] {
+            &self.inputs
+        }
+
+        fn outputs(&self) -> &[Terminal] {
+            &[]
+        }
+    }
+
+    fn run(host: &mut CvHost, input: &[Sample], cv: InputRef) {
+        host.run_tick(0, &[InputRef::Stereo(input), cv], &mut []);
+    }
+
+    #[test]
+    fn applies_only_changes_in_cv() {
+        let updates = Arc::new(Mutex::new(Vec::new()));
+
+        let mut host = CvHost::new(Box::new(Recorder {
+            params: ModuleParams::Amplifier(AmplifierParams { amplitude: 1.0, mod_depth: 0.0 }),
+            updates: updates.clone(),
+            inputs: vec![LineType::Stereo.unlabeled()],
+        }));
+
+        host.set_cv(vec![CvBinding { param: "amplitude".to_owned(), depth: 0.5, offset: 0.0 }]);
+
+        let input = vec![0.0; 256];
+        let steady = vec![1.0; 128];
+
+        let mut step = vec![1.0; 128];
+        for sample in &mut step[40..] {
+            *sample = 0.0;
+        }
+
+        run(&mut host, &input, InputRef::Mono(&steady));
+        run(&mut host, &input, InputRef::Mono(&steady));
+        assert_eq!(*updates.lock().unwrap(), vec![(0.5, 0)]);
+
+        // the change is picked up at the next read after it, 64 samples in:
+        run(&mut host, &input, InputRef::Mono(&step));
+        assert_eq!(*updates.lock().unwrap(), vec![(0.5, 0), (0.0, 64)]);
+
+        // once disconnected the params go back as they were set:
+        run(&mut host, &input, InputRef::Disconnected(128));
+        assert_eq!(*updates.lock().unwrap(), vec![(0.5, 0), (0.0, 64), (1.0, 0)]);
+
+        match host.params() {
+            ModuleParams::Amplifier(params) => assert_eq!(params.amplitude, 1.0),
+            _ => panic!("expected amplifier params"),
+        }
+    }
+}
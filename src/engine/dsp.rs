@@ -0,0 +1,23 @@
+use crate::engine::Sample;
+
+// Helpers shared by the dynamics modules (compressor, gate) for following
+// the level of a signal.
+
+// levels are floored here, so that silence is finite in decibels:
+const FLOOR_DB: f64 = -120.0;
+
+// peak level of a frame in decibels
+pub fn level_db(frame: &[Sample]) -> f64 {
+    let peak = frame.iter().fold(0.0, |peak: f64, sample| peak.max(sample.abs() as f64));
+    (20.0 * peak.log10()).max(FLOOR_DB)
+}
+
+// coefficient for a one pole smoother to move most of the way (1 - 1/e) to a
+// new value in the given time. zero times are instant
+pub fn time_coefficient(ms: f64, sample_rate: usize) -> f64 {
+    if ms <= 0.0 {
+        0.0
+    } else {
+        f64::exp(-1000.0 / (ms * sample_rate as f64))
+    }
+}
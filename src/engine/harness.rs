@@ -0,0 +1,305 @@
+use std::env;
+use std::f64;
+use std::fs;
+use std::path::PathBuf;
+
+use serde::Serialize;
+use serde_json::{json, Value};
+use tokio::runtime::{self, Runtime};
+use tokio::sync::mpsc;
+
+use mixlab_protocol::TransportOp;
+
+use crate::engine::{EngineConfig, InputRef, Output, ModuleCtx, Sample, CHANNELS};
+use crate::module::ModuleT;
+use crate::project::{ProjectBase, ProjectBaseRef};
+
+// Runs a single module outside the engine for tests. Time only moves when
+// tick is called: each tick runs at `t = ticks * samples_per_tick` and the
+// transport is advanced after it, just as the engine does, so runs are
+// reproducible regardless of how fast the test machine is.
+//
+// Everything the module does is recorded (the indication it was created with,
+// param updates, events, transport ops, and the outputs and indication of
+// each tick)
+// and can be compared against a golden fixture with assert_fixture.
+// Fixtures live in fixtures/module/<name>.json. Running tests with
+// MIXLAB_BLESS=1 writes them, for new tests or after an intentional change in
+// output. Otherwise a missing fixture fails the test, so that one which was
+// never committed can't pass silently.
+
+// samples may differ by this much from the fixture, to allow for differences
+// in floating point between platforms:
+const TOLERANCE: f64 = 1e-5;
+
+pub struct Harness<M: ModuleT> {
+    module: M,
+    events: mpsc::Receiver<M::Event>,
+    base: ProjectBaseRef,
+    // only driven by receive_event, see there:
+    runtime: Runtime,
+    ticks: u64,
+    // returned by the module from the most recent tick:
+    indication: Option<M::Indication>,
+    capture: Vec<Value>,
+}
+
+impl<M: ModuleT> Harness<M> where M::Indication: Serialize {
+    pub fn new(params: M::Params) -> Self {
+        Self::with_config(params, EngineConfig {
+            sample_rate: 44100,
+            // small ticks keep fixtures small:
+            ticks_per_second: 1050,
+        })
+    }
+
+    pub fn with_config(params: M::Params, config: EngineConfig) -> Self {
+        let config = config.validate().expect("valid engine config");
+
+        let runtime = runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .expect("build tokio runtime");
+
+        let base = ProjectBase::stub(config, runtime.handle().clone());
+
+        let (module, events, indication) = runtime.enter(|| {
+            let (ctx, events) = ModuleCtx::new(base.clone());
+            let (module, indication) = M::create(params, ctx);
+            (module, events, indication)
+        });
+
+        let mut harness = Harness {
+            module,
+            events,
+            base,
+            runtime,
+            ticks: 0,
+            indication: None,
+            capture: Vec::new(),
+        };
+
+        harness.record(json!({ "create": capture_indication(Some(indication)) }));
+        harness
+    }
+
+    pub fn module(&mut self) -> &mut M {
+        &mut self.module
+    }
+
+    pub fn samples_per_tick(&self) -> usize {
+        self.base.config().samples_per_tick()
+    }
+
+    // current position of the virtual clock in samples
+    pub fn t(&self) -> u64 {
+        self.ticks * self.samples_per_tick() as u64
+    }
+
+    pub fn indication(&self) -> Option<&M::Indication> {
+        self.indication.as_ref()
+    }
+
+    pub fn disconnected(&self) -> InputRef<'static> {
+        InputRef::Disconnected(self.samples_per_tick())
+    }
+
+    pub fn update(&mut self, params: M::Params) -> Option<M::Indication> {
+        self.update_at(params, 0)
+    }
+
+    pub fn update_at(&mut self, params: M::Params, offset: usize) -> Option<M::Indication> {
+        let module = &mut self.module;
+        let indication = self.runtime.enter(|| module.update_at(params, offset));
+
+        self.record(json!({
+            "update": {
+                "offset": offset,
+                "indication": capture_indication(indication.as_ref()),
+            }
+        }));
+
+        indication
+    }
+
+    // applied between ticks, as the engine does
+    pub fn transport(&mut self, op: TransportOp) {
+        self.base.transport().apply(op);
+        self.record(json!({ "transport": op }));
+    }
+
+    // tasks the module spawns only make progress while this waits, so events
+    // arrive at the same tick on every run
+    pub fn receive_event(&mut self) {
+        let events = &mut self.events;
+        let ev = self.runtime.block_on(events.recv()).expect("module link closed");
+
+        let module = &mut self.module;
+        self.runtime.enter(|| module.receive_event(ev));
+
+        self.record(json!("event"));
+    }
+
+    // runs one tick with the given inputs, returning the module's outputs
+    pub fn tick(&mut self, inputs: &[InputRef]) -> Vec<Output> {
+        let samples_per_tick = self.samples_per_tick();
+        let t = self.t();
+
+        let mut outputs = self.module.outputs().iter()
+            .map(|terminal| Output::from_line_type(terminal.line_type(), samples_per_tick))
+            .collect::<Vec<_>>();
+
+        let module = &mut self.module;
+        let events = &mut self.events;
+
+        let indication = self.runtime.enter(|| {
+            if let Ok(ev) = events.try_recv() {
+                module.receive_event(ev);
+            }
+
+            let mut output_refs = outputs.iter_mut()
+                .map(|output| output.as_output_ref())
+                .collect::<Vec<_>>();
+
+            module.run_tick(t, inputs, &mut output_refs)
+        });
+
+        self.base.transport().advance(samples_per_tick);
+        self.ticks += 1;
+
+        self.record(json!({
+            "tick": {
+                "t": t,
+                "indication": capture_indication(indication.as_ref()),
+                "outputs": outputs.iter().map(capture_output).collect::<Vec<_>>(),
+            }
+        }));
+
+        self.indication = indication;
+        outputs
+    }
+
+    // runs a number of ticks with all inputs disconnected
+    pub fn idle(&mut self, ticks: usize) {
+        let inputs = vec![self.disconnected(); self.module.inputs().len()];
+
+        for _ in 0..ticks {
+            self.tick(&inputs);
+        }
+    }
+
+    // a tick's worth of sine wave, continuing on from the previous tick
+    pub fn sine(&self, freq: f64, amplitude: f64) -> Vec<Sample> {
+        let sample_rate = self.base.config().sample_rate as f64;
+
+        (0..self.samples_per_tick() as u64)
+            .map(|i| (self.t() + i) as f64 / sample_rate)
+            .map(|secs| (amplitude * f64::sin(2.0 * f64::consts::PI * freq * secs)) as Sample)
+            .collect()
+    }
+
+    fn record(&mut self, entry: Value) {
+        self.capture.push(entry);
+    }
+
+    pub fn assert_fixture(&self, name: &str) {
+        let config = self.base.config();
+
+        let actual = json!({
+            "sample_rate": config.sample_rate,
+            "ticks_per_second": config.ticks_per_second,
+            "log": self.capture,
+        });
+
+        let path = fixture_path(name);
+        let bless = env::var_os("MIXLAB_BLESS").is_some();
+
+        if bless {
+            let json = serde_json::to_string_pretty(&actual).expect("serialize capture");
+            fs::create_dir_all(path.parent().unwrap()).expect("create fixture directory");
+            fs::write(&path, json + "\n").expect("write fixture");
+            return;
+        }
+
+        if !path.exists() {
+            panic!("missing fixture {}\n\
+                (run with MIXLAB_BLESS=1 to write it)", path.display());
+        }
+
+        let expected = fs::read(&path).expect("read fixture");
+        let expected = serde_json::from_slice::<Value>(&expected).expect("parse fixture");
+
+        if let Err(at) = compare(&expected, &actual, String::new()) {
+            panic!("module output differs from fixture {} at {}\n\
+                (run with MIXLAB_BLESS=1 to update the fixture if this is intended)",
+                path.display(), at);
+        }
+    }
+}
+
+pub fn stereo(mono: &[Sample]) -> Vec<Sample> {
+    mono.iter()
+        .flat_map(|sample| std::iter::repeat(*sample).take(CHANNELS))
+        .collect()
+}
+
+fn fixture_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("fixtures/module")
+        .join(format!("{}.json", name))
+}
+
+fn capture_indication<I: Serialize>(indication: Option<I>) -> Value {
+    serde_json::to_value(indication).expect("serialize indication")
+}
+
+fn capture_output(output: &Output) -> Value {
+    match output {
+        Output::Mono(samples) => json!({ "mono": samples }),
+        Output::Stereo(samples) => json!({ "stereo": samples }),
+        Output::Multi(channels, samples) => json!({ "multi": { "channels": channels, "samples": samples } }),
+        Output::Video(frame) => json!({ "video": frame.is_some() }),
+        Output::Events(events) => json!({ "events": events }),
+    }
+}
+
+// like equality, but with some leeway for numbers. returns the path to the
+// first difference
+fn compare(expected: &Value, actual: &Value, path: String) -> Result<(), String> {
+    match (expected, actual) {
+        (Value::Number(expected), Value::Number(actual)) => {
+            match (expected.as_f64(), actual.as_f64()) {
+                (Some(expected), Some(actual)) if (expected - actual).abs() <= TOLERANCE => Ok(()),
+                _ => Err(format!("{}: expected {}, got {}", path, expected, actual)),
+            }
+        }
+        (Value::Array(expected), Value::Array(actual)) => {
+            if expected.len() != actual.len() {
+                return Err(format!("{}: expected {} elements, got {}", path, expected.len(), actual.len()));
+            }
+
+            for (i, (expected, actual)) in expected.iter().zip(actual).enumerate() {
+                compare(expected, actual, format!("{}[{}]", path, i))?;
+            }
+
+            Ok(())
+        }
+        (Value::Object(expected), Value::Object(actual)) => {
+            for (key, expected) in expected {
+                let actual = actual.get(key)
+                    .ok_or_else(|| format!("{}.{}: missing", path, key))?;
+
+                compare(expected, actual, format!("{}.{}", path, key))?;
+            }
+
+            if let Some(key) = actual.keys().find(|key| !expected.contains_key(*key)) {
+                return Err(format!("{}.{}: unexpected", path, key));
+            }
+
+            Ok(())
+        }
+        (expected, actual) if expected == actual => Ok(()),
+        (expected, actual) => Err(format!("{}: expected {}, got {}", path, expected, actual)),
+    }
+}
@@ -0,0 +1,250 @@
+use std::collections::{HashMap, VecDeque};
+
+use mixlab_protocol::{ModuleId, ModuleParams, WindowGeometry, InputId, OutputId, CvBinding};
+
+use crate::engine::SessionId;
+
+// maximum number of undo steps kept per session:
+const MAX_UNDO: usize = 100;
+
+// primitive workspace changes. every WorkspaceOp applies as one or more edits,
+// and applying an edit yields the edits which revert it
+#[derive(Debug, Clone)]
+pub enum Edit {
+    CreateModule { id: ModuleId, params: ModuleParams, geometry: WindowGeometry },
+    UpdateModuleParams(ModuleId, ModuleParams),
+    UpdateWindowGeometry(ModuleId, WindowGeometry),
+    // also deletes all connections to and from the module:
+    DeleteModule(ModuleId),
+    Connect { input: InputId, output: OutputId, feedback: bool },
+    Disconnect(InputId),
+    // also deletes connections to cv inputs which go away or change param:
+    SetCv(ModuleId, Vec<CvBinding>),
+}
+
+// continuous changes like dragging a fader or a window arrive as a stream of
+// ops. consecutive ops with the same key coalesce into a single undo step
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Coalesce {
+    ModuleParams(ModuleId),
+    WindowGeometry(ModuleId),
+}
+
+#[derive(Default)]
+pub struct History {
+    sessions: HashMap<SessionId, SessionHistory>,
+}
+
+#[derive(Default)]
+struct SessionHistory {
+    undo: VecDeque<Vec<Edit>>,
+    redo: Vec<Vec<Edit>>,
+    last_coalesce: Option<Coalesce>,
+}
+
+impl History {
+    // records the inverse of a new change made by a session
+    pub fn record(&mut self, session_id: SessionId, inverse: Vec<Edit>, coalesce: Option<Coalesce>) {
+        if inverse.is_empty() {
+            return;
+        }
+
+        let session = self.sessions.entry(session_id).or_default();
+
+        session.redo.clear();
+
+        if coalesce.is_some() && coalesce == session.last_coalesce && !session.undo.is_empty() {
+            // the undo step already on top of the stack restores the state
+            // from before this run of changes began
+            return;
+        }
+
+        session.last_coalesce = coalesce;
+        session.push_undo(inverse);
+    }
+
+    pub fn take_undo(&mut self, session_id: SessionId) -> Option<Vec<Edit>> {
+        let session = self.sessions.get_mut(&session_id)?;
+        session.last_coalesce = None;
+        session.undo.pop_back()
+    }
+
+    pub fn take_redo(&mut self, session_id: SessionId) -> Option<Vec<Edit>> {
+        let session = self.sessions.get_mut(&session_id)?;
+        session.last_coalesce = None;
+        session.redo.pop()
+    }
+
+    // records the inverse of an undo step
+    pub fn push_redo(&mut self, session_id: SessionId, inverse: Vec<Edit>) {
+        if !inverse.is_empty() {
+            self.sessions.entry(session_id).or_default().redo.push(inverse);
+        }
+    }
+
+    // records the inverse of a redo step, leaving the rest of the redo stack
+    // intact
+    pub fn push_undo(&mut self, session_id: SessionId, inverse: Vec<Edit>) {
+        if !inverse.is_empty() {
+            self.sessions.entry(session_id).or_default().push_undo(inverse);
+        }
+    }
+
+    pub fn remove_session(&mut self, session_id: SessionId) {
+        self.sessions.remove(&session_id);
+    }
+}
+
+impl SessionHistory {
+    fn push_undo(&mut self, inverse: Vec<Edit>) {
+        if self.undo.len() == MAX_UNDO {
+            self.undo.pop_front();
+        }
+
+        self.undo.push_back(inverse);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use mixlab_protocol::{ModuleId, InputId};
+
+    use crate::engine::SessionId;
+    use super::{History, Edit, Coalesce, MAX_UNDO};
+
+    fn session(id: usize) -> SessionId {
+        SessionId(NonZeroUsize::new(id).unwrap())
+    }
+
+    fn id(id: usize) -> ModuleId {
+        ModuleId(NonZeroUsize::new(id).unwrap())
+    }
+
+    // a distinguishable undo step
+    fn step(n: usize) -> Vec<Edit> {
+        vec![Edit::Disconnect(InputId(id(n), 0))]
+    }
+
+    fn which(edits: Option<Vec<Edit>>) -> Option<usize> {
+        match edits?.as_slice() {
+            [Edit::Disconnect(InputId(module_id, 0))] => Some(module_id.0.get()),
+            edits => panic!("unexpected edits: {:?}", edits),
+        }
+    }
+
+    #[test]
+    fn undo_then_redo() {
+        let mut history = History::default();
+        let s = session(1);
+
+        history.record(s, step(1), None);
+        history.record(s, step(2), None);
+
+        assert_eq!(which(history.take_undo(s)), Some(2));
+        history.push_redo(s, step(3));
+
+        assert_eq!(which(history.take_redo(s)), Some(3));
+        history.push_undo(s, step(4));
+
+        assert_eq!(which(history.take_undo(s)), Some(4));
+        assert_eq!(which(history.take_undo(s)), Some(1));
+        assert_eq!(which(history.take_undo(s)), None);
+        assert_eq!(which(history.take_redo(s)), None);
+    }
+
+    #[test]
+    fn coalesces_runs_of_the_same_param() {
+        let mut history = History::default();
+        let s = session(1);
+        let fader = Some(Coalesce::ModuleParams(id(9)));
+
+        // only the first inverse of the run is kept, as it restores the state
+        // from before the run began:
+        history.record(s, step(1), fader);
+        history.record(s, step(2), fader);
+        history.record(s, step(3), fader);
+
+        // a different module starts a new step:
+        history.record(s, step(4), Some(Coalesce::ModuleParams(id(8))));
+        history.record(s, step(5), Some(Coalesce::ModuleParams(id(8))));
+
+        // and so does the same module after something else:
+        history.record(s, step(6), None);
+        history.record(s, step(7), fader);
+
+        assert_eq!(which(history.take_undo(s)), Some(7));
+
+        // after an undo, the next change can't coalesce into the step below:
+        history.record(s, step(8), fader);
+
+        assert_eq!(which(history.take_undo(s)), Some(8));
+        assert_eq!(which(history.take_undo(s)), Some(6));
+        assert_eq!(which(history.take_undo(s)), Some(4));
+        assert_eq!(which(history.take_undo(s)), Some(1));
+        assert_eq!(which(history.take_undo(s)), None);
+    }
+
+    #[test]
+    fn new_edit_clears_redo() {
+        let mut history = History::default();
+        let s = session(1);
+
+        history.record(s, step(1), None);
+        history.take_undo(s);
+        history.push_redo(s, step(2));
+
+        history.record(s, step(3), None);
+        assert_eq!(which(history.take_redo(s)), None);
+
+        // but redoing keeps the rest of the redo stack:
+        history.record(s, step(4), None);
+        history.take_undo(s);
+        history.push_redo(s, step(5));
+        history.take_undo(s);
+        history.push_redo(s, step(6));
+
+        assert_eq!(which(history.take_redo(s)), Some(6));
+        history.push_undo(s, step(7));
+        assert_eq!(which(history.take_redo(s)), Some(5));
+    }
+
+    #[test]
+    fn caps_undo_steps() {
+        let mut history = History::default();
+        let s = session(1);
+
+        for n in 1..=(MAX_UNDO + 50) {
+            history.record(s, step(n), None);
+        }
+
+        // the oldest steps are dropped:
+        for n in (51..=(MAX_UNDO + 50)).rev() {
+            assert_eq!(which(history.take_undo(s)), Some(n));
+        }
+
+        assert_eq!(which(history.take_undo(s)), None);
+    }
+
+    #[test]
+    fn sessions_have_their_own_stacks() {
+        let mut history = History::default();
+        let fader = Some(Coalesce::ModuleParams(id(9)));
+
+        history.record(session(1), step(1), fader);
+        history.record(session(2), step(2), fader);
+        history.record(session(1), step(3), fader);
+
+        // the other session's change didn't break session 1's run:
+        assert_eq!(which(history.take_undo(session(1))), Some(1));
+        assert_eq!(which(history.take_undo(session(1))), None);
+
+        history.push_redo(session(1), step(4));
+        history.record(session(2), step(5), None);
+        assert_eq!(which(history.take_redo(session(1))), Some(4));
+
+        history.remove_session(session(2));
+        assert_eq!(which(history.take_undo(session(2))), None);
+    }
+}
@@ -1,12 +1,14 @@
-use mixlab_protocol::LineType;
+use mixlab_protocol::{LineType, MAX_CHANNELS};
+use serde::Serialize;
 use mixlab_util::time::MediaDuration;
 
-use crate::engine::{CHANNELS, SAMPLES_PER_TICK};
+use crate::engine::CHANNELS;
 use crate::engine::Sample;
+use crate::engine::config::MAX_SAMPLES_PER_TICK;
 use crate::video;
 
-pub static ZERO_BUFFER_STEREO: [Sample; SAMPLES_PER_TICK * CHANNELS] = [0.0; SAMPLES_PER_TICK * CHANNELS];
-pub static ZERO_BUFFER_MONO: [Sample; SAMPLES_PER_TICK] = [0.0; SAMPLES_PER_TICK];
+// disconnected inputs read from a slice of this buffer sized to the tick:
+static ZERO_BUFFER: [Sample; MAX_SAMPLES_PER_TICK * MAX_CHANNELS] = [0.0; MAX_SAMPLES_PER_TICK * MAX_CHANNELS];
 
 #[derive(Debug, Clone)]
 pub struct VideoFrame {
@@ -16,63 +18,136 @@ pub struct VideoFrame {
     pub tick_offset: MediaDuration,
 }
 
+// a message on an event line, timestamped in samples after the enclosing tick
+// begins. events on a line are in order of offset
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct TimedEvent {
+    pub offset: usize,
+    pub message: EventMessage,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum EventMessage {
+    // velocity and cc values are 0-127, as in midi
+    NoteOn { note: u8, velocity: u8 },
+    NoteOff { note: u8 },
+    Bang,
+    Cc { controller: u8, value: u8 },
+}
+
+#[derive(Clone, Copy)]
 pub enum InputRef<'a> {
-    Disconnected,
+    // carries the number of samples per channel in the current tick
+    Disconnected(usize),
     Mono(&'a [Sample]),
     Stereo(&'a [Sample]),
+    // carries the number of channels interleaved in the buffer
+    Multi(usize, &'a [Sample]),
     Video(Option<&'a VideoFrame>),
+    Events(&'a [TimedEvent]),
 }
 
 impl<'a> InputRef<'a> {
     pub fn connected(&self) -> bool {
         match self {
-            InputRef::Disconnected => false,
+            InputRef::Disconnected(_) => false,
             InputRef::Mono(_) |
             InputRef::Stereo(_) |
-            InputRef::Video(_) => true,
+            InputRef::Multi(..) |
+            InputRef::Video(_) |
+            InputRef::Events(_) => true,
         }
     }
 
     pub fn expect_mono(&self) -> &'a [Sample] {
         match self {
-            InputRef::Disconnected => &ZERO_BUFFER_MONO,
+            InputRef::Disconnected(samples) => &ZERO_BUFFER[0..*samples],
             InputRef::Mono(buff) => buff,
             InputRef::Stereo(_) => panic!("expected mono input, got stereo"),
+            InputRef::Multi(..) => panic!("expected mono input, got multi-channel"),
             InputRef::Video(_) => panic!("expected mono input, got avc"),
+            InputRef::Events(_) => panic!("expected mono input, got events"),
         }
     }
 
     pub fn expect_stereo(&self) -> &'a [Sample] {
         match self {
-            InputRef::Disconnected => &ZERO_BUFFER_STEREO,
+            InputRef::Disconnected(_) => self.expect_multi(CHANNELS),
             InputRef::Stereo(buff) => buff,
             InputRef::Mono(_) => panic!("expected stereo input, got mono"),
+            InputRef::Multi(..) => panic!("expected stereo input, got multi-channel"),
             InputRef::Video(_) => panic!("expected stereo input, got avc"),
+            InputRef::Events(_) => panic!("expected stereo input, got events"),
+        }
+    }
+
+    pub fn expect_multi(&self, channels: usize) -> &'a [Sample] {
+        match self {
+            InputRef::Disconnected(samples) => {
+                assert!(channels <= MAX_CHANNELS, "too many channels: {}", channels);
+                &ZERO_BUFFER[0..(*samples * channels)]
+            }
+            InputRef::Multi(buff_channels, buff) if *buff_channels == channels => buff,
+            InputRef::Multi(buff_channels, _) => panic!("expected {} channel input, got {} channels", channels, buff_channels),
+            InputRef::Mono(_) => panic!("expected multi-channel input, got mono"),
+            InputRef::Stereo(_) => panic!("expected multi-channel input, got stereo"),
+            InputRef::Video(_) => panic!("expected multi-channel input, got avc"),
+            InputRef::Events(_) => panic!("expected multi-channel input, got events"),
         }
     }
 
     pub fn expect_video(&self) -> Option<&VideoFrame> {
         match self {
-            InputRef::Disconnected => None,
+            InputRef::Disconnected(_) => None,
             InputRef::Stereo(_) => panic!("expected stereo input, got stereo"),
             InputRef::Mono(_) => panic!("expected stereo input, got mono"),
+            InputRef::Multi(..) => panic!("expected video input, got multi-channel"),
             InputRef::Video(frame) => *frame,
+            InputRef::Events(_) => panic!("expected video input, got events"),
+        }
+    }
+
+    pub fn expect_events(&self) -> &'a [TimedEvent] {
+        match self {
+            InputRef::Disconnected(_) => &[],
+            InputRef::Events(events) => events,
+            InputRef::Mono(_) => panic!("expected event input, got mono"),
+            InputRef::Stereo(_) => panic!("expected event input, got stereo"),
+            InputRef::Multi(..) => panic!("expected event input, got multi-channel"),
+            InputRef::Video(_) => panic!("expected event input, got video"),
         }
     }
 }
 
+#[derive(Clone)]
 pub enum Output {
     Mono(Vec<Sample>),
     Stereo(Vec<Sample>),
+    Multi(usize, Vec<Sample>),
     Video(Option<VideoFrame>),
+    Events(Vec<TimedEvent>),
 }
 
 impl Output {
-    pub fn from_line_type(line_type: LineType) -> Output {
+    pub fn from_line_type(line_type: LineType, samples_per_tick: usize) -> Output {
         match line_type {
-            LineType::Mono => Output::Mono(vec![0.0; SAMPLES_PER_TICK]),
-            LineType::Stereo => Output::Stereo(vec![0.0; SAMPLES_PER_TICK * CHANNELS]),
+            LineType::Mono => Output::Mono(vec![0.0; samples_per_tick]),
+            LineType::Stereo => Output::Stereo(vec![0.0; samples_per_tick * CHANNELS]),
+            LineType::Multi(channels) => Output::Multi(channels as usize, vec![0.0; samples_per_tick * channels as usize]),
             LineType::Video => Output::Video(None),
+            LineType::Event => Output::Events(Vec::new()),
+        }
+    }
+
+    // copies a connected input into an owned buffer
+    pub fn from_input_ref(input: InputRef) -> Option<Output> {
+        match input {
+            InputRef::Disconnected(_) => None,
+            InputRef::Mono(buff) => Some(Output::Mono(buff.to_vec())),
+            InputRef::Stereo(buff) => Some(Output::Stereo(buff.to_vec())),
+            InputRef::Multi(channels, buff) => Some(Output::Multi(channels, buff.to_vec())),
+            InputRef::Video(frame) => Some(Output::Video(frame.cloned())),
+            InputRef::Events(events) => Some(Output::Events(events.to_vec())),
         }
     }
 
@@ -80,7 +155,9 @@ impl Output {
         match self {
             Output::Mono(buff) => InputRef::Mono(buff),
             Output::Stereo(buff) => InputRef::Stereo(buff),
+            Output::Multi(channels, buff) => InputRef::Multi(*channels, buff),
             Output::Video(packet) => InputRef::Video(packet.as_ref()),
+            Output::Events(events) => InputRef::Events(events),
         }
     }
 
@@ -88,7 +165,9 @@ impl Output {
         match self {
             Output::Mono(buff) => OutputRef::Mono(buff),
             Output::Stereo(buff) => OutputRef::Stereo(buff),
+            Output::Multi(channels, buff) => OutputRef::Multi(*channels, buff),
             Output::Video(frame) => OutputRef::Video(frame),
+            Output::Events(events) => OutputRef::Events(events),
         }
     }
 }
@@ -96,7 +175,9 @@ impl Output {
 pub enum OutputRef<'a> {
     Mono(&'a mut [Sample]),
     Stereo(&'a mut [Sample]),
-    Video(&'a mut Option<VideoFrame>)
+    Multi(usize, &'a mut [Sample]),
+    Video(&'a mut Option<VideoFrame>),
+    Events(&'a mut Vec<TimedEvent>),
 }
 
 impl<'a> OutputRef<'a> {
@@ -104,7 +185,9 @@ impl<'a> OutputRef<'a> {
         match self {
             OutputRef::Mono(buff) => buff,
             OutputRef::Stereo(_) => panic!("expected mono output, got stereo"),
+            OutputRef::Multi(..) => panic!("expected mono output, got multi-channel"),
             OutputRef::Video(_) => panic!("expected mono output, got video"),
+            OutputRef::Events(_) => panic!("expected mono output, got events"),
         }
     }
 
@@ -112,7 +195,20 @@ impl<'a> OutputRef<'a> {
         match self {
             OutputRef::Stereo(buff) => buff,
             OutputRef::Mono(_) => panic!("expected stereo output, got mono"),
+            OutputRef::Multi(..) => panic!("expected stereo output, got multi-channel"),
             OutputRef::Video(_) => panic!("expected mono output, got video"),
+            OutputRef::Events(_) => panic!("expected stereo output, got events"),
+        }
+    }
+
+    pub fn expect_multi(&mut self, channels: usize) -> &mut [Sample] {
+        match self {
+            OutputRef::Multi(buff_channels, buff) if *buff_channels == channels => buff,
+            OutputRef::Multi(buff_channels, _) => panic!("expected {} channel output, got {} channels", channels, buff_channels),
+            OutputRef::Mono(_) => panic!("expected multi-channel output, got mono"),
+            OutputRef::Stereo(_) => panic!("expected multi-channel output, got stereo"),
+            OutputRef::Video(_) => panic!("expected multi-channel output, got video"),
+            OutputRef::Events(_) => panic!("expected multi-channel output, got events"),
         }
     }
 
@@ -120,7 +216,48 @@ impl<'a> OutputRef<'a> {
         match self {
             OutputRef::Stereo(_) => panic!("expected stereo output, got video"),
             OutputRef::Mono(_) => panic!("expected mono input, got video"),
+            OutputRef::Multi(..) => panic!("expected video output, got multi-channel"),
             OutputRef::Video(frame) => *frame,
+            OutputRef::Events(_) => panic!("expected video output, got events"),
+        }
+    }
+
+    // events must be pushed in order of offset
+    pub fn expect_events(&mut self) -> &mut Vec<TimedEvent> {
+        match self {
+            OutputRef::Events(events) => events,
+            OutputRef::Mono(_) => panic!("expected event output, got mono"),
+            OutputRef::Stereo(_) => panic!("expected event output, got stereo"),
+            OutputRef::Multi(..) => panic!("expected event output, got multi-channel"),
+            OutputRef::Video(_) => panic!("expected event output, got video"),
+        }
+    }
+
+    pub fn copy_from(&mut self, output: &Output) {
+        match (self, output) {
+            (OutputRef::Mono(buff), Output::Mono(src)) |
+            (OutputRef::Stereo(buff), Output::Stereo(src)) |
+            (OutputRef::Multi(_, buff), Output::Multi(_, src)) => buff.copy_from_slice(src),
+            (OutputRef::Video(frame), Output::Video(src)) => **frame = src.clone(),
+            (OutputRef::Events(events), Output::Events(src)) => {
+                events.clear();
+                events.extend_from_slice(src);
+            }
+            _ => panic!("line type mismatch copying output"),
+        }
+    }
+
+    pub fn silence(&mut self) {
+        match self {
+            OutputRef::Mono(buff) |
+            OutputRef::Stereo(buff) |
+            OutputRef::Multi(_, buff) => {
+                for sample in buff.iter_mut() {
+                    *sample = 0.0;
+                }
+            }
+            OutputRef::Video(frame) => { **frame = None; }
+            OutputRef::Events(events) => { events.clear(); }
         }
     }
 }
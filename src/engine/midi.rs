@@ -0,0 +1,138 @@
+use tokio::sync::watch;
+
+use mixlab_protocol::{ModuleId, MidiBinding, MidiControl};
+
+use crate::persist;
+
+// MIDI bindings are applied here rather than in the client, so that they keep
+// working whichever client the device happens to be attached to.
+//
+// Bindings to a deleted module are kept, as undoing the delete brings the
+// module back under the same id. They do nothing in the meantime.
+pub struct MidiBindings {
+    bindings: persist::MidiBindings,
+    persist_tx: watch::Sender<persist::MidiBindings>,
+}
+
+impl MidiBindings {
+    pub fn new(bindings: persist::MidiBindings) -> (MidiBindings, watch::Receiver<persist::MidiBindings>) {
+        let (persist_tx, persist_rx) = watch::channel(bindings.clone());
+
+        let bindings = MidiBindings {
+            bindings,
+            persist_tx,
+        };
+
+        (bindings, persist_rx)
+    }
+
+    pub fn bindings(&self) -> &[MidiBinding] {
+        &self.bindings
+    }
+
+    pub fn bind(&mut self, binding: MidiBinding) {
+        self.bindings.retain(|existing| !(existing.module == binding.module && existing.param == binding.param));
+        self.bindings.push(binding);
+        self.persist();
+    }
+
+    pub fn unbind(&mut self, module_id: ModuleId, param: &str) -> bool {
+        let len = self.bindings.len();
+        self.bindings.retain(|existing| !(existing.module == module_id && existing.param == param));
+
+        let unbound = self.bindings.len() != len;
+
+        if unbound {
+            self.persist();
+        }
+
+        unbound
+    }
+
+    // the params bound to a control, and the values to set them to
+    pub fn map(&self, control: &MidiControl, value: u8) -> Vec<(ModuleId, &str, f64)> {
+        self.bindings.iter()
+            .filter(|binding| binding.control == *control)
+            .map(|binding| (binding.module, binding.param.as_str(), binding.value(value)))
+            .collect()
+    }
+
+    fn persist(&mut self) {
+        let _ = self.persist_tx.broadcast(self.bindings.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use mixlab_protocol::{ModuleId, MidiBinding, MidiControl, MidiControlKind};
+
+    use super::MidiBindings;
+
+    fn id(id: usize) -> ModuleId {
+        ModuleId(NonZeroUsize::new(id).unwrap())
+    }
+
+    fn cc(device: &str, channel: u8, number: u8) -> MidiControl {
+        MidiControl { device: device.to_owned(), channel, kind: MidiControlKind::Cc(number) }
+    }
+
+    fn binding(control: MidiControl, module: ModuleId, param: &str, min: f64, max: f64) -> MidiBinding {
+        MidiBinding { control, module, param: param.to_owned(), min, max }
+    }
+
+    #[test]
+    fn scales_values_onto_range() {
+        let binding = binding(cc("nanoKONTROL", 0, 7), id(1), "amplitude", 0.0, 2.0);
+
+        assert_eq!(binding.value(0), 0.0);
+        assert_eq!(binding.value(127), 2.0);
+        assert!((binding.value(64) - 128.0 / 127.0).abs() < 1e-9);
+
+        // out of range values are clamped:
+        assert_eq!(binding.value(255), 2.0);
+
+        // and ranges may run backwards:
+        let inverted = MidiBinding { min: 1.0, max: 0.0, ..binding };
+        assert_eq!(inverted.value(0), 1.0);
+        assert_eq!(inverted.value(127), 0.0);
+    }
+
+    #[test]
+    fn maps_controls_to_bound_params() {
+        let (mut midi, _) = MidiBindings::new(Default::default());
+
+        midi.bind(binding(cc("nanoKONTROL", 0, 7), id(1), "amplitude", 0.0, 1.0));
+        midi.bind(binding(cc("nanoKONTROL", 0, 7), id(2), "amplitude", 0.0, 2.0));
+        midi.bind(binding(cc("nanoKONTROL", 1, 7), id(3), "amplitude", 0.0, 1.0));
+        midi.bind(binding(cc("Launch Control", 0, 7), id(4), "amplitude", 0.0, 1.0));
+
+        assert_eq!(midi.map(&cc("nanoKONTROL", 0, 7), 127), vec![(id(1), "amplitude", 1.0), (id(2), "amplitude", 2.0)]);
+        assert_eq!(midi.map(&cc("nanoKONTROL", 1, 7), 127), vec![(id(3), "amplitude", 1.0)]);
+        assert_eq!(midi.map(&cc("Launch Control", 0, 7), 0), vec![(id(4), "amplitude", 0.0)]);
+
+        // notes are distinct from controllers of the same number:
+        let note = MidiControl { kind: MidiControlKind::Note(7), ..cc("nanoKONTROL", 0, 7) };
+        assert!(midi.map(&note, 127).is_empty());
+    }
+
+    #[test]
+    fn rebinding_a_param_replaces_its_binding() {
+        let (mut midi, persist_rx) = MidiBindings::new(Default::default());
+
+        midi.bind(binding(cc("nanoKONTROL", 0, 7), id(1), "amplitude", 0.0, 1.0));
+        midi.bind(binding(cc("nanoKONTROL", 0, 8), id(1), "amplitude", 0.0, 1.0));
+        midi.bind(binding(cc("nanoKONTROL", 0, 9), id(1), "mod_depth", 0.0, 1.0));
+
+        assert!(midi.map(&cc("nanoKONTROL", 0, 7), 127).is_empty());
+        assert_eq!(midi.map(&cc("nanoKONTROL", 0, 8), 127), vec![(id(1), "amplitude", 1.0)]);
+
+        assert!(midi.unbind(id(1), "mod_depth"));
+        assert!(!midi.unbind(id(1), "mod_depth"));
+
+        // each change is published for persisting:
+        let persisted = persist_rx.borrow().clone();
+        assert_eq!(persisted, vec![binding(cc("nanoKONTROL", 0, 8), id(1), "amplitude", 0.0, 1.0)]);
+    }
+}
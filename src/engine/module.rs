@@ -1,12 +1,15 @@
+use std::any::Any;
 use std::fmt;
 use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
 
 use tokio::runtime;
 use tokio::sync::mpsc;
 
-use mixlab_protocol::{ModuleParams, Indication, Terminal};
+use mixlab_protocol::{ModuleParams, Indication, ModuleFault, Terminal, CvBinding};
 
-use crate::engine::{InputRef, OutputRef};
+use crate::engine::{EngineConfig, InputRef, OutputRef, TransportRef};
+use crate::engine::cv::CvHost;
 use crate::module::{self, ModuleT};
 use crate::project::ProjectBaseRef;
 
@@ -14,14 +17,46 @@ use crate::project::ProjectBaseRef;
 pub struct ModuleCtx<M: ModuleT> {
     runtime: runtime::Handle,
     base: ProjectBaseRef,
+    config: EngineConfig,
     link: ModuleLink<M>,
 }
 
 impl<M: ModuleT> ModuleCtx<M> {
+    // returns the receiving end of the module's link, for the host to deliver
+    // events from. modules may be created on the worker pool, outside of the
+    // tokio runtime, so the runtime comes from the project rather than the
+    // current thread
+    pub(in crate::engine) fn new(base: ProjectBaseRef) -> (Self, mpsc::Receiver<M::Event>) {
+        let (events_tx, events_rx) = mpsc::channel(2);
+
+        let ctx = ModuleCtx {
+            runtime: base.runtime(),
+            config: base.config(),
+            base,
+            link: ModuleLink { events: events_tx },
+        };
+
+        (ctx, events_rx)
+    }
+
     pub fn project(&self) -> ProjectBaseRef {
         self.base.clone()
     }
 
+    pub fn config(&self) -> EngineConfig {
+        self.config
+    }
+
+    pub fn transport(&self) -> TransportRef {
+        self.base.transport()
+    }
+
+    // for modules which drive async work of their own. never use
+    // Handle::current in module code, see `new`
+    pub fn runtime(&self) -> runtime::Handle {
+        self.runtime.clone()
+    }
+
     pub fn link(&self) -> ModuleLink<M> {
         self.link.clone()
     }
@@ -59,38 +94,107 @@ impl<M: ModuleT> fmt::Debug for ModuleLink<M> {
     }
 }
 
+// Modules all run on the engine thread (or its worker pool), so a panic in any
+// one of them would take down the whole workspace. The host catches panics
+// from a module's create, update and tick and quarantines the module: it is
+// not run again, its outputs stay silent, and a fault indication is sent in
+// place of its own. Deleting the module and undoing recreates it afresh.
 pub struct ModuleHost<M: ModuleT> {
     module: M,
     events: mpsc::Receiver<M::Event>,
+    faulted: bool,
 }
 
 impl<M: ModuleT> ModuleHost<M> {
     fn new(params: M::Params, base: ProjectBaseRef) -> (Self, M::Indication) {
-        let (events_tx, events_rx) = mpsc::channel(2);
-
-        let ctx = ModuleCtx {
-            runtime: runtime::Handle::current(),
-            base,
-            link: ModuleLink { events: events_tx },
-        };
-
+        let (ctx, events_rx) = ModuleCtx::new(base);
         let (module, indication) = M::create(params, ctx);
 
         let host = ModuleHost {
             module,
             events: events_rx,
+            faulted: false,
         };
 
         (host, indication)
     }
+
+    fn fault(&mut self, message: String) -> Indication {
+        eprintln!("engine: quarantining faulted module {}: {}", std::any::type_name::<M>(), message);
+        self.faulted = true;
+        Indication::Fault(ModuleFault { message })
+    }
+}
+
+// stands in for a module which panicked while being created. it starts out
+// with no terminals, as only the module itself knows what they are, until the
+// workspace restores them from the connections it was saved with
+struct FaultedHost {
+    params: ModuleParams,
+    inputs: Vec<Terminal>,
+    outputs: Vec<Terminal>,
+}
+
+impl FaultedHost {
+    fn new(params: ModuleParams) -> Self {
+        FaultedHost {
+            params,
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+        }
+    }
 }
 
-pub trait DynModuleHostT {
+impl DynModuleHostT for FaultedHost {
+    fn params(&self) -> ModuleParams {
+        self.params.clone()
+    }
+
+    fn update(&mut self, _: ModuleParams, _: usize) -> Option<Indication> {
+        None
+    }
+
+    fn run_tick(&mut self, _: u64, _: &[InputRef], _: &mut [OutputRef]) -> Option<Indication> {
+        None
+    }
+
+    fn inputs(&self) -> &[Terminal] {
+        &self.inputs
+    }
+
+    fn outputs(&self) -> &[Terminal] {
+        &self.outputs
+    }
+
+    fn restore_terminals(&mut self, inputs: Vec<Terminal>, outputs: Vec<Terminal>) {
+        self.inputs = inputs;
+        self.outputs = outputs;
+    }
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "module panicked".to_owned()
+    }
+}
+
+pub trait DynModuleHostT: Send {
     fn params(&self) -> ModuleParams;
-    fn update(&mut self, new_params: ModuleParams) -> Option<Indication>;
+    fn update(&mut self, new_params: ModuleParams, offset: usize) -> Option<Indication>;
     fn run_tick(&mut self, t: u64, inputs: &[InputRef], outputs: &mut [OutputRef]) -> Option<Indication>;
     fn inputs(&self) -> &[Terminal];
     fn outputs(&self) -> &[Terminal];
+    // params bound to control inputs, see CvHost:
+    fn cv(&self) -> &[CvBinding] { &[] }
+    fn set_cv(&mut self, _: Vec<CvBinding>) {}
+    // only meaningful for modules which faulted on create, see FaultedHost.
+    // live modules always know their own terminals
+    fn restore_terminals(&mut self, _inputs: Vec<Terminal>, _outputs: Vec<Terminal>) {}
+    fn finished(&self) -> Option<bool> { None }
 }
 
 macro_rules! gen_dyn_module_impls {
@@ -101,21 +205,56 @@ macro_rules! gen_dyn_module_impls {
                     ModuleParams::$module(self.module.params())
                 }
 
-                fn update(&mut self, new_params: ModuleParams) -> Option<Indication> {
-                    if let ModuleParams::$module(params) = new_params {
-                        self.module.update(params).map(Indication::$module)
-                    } else {
-                        panic!("module params mismatch! module = {:?}, params = {:?}", self.module, new_params);
+                fn update(&mut self, new_params: ModuleParams, offset: usize) -> Option<Indication> {
+                    if self.faulted {
+                        return None;
+                    }
+
+                    let params = match new_params {
+                        ModuleParams::$module(params) => params,
+                        new_params => {
+                            let message = format!("module params mismatch! params = {:?}", new_params);
+                            return Some(self.fault(message));
+                        }
+                    };
+
+                    let module = &mut self.module;
+
+                    match panic::catch_unwind(AssertUnwindSafe(|| module.update_at(params, offset))) {
+                        Ok(indication) => indication.map(Indication::$module),
+                        Err(payload) => Some(self.fault(panic_message(payload))),
                     }
                 }
 
                 fn run_tick(&mut self, t: u64, inputs: &[InputRef], outputs: &mut [OutputRef]) -> Option<Indication> {
-                    if let Some(ev) = self.events.try_recv().ok() {
-                        self.module.receive_event(ev);
+                    if self.faulted {
+                        // outputs are left silent
+                        return None;
                     }
 
-                    self.module.run_tick(t, inputs, outputs)
-                        .map(Indication::$module)
+                    let module = &mut self.module;
+                    let events = &mut self.events;
+
+                    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                        if let Some(ev) = events.try_recv().ok() {
+                            module.receive_event(ev);
+                        }
+
+                        module.run_tick(t, inputs, outputs)
+                    }));
+
+                    match result {
+                        Ok(indication) => indication.map(Indication::$module),
+                        Err(payload) => {
+                            // the module may have written part of its output
+                            // before panicking
+                            for output in outputs.iter_mut() {
+                                output.silence();
+                            }
+
+                            Some(self.fault(panic_message(payload)))
+                        }
+                    }
                 }
 
                 fn inputs(&self) -> &[Terminal] {
@@ -125,6 +264,15 @@ macro_rules! gen_dyn_module_impls {
                 fn outputs(&self) -> &[Terminal] {
                     self.module.outputs()
                 }
+
+                fn finished(&self) -> Option<bool> {
+                    // a faulted module will never reach its end
+                    if self.faulted {
+                        return None;
+                    }
+
+                    self.module.finished()
+                }
             }
         )*
     }
@@ -132,12 +280,26 @@ macro_rules! gen_dyn_module_impls {
 
 macro_rules! gen_host_fn {
     ($( $mod_name:ident::$module:ident , )*) => {
-        pub fn host(params: ModuleParams, base: ProjectBaseRef) -> (DynModuleHost, Indication) {
+        fn host_module(params: ModuleParams, base: ProjectBaseRef) -> (DynModuleHost, Indication) {
             match params {
                 $(
                     ModuleParams::$module(params) => {
-                        let (host, indication) = ModuleHost::<module::$mod_name::$module>::new(params, base);
-                        (Box::new(host) as DynModuleHost, Indication::$module(indication))
+                        let create = AssertUnwindSafe(|| {
+                            ModuleHost::<module::$mod_name::$module>::new(params.clone(), base)
+                        });
+
+                        match panic::catch_unwind(create) {
+                            Ok((host, indication)) => {
+                                (Box::new(host) as DynModuleHost, Indication::$module(indication))
+                            }
+                            Err(payload) => {
+                                let message = panic_message(payload);
+                                eprintln!("engine: quarantining module which faulted on create: {}", message);
+
+                                let host = FaultedHost::new(ModuleParams::$module(params));
+                                (Box::new(host) as DynModuleHost, Indication::Fault(ModuleFault { message }))
+                            }
+                        }
                     }
                 )*
             }
@@ -148,4 +310,15 @@ macro_rules! gen_host_fn {
 crate::enumerate_modules!{then gen_dyn_module_impls!}
 crate::enumerate_modules!{then gen_host_fn!}
 
+pub fn host(params: ModuleParams, base: ProjectBaseRef) -> (DynModuleHost, Indication) {
+    let (module, indication) = host_module(params, base);
+    (Box::new(CvHost::new(module)), indication)
+}
+
+// a module as it would be after panicking on create
+#[cfg(test)]
+pub fn faulted(params: ModuleParams) -> DynModuleHost {
+    Box::new(CvHost::new(Box::new(FaultedHost::new(params))))
+}
+
 pub type DynModuleHost = Box<dyn DynModuleHostT>;
@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use mixlab_protocol::{ModuleId, InputId, OutputId, SubPatchModule};
+
+use crate::engine::{self, DynModuleHost, EngineConfig, Output, TickOutput};
+use crate::engine::timing::EngineStat;
+use crate::engine::workspace::Workspace;
+use crate::persist;
+use crate::project::ProjectBaseRef;
+
+// A workspace run from within a module, ie. the contents of a sub-patch. It
+// steps in lockstep with the enclosing workspace, one tick per outer tick.
+pub struct NestedWorkspace {
+    workspace: Workspace,
+    config: EngineConfig,
+    stat: EngineStat,
+    base: ProjectBaseRef,
+}
+
+impl NestedWorkspace {
+    pub fn new(save: &persist::Workspace, base: ProjectBaseRef) -> Self {
+        let config = base.config();
+
+        NestedWorkspace {
+            workspace: Workspace::from_persist(save, base.clone()),
+            config,
+            stat: EngineStat::new(config),
+            base,
+        }
+    }
+
+    pub fn to_persist(&self) -> persist::Workspace {
+        self.workspace.to_persist()
+    }
+
+    pub fn update(&mut self, save: &persist::Workspace) {
+        self.workspace.apply_persist(save, self.base.clone());
+    }
+
+    // modules in id order
+    pub fn modules(&self) -> Vec<(ModuleId, &DynModuleHost)> {
+        let mut modules = self.workspace.modules.iter()
+            .map(|(module_id, module)| (*module_id, module))
+            .collect::<Vec<_>>();
+
+        modules.sort_by_key(|(module_id, _)| *module_id);
+        modules
+    }
+
+    pub fn connection(&self, input_id: InputId) -> Option<OutputId> {
+        self.workspace.connections.get(&input_id).copied()
+    }
+
+    pub fn finished(&self) -> Option<bool> {
+        self.workspace.finished()
+    }
+
+    pub fn module_states(&self) -> Vec<SubPatchModule> {
+        self.modules().into_iter()
+            .filter_map(|(id, _)| self.module_state(id))
+            .collect()
+    }
+
+    pub fn module_state(&self, id: ModuleId) -> Option<SubPatchModule> {
+        let module = self.workspace.modules.get(&id)?;
+        let indication = self.workspace.indications.get(&id)?.clone();
+
+        Some(SubPatchModule {
+            id,
+            indication,
+            inputs: module.inputs().to_vec(),
+            outputs: module.outputs().to_vec(),
+        })
+    }
+
+    // t is the sample timestamp of the enclosing tick
+    pub fn run_tick(&mut self, t: u64, external: &HashMap<OutputId, Output>) -> TickOutput {
+        let tick = t / self.config.samples_per_tick() as u64;
+        let workspace = &mut self.workspace;
+        let config = self.config;
+
+        let output = self.stat.record_offline_tick(|tick_stat|
+            engine::run_tick(workspace, config, tick, tick_stat, external));
+
+        for (module_id, indication) in &output.indications {
+            if let Some(current) = self.workspace.indications.get_mut(module_id) {
+                current.apply(indication.clone());
+            }
+        }
+
+        output
+    }
+}
+
+impl fmt::Debug for NestedWorkspace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "NestedWorkspace {{ modules: {} }}", self.workspace.modules.len())
+    }
+}
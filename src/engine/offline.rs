@@ -0,0 +1,423 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use mixlab_protocol::{ModuleId, InputId, OutputId, Terminal, TransportOp};
+
+use crate::engine::{self, Automation, EngineConfig, TickOutput, TransportRef};
+use crate::engine::timing::EngineStat;
+use crate::engine::workspace::Workspace;
+use crate::persist;
+use crate::project::ProjectBaseRef;
+
+// Drives a workspace as fast as possible rather than in step with the wall
+// clock. Modules spawn their async tasks onto the project's runtime, which
+// must keep running while the engine is stepped.
+pub struct OfflineEngine {
+    workspace: Workspace,
+    config: EngineConfig,
+    transport: TransportRef,
+    automation: Option<Automation>,
+    stat: EngineStat,
+    tick: u64,
+}
+
+impl OfflineEngine {
+    pub fn new(save: &persist::Workspace, base: ProjectBaseRef) -> Self {
+        let config = base.config();
+
+        OfflineEngine {
+            transport: base.transport(),
+            workspace: Workspace::from_persist(save, base),
+            automation: None,
+            config,
+            stat: EngineStat::new(config),
+            tick: 0,
+        }
+    }
+
+    // plays back automation lanes in play mode as the transport runs. lanes
+    // are never recorded into offline
+    pub fn with_automation(mut self, lanes: persist::Automation) -> Self {
+        let (automation, _) = Automation::new(lanes);
+        self.automation = Some(automation);
+        self
+    }
+
+    pub fn play(&mut self) {
+        self.transport.apply(TransportOp::Play);
+    }
+
+    pub fn config(&self) -> EngineConfig {
+        self.config
+    }
+
+    // the number of ticks needed to render at least `duration`
+    pub fn ticks_for(&self, duration: Duration) -> u64 {
+        let micros = duration.as_micros() as u64;
+        let ticks_per_second = self.config.ticks_per_second as u64;
+        (micros * ticks_per_second + 999_999) / 1_000_000
+    }
+
+    // terminal modules in id order
+    pub fn terminal_modules(&self) -> Vec<ModuleId> {
+        let mut modules = self.workspace.terminal_modules()
+            .into_iter()
+            .collect::<Vec<_>>();
+
+        modules.sort();
+        modules
+    }
+
+    pub fn inputs(&self, module_id: ModuleId) -> Option<&[Terminal]> {
+        self.workspace.modules.get(&module_id).map(|module| module.inputs())
+    }
+
+    pub fn connection(&self, input_id: InputId) -> Option<OutputId> {
+        self.workspace.connections.get(&input_id).copied()
+    }
+
+    fn step(&mut self) -> TickOutput {
+        let tick = self.tick;
+        self.tick += 1;
+
+        self.play_automation();
+
+        let workspace = &mut self.workspace;
+        let config = self.config;
+
+        let output = self.stat.record_offline_tick(|tick_stat|
+            engine::run_tick(workspace, config, tick, tick_stat, &HashMap::new()));
+
+        self.transport.advance(config.samples_per_tick());
+
+        for (module_id, indication) in &output.indications {
+            if let Some(current) = self.workspace.indications.get_mut(module_id) {
+                current.apply(indication.clone());
+            }
+        }
+
+        output
+    }
+
+    fn play_automation(&mut self) {
+        let automation = match &mut self.automation {
+            Some(automation) => automation,
+            None => { return; }
+        };
+
+        let now = self.transport.now();
+        let samples_per_tick = self.config.samples_per_tick();
+        let tick_beats = now.beat_at(samples_per_tick) - now.state.position;
+
+        for (module_id, params, position) in automation.playback(&now.state, tick_beats) {
+            let offset = now.offset_of(position, samples_per_tick);
+            engine::update_module_params(&mut self.workspace, module_id, params, offset);
+        }
+    }
+
+    // steps the engine until everything in the patch with an end (eg. media
+    // sources) has played to it, for at most `max_ticks`, handing each tick's
+    // output to `f` as it is produced. patches with nothing that ends run for
+    // `max_ticks`. returns the number of ticks run
+    pub fn run(&mut self, max_ticks: u64, mut f: impl FnMut(u64, TickOutput)) -> u64 {
+        for ticks in 0..max_ticks {
+            if self.workspace.finished() == Some(true) {
+                return ticks;
+            }
+
+            let tick = self.tick;
+            let output = self.step();
+            f(tick, output);
+        }
+
+        max_ticks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+    use std::time::Duration;
+
+    use tokio::runtime;
+
+    use mixlab_protocol::{ModuleId, ModuleParams, InputId, OutputId, SavedWorkspace, SavedModule, OscillatorParams, Waveform, AmplifierParams, MixerParams, MixerChannelParams, Decibel, AutomationLane, AutomationMode, AutomationPoint, Indication, Terminal};
+
+    use crate::engine::{EngineConfig, InputRef, OutputRef, Output, Sample};
+    use crate::engine::module::DynModuleHostT;
+    use crate::persist;
+    use crate::project::ProjectBase;
+    use super::OfflineEngine;
+
+    fn id(id: usize) -> ModuleId {
+        ModuleId(NonZeroUsize::new(id).unwrap())
+    }
+
+    fn module(params: ModuleParams, inputs: Vec<Option<OutputId>>) -> SavedModule {
+        SavedModule {
+            params,
+            geometry: Default::default(),
+            inputs,
+            feedback: Vec::new(),
+            cv: Vec::new(),
+        }
+    }
+
+    // oscillator -> amplifier -> plotter
+    fn patch() -> SavedWorkspace {
+        let mut patch = SavedWorkspace::default();
+        patch.module_seq = 3;
+        patch.modules.insert(id(1), module(ModuleParams::Oscillator(OscillatorParams { freq: 440.0, waveform: Waveform::Sine }), vec![]));
+        patch.modules.insert(id(2), module(ModuleParams::Amplifier(AmplifierParams { amplitude: 0.5, mod_depth: 0.0 }), vec![Some(OutputId(id(1), 1)), None]));
+        patch.modules.insert(id(3), module(ModuleParams::Plotter(()), vec![Some(OutputId(id(2), 0))]));
+        patch
+    }
+
+    // renders the patch, returning the oscillator and amplifier output of
+    // each tick
+    fn render(ticks: u64) -> (Vec<Sample>, Vec<Sample>) {
+        let runtime = runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .expect("build tokio runtime");
+
+        let config = EngineConfig { sample_rate: 44100, ticks_per_second: 100 }
+            .validate()
+            .expect("valid engine config");
+
+        let base = ProjectBase::stub(config, runtime.handle().clone());
+
+        runtime.enter(|| {
+            let mut engine = OfflineEngine::new(&patch(), base);
+
+            assert_eq!(engine.terminal_modules(), vec![id(3)]);
+            assert_eq!(engine.connection(InputId(id(3), 0)), Some(OutputId(id(2), 0)));
+
+            let mut oscillator = Vec::new();
+            let mut amplifier = Vec::new();
+
+            engine.run(ticks, |_, mut output| {
+                match output.buffers.remove(&OutputId(id(1), 1)) {
+                    Some(Output::Stereo(samples)) => oscillator.extend(samples),
+                    _ => panic!("expected stereo oscillator output"),
+                }
+
+                match output.buffers.remove(&OutputId(id(2), 0)) {
+                    Some(Output::Stereo(samples)) => amplifier.extend(samples),
+                    _ => panic!("expected stereo amplifier output"),
+                }
+            });
+
+            (oscillator, amplifier)
+        })
+    }
+
+    #[test]
+    fn renders_whole_patch() {
+        let (oscillator, amplifier) = render(50);
+
+        assert_eq!(oscillator.len(), 44100);
+        assert_eq!(amplifier.len(), oscillator.len());
+        assert!(oscillator.iter().any(|sample| sample.abs() > 0.9));
+
+        for (osc, amp) in oscillator.iter().zip(&amplifier) {
+            assert!((osc * 0.5 - amp).abs() < 1e-6);
+        }
+
+        // offline renders don't depend on the wall clock:
+        assert_eq!(render(50), (oscillator, amplifier));
+    }
+
+    // stands in for a media source, playing to its end after some ticks
+    struct Ends {
+        remaining: u64,
+    }
+
+    impl DynModuleHostT for Ends {
+        fn params(&self) -> ModuleParams {
+            ModuleParams::Plotter(())
+        }
+
+        fn update(&mut self, _: ModuleParams, _: usize) -> Option<Indication> {
+            None
+        }
+
+        fn run_tick(&mut self, _: u64, _: &[InputRef], _: &mut [OutputRef]) -> Option<Indication> {
+            self.remaining = self.remaining.saturating_sub(1);
+            None
+        }
+
+        fn inputs(&self) -> &[Terminal] {
+            &[]
+        }
+
+        fn outputs(&self) -> &[Terminal] {
+            &[]
+        }
+
+        fn finished(&self) -> Option<bool> {
+            Some(self.remaining == 0)
+        }
+    }
+
+    #[test]
+    fn runs_until_patch_finishes() {
+        let runtime = runtime::Builder::new()
+            .basic_scheduler()
+            .build()
+            .expect("build tokio runtime");
+
+        let config = EngineConfig { sample_rate: 44100, ticks_per_second: 100 };
+        let base = ProjectBase::stub(config, runtime.handle().clone());
+
+        runtime.enter(|| {
+            let mut engine = OfflineEngine::new(&patch(), base);
+
+            // nothing in the patch ends, so it runs for as long as it may:
+            assert_eq!(engine.run(10, |_, _| {}), 10);
+
+            engine.workspace.modules.insert(id(4), Box::new(Ends { remaining: 3 }));
+
+            let mut ticks = Vec::new();
+            assert_eq!(engine.run(100, |tick, _| ticks.push(tick)), 3);
+            assert_eq!(ticks, vec![10, 11, 12]);
+
+            // and is cut short if it doesn't end in time:
+            engine.workspace.modules.insert(id(4), Box::new(Ends { remaining: 50 }));
+            assert_eq!(engine.run(20, |_, _| {}), 20);
+        })
+    }
+
+    // oscillator -> two amplifiers -> mixer. both amplifiers run in the same
+    // level, so may run in parallel
+    fn diamond() -> SavedWorkspace {
+        let channel = MixerChannelParams { gain: Decibel(0.0), fader: 1.0, cue: false };
+        let mixer = MixerParams { channels: vec![channel.clone(), channel] };
+
+        let mut patch = SavedWorkspace::default();
+        patch.module_seq = 4;
+        patch.modules.insert(id(1), module(ModuleParams::Oscillator(OscillatorParams { freq: 440.0, waveform: Waveform::Sine }), vec![]));
+        patch.modules.insert(id(2), module(ModuleParams::Amplifier(AmplifierParams { amplitude: 0.5, mod_depth: 0.0 }), vec![Some(OutputId(id(1), 1)), None]));
+        patch.modules.insert(id(3), module(ModuleParams::Amplifier(AmplifierParams { amplitude: 0.25, mod_depth: 0.0 }), vec![Some(OutputId(id(1), 1)), None]));
+        patch.modules.insert(id(4), module(ModuleParams::Mixer(mixer), vec![Some(OutputId(id(2), 0)), Some(OutputId(id(3), 0))]));
+        patch
+    }
+
+    // renders a patch, returning each of `outputs` over the whole render.
+    // plays the transport if there are automation lanes to play back
+    fn capture(patch: SavedWorkspace, automation: persist::Automation, ticks: u64, outputs: &[OutputId]) -> Vec<Vec<Sample>> {
+        let runtime = runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .expect("build tokio runtime");
+
+        let config = EngineConfig { sample_rate: 44100, ticks_per_second: 100 };
+        let base = ProjectBase::stub(config, runtime.handle().clone());
+
+        runtime.enter(|| {
+            let mut engine = OfflineEngine::new(&patch, base);
+
+            if !automation.is_empty() {
+                engine = engine.with_automation(automation);
+                engine.play();
+            }
+
+            let mut captured = vec![Vec::new(); outputs.len()];
+
+            engine.run(ticks, |_, mut output| {
+                for (output_id, captured) in outputs.iter().zip(captured.iter_mut()) {
+                    match output.buffers.remove(output_id) {
+                        Some(Output::Stereo(samples)) => captured.extend(samples),
+                        _ => panic!("expected stereo output from {:?}", output_id),
+                    }
+                }
+            });
+
+            captured
+        })
+    }
+
+    #[test]
+    fn renders_diamond_as_if_serial() {
+        let outputs = [OutputId(id(1), 1), OutputId(id(2), 0), OutputId(id(3), 0), OutputId(id(4), 0)];
+        let captured = capture(diamond(), persist::Automation::new(), 20, &outputs);
+
+        let (oscillator, half, quarter, mix) = match captured.as_slice() {
+            [oscillator, half, quarter, mix] => (oscillator, half, quarter, mix),
+            _ => unreachable!(),
+        };
+
+        assert_eq!(mix.len(), 441 * 2 * 20);
+        assert!(oscillator.iter().any(|sample| sample.abs() > 0.9));
+
+        // each module sees the output of its upstream modules from the same
+        // tick, exactly as if the modules had run one after another:
+        for i in 0..oscillator.len() {
+            assert!((half[i] - oscillator[i] * 0.5).abs() < 1e-6);
+            assert!((quarter[i] - oscillator[i] * 0.25).abs() < 1e-6);
+            assert!((mix[i] - (half[i] + quarter[i])).abs() < 1e-6);
+        }
+
+        // and however the level happens to be scheduled:
+        for _ in 0..4 {
+            assert_eq!(capture(diamond(), persist::Automation::new(), 20, &outputs), captured);
+        }
+    }
+
+    #[test]
+    fn applies_automation_at_sample_offsets() {
+        let mut patch = SavedWorkspace::default();
+        patch.module_seq = 2;
+        patch.modules.insert(id(1), module(ModuleParams::Oscillator(OscillatorParams { freq: 440.0, waveform: Waveform::Sine }), vec![]));
+        patch.modules.insert(id(2), module(ModuleParams::Amplifier(AmplifierParams { amplitude: 1.0, mod_depth: 0.0 }), vec![Some(OutputId(id(1), 1)), None]));
+
+        let point = |position, amplitude| AutomationPoint {
+            position,
+            params: ModuleParams::Amplifier(AmplifierParams { amplitude, mod_depth: 0.0 }),
+        };
+
+        // at 120 bpm and 44100 Hz, a beat is 22050 samples. the second point
+        // falls 100 samples into the third tick, each of 441 samples:
+        let silence_at = 441 * 2 + 100;
+        let mut automation = persist::Automation::new();
+        automation.insert(id(2), AutomationLane {
+            mode: AutomationMode::Play,
+            points: vec![point(0.0, 1.0), point((silence_at as f64 + 0.5) / 22050.0, 0.0)],
+        });
+
+        let outputs = [OutputId(id(1), 1), OutputId(id(2), 0)];
+        let captured = capture(patch, automation, 5, &outputs);
+
+        let (oscillator, amplifier) = match captured.as_slice() {
+            [oscillator, amplifier] => (oscillator, amplifier),
+            _ => unreachable!(),
+        };
+
+        // untouched up to the sample the point falls on:
+        assert_eq!(amplifier[..silence_at * 2], oscillator[..silence_at * 2]);
+        assert_ne!(amplifier[silence_at * 2 + 2..][..2], oscillator[silence_at * 2 + 2..][..2]);
+
+        // then ramping down to silence over 10ms:
+        let silent = (silence_at + 441) * 2;
+        assert!(amplifier[silence_at * 2..silent].iter().zip(&oscillator[silence_at * 2..silent])
+            .all(|(amp, osc)| amp.abs() <= osc.abs()));
+        assert!(amplifier[silent..].iter().all(|sample| *sample == 0.0));
+    }
+
+    #[test]
+    fn rounds_duration_up_to_whole_ticks() {
+        let runtime = runtime::Builder::new()
+            .basic_scheduler()
+            .build()
+            .expect("build tokio runtime");
+
+        let config = EngineConfig { sample_rate: 44100, ticks_per_second: 100 };
+        let base = ProjectBase::stub(config, runtime.handle().clone());
+        let engine = runtime.enter(|| OfflineEngine::new(&SavedWorkspace::default(), base));
+
+        assert_eq!(engine.ticks_for(Duration::from_millis(1000)), 100);
+        assert_eq!(engine.ticks_for(Duration::from_millis(1001)), 101);
+    }
+}
@@ -0,0 +1,203 @@
+use std::collections::HashMap;
+use std::mem;
+
+use tokio::sync::watch;
+
+use mixlab_protocol::{ModuleId, ModuleParams, Scene};
+
+use crate::persist;
+
+pub struct Scenes {
+    scenes: persist::Scenes,
+    fade: Option<SceneFade>,
+    persist_tx: watch::Sender<persist::Scenes>,
+}
+
+struct SceneFade {
+    // params of each module at the start of the fade, and in the scene:
+    modules: HashMap<ModuleId, (ModuleParams, ModuleParams)>,
+    elapsed_ticks: u64,
+    duration_ticks: u64,
+}
+
+impl Scenes {
+    pub fn new(scenes: persist::Scenes) -> (Scenes, watch::Receiver<persist::Scenes>) {
+        let (persist_tx, persist_rx) = watch::channel(scenes.clone());
+
+        let scenes = Scenes {
+            scenes,
+            fade: None,
+            persist_tx,
+        };
+
+        (scenes, persist_rx)
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.scenes.keys().cloned().collect()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Scene> {
+        self.scenes.get(name)
+    }
+
+    pub fn capture(&mut self, name: String, scene: Scene) {
+        self.scenes.insert(name, scene);
+        self.persist();
+    }
+
+    pub fn delete(&mut self, name: &str) -> bool {
+        let deleted = self.scenes.remove(name).is_some();
+
+        if deleted {
+            self.persist();
+        }
+
+        deleted
+    }
+
+    // replaces any fade already in progress
+    pub fn start_fade(&mut self, modules: HashMap<ModuleId, (ModuleParams, ModuleParams)>, duration_ticks: u64) {
+        self.fade = Some(SceneFade {
+            modules,
+            elapsed_ticks: 0,
+            duration_ticks,
+        });
+    }
+
+    // stops fading a module, eg. because someone has taken hold of it
+    pub fn cancel_fade(&mut self, module_id: ModuleId) {
+        if let Some(fade) = &mut self.fade {
+            fade.modules.remove(&module_id);
+        }
+    }
+
+    // advances the fade in progress by one tick, returning the params to
+    // apply and whether this is the final step of the fade
+    pub fn step_fade(&mut self) -> Option<(Vec<(ModuleId, ModuleParams)>, bool)> {
+        let fade = self.fade.as_mut()?;
+
+        fade.elapsed_ticks += 1;
+
+        if fade.elapsed_ticks >= fade.duration_ticks {
+            let fade = self.fade.take()?;
+
+            let params = fade.modules.into_iter()
+                .map(|(module_id, (_, to))| (module_id, to))
+                .collect();
+
+            return Some((params, true));
+        }
+
+        let t = fade.elapsed_ticks as f64 / fade.duration_ticks as f64;
+
+        let params = fade.modules.iter()
+            .map(|(module_id, (from, to))| (*module_id, ModuleParams::interpolate(from, to, t)))
+            .collect();
+
+        Some((params, false))
+    }
+
+    fn persist(&mut self) {
+        let _ = self.persist_tx.broadcast(self.scenes.clone());
+    }
+}
+
+// modules whose params carry one-off actions (eg. connecting a stream, firing
+// a trigger) would repeat them on recall, so are left out of scenes
+pub fn in_scene(params: &ModuleParams) -> bool {
+    match params {
+        ModuleParams::StreamOutput(_) |
+        ModuleParams::Trigger(_) => false,
+        _ => true,
+    }
+}
+
+pub fn same_module_kind(a: &ModuleParams, b: &ModuleParams) -> bool {
+    mem::discriminant(a) == mem::discriminant(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use mixlab_protocol::{ModuleId, ModuleParams, AmplifierParams, GateState};
+
+    use super::Scenes;
+
+    fn id(id: usize) -> ModuleId {
+        ModuleId(NonZeroUsize::new(id).unwrap())
+    }
+
+    fn amplifier(amplitude: f64) -> ModuleParams {
+        ModuleParams::Amplifier(AmplifierParams { amplitude, mod_depth: 0.0 })
+    }
+
+    fn amplitudes(mut params: Vec<(ModuleId, ModuleParams)>) -> Vec<(ModuleId, f64)> {
+        params.sort_by_key(|(module_id, _)| *module_id);
+
+        params.into_iter()
+            .map(|(module_id, params)| match params {
+                ModuleParams::Amplifier(params) => (module_id, params.amplitude),
+                _ => panic!("expected amplifier params"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fades_linearly_to_scene() {
+        let (mut scenes, _) = Scenes::new(Default::default());
+
+        let modules = vec![
+            (id(1), (amplifier(0.0), amplifier(1.0))),
+            (id(2), (amplifier(1.0), amplifier(0.5))),
+        ];
+
+        scenes.start_fade(modules.into_iter().collect(), 4);
+
+        let mut steps = Vec::new();
+
+        while let Some((params, finished)) = scenes.step_fade() {
+            steps.push((amplitudes(params), finished));
+        }
+
+        assert_eq!(steps, vec![
+            (vec![(id(1), 0.25), (id(2), 0.875)], false),
+            (vec![(id(1), 0.5), (id(2), 0.75)], false),
+            (vec![(id(1), 0.75), (id(2), 0.625)], false),
+            (vec![(id(1), 1.0), (id(2), 0.5)], true),
+        ]);
+    }
+
+    #[test]
+    fn cancelled_modules_stop_fading() {
+        let (mut scenes, _) = Scenes::new(Default::default());
+
+        let modules = vec![
+            (id(1), (amplifier(0.0), amplifier(1.0))),
+            (id(2), (amplifier(1.0), amplifier(0.5))),
+        ];
+
+        scenes.start_fade(modules.into_iter().collect(), 2);
+        scenes.cancel_fade(id(1));
+
+        assert_eq!(scenes.step_fade().map(|(params, finished)| (amplitudes(params), finished)),
+            Some((vec![(id(2), 0.75)], false)));
+
+        // a new fade replaces the one in progress:
+        scenes.start_fade(vec![(id(1), (amplifier(0.0), amplifier(0.2)))].into_iter().collect(), 1);
+
+        assert_eq!(scenes.step_fade().map(|(params, finished)| (amplitudes(params), finished)),
+            Some((vec![(id(1), 0.2)], true)));
+        assert!(scenes.step_fade().is_none());
+    }
+
+    #[test]
+    fn leaves_one_off_actions_out_of_scenes() {
+        assert!(super::in_scene(&amplifier(1.0)));
+        assert!(!super::in_scene(&ModuleParams::Trigger(GateState::Closed)));
+
+        assert!(super::same_module_kind(&amplifier(1.0), &amplifier(0.0)));
+        assert!(!super::same_module_kind(&amplifier(1.0), &ModuleParams::Trigger(GateState::Closed)));
+    }
+}
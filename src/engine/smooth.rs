@@ -0,0 +1,100 @@
+// time taken to ramp to a new value:
+const RAMP_MS: usize = 10;
+
+// A param value which ramps linearly to new values rather than jumping to
+// them, so that gain and fader changes don't click. New values can be set to
+// take effect part way into the next tick, as delivered by `update_at`.
+//
+// Call `begin_tick` at the start of each tick, then `next` once per sample
+// (per channel, not per interleaved sample) to step through the tick.
+#[derive(Debug, Clone)]
+pub struct Smoothed {
+    current: f64,
+    target: f64,
+    step: f64,
+    remaining: usize,
+    ramp_samples: usize,
+    // targets to ramp to once `next` reaches their sample offset, in order:
+    pending: Vec<Pending>,
+    offset: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Pending {
+    offset: usize,
+    target: f64,
+    // set since the last call to begin_tick, so meant for the coming tick:
+    fresh: bool,
+}
+
+impl Smoothed {
+    pub fn new(value: f64, sample_rate: usize) -> Self {
+        Smoothed {
+            current: value,
+            target: value,
+            step: 0.0,
+            remaining: 0,
+            ramp_samples: (sample_rate * RAMP_MS / 1000).max(1),
+            pending: Vec::new(),
+            offset: 0,
+        }
+    }
+
+    // the value most recently set, whether or not it has been reached yet
+    pub fn target(&self) -> f64 {
+        self.pending.last()
+            .map(|pending| pending.target)
+            .unwrap_or(self.target)
+    }
+
+    pub fn set_at(&mut self, target: f64, offset: usize) {
+        // a later change supersedes any pending at or after its offset:
+        self.pending.retain(|pending| pending.offset < offset);
+        self.pending.push(Pending { offset, target, fresh: true });
+    }
+
+    pub fn begin_tick(&mut self) {
+        // anything left pending from an earlier tick is now overdue:
+        for pending in self.pending.iter_mut() {
+            if !pending.fresh {
+                pending.offset = 0;
+            }
+
+            pending.fresh = false;
+        }
+
+        self.offset = 0;
+    }
+
+    pub fn next(&mut self) -> f64 {
+        while let Some(pending) = self.pending.first() {
+            if pending.offset > self.offset {
+                break;
+            }
+
+            let target = pending.target;
+            self.pending.remove(0);
+            self.ramp_to(target);
+        }
+
+        self.offset += 1;
+
+        if self.remaining > 0 {
+            self.remaining -= 1;
+
+            if self.remaining == 0 {
+                self.current = self.target;
+            } else {
+                self.current += self.step;
+            }
+        }
+
+        self.current
+    }
+
+    fn ramp_to(&mut self, target: f64) {
+        self.target = target;
+        self.remaining = self.ramp_samples;
+        self.step = (target - self.current) / self.ramp_samples as f64;
+    }
+}
@@ -1,24 +1,35 @@
 use std::collections::HashMap;
+use std::sync::atomic::Ordering;
 use std::time::{Instant, Duration};
 
 use mixlab_protocol::{ModuleId, PerformanceInfo, PerformanceAccount, PerformanceMetric, Microseconds};
 
-use crate::engine;
+use crate::engine::EngineConfig;
+use crate::metrics;
 use crate::util;
 
-pub const TICK_BUDGET: Duration = Duration::from_micros(1_000_000 / engine::TICKS_PER_SECOND as u64);
+// percentiles are taken over this many seconds worth of recent ticks:
+const WINDOW_SECONDS: usize = 10;
 
 pub struct EngineStat {
+    tick_rate: usize,
+    tick_budget: Duration,
     is_realtime: bool,
     last_lagged: Option<Instant>,
+    overruns: u64,
+    window: usize,
     accounts: HashMap<PerformanceAccount, Stat>,
 }
 
 impl EngineStat {
-    pub fn new() -> Self {
+    pub fn new(config: EngineConfig) -> Self {
         EngineStat {
+            tick_rate: config.ticks_per_second,
+            tick_budget: config.tick_budget(),
             is_realtime: false,
             last_lagged: None,
+            overruns: 0,
+            window: config.ticks_per_second * WINDOW_SECONDS,
             accounts: HashMap::new(),
         }
     }
@@ -33,9 +44,12 @@ impl EngineStat {
 
         let tick_time = end - start;
 
-        if tick_time > TICK_BUDGET {
+        let tick_budget = tick.stat.tick_budget;
+
+        if tick_time > tick_budget {
             tick.stat.last_lagged = Some(Instant::now());
-            eprintln!("WARNING: tick ran over time! elapsed: {} us, budget: {} us", tick_time.as_micros(), TICK_BUDGET.as_micros());
+            tick.stat.overruns += 1;
+            eprintln!("WARNING: tick ran over time! elapsed: {} us, budget: {} us", tick_time.as_micros(), tick_budget.as_micros());
         }
 
         tick.stat.add_sample(PerformanceAccount::Engine, tick_time - tick.modules_accounted_for);
@@ -43,19 +57,33 @@ impl EngineStat {
         retn
     }
 
+    // offline ticks have no schedule to keep, so they never count as lagging
+    pub fn record_offline_tick<T>(&mut self, f: impl FnOnce(&mut TickStat) -> T) -> T {
+        let start = Instant::now();
+        let mut tick = TickStat::new(self);
+        let retn = f(&mut tick);
+        let tick_time = Instant::now() - start;
+
+        tick.stat.add_sample(PerformanceAccount::Engine, tick_time - tick.modules_accounted_for);
+
+        retn
+    }
+
     pub fn report(&self) -> PerformanceInfo {
         let time_since_lag = self.last_lagged.map(|time| Instant::now() - time);
 
         PerformanceInfo {
             realtime: self.is_realtime,
             lag: util::temporal_warning(time_since_lag),
-            tick_rate: engine::TICKS_PER_SECOND,
-            tick_budget: Microseconds(TICK_BUDGET.as_micros() as u64),
+            tick_rate: self.tick_rate,
+            tick_budget: Microseconds(self.tick_budget.as_micros() as u64),
+            overruns: self.overruns,
             accounts: self.accounts.iter().map(|(account, stat)| {
-                (*account, PerformanceMetric {
-                    last: Microseconds(stat.last().as_micros() as u64),
-                })
-            }).collect()
+                (*account, stat.metric())
+            }).collect(),
+            lagged_clients: metrics::LAGGED_CLIENTS.load(Ordering::Relaxed),
+            lagged_messages: metrics::LAGGED_MESSAGES.load(Ordering::Relaxed),
+            encoder_queues: metrics::encoder_queues(),
         }
     }
 
@@ -64,9 +92,11 @@ impl EngineStat {
     }
 
     fn add_sample(&mut self, account: PerformanceAccount, sample: Duration) {
+        let window = self.window;
+
         self.accounts.entry(account)
-            .and_modify(|stat| stat.add_sample(sample))
-            .or_insert(Stat::with_initial_sample(sample));
+            .or_insert_with(|| Stat::new(window))
+            .add_sample(sample);
     }
 }
 
@@ -92,24 +122,174 @@ impl<'a> TickStat<'a> {
         self.stat.add_sample(PerformanceAccount::Module(module_id), elapsed_time);
         retn
     }
+
+    // modules within a dependency level run concurrently, so while each
+    // module's own time is recorded, only the wall clock time of the whole
+    // level is accounted against the tick
+    pub fn record_level<T>(&mut self, f: impl FnOnce() -> Vec<(ModuleId, Duration, T)>) -> Vec<(ModuleId, T)> {
+        let start = Instant::now();
+        let results = f();
+        self.modules_accounted_for += Instant::now() - start;
+
+        results.into_iter()
+            .map(|(module_id, elapsed_time, retn)| {
+                self.stat.add_sample(PerformanceAccount::Module(module_id), elapsed_time);
+                (module_id, retn)
+            })
+            .collect()
+    }
 }
 
+// keeps a rolling window of samples in microseconds
 struct Stat {
-    last: u128
+    samples: Vec<u64>,
+    // index of the most recent sample:
+    last: usize,
+    window: usize,
 }
 
 impl Stat {
-    pub fn with_initial_sample(sample: Duration) -> Self {
+    pub fn new(window: usize) -> Self {
         Stat {
-            last: sample.as_micros(),
+            samples: Vec::with_capacity(window),
+            last: 0,
+            window: window.max(1),
         }
     }
 
-    pub fn last(&self) -> Duration {
-        Duration::from_micros(self.last as u64)
+    pub fn add_sample(&mut self, sample: Duration) {
+        let sample = sample.as_micros() as u64;
+
+        if self.samples.len() < self.window {
+            self.last = self.samples.len();
+            self.samples.push(sample);
+        } else {
+            self.last = (self.last + 1) % self.window;
+            self.samples[self.last] = sample;
+        }
     }
 
-    pub fn add_sample(&mut self, sample: Duration) {
-        self.last = sample.as_micros();
+    pub fn metric(&self) -> PerformanceMetric {
+        let mut sorted = self.samples.clone();
+        sorted.sort_unstable();
+
+        let percentile = |p: f64| {
+            if sorted.is_empty() {
+                return Microseconds(0);
+            }
+
+            let index = ((sorted.len() - 1) as f64 * p).round() as usize;
+            Microseconds(sorted[index])
+        };
+
+        PerformanceMetric {
+            last: Microseconds(self.samples.get(self.last).copied().unwrap_or(0)),
+            p50: percentile(0.5),
+            p99: percentile(0.99),
+            max: Microseconds(sorted.last().copied().unwrap_or(0)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+    use std::thread;
+    use std::time::{Instant, Duration};
+
+    use mixlab_protocol::{PerformanceAccount, Microseconds, ModuleId};
+
+    use crate::engine::EngineConfig;
+    use super::{EngineStat, Stat};
+
+    fn micros(micros: u64) -> Duration {
+        Duration::from_micros(micros)
+    }
+
+    #[test]
+    fn percentiles_over_window() {
+        let mut stat = Stat::new(100);
+
+        for sample in 1..=100 {
+            stat.add_sample(micros(sample));
+        }
+
+        let metric = stat.metric();
+        assert_eq!(metric.last, Microseconds(100));
+        assert_eq!(metric.p50, Microseconds(51));
+        assert_eq!(metric.p99, Microseconds(99));
+        assert_eq!(metric.max, Microseconds(100));
+    }
+
+    #[test]
+    fn window_wraps_around() {
+        let mut stat = Stat::new(4);
+
+        for sample in &[1000, 1, 2, 3] {
+            stat.add_sample(micros(*sample));
+        }
+
+        assert_eq!(stat.metric().max, Microseconds(1000));
+
+        // the fifth sample replaces the oldest, so the outlier falls out of
+        // the window:
+        stat.add_sample(micros(4));
+
+        let metric = stat.metric();
+        assert_eq!(stat.samples.len(), 4);
+        assert_eq!(metric.last, Microseconds(4));
+        assert_eq!(metric.p50, Microseconds(3));
+        assert_eq!(metric.max, Microseconds(4));
+
+        // and keeps on wrapping:
+        for sample in 5..=8 {
+            stat.add_sample(micros(sample));
+        }
+
+        let metric = stat.metric();
+        assert_eq!(metric.last, Microseconds(8));
+        assert_eq!(metric.max, Microseconds(8));
+        assert_eq!(metric.p50, Microseconds(7));
+    }
+
+    #[test]
+    fn empty_window() {
+        let metric = Stat::new(10).metric();
+        assert_eq!(metric.last, Microseconds(0));
+        assert_eq!(metric.max, Microseconds(0));
+    }
+
+    #[test]
+    fn counts_overruns() {
+        // a budget of 1ms per tick:
+        let mut stat = EngineStat::new(EngineConfig { sample_rate: 48000, ticks_per_second: 1000 });
+        let module_id = ModuleId(NonZeroUsize::new(1).unwrap());
+        let deadline = Instant::now() + Duration::from_secs(60);
+
+        stat.record_tick(deadline, |_| {});
+        assert_eq!(stat.report().overruns, 0);
+
+        stat.record_tick(deadline, |_| thread::sleep(Duration::from_millis(5)));
+        stat.record_tick(deadline, |tick| {
+            tick.record_module(module_id, || thread::sleep(Duration::from_millis(5)));
+        });
+
+        let report = stat.report();
+        assert_eq!(report.overruns, 2);
+        assert!(report.lag.is_some());
+        assert!(report.realtime);
+
+        // module time is accounted to the module, not to the engine:
+        let account = |account| report.accounts.iter()
+            .find(|(acct, _)| *acct == account)
+            .map(|(_, metric)| metric.clone())
+            .unwrap();
+
+        assert!(account(PerformanceAccount::Module(module_id)).last.0 >= 5000);
+        assert!(account(PerformanceAccount::Engine).last.0 < 5000);
+
+        // offline ticks have no budget to overrun:
+        stat.record_offline_tick(|_| thread::sleep(Duration::from_millis(5)));
+        assert_eq!(stat.report().overruns, 2);
     }
 }
@@ -0,0 +1,224 @@
+use std::cmp;
+use std::sync::{Arc, RwLock};
+
+use mixlab_protocol::{Tempo, TransportOp, TransportState};
+
+// The engine's musical clock. There is one transport per project, shared by
+// the engine and every module (including those within sub-patches) via the
+// project base. The engine applies transport ops between ticks and advances
+// the position at the end of each tick, so modules always see the transport
+// as of the start of the tick they are running.
+#[derive(Debug)]
+pub struct Transport {
+    state: RwLock<TransportState>,
+    sample_rate: usize,
+}
+
+pub type TransportRef = Arc<Transport>;
+
+impl Transport {
+    pub fn new(tempo: Tempo, sample_rate: usize) -> TransportRef {
+        Arc::new(Transport {
+            state: RwLock::new(TransportState {
+                tempo,
+                playing: false,
+                position: 0.0,
+            }),
+            sample_rate,
+        })
+    }
+
+    pub fn state(&self) -> TransportState {
+        *self.state.read().expect("transport lock")
+    }
+
+    pub fn now(&self) -> TransportTime {
+        TransportTime {
+            state: self.state(),
+            sample_rate: self.sample_rate,
+        }
+    }
+
+    pub(in crate::engine) fn apply(&self, op: TransportOp) -> TransportState {
+        let mut state = self.state.write().expect("transport lock");
+
+        match op {
+            TransportOp::Play => { state.playing = true; }
+            TransportOp::Pause => { state.playing = false; }
+            TransportOp::Stop => {
+                state.playing = false;
+                state.position = 0.0;
+            }
+            TransportOp::Locate(position) => {
+                if position.is_finite() {
+                    state.position = position.max(0.0);
+                }
+            }
+            TransportOp::SetTempo(tempo) => {
+                if tempo.is_valid() {
+                    state.tempo = tempo;
+                }
+            }
+        }
+
+        *state
+    }
+
+    pub(in crate::engine) fn advance(&self, samples: usize) {
+        let mut state = self.state.write().expect("transport lock");
+
+        if state.playing {
+            let beats_per_sample = beats_per_sample(&state.tempo, self.sample_rate);
+            state.position += samples as f64 * beats_per_sample;
+        }
+    }
+}
+
+// a snapshot of the transport for the duration of one tick
+#[derive(Debug, Clone, Copy)]
+pub struct TransportTime {
+    pub state: TransportState,
+    sample_rate: usize,
+}
+
+impl TransportTime {
+    pub fn playing(&self) -> bool {
+        self.state.playing
+    }
+
+    pub fn tempo(&self) -> Tempo {
+        self.state.tempo
+    }
+
+    pub fn samples_per_beat(&self) -> f64 {
+        1.0 / beats_per_sample(&self.state.tempo, self.sample_rate)
+    }
+
+    // position in beats of the sample at the given offset into the tick. the
+    // position stands still while the transport is paused
+    pub fn beat_at(&self, offset: usize) -> f64 {
+        if self.state.playing {
+            self.state.position + offset as f64 * beats_per_sample(&self.state.tempo, self.sample_rate)
+        } else {
+            self.state.position
+        }
+    }
+
+    // offset into a tick of `samples_per_tick` samples at which the given
+    // position falls, clamped to within the tick
+    pub fn offset_of(&self, beat: f64, samples_per_tick: usize) -> usize {
+        if !self.state.playing {
+            return 0;
+        }
+
+        let offset = (beat - self.state.position) * self.samples_per_beat();
+
+        cmp::min(offset.max(0.0) as usize, samples_per_tick.saturating_sub(1))
+    }
+}
+
+fn beats_per_sample(tempo: &Tempo, sample_rate: usize) -> f64 {
+    tempo.bpm / 60.0 / sample_rate as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use mixlab_protocol::{Tempo, TransportOp};
+
+    use super::Transport;
+
+    // 120 bpm at 48 kHz is one beat every 24000 samples
+    const SAMPLE_RATE: usize = 48000;
+
+    #[test]
+    fn plays_pauses_and_stops() {
+        let transport = Transport::new(Tempo::default(), SAMPLE_RATE);
+
+        // stopped transports stand still:
+        transport.advance(24000);
+        assert!(!transport.state().playing);
+        assert_eq!(transport.state().position, 0.0);
+
+        transport.apply(TransportOp::Play);
+        transport.advance(24000);
+        transport.advance(12000);
+        assert_eq!(transport.state().position, 1.5);
+
+        transport.apply(TransportOp::Pause);
+        transport.advance(24000);
+        assert!(!transport.state().playing);
+        assert_eq!(transport.state().position, 1.5);
+
+        transport.apply(TransportOp::Play);
+        transport.advance(12000);
+        assert_eq!(transport.state().position, 2.0);
+
+        let state = transport.apply(TransportOp::Stop);
+        assert!(!state.playing);
+        assert_eq!(state.position, 0.0);
+    }
+
+    #[test]
+    fn seeks() {
+        let transport = Transport::new(Tempo::default(), SAMPLE_RATE);
+
+        transport.apply(TransportOp::Locate(8.0));
+        assert_eq!(transport.state().position, 8.0);
+
+        transport.apply(TransportOp::Play);
+        transport.advance(6000);
+        assert_eq!(transport.state().position, 8.25);
+
+        // before the start clamps to it, and nonsense is ignored:
+        transport.apply(TransportOp::Locate(-3.0));
+        assert_eq!(transport.state().position, 0.0);
+
+        transport.apply(TransportOp::Locate(4.0));
+        transport.apply(TransportOp::Locate(f64::NAN));
+        transport.apply(TransportOp::Locate(f64::INFINITY));
+        assert_eq!(transport.state().position, 4.0);
+    }
+
+    #[test]
+    fn follows_tempo() {
+        let transport = Transport::new(Tempo::default(), SAMPLE_RATE);
+        transport.apply(TransportOp::Play);
+
+        transport.apply(TransportOp::SetTempo(Tempo { bpm: 60.0, ..Tempo::default() }));
+        transport.advance(48000);
+        assert_eq!(transport.state().position, 1.0);
+
+        // invalid tempos are ignored:
+        transport.apply(TransportOp::SetTempo(Tempo { bpm: 0.0, ..Tempo::default() }));
+        assert_eq!(transport.state().tempo.bpm, 60.0);
+    }
+
+    #[test]
+    fn positions_within_tick() {
+        let transport = Transport::new(Tempo::default(), SAMPLE_RATE);
+        transport.apply(TransportOp::Locate(2.0));
+
+        // paused, every sample of the tick is at the same position:
+        let now = transport.now();
+        assert_eq!(now.beat_at(0), 2.0);
+        assert_eq!(now.beat_at(479), 2.0);
+        assert_eq!(now.offset_of(2.01, 480), 0);
+
+        transport.apply(TransportOp::Play);
+
+        let now = transport.now();
+        assert_eq!(now.samples_per_beat(), 24000.0);
+        assert_eq!(now.beat_at(0), 2.0);
+        assert!((now.beat_at(240) - 2.01).abs() < 1e-9);
+        assert_eq!(now.offset_of(2.25, 8000), 6000);
+
+        // positions outside the tick clamp to its first and last sample:
+        assert_eq!(now.offset_of(1.0, 480), 0);
+        assert_eq!(now.offset_of(3.0, 480), 479);
+
+        // the snapshot holds still while the transport moves on:
+        transport.advance(480);
+        assert_eq!(now.beat_at(0), 2.0);
+        assert!((transport.now().beat_at(0) - 2.02).abs() < 1e-9);
+    }
+}
@@ -1,10 +1,12 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::mem;
 use std::ops::{Deref, DerefMut};
 
 use tokio::sync::watch;
 
-use mixlab_protocol::{ModuleId, InputId, OutputId, TerminalId, WindowGeometry, Indication, LineType};
+use mixlab_protocol::{ModuleId, InputId, OutputId, TerminalId, WindowGeometry, Indication, LineType, Tempo};
 
+use crate::engine::Output;
 use crate::engine::module::{self, DynModuleHost};
 use crate::persist;
 use crate::project::ProjectBaseRef;
@@ -12,9 +14,17 @@ use crate::util::Sequence;
 
 pub struct Workspace {
     pub(in crate::engine) module_seq: Sequence,
+    // tempo of the project's transport, set on the top level workspace only:
+    pub(in crate::engine) tempo: Option<Tempo>,
     pub(in crate::engine) modules: HashMap<ModuleId, DynModuleHost>,
     pub(in crate::engine) geometry: HashMap<ModuleId, WindowGeometry>,
     pub(in crate::engine) connections: HashMap<InputId, OutputId>,
+    // inputs in connections which are connected via feedback connections.
+    // these may form cycles, and read the previous tick's output buffer
+    pub(in crate::engine) feedback: HashSet<InputId>,
+    // not persisted, carries buffers read by feedback connections over to
+    // the next tick:
+    pub(in crate::engine) feedback_buffers: HashMap<OutputId, Output>,
     pub(in crate::engine) indications: HashMap<ModuleId, Indication>,
 }
 
@@ -26,38 +36,160 @@ impl Workspace {
 
         // load modules and geometry
         for (module_id, saved_module) in &save.modules {
-            let (module, indication) = module::host(saved_module.params.clone(), base.clone());
+            let (mut module, indication) = module::host(saved_module.params.clone(), base.clone());
+            module.set_cv(saved_module.cv.clone());
             modules.insert(*module_id, module);
             geometry.insert(*module_id, saved_module.geometry.clone());
             indications.insert(*module_id, indication);
         }
 
         let mut workspace = Workspace {
-            module_seq: save.module_seq.clone(),
+            module_seq: Sequence::resume(save.module_seq),
+            tempo: save.tempo,
             modules,
             geometry,
             connections: HashMap::new(),
+            feedback: HashSet::new(),
+            feedback_buffers: HashMap::new(),
             indications,
         };
 
         // load connections after loading all modules
+        workspace.restore_faulted_terminals(save);
+        workspace.load_connections(save);
+
+        workspace
+    }
+
+    // brings the workspace in line with a saved workspace. modules which are
+    // still present and of the same kind are updated in place, keeping their
+    // state
+    pub fn apply_persist(&mut self, save: &persist::Workspace, base: ProjectBaseRef) {
+        self.module_seq = Sequence::resume(save.module_seq);
+        self.tempo = save.tempo;
+
+        self.modules.retain(|module_id, _| save.modules.contains_key(module_id));
+        self.geometry.retain(|module_id, _| save.modules.contains_key(module_id));
+        self.indications.retain(|module_id, _| save.modules.contains_key(module_id));
+
+        for (module_id, saved_module) in &save.modules {
+            let live_module = self.modules.get_mut(module_id)
+                .filter(|module| mem::discriminant(&module.params()) == mem::discriminant(&saved_module.params));
+
+            match live_module {
+                Some(module) => {
+                    if let Some(indication) = module.update(saved_module.params.clone(), 0) {
+                        self.indications.insert(*module_id, indication);
+                    }
+
+                    if module.cv() != saved_module.cv.as_slice() {
+                        module.set_cv(saved_module.cv.clone());
+                    }
+                }
+                None => {
+                    let (mut module, indication) = module::host(saved_module.params.clone(), base.clone());
+                    module.set_cv(saved_module.cv.clone());
+                    self.modules.insert(*module_id, module);
+                    self.indications.insert(*module_id, indication);
+                }
+            }
+
+            self.geometry.insert(*module_id, saved_module.geometry.clone());
+        }
+
+        self.connections.clear();
+        self.feedback.clear();
+        self.restore_faulted_terminals(save);
+        self.load_connections(save);
+    }
+
+    // modules which faulted on create have no terminals of their own. they
+    // are given back the terminals their saved connections need, typed after
+    // whatever is on the other end, so that the connections are kept when the
+    // workspace is next saved. unconnected terminals in between are stereo
+    fn restore_faulted_terminals(&mut self, save: &persist::Workspace) {
+        for (module_id, saved_module) in &save.modules {
+            let (input_count, output_count) = match self.modules.get(module_id) {
+                Some(module) => (module.inputs().len(), module.outputs().len()),
+                None => continue,
+            };
+
+            // (output index, type of the input it feeds)
+            let fed = save.modules.iter()
+                .flat_map(|(other_id, other)| {
+                    other.inputs.iter().enumerate()
+                        .filter_map(move |(idx, output_id)| Some((InputId(*other_id, idx), (*output_id)?)))
+                })
+                .filter(|(_, output_id)| output_id.module_id() == *module_id)
+                .map(|(input_id, output_id)| (output_id.index(), self.terminal_type(TerminalId::Input(input_id))))
+                .collect::<Vec<_>>();
+
+            let outputs_needed = fed.iter()
+                .map(|(idx, _)| idx + 1)
+                .max()
+                .unwrap_or(0);
+
+            if input_count >= saved_module.inputs.len() && output_count >= outputs_needed {
+                continue;
+            }
+
+            // cv inputs are added back by the module's cv host:
+            let own_inputs = saved_module.inputs.len().saturating_sub(saved_module.cv.len());
+
+            let inputs = saved_module.inputs[..own_inputs].iter()
+                .map(|output_id| {
+                    output_id
+                        .and_then(|output_id| self.terminal_type(TerminalId::Output(output_id)))
+                        .unwrap_or(LineType::Stereo)
+                        .unlabeled()
+                })
+                .collect();
+
+            let outputs = (0..outputs_needed)
+                .map(|idx| {
+                    fed.iter()
+                        .find(|(output_idx, _)| *output_idx == idx)
+                        .and_then(|(_, line_type)| *line_type)
+                        .unwrap_or(LineType::Stereo)
+                        .unlabeled()
+                })
+                .collect();
+
+            if let Some(module) = self.modules.get_mut(module_id) {
+                module.restore_terminals(inputs, outputs);
+            }
+        }
+    }
+
+    fn load_connections(&mut self, save: &persist::Workspace) {
         for (module_id, saved_module) in &save.modules {
             for (input_idx, output_id) in saved_module.inputs.iter().enumerate() {
                 let input_id = InputId(*module_id, input_idx);
 
                 if let Some(output_id) = output_id {
-                    // ignore workspace connect error for now... should we log?
-                    let _ = workspace.connect(input_id, *output_id);
+                    let result = if saved_module.feedback.contains(&input_idx) {
+                        self.connect_feedback(input_id, *output_id)
+                    } else {
+                        self.connect(input_id, *output_id)
+                    };
+
+                    // workspaces saved before feedback connections existed
+                    // may contain cycles, keep them as feedback connections
+                    // rather than dropping them
+                    if let Err(ConnectError::Cycle) = result {
+                        let _ = self.connect_feedback(input_id, *output_id);
+                    }
+
+                    // ignore other workspace connect errors for now... should we log?
                 }
             }
         }
-
-        workspace
     }
 
     pub fn to_persist(&self) -> persist::Workspace {
         persist::Workspace {
-            module_seq: self.module_seq.clone(),
+            module_seq: self.module_seq.last(),
+            tempo: self.tempo,
             modules: self.modules.iter()
                 .map(|(module_id, module)| {
                     let params = module.params();
@@ -71,16 +203,46 @@ impl Workspace {
                         .map(|input_id| self.connections.get(&input_id).cloned())
                         .collect();
 
+                    let feedback = (0..module.inputs().len())
+                        .filter(|idx| self.feedback.contains(&InputId(*module_id, *idx)))
+                        .collect();
+
                     (*module_id, persist::Module {
                         params,
                         geometry,
                         inputs,
+                        feedback,
+                        cv: module.cv().to_vec(),
                     })
                 })
                 .collect()
         }
     }
 
+    // terminal modules are modules which do not send their output to the
+    // input of any other module, not counting feedback connections
+    pub fn terminal_modules(&self) -> HashSet<ModuleId> {
+        let mut terminal_modules = self.modules.keys()
+            .copied()
+            .collect::<HashSet<_>>();
+
+        for (input, output) in &self.connections {
+            if !self.feedback.contains(input) {
+                terminal_modules.remove(&output.module_id());
+            }
+        }
+
+        terminal_modules
+    }
+
+    // whether every module playing something with an end has reached it.
+    // None if nothing in the workspace has an end
+    pub fn finished(&self) -> Option<bool> {
+        self.modules.values()
+            .filter_map(|module| module.finished())
+            .fold(None, |finished, module_finished| Some(finished.unwrap_or(true) && module_finished))
+    }
+
     fn terminal_type(&self, terminal: TerminalId) -> Option<LineType> {
         self.modules.get(&terminal.module_id()).and_then(|module| {
             match terminal {
@@ -94,7 +256,42 @@ impl Workspace {
         })
     }
 
-    pub fn connect(&mut self, input_id: InputId, output_id: OutputId) -> Result<Option<OutputId>, ConnectError> {
+    // returns true if module_id is fed, directly or indirectly, by the
+    // output of dependency. feedback connections are not followed
+    fn depends_on(&self, module_id: ModuleId, dependency: ModuleId) -> bool {
+        let mut seen = HashSet::new();
+        let mut stack = vec![module_id];
+
+        while let Some(module_id) = stack.pop() {
+            if module_id == dependency {
+                return true;
+            }
+
+            if !seen.insert(module_id) {
+                continue;
+            }
+
+            let input_count = self.modules.get(&module_id)
+                .map(|module| module.inputs().len())
+                .unwrap_or(0);
+
+            for i in 0..input_count {
+                let input_id = InputId(module_id, i);
+
+                if self.feedback.contains(&input_id) {
+                    continue;
+                }
+
+                if let Some(output_id) = self.connections.get(&input_id) {
+                    stack.push(output_id.module_id());
+                }
+            }
+        }
+
+        false
+    }
+
+    fn check_connection(&self, input_id: InputId, output_id: OutputId) -> Result<(), ConnectError> {
         let input_type = match self.terminal_type(TerminalId::Input(input_id)) {
             Some(ty) => ty,
             None => return Err(ConnectError::NoInput),
@@ -105,15 +302,39 @@ impl Workspace {
             None => return Err(ConnectError::NoOutput),
         };
 
+        // multi-channel lines only match when their channel counts do too,
+        // anything else must go through an up or down mix module
         if input_type == output_type {
-            Ok(self.connections.insert(input_id, output_id))
+            Ok(())
         } else {
             // type mismatch, don't connect
             Err(ConnectError::TypeMismatch)
         }
     }
 
+    pub fn connect(&mut self, input_id: InputId, output_id: OutputId) -> Result<Option<OutputId>, ConnectError> {
+        self.check_connection(input_id, output_id)?;
+
+        // a regular connection must not complete a cycle, the engine would
+        // have no order to run the modules in. cycles must go through an
+        // explicit feedback connection instead
+        if self.depends_on(output_id.module_id(), input_id.module_id()) {
+            return Err(ConnectError::Cycle);
+        }
+
+        self.feedback.remove(&input_id);
+        Ok(self.connections.insert(input_id, output_id))
+    }
+
+    pub fn connect_feedback(&mut self, input_id: InputId, output_id: OutputId) -> Result<Option<OutputId>, ConnectError> {
+        self.check_connection(input_id, output_id)?;
+
+        self.feedback.insert(input_id);
+        Ok(self.connections.insert(input_id, output_id))
+    }
+
     pub fn disconnect(&mut self, input_id: InputId) -> Option<OutputId> {
+        self.feedback.remove(&input_id);
         self.connections.remove(&input_id)
     }
 }
@@ -122,6 +343,7 @@ pub enum ConnectError {
     NoInput,
     NoOutput,
     TypeMismatch,
+    Cycle,
 }
 
 pub struct WorkspaceEmbryo {
@@ -194,3 +416,193 @@ impl<'a> DerefMut for WorkspaceBorrowMut<'a> {
         &mut self.sync.workspace
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::num::NonZeroUsize;
+
+    use tokio::runtime::{self, Runtime};
+
+    use mixlab_protocol::{ModuleId, ModuleParams, InputId, OutputId, SavedWorkspace, SavedModule, OscillatorParams, Waveform, AmplifierParams, LineType};
+
+    use crate::engine::{self, EngineConfig, Output, Sample};
+    use crate::engine::module::faulted;
+    use crate::engine::timing::EngineStat;
+    use crate::project::{ProjectBase, ProjectBaseRef};
+    use super::{Workspace, ConnectError};
+
+    fn id(id: usize) -> ModuleId {
+        ModuleId(NonZeroUsize::new(id).unwrap())
+    }
+
+    fn saved(params: ModuleParams, inputs: Vec<Option<OutputId>>) -> SavedModule {
+        SavedModule {
+            params,
+            geometry: Default::default(),
+            inputs,
+            feedback: Vec::new(),
+            cv: Vec::new(),
+        }
+    }
+
+    fn oscillator() -> ModuleParams {
+        ModuleParams::Oscillator(OscillatorParams { freq: 440.0, waveform: Waveform::Sine })
+    }
+
+    fn amplifier() -> ModuleParams {
+        ModuleParams::Amplifier(AmplifierParams { amplitude: 1.0, mod_depth: 0.0 })
+    }
+
+    fn stub() -> (Runtime, ProjectBaseRef) {
+        let runtime = runtime::Builder::new()
+            .basic_scheduler()
+            .build()
+            .expect("build tokio runtime");
+
+        let config = EngineConfig { sample_rate: 44100, ticks_per_second: 100 };
+        let base = ProjectBase::stub(config, runtime.handle().clone());
+        (runtime, base)
+    }
+
+    // oscillator -> amplifier -> amplifier
+    fn chain() -> SavedWorkspace {
+        let mut save = SavedWorkspace::default();
+        save.module_seq = 3;
+        save.modules.insert(id(1), saved(oscillator(), vec![]));
+        save.modules.insert(id(2), saved(amplifier(), vec![Some(OutputId(id(1), 1)), None]));
+        save.modules.insert(id(3), saved(amplifier(), vec![Some(OutputId(id(2), 0)), None]));
+        save
+    }
+
+    fn is_cycle(result: Result<Option<OutputId>, ConnectError>) -> bool {
+        match result {
+            Err(ConnectError::Cycle) => true,
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn rejects_connections_closing_a_cycle() {
+        let (runtime, base) = stub();
+
+        runtime.enter(|| {
+            let mut workspace = Workspace::from_persist(&chain(), base);
+
+            // the last amplifier back into the first:
+            assert!(is_cycle(workspace.connect(InputId(id(2), 0), OutputId(id(3), 0))));
+            assert!(is_cycle(workspace.connect(InputId(id(3), 0), OutputId(id(3), 0))));
+            assert_eq!(workspace.connections.get(&InputId(id(2), 0)), Some(&OutputId(id(1), 1)));
+
+            // unless it's a feedback connection:
+            assert_eq!(workspace.connect_feedback(InputId(id(2), 0), OutputId(id(3), 0)).ok(), Some(Some(OutputId(id(1), 1))));
+            assert!(workspace.feedback.contains(&InputId(id(2), 0)));
+
+            // feedback connections are not followed looking for cycles, and
+            // make their source a terminal module:
+            assert!(workspace.connect(InputId(id(3), 1), OutputId(id(1), 0)).is_ok());
+            assert_eq!(workspace.terminal_modules().into_iter().collect::<Vec<_>>(), vec![id(3)]);
+
+            // reconnecting normally clears the feedback flag:
+            assert!(workspace.connect(InputId(id(2), 0), OutputId(id(1), 1)).is_ok());
+            assert!(workspace.feedback.is_empty());
+        });
+    }
+
+    #[test]
+    fn feedback_reads_previous_tick() {
+        let (runtime, base) = stub();
+        let config = base.config();
+
+        // the second amplifier is fed from the first via feedback:
+        let mut save = chain();
+        save.modules.get_mut(&id(3)).unwrap().feedback = vec![0];
+
+        runtime.enter(|| {
+            let mut workspace = Workspace::from_persist(&save, base);
+            let mut stat = EngineStat::new(config);
+
+            let stereo = |output: Option<Output>| -> Vec<Sample> {
+                match output {
+                    Some(Output::Stereo(samples)) => samples,
+                    _ => panic!("expected stereo output"),
+                }
+            };
+
+            let mut previous = vec![0.0; config.samples_per_tick() * 2];
+
+            for tick in 0..4 {
+                let mut output = stat.record_offline_tick(|tick_stat|
+                    engine::run_tick(&mut workspace, config, tick, tick_stat, &HashMap::new()));
+
+                let first = stereo(output.buffers.remove(&OutputId(id(2), 0)));
+                let second = stereo(output.buffers.remove(&OutputId(id(3), 0)));
+
+                assert!(first.iter().any(|sample| *sample != 0.0));
+                assert_eq!(second, previous);
+
+                previous = first;
+            }
+        });
+    }
+
+    #[test]
+    fn loads_legacy_cycles_as_feedback() {
+        let (runtime, base) = stub();
+
+        // saved before feedback connections existed, the first amplifier is
+        // fed back from the last without being marked as feedback:
+        let mut save = chain();
+        save.modules.get_mut(&id(2)).unwrap().inputs = vec![Some(OutputId(id(3), 0)), None];
+
+        runtime.enter(|| {
+            let workspace = Workspace::from_persist(&save, base);
+
+            assert_eq!(workspace.connections.get(&InputId(id(2), 0)), Some(&OutputId(id(3), 0)));
+            assert_eq!(workspace.connections.get(&InputId(id(3), 0)), Some(&OutputId(id(2), 0)));
+            assert_eq!(workspace.feedback.len(), 1);
+
+            // and it is saved as one from then on:
+            let persisted = workspace.to_persist();
+            let feedback = persisted.modules.values().map(|module| module.feedback.len()).sum::<usize>();
+            assert_eq!(feedback, 1);
+        });
+    }
+
+    #[test]
+    fn keeps_connections_of_faulted_modules() {
+        let runtime = runtime::Builder::new()
+            .basic_scheduler()
+            .build()
+            .expect("build tokio runtime");
+
+        let config = EngineConfig { sample_rate: 44100, ticks_per_second: 100 };
+        let base = ProjectBase::stub(config, runtime.handle().clone());
+
+        // oscillator -> amplifier -> plotter
+        let amplifier = ModuleParams::Amplifier(AmplifierParams { amplitude: 0.5, mod_depth: 0.0 });
+
+        let mut save = SavedWorkspace::default();
+        save.module_seq = 3;
+        save.modules.insert(id(1), saved(ModuleParams::Oscillator(OscillatorParams { freq: 440.0, waveform: Waveform::Sine }), vec![]));
+        save.modules.insert(id(2), saved(amplifier.clone(), vec![Some(OutputId(id(1), 1)), None]));
+        save.modules.insert(id(3), saved(ModuleParams::Plotter(()), vec![Some(OutputId(id(2), 0))]));
+
+        runtime.enter(|| {
+            let mut workspace = Workspace::from_persist(&save, base.clone());
+
+            // as if the amplifier had panicked on create:
+            workspace.modules.insert(id(2), faulted(amplifier));
+            workspace.apply_persist(&save, base);
+
+            let faulted = &workspace.modules[&id(2)];
+            assert_eq!(faulted.inputs().iter().map(|terminal| terminal.line_type()).collect::<Vec<_>>(),
+                vec![LineType::Stereo, LineType::Stereo]);
+            assert_eq!(faulted.outputs().len(), 1);
+
+            let persisted = workspace.to_persist();
+            assert_eq!(persisted.modules[&id(2)].inputs, vec![Some(OutputId(id(1), 1)), None]);
+            assert_eq!(persisted.modules[&id(3)].inputs, vec![Some(OutputId(id(2), 0))]);
+        });
+    }
+}
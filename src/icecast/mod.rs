@@ -11,9 +11,8 @@ use mixlab_codec::ogg::{self, OggStream};
 use mixlab_codec::{AudioStream, StreamRead, StreamError};
 use mixlab_util::time::{MediaTime, MediaDuration};
 
-use crate::engine::SAMPLE_RATE;
 use crate::listen::PeekTcpStream;
-use crate::source::{Registry, ListenError, SourceRecv, SourceSend};
+use crate::source::{Registry, ListenError, SourceRecv, SourceSend, AudioData};
 use crate::throttle::AudioThrottle;
 use crate::util::SyncRead;
 
@@ -91,13 +90,9 @@ fn run_decode_thread(mut send: SourceSend, stream: impl io::Read, content_type:
         return Ok(());
     }
 
-    if audio.sample_rate() != SAMPLE_RATE {
-        // not much we can do for now. TODO implement resampling
-        return Ok(());
-    }
-
+    let sample_rate = audio.sample_rate();
     let mut timestamp = MediaTime::zero();
-    let mut throttle = AudioThrottle::new();
+    let mut throttle = AudioThrottle::new(sample_rate);
 
     while let Some(packet) = audio.read().transpose() {
         match packet {
@@ -122,10 +117,10 @@ fn run_decode_thread(mut send: SourceSend, stream: impl io::Read, content_type:
                     }
                 }
 
-                send.write_audio(timestamp, samples)
+                send.write_audio(timestamp, AudioData { sample_rate, samples })
                     .map_err(|()| DecodeThreadError::ListenerDisconnected)?;
 
-                timestamp += MediaDuration::new(sample_count as i64, audio.sample_rate() as i64);
+                timestamp += MediaDuration::new(sample_count as i64, sample_rate as i64);
                 throttle.send_samples(sample_count);
             }
             Ok(StreamRead::Metadata(_)) => {
@@ -1,9 +1,13 @@
+mod api;
 mod db;
 mod engine;
 mod icecast;
 mod listen;
+mod metrics;
+mod osc;
 mod persist;
 mod project;
+mod render;
 mod rtmp;
 mod server;
 mod source;
@@ -14,6 +18,8 @@ mod video;
 #[macro_use]
 mod module;
 
+use std::env;
+
 use structopt::StructOpt;
 
 #[derive(StructOpt)]
@@ -22,10 +28,24 @@ struct Opts {
     run: server::RunOpts,
 }
 
+#[derive(StructOpt)]
+enum Command {
+    /// render a project to a file without starting the server
+    Render(render::RenderOpts),
+}
+
 fn main() {
     env_logger::init();
 
-    let opts = Opts::from_args();
+    // `mixlab <path>` starts the server and web UI. other commands are named
+    // by the first argument, so a project which is itself called `render`
+    // must be given as `./render`
+    let args = env::args_os().collect::<Vec<_>>();
+
+    let command = match args.get(1).and_then(|arg| arg.to_str()) {
+        Some("render") => Some(Command::from_iter(&args)),
+        _ => None,
+    };
 
     let mut runtime = tokio::runtime::Builder::new()
         .enable_all()
@@ -33,5 +53,16 @@ fn main() {
         .build()
         .unwrap();
 
-    runtime.block_on(server::run(opts.run));
+    match command {
+        None => {
+            let opts = Opts::from_iter(&args);
+            runtime.block_on(server::run(opts.run));
+        }
+        Some(Command::Render(opts)) => {
+            if let Err(e) = runtime.block_on(render::run(opts)) {
+                eprintln!("render failed: {:?}", e);
+                std::process::exit(1);
+            }
+        }
+    }
 }
@@ -0,0 +1,237 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+
+use mixlab_protocol::{PerformanceInfo, PerformanceAccount, EncoderQueueInfo};
+
+// Process wide counters for things which happen outside of the engine's own
+// tick accounting. They are read into each performance report, which is both
+// sent to clients and served as text from /metrics.
+
+// clients disconnected for falling too far behind the engine's update
+// broadcast, and the number of updates they missed:
+pub static LAGGED_CLIENTS: AtomicU64 = AtomicU64::new(0);
+pub static LAGGED_MESSAGES: AtomicU64 = AtomicU64::new(0);
+
+lazy_static::lazy_static! {
+    static ref QUEUES: Mutex<Vec<Weak<QueueState>>> = Mutex::new(Vec::new());
+}
+
+static QUEUE_SEQ: AtomicUsize = AtomicUsize::new(0);
+
+// tracks the depth of a queue feeding an encoder thread. clones share the same
+// queue, which is reported for as long as any clone is alive
+#[derive(Debug, Clone)]
+pub struct QueueGauge(Arc<QueueState>);
+
+#[derive(Debug)]
+struct QueueState {
+    name: String,
+    depth: AtomicUsize,
+    dropped: AtomicU64,
+}
+
+impl QueueGauge {
+    pub fn register(kind: &str) -> QueueGauge {
+        let seq = QUEUE_SEQ.fetch_add(1, Ordering::Relaxed) + 1;
+
+        let state = Arc::new(QueueState {
+            name: format!("{}-{}", kind, seq),
+            depth: AtomicUsize::new(0),
+            dropped: AtomicU64::new(0),
+        });
+
+        let mut queues = QUEUES.lock().expect("lock metrics queues");
+        queues.retain(|queue| queue.strong_count() > 0);
+        queues.push(Arc::downgrade(&state));
+
+        QueueGauge(state)
+    }
+
+    pub fn push(&self) {
+        self.0.depth.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn pop(&self) {
+        self.0.depth.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn drop_item(&self) {
+        self.0.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+pub fn encoder_queues() -> Vec<EncoderQueueInfo> {
+    let queues = QUEUES.lock().expect("lock metrics queues");
+
+    queues.iter()
+        .filter_map(Weak::upgrade)
+        .map(|queue| EncoderQueueInfo {
+            name: queue.name.clone(),
+            depth: queue.depth.load(Ordering::Relaxed),
+            dropped: queue.dropped.load(Ordering::Relaxed),
+        })
+        .collect()
+}
+
+// renders a performance report in the prometheus text exposition format
+pub fn render(info: &PerformanceInfo) -> String {
+    let mut out = String::new();
+
+    // writing to a string never fails
+    let _ = render_to(&mut out, info);
+
+    out
+}
+
+fn render_to(out: &mut String, info: &PerformanceInfo) -> std::fmt::Result {
+    header(out, "mixlab_realtime", "gauge", "Whether the engine is keeping up with real time.")?;
+    writeln!(out, "mixlab_realtime {}", info.realtime as u8)?;
+
+    header(out, "mixlab_tick_rate", "gauge", "Engine ticks per second.")?;
+    writeln!(out, "mixlab_tick_rate {}", info.tick_rate)?;
+
+    header(out, "mixlab_tick_budget_microseconds", "gauge", "Time available to run each tick.")?;
+    writeln!(out, "mixlab_tick_budget_microseconds {}", info.tick_budget.0)?;
+
+    header(out, "mixlab_tick_overruns_total", "counter", "Ticks which ran over their budget.")?;
+    writeln!(out, "mixlab_tick_overruns_total {}", info.overruns)?;
+
+    header(out, "mixlab_tick_microseconds", "summary", "Time taken per tick by the engine and by each module.")?;
+
+    for (account, metric) in &info.accounts {
+        let labels = account_labels(account);
+
+        writeln!(out, "mixlab_tick_microseconds{{{},quantile=\"0.5\"}} {}", labels, metric.p50.0)?;
+        writeln!(out, "mixlab_tick_microseconds{{{},quantile=\"0.99\"}} {}", labels, metric.p99.0)?;
+        writeln!(out, "mixlab_tick_microseconds{{{},quantile=\"1\"}} {}", labels, metric.max.0)?;
+    }
+
+    header(out, "mixlab_broadcast_lagged_clients_total", "counter", "Clients which fell behind the update broadcast.")?;
+    writeln!(out, "mixlab_broadcast_lagged_clients_total {}", info.lagged_clients)?;
+
+    header(out, "mixlab_broadcast_lagged_messages_total", "counter", "Updates missed by lagging clients.")?;
+    writeln!(out, "mixlab_broadcast_lagged_messages_total {}", info.lagged_messages)?;
+
+    header(out, "mixlab_encoder_queue_depth", "gauge", "Frames waiting to be encoded.")?;
+
+    for queue in &info.encoder_queues {
+        writeln!(out, "mixlab_encoder_queue_depth{{queue=\"{}\"}} {}", queue.name, queue.depth)?;
+    }
+
+    header(out, "mixlab_encoder_queue_dropped_total", "counter", "Frames dropped from a full encoder queue.")?;
+
+    for queue in &info.encoder_queues {
+        writeln!(out, "mixlab_encoder_queue_dropped_total{{queue=\"{}\"}} {}", queue.name, queue.dropped)?;
+    }
+
+    Ok(())
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) -> std::fmt::Result {
+    writeln!(out, "# HELP {} {}", name, help)?;
+    writeln!(out, "# TYPE {} {}", name, kind)
+}
+
+fn account_labels(account: &PerformanceAccount) -> String {
+    match account {
+        PerformanceAccount::Engine => "account=\"engine\"".to_owned(),
+        PerformanceAccount::Module(module_id) => format!("account=\"module\",module_id=\"{}\"", module_id.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use mixlab_protocol::{PerformanceInfo, PerformanceAccount, PerformanceMetric, Microseconds, ModuleId, EncoderQueueInfo};
+
+    fn metric(p50: u64, p99: u64, max: u64) -> PerformanceMetric {
+        PerformanceMetric {
+            last: Microseconds(p50),
+            p50: Microseconds(p50),
+            p99: Microseconds(p99),
+            max: Microseconds(max),
+        }
+    }
+
+    #[test]
+    fn renders_prometheus_text() {
+        let info = PerformanceInfo {
+            realtime: true,
+            lag: None,
+            tick_rate: 100,
+            tick_budget: Microseconds(10000),
+            overruns: 3,
+            accounts: vec![
+                (PerformanceAccount::Engine, metric(120, 400, 900)),
+                (PerformanceAccount::Module(ModuleId(NonZeroUsize::new(7).unwrap())), metric(10, 20, 30)),
+            ],
+            lagged_clients: 2,
+            lagged_messages: 41,
+            encoder_queues: vec![
+                EncoderQueueInfo { name: "monitor-1".to_owned(), depth: 4, dropped: 1 },
+            ],
+        };
+
+        let text = super::render(&info);
+        let lines = text.lines().collect::<Vec<_>>();
+
+        for (name, kind) in &[
+            ("mixlab_realtime", "gauge"),
+            ("mixlab_tick_rate", "gauge"),
+            ("mixlab_tick_budget_microseconds", "gauge"),
+            ("mixlab_tick_overruns_total", "counter"),
+            ("mixlab_tick_microseconds", "summary"),
+            ("mixlab_broadcast_lagged_clients_total", "counter"),
+            ("mixlab_broadcast_lagged_messages_total", "counter"),
+            ("mixlab_encoder_queue_depth", "gauge"),
+            ("mixlab_encoder_queue_dropped_total", "counter"),
+        ] {
+            // each metric has HELP then TYPE, before any of its samples:
+            let help = lines.iter().position(|line| line.starts_with(&format!("# HELP {} ", name)))
+                .unwrap_or_else(|| panic!("no HELP for {}", name));
+            assert_eq!(lines[help + 1], format!("# TYPE {} {}", name, kind));
+
+            let first_sample = lines.iter().position(|line| line.starts_with(name)).unwrap();
+            assert!(first_sample > help + 1);
+        }
+
+        for sample in &[
+            "mixlab_realtime 1",
+            "mixlab_tick_rate 100",
+            "mixlab_tick_budget_microseconds 10000",
+            "mixlab_tick_overruns_total 3",
+            "mixlab_tick_microseconds{account=\"engine\",quantile=\"0.5\"} 120",
+            "mixlab_tick_microseconds{account=\"engine\",quantile=\"0.99\"} 400",
+            "mixlab_tick_microseconds{account=\"engine\",quantile=\"1\"} 900",
+            "mixlab_tick_microseconds{account=\"module\",module_id=\"7\",quantile=\"0.5\"} 10",
+            "mixlab_broadcast_lagged_clients_total 2",
+            "mixlab_broadcast_lagged_messages_total 41",
+            "mixlab_encoder_queue_depth{queue=\"monitor-1\"} 4",
+            "mixlab_encoder_queue_dropped_total{queue=\"monitor-1\"} 1",
+        ] {
+            assert!(lines.contains(sample), "missing {:?} in:\n{}", sample, text);
+        }
+    }
+
+    #[test]
+    fn reports_registered_queues() {
+        let gauge = super::QueueGauge::register("metrics-test");
+        gauge.push();
+        gauge.push();
+        gauge.pop();
+        gauge.drop_item();
+
+        let queue = super::encoder_queues().into_iter()
+            .find(|queue| queue.name.starts_with("metrics-test-"))
+            .expect("registered queue");
+
+        assert_eq!(queue.depth, 1);
+        assert_eq!(queue.dropped, 1);
+
+        // queues are reported for as long as a gauge is alive:
+        drop(gauge);
+        assert!(super::encoder_queues().iter().all(|queue| !queue.name.starts_with("metrics-test-")));
+    }
+}
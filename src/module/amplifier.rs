@@ -1,4 +1,4 @@
-use crate::engine::{self, Sample, InputRef, OutputRef};
+use crate::engine::{self, Sample, InputRef, OutputRef, Smoothed, CHANNELS};
 use crate::module::{ModuleT, LineType, Terminal};
 
 use mixlab_protocol::AmplifierParams;
@@ -6,6 +6,7 @@ use mixlab_protocol::AmplifierParams;
 #[derive(Debug)]
 pub struct Amplifier {
     params: AmplifierParams,
+    amplitude: Smoothed,
     inputs: Vec<Terminal>,
     outputs: Vec<Terminal>,
 }
@@ -15,8 +16,9 @@ impl ModuleT for Amplifier {
     type Indication = ();
     type Event = ();
 
-    fn create(params: Self::Params, _: engine::ModuleCtx<Self>) -> (Self, Self::Indication) {
+    fn create(params: Self::Params, ctx: engine::ModuleCtx<Self>) -> (Self, Self::Indication) {
         (Self {
+            amplitude: Smoothed::new(params.amplitude, ctx.config().sample_rate),
             params,
             inputs: vec![
                 LineType::Stereo.labeled("Input"),
@@ -31,12 +33,17 @@ impl ModuleT for Amplifier {
     }
 
     fn update(&mut self, params: Self::Params) -> Option<Self::Indication> {
+        self.update_at(params, 0)
+    }
+
+    fn update_at(&mut self, params: Self::Params, offset: usize) -> Option<Self::Indication> {
+        self.amplitude.set_at(params.amplitude, offset);
         self.params = params;
         None
     }
 
     fn run_tick(&mut self, _t: u64, inputs: &[InputRef], outputs: &mut [OutputRef]) -> Option<Self::Indication> {
-        let AmplifierParams {mod_depth, amplitude} = self.params;
+        let mod_depth = self.params.mod_depth;
 
         let input = inputs[0].expect_stereo();
         let mod_input = if inputs[1].connected() {
@@ -49,7 +56,15 @@ impl ModuleT for Amplifier {
 
         let len = input.len();
 
+        self.amplitude.begin_tick();
+
+        let mut amplitude = 0.0;
+
         for i in 0..len {
+            if i % CHANNELS == 0 {
+                amplitude = self.amplitude.next();
+            }
+
             // mod input is a mono channel and so half the length:
             let mod_value = mod_input.map(|buff| buff[i / 2] as f64).unwrap_or(1.0);
 
@@ -71,3 +86,58 @@ impl ModuleT for Amplifier {
 pub fn depth(value: f64, depth: f64) -> f64 {
     1.0 - depth + depth * value
 }
+
+#[cfg(test)]
+mod tests {
+    use mixlab_protocol::AmplifierParams;
+
+    use crate::engine::{InputRef, Output, Sample};
+    use crate::engine::harness::{self, Harness};
+    use super::Amplifier;
+
+    fn tick(amp: &mut Harness<Amplifier>, input: &[Sample], control: InputRef) -> Vec<Sample> {
+        match &amp.tick(&[InputRef::Stereo(input), control])[..] {
+            [Output::Stereo(samples)] => samples.clone(),
+            _ => panic!("expected one stereo output"),
+        }
+    }
+
+    #[test]
+    fn ramps_gain_and_follows_control() {
+        let mut amp = Harness::<Amplifier>::new(AmplifierParams { amplitude: 1.0, mod_depth: 0.5 });
+        let control = amp.disconnected();
+
+        // a disconnected control input leaves the signal at full depth:
+        for _ in 0..2 {
+            let input = harness::stereo(&amp.sine(440.0, 0.5));
+            assert_eq!(tick(&mut amp, &input, control), input);
+        }
+
+        amp.update_at(AmplifierParams { amplitude: 0.25, mod_depth: 0.5 }, 10);
+
+        // untouched up to the offset of the change, then ramping down:
+        let input = harness::stereo(&amp.sine(440.0, 0.5));
+        let output = tick(&mut amp, &input, control);
+        assert_eq!(output[..20], input[..20]);
+        assert!(output[20..].iter().zip(&input[20..]).all(|(out, input)| out.abs() <= input.abs()));
+
+        // the ramp takes 10ms, or 441 samples:
+        for _ in 0..11 {
+            let input = harness::stereo(&amp.sine(440.0, 0.5));
+            tick(&mut amp, &input, control);
+        }
+
+        for _ in 0..3 {
+            let input = harness::stereo(&amp.sine(440.0, 0.5));
+            let control = amp.sine(50.0, 1.0);
+            let output = tick(&mut amp, &input, InputRef::Mono(&control));
+
+            for (i, (out, input)) in output.iter().zip(&input).enumerate() {
+                let expected = input * 0.25 * (0.5 + 0.5 * control[i / 2]);
+                assert!((out - expected).abs() < 1e-6, "sample {}: expected {}, got {}", i, expected, out);
+            }
+        }
+
+        amp.assert_fixture("amplifier");
+    }
+}
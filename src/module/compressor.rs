@@ -0,0 +1,250 @@
+use mixlab_protocol::{CompressorParams, CompressorIndication, Decibel, LineType, Terminal, COMPRESSOR_MAX_RATIO, COMPRESSOR_MAX_LOOKAHEAD_MS};
+
+use crate::engine::{self, Sample, InputRef, OutputRef, Smoothed, CHANNELS, level_db, time_coefficient};
+use crate::module::ModuleT;
+
+// gain reduction is indicated at most every this many ticks:
+const INDICATION_TICKS: usize = 6;
+
+// Feed forward compressor with a stereo linked peak detector. Gain reduction
+// is computed in decibels from the louder channel of the sidechain input, or
+// of the main input when the sidechain is disconnected, and smoothed with
+// separate attack and release times before being applied to both channels.
+#[derive(Debug)]
+pub struct Compressor {
+    params: CompressorParams,
+    sample_rate: usize,
+    makeup: Smoothed,
+    // smoothed gain reduction, in decibels:
+    reduction: f64,
+    // most gain reduction since the last indication, and the last value sent:
+    peak_reduction: f64,
+    indicated: f64,
+    count: usize,
+    // ring buffer of input frames for lookahead:
+    delay: Vec<[f64; CHANNELS]>,
+    delay_pos: usize,
+    inputs: Vec<Terminal>,
+    outputs: Vec<Terminal>,
+}
+
+impl ModuleT for Compressor {
+    type Params = CompressorParams;
+    type Indication = CompressorIndication;
+    type Event = ();
+
+    fn create(params: Self::Params, ctx: engine::ModuleCtx<Self>) -> (Self, Self::Indication) {
+        let sample_rate = ctx.config().sample_rate;
+        let max_lookahead = ms_to_samples(COMPRESSOR_MAX_LOOKAHEAD_MS, sample_rate);
+
+        (Self {
+            sample_rate,
+            makeup: Smoothed::new(params.makeup.to_linear(), sample_rate),
+            reduction: 0.0,
+            peak_reduction: 0.0,
+            indicated: 0.0,
+            count: 0,
+            delay: vec![[0.0; CHANNELS]; max_lookahead + 1],
+            delay_pos: 0,
+            inputs: vec![
+                LineType::Stereo.labeled("Input"),
+                LineType::Stereo.labeled("Sidechain"),
+            ],
+            outputs: vec![LineType::Stereo.unlabeled()],
+            params,
+        }, CompressorIndication { gain_reduction: Decibel(0.0) })
+    }
+
+    fn params(&self) -> Self::Params {
+        self.params.clone()
+    }
+
+    fn update(&mut self, params: Self::Params) -> Option<Self::Indication> {
+        self.update_at(params, 0)
+    }
+
+    fn update_at(&mut self, params: Self::Params, offset: usize) -> Option<Self::Indication> {
+        self.makeup.set_at(params.makeup.to_linear(), offset);
+        self.params = params;
+        None
+    }
+
+    fn run_tick(&mut self, _t: u64, inputs: &[InputRef], outputs: &mut [OutputRef]) -> Option<Self::Indication> {
+        let input = inputs[0].expect_stereo();
+
+        let sidechain = if inputs[1].connected() {
+            inputs[1].expect_stereo()
+        } else {
+            input
+        };
+
+        let output = outputs[0].expect_stereo();
+
+        let threshold = self.params.threshold.0;
+        let knee = self.params.knee.0.max(0.0);
+        let slope = ratio_slope(self.params.ratio);
+        let attack = time_coefficient(self.params.attack_ms, self.sample_rate);
+        let release = time_coefficient(self.params.release_ms, self.sample_rate);
+
+        let lookahead = ms_to_samples(self.params.lookahead_ms.max(0.0), self.sample_rate)
+            .min(self.delay.len() - 1);
+
+        self.makeup.begin_tick();
+
+        for ((frame, key), out) in input.chunks(CHANNELS).zip(sidechain.chunks(CHANNELS)).zip(output.chunks_mut(CHANNELS)) {
+            let target = gain_reduction(level_db(key), threshold, slope, knee);
+
+            let coefficient = if target > self.reduction { attack } else { release };
+            self.reduction = coefficient * self.reduction + (1.0 - coefficient) * target;
+            self.peak_reduction = self.peak_reduction.max(self.reduction);
+
+            let len = self.delay.len();
+            self.delay[self.delay_pos] = [frame[0] as f64, frame[1] as f64];
+            let delayed = self.delay[(self.delay_pos + len - lookahead) % len];
+            self.delay_pos = (self.delay_pos + 1) % len;
+
+            let gain = self.makeup.next() * Decibel(-self.reduction).to_linear();
+
+            for (out, sample) in out.iter_mut().zip(delayed.iter()) {
+                *out = (sample * gain) as Sample;
+            }
+        }
+
+        self.count += 1;
+
+        if self.count % INDICATION_TICKS != 0 {
+            return None;
+        }
+
+        // to the nearest tenth of a decibel, so that steady gain reduction
+        // isn't sent over and over:
+        let reduction = (self.peak_reduction * 10.0).round() / 10.0;
+        self.peak_reduction = 0.0;
+
+        if reduction == self.indicated {
+            return None;
+        }
+
+        self.indicated = reduction;
+        Some(CompressorIndication { gain_reduction: Decibel(reduction) })
+    }
+
+    fn inputs(&self) -> &[Terminal] {
+        &self.inputs
+    }
+
+    fn outputs(&self) -> &[Terminal] {
+        &self.outputs
+    }
+}
+
+// fraction of the overshoot above threshold which is taken off
+fn ratio_slope(ratio: f64) -> f64 {
+    if ratio >= COMPRESSOR_MAX_RATIO {
+        1.0
+    } else {
+        1.0 - 1.0 / ratio.max(1.0)
+    }
+}
+
+// gain reduction in decibels for a level, with a soft knee `knee` decibels
+// wide, centred on the threshold
+fn gain_reduction(level: f64, threshold: f64, slope: f64, knee: f64) -> f64 {
+    let overshoot = level - threshold;
+
+    if knee > 0.0 && overshoot.abs() <= knee / 2.0 {
+        slope * (overshoot + knee / 2.0).powi(2) / (2.0 * knee)
+    } else if overshoot > 0.0 {
+        slope * overshoot
+    } else {
+        0.0
+    }
+}
+
+fn ms_to_samples(ms: f64, sample_rate: usize) -> usize {
+    (ms * sample_rate as f64 / 1000.0).round() as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use mixlab_protocol::{CompressorParams, Decibel};
+
+    use crate::engine::{InputRef, Output};
+    use crate::engine::harness::{self, Harness};
+    use super::{Compressor, gain_reduction};
+
+    fn params() -> CompressorParams {
+        CompressorParams {
+            threshold: Decibel(-18.0),
+            ratio: 4.0,
+            attack_ms: 1.0,
+            release_ms: 20.0,
+            knee: Decibel(0.0),
+            makeup: Decibel(0.0),
+            lookahead_ms: 0.0,
+        }
+    }
+
+    #[test]
+    fn soft_knee_meets_hard_knee() {
+        // the knee joins up with no reduction below it and the full ratio
+        // above it:
+        assert_eq!(gain_reduction(-21.0, -18.0, 0.75, 6.0), 0.0);
+        assert!((gain_reduction(-15.0, -18.0, 0.75, 6.0) - gain_reduction(-15.0, -18.0, 0.75, 0.0)).abs() < 1e-9);
+        assert!(gain_reduction(-18.0, -18.0, 0.75, 6.0) > 0.0);
+    }
+
+    #[test]
+    fn compresses_above_threshold() {
+        let mut comp = Harness::<Compressor>::new(params());
+        let none = comp.disconnected();
+
+        // half scale is about 12 dB over threshold, so settles at 9 dB of
+        // gain reduction:
+        let loud = vec![0.5; comp.samples_per_tick() * 2];
+
+        for _ in 0..60 {
+            comp.tick(&[InputRef::Stereo(&loud), none]);
+        }
+
+        let expected = 0.5 * f64::powf(10.0, -0.75 * (20.0 * 0.5f64.log10() + 18.0) / 20.0);
+
+        match &comp.tick(&[InputRef::Stereo(&loud), none])[..] {
+            [Output::Stereo(samples)] => {
+                assert!(samples.iter().all(|sample| (*sample as f64 - expected).abs() < 1e-4));
+            }
+            _ => panic!("expected one stereo output"),
+        }
+
+        // and recovers as a quieter signal falls below threshold:
+        for _ in 0..60 {
+            let quiet = harness::stereo(&comp.sine(440.0, 0.05));
+            comp.tick(&[InputRef::Stereo(&quiet), none]);
+        }
+
+        comp.assert_fixture("compressor");
+    }
+
+    #[test]
+    fn sidechain_drives_reduction() {
+        let mut comp = Harness::<Compressor>::new(CompressorParams { lookahead_ms: 1.0, ..params() });
+
+        let input = vec![0.1; comp.samples_per_tick() * 2];
+        let key = vec![1.0; comp.samples_per_tick() * 2];
+
+        for _ in 0..60 {
+            comp.tick(&[InputRef::Stereo(&input), InputRef::Stereo(&key)]);
+        }
+
+        match &comp.tick(&[InputRef::Stereo(&input), InputRef::Stereo(&key)])[..] {
+            [Output::Stereo(samples)] => {
+                // 18 dB over threshold at 4:1 takes 13.5 dB off the input:
+                let expected = 0.1 * f64::powf(10.0, -13.5 / 20.0);
+                assert!(samples.iter().all(|sample| (*sample as f64 - expected).abs() < 1e-4));
+            }
+            _ => panic!("expected one stereo output"),
+        }
+
+        comp.assert_fixture("compressor_sidechain");
+    }
+}
@@ -0,0 +1,252 @@
+use mixlab_protocol::{DelayParams, FilterType, Decibel, LineType, Terminal, DELAY_MAX_MS};
+
+use crate::engine::{self, Sample, InputRef, OutputRef, Smoothed, TransportRef, CHANNELS};
+use crate::module::ModuleT;
+use crate::module::parametric_eq::{Biquad, Coefficients};
+
+// feedback is kept just short of unity so repeats always die away:
+const MAX_FEEDBACK: f64 = 0.99;
+const FILTER_Q: f64 = 0.707;
+
+#[derive(Debug)]
+pub struct Delay {
+    params: DelayParams,
+    sample_rate: usize,
+    transport: TransportRef,
+    // delay time in samples. changes glide rather than jump, which bends the
+    // pitch of the repeats a little instead of clicking
+    time: Smoothed,
+    mix: Smoothed,
+    // ring buffer of stereo frames, long enough for the longest delay:
+    buffer: Vec<[f64; CHANNELS]>,
+    write_pos: usize,
+    filter: Coefficients,
+    filters: [Biquad; CHANNELS],
+    inputs: Vec<Terminal>,
+    outputs: Vec<Terminal>,
+}
+
+impl ModuleT for Delay {
+    type Params = DelayParams;
+    type Indication = ();
+    type Event = ();
+
+    fn create(params: Self::Params, ctx: engine::ModuleCtx<Self>) -> (Self, Self::Indication) {
+        let sample_rate = ctx.config().sample_rate;
+        let transport = ctx.transport();
+        let max_samples = (DELAY_MAX_MS * sample_rate as f64 / 1000.0) as usize;
+
+        let mut delay = Self {
+            time: Smoothed::new(0.0, sample_rate),
+            mix: Smoothed::new(params.mix, sample_rate),
+            filter: filter(&params, sample_rate),
+            params,
+            sample_rate,
+            transport,
+            buffer: vec![[0.0; CHANNELS]; max_samples + 2],
+            write_pos: 0,
+            filters: Default::default(),
+            inputs: vec![LineType::Stereo.unlabeled()],
+            outputs: vec![LineType::Stereo.unlabeled()],
+        };
+
+        delay.time = Smoothed::new(delay.time_samples(), sample_rate);
+
+        (delay, ())
+    }
+
+    fn params(&self) -> Self::Params {
+        self.params.clone()
+    }
+
+    fn update(&mut self, params: Self::Params) -> Option<Self::Indication> {
+        self.update_at(params, 0)
+    }
+
+    fn update_at(&mut self, params: Self::Params, offset: usize) -> Option<Self::Indication> {
+        self.mix.set_at(params.mix, offset);
+        self.filter = filter(&params, self.sample_rate);
+        self.params = params;
+
+        let time = self.time_samples();
+        self.time.set_at(time, offset);
+        None
+    }
+
+    fn run_tick(&mut self, _t: u64, inputs: &[InputRef], outputs: &mut [OutputRef]) -> Option<Self::Indication> {
+        // synced delays follow tempo changes:
+        let time = self.time_samples();
+
+        if time != self.time.target() {
+            self.time.set_at(time, 0);
+        }
+
+        let input = inputs[0].expect_stereo();
+        let output = outputs[0].expect_stereo();
+
+        let feedback = self.params.feedback.max(0.0).min(MAX_FEEDBACK);
+
+        self.time.begin_tick();
+        self.mix.begin_tick();
+
+        for (frame, out) in input.chunks(CHANNELS).zip(output.chunks_mut(CHANNELS)) {
+            let time = self.time.next();
+            let delayed = self.read(time);
+
+            let repeat_l = self.filters[0].process(&self.filter, delayed[0]) * feedback;
+            let repeat_r = self.filters[1].process(&self.filter, delayed[1]) * feedback;
+
+            let (left, right) = (frame[0] as f64, frame[1] as f64);
+
+            self.buffer[self.write_pos] = if self.params.ping_pong {
+                // the input goes in on the left, then repeats cross over:
+                [(left + right) / 2.0 + repeat_r, repeat_l]
+            } else {
+                [left + repeat_l, right + repeat_r]
+            };
+
+            self.write_pos = (self.write_pos + 1) % self.buffer.len();
+
+            let mix = self.mix.next();
+            out[0] = (left * (1.0 - mix) + delayed[0] * mix) as Sample;
+            out[1] = (right * (1.0 - mix) + delayed[1] * mix) as Sample;
+        }
+
+        None
+    }
+
+    fn inputs(&self) -> &[Terminal] {
+        &self.inputs
+    }
+
+    fn outputs(&self) -> &[Terminal] {
+        &self.outputs
+    }
+}
+
+impl Delay {
+    fn time_samples(&self) -> f64 {
+        let samples = match self.params.sync {
+            Some(note) => {
+                let now = self.transport.now();
+                note.beats(&now.tempo()) * now.samples_per_beat()
+            }
+            None => self.params.time_ms * self.sample_rate as f64 / 1000.0,
+        };
+
+        samples.max(1.0).min((self.buffer.len() - 2) as f64)
+    }
+
+    // reads the frame `delay` samples before the next to be written,
+    // interpolating between samples
+    fn read(&self, delay: f64) -> [f64; CHANNELS] {
+        let len = self.buffer.len();
+        let pos = self.write_pos as f64 - delay + len as f64;
+
+        let index = pos.floor() as usize % len;
+        let frac = pos.fract();
+
+        let a = self.buffer[index];
+        let b = self.buffer[(index + 1) % len];
+
+        [
+            a[0] + (b[0] - a[0]) * frac,
+            a[1] + (b[1] - a[1]) * frac,
+        ]
+    }
+}
+
+fn filter(params: &DelayParams, sample_rate: usize) -> Coefficients {
+    Coefficients::new(FilterType::LowPass, params.filter_freq, FILTER_Q, Decibel(0.0), sample_rate)
+}
+
+#[cfg(test)]
+mod tests {
+    use mixlab_protocol::{DelayParams, NoteValue, Tempo, TransportOp};
+
+    use crate::engine::{InputRef, Output, Sample};
+    use crate::engine::harness::Harness;
+    use super::Delay;
+
+    // runs the delay over an impulse on the left channel, returning the
+    // left and right output
+    fn impulse(delay: &mut Harness<Delay>, ticks: usize) -> (Vec<Sample>, Vec<Sample>) {
+        let mut input = vec![0.0; delay.samples_per_tick() * 2];
+        input[0] = 1.0;
+
+        let silence = vec![0.0; input.len()];
+
+        let mut left = Vec::new();
+        let mut right = Vec::new();
+
+        for tick in 0..ticks {
+            let input = if tick == 0 { &input } else { &silence };
+
+            match &delay.tick(&[InputRef::Stereo(input)])[..] {
+                [Output::Stereo(samples)] => {
+                    left.extend(samples.iter().step_by(2));
+                    right.extend(samples.iter().skip(1).step_by(2));
+                }
+                _ => panic!("expected one stereo output"),
+            }
+        }
+
+        (left, right)
+    }
+
+    fn peaks(samples: &[Sample]) -> Vec<usize> {
+        samples.iter()
+            .enumerate()
+            .filter(|(_, sample)| sample.abs() > 0.01)
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    #[test]
+    fn repeats_ping_pong() {
+        // 2ms at 44.1 kHz is 88.2 samples
+        let mut delay = Harness::<Delay>::new(DelayParams {
+            time_ms: 2.0,
+            sync: None,
+            feedback: 0.5,
+            ping_pong: true,
+            mix: 1.0,
+            filter_freq: 20000.0,
+        });
+
+        let (left, right) = impulse(&mut delay, 10);
+        let (left, right) = (peaks(&left), peaks(&right));
+
+        // the first repeat comes out on the left, the next on the right:
+        assert!((88..=89).contains(&left[0]));
+        assert!((176..=178).contains(&right[0]));
+
+        delay.assert_fixture("delay");
+    }
+
+    #[test]
+    fn syncs_to_tempo() {
+        let mut delay = Harness::<Delay>::new(DelayParams {
+            sync: Some(NoteValue::Sixteenth),
+            mix: 1.0,
+            feedback: 0.0,
+            ..DelayParams::default()
+        });
+
+        delay.transport(TransportOp::SetTempo(Tempo { bpm: 999.0, ..Tempo::default() }));
+
+        // let the delay time glide to the new tempo, which takes 10ms:
+        delay.idle(12);
+
+        // a sixteenth is a quarter of a beat, 60 / 999 / 4 seconds:
+        let expected = 44100.0 * 60.0 / 999.0 / 4.0;
+
+        let (left, _) = impulse(&mut delay, 20);
+        let peaks = peaks(&left);
+
+        assert!(!peaks.is_empty());
+        assert!(peaks.iter().all(|index| (*index as f64 - expected).abs() < 2.0));
+
+        delay.assert_fixture("delay_sync");
+    }
+}
@@ -0,0 +1,159 @@
+use std::f64;
+
+use mixlab_protocol::{DownmixParams, ChannelLayout, Speaker, LineType, Terminal};
+
+use crate::engine::{self, Sample, InputRef, OutputRef, CHANNELS};
+use crate::module::ModuleT;
+
+// level of the centre and surround speakers in the fold down, -3dB:
+const FOLD: f64 = f64::consts::FRAC_1_SQRT_2;
+
+#[derive(Debug)]
+pub struct Downmix {
+    params: DownmixParams,
+    inputs: Vec<Terminal>,
+    outputs: Vec<Terminal>,
+}
+
+impl ModuleT for Downmix {
+    type Params = DownmixParams;
+    type Indication = ();
+    type Event = ();
+
+    fn create(params: Self::Params, _: engine::ModuleCtx<Self>) -> (Self, Self::Indication) {
+        (Self {
+            inputs: vec![params.layout.line_type().unlabeled()],
+            outputs: vec![LineType::Stereo.unlabeled()],
+            params,
+        }, ())
+    }
+
+    fn params(&self) -> Self::Params {
+        self.params.clone()
+    }
+
+    fn update(&mut self, params: Self::Params) -> Option<Self::Indication> {
+        self.inputs = vec![params.layout.line_type().unlabeled()];
+        self.params = params;
+        None
+    }
+
+    fn run_tick(&mut self, _t: u64, inputs: &[InputRef], outputs: &mut [OutputRef]) -> Option<Self::Indication> {
+        let layout = self.params.layout;
+        let input = inputs[0].expect_multi(layout.channels());
+        let output = outputs[0].expect_stereo();
+
+        for (frame, out) in input.chunks(layout.channels()).zip(output.chunks_mut(CHANNELS)) {
+            let (left, right) = downmix_frame(layout, frame);
+            out[0] = left as Sample;
+            out[1] = right as Sample;
+        }
+
+        None
+    }
+
+    fn inputs(&self) -> &[Terminal] {
+        &self.inputs
+    }
+
+    fn outputs(&self) -> &[Terminal] {
+        &self.outputs
+    }
+}
+
+// folds one frame of a multi-channel line down to left and right. the LFE
+// channel is dropped, as is usual for stereo
+pub fn downmix_frame(layout: ChannelLayout, frame: &[Sample]) -> (f64, f64) {
+    let mut left = 0.0;
+    let mut right = 0.0;
+
+    for (speaker, sample) in layout.speakers().iter().zip(frame) {
+        let sample = *sample as f64;
+
+        match speaker {
+            Speaker::Left => { left += sample; }
+            Speaker::Right => { right += sample; }
+            Speaker::Centre => {
+                left += FOLD * sample;
+                right += FOLD * sample;
+            }
+            Speaker::Lfe => {}
+            Speaker::SurroundLeft | Speaker::RearLeft => { left += FOLD * sample; }
+            Speaker::SurroundRight | Speaker::RearRight => { right += FOLD * sample; }
+        }
+    }
+
+    (left, right)
+}
+
+#[cfg(test)]
+mod tests {
+    use mixlab_protocol::{ChannelLayout, DownmixParams, UpmixParams};
+
+    use crate::engine::{InputRef, Output};
+    use crate::engine::harness::{self, Harness};
+    use crate::module::ModuleT;
+    use crate::module::upmix::Upmix;
+    use super::{Downmix, FOLD};
+
+    #[test]
+    fn folds_surround_to_stereo() {
+        let mut downmix = Harness::<Downmix>::new(DownmixParams { layout: ChannelLayout::Surround51 });
+
+        for _ in 0..2 {
+            // a different tone on each speaker:
+            let speakers = (0..6)
+                .map(|i| downmix.sine(220.0 * (i + 1) as f64, 0.25))
+                .collect::<Vec<_>>();
+
+            let input = (0..downmix.samples_per_tick())
+                .flat_map(|i| speakers.iter().map(move |speaker| speaker[i]))
+                .collect::<Vec<_>>();
+
+            let output = match &downmix.tick(&[InputRef::Multi(6, &input)])[..] {
+                [Output::Stereo(samples)] => samples.clone(),
+                _ => panic!("expected one stereo output"),
+            };
+
+            // centre and surrounds come in at -3dB, and the lfe is dropped:
+            for (i, out) in output.chunks(2).enumerate() {
+                let speaker = |n: usize| speakers[n][i] as f64;
+
+                let left = speaker(0) + FOLD * speaker(2) + FOLD * speaker(4);
+                let right = speaker(1) + FOLD * speaker(2) + FOLD * speaker(5);
+
+                assert!((out[0] as f64 - left).abs() < 1e-6);
+                assert!((out[1] as f64 - right).abs() < 1e-6);
+            }
+        }
+
+        downmix.update(DownmixParams { layout: ChannelLayout::Quad });
+        assert_eq!(downmix.module().inputs(), &[ChannelLayout::Quad.line_type().unlabeled()]);
+        downmix.idle(1);
+
+        downmix.assert_fixture("downmix");
+    }
+
+    #[test]
+    fn round_trips_plain_upmix() {
+        for layout in ChannelLayout::ALL {
+            let mut upmix = Harness::<Upmix>::new(UpmixParams { layout: *layout, centre: 0.0, surround: 0.0 });
+            let mut downmix = Harness::<Downmix>::new(DownmixParams { layout: *layout });
+
+            let stereo = harness::stereo(&upmix.sine(440.0, 0.5));
+
+            let multi = match &upmix.tick(&[InputRef::Stereo(&stereo)])[..] {
+                [Output::Multi(channels, samples)] => {
+                    assert_eq!(*channels, layout.channels());
+                    samples.clone()
+                }
+                _ => panic!("expected one multi-channel output"),
+            };
+
+            match &downmix.tick(&[InputRef::Multi(layout.channels(), &multi)])[..] {
+                [Output::Stereo(samples)] => assert_eq!(samples, &stereo),
+                _ => panic!("expected one stereo output"),
+            }
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use crate::engine::{self, InputRef, OutputRef, SAMPLE_RATE};
+use crate::engine::{self, InputRef, OutputRef, EventMessage};
 use crate::module::{ModuleT, LineType, Terminal};
 
 use mixlab_protocol::EnvelopeParams;
@@ -13,8 +13,8 @@ enum EnvelopeState {
 }
 
 type Ms = f64;
-fn sample_seq_duration_ms(first: SampleSeq, last: SampleSeq) -> Ms {
-    (last - first) as f64 / SAMPLE_RATE as f64 * 1000.0
+fn sample_seq_duration_ms(first: SampleSeq, last: SampleSeq, sample_rate: usize) -> Ms {
+    (last - first) as f64 / sample_rate as f64 * 1000.0
 }
 
 fn clamp(x: f64) -> f64 {
@@ -31,11 +31,11 @@ fn invert(x: f64) -> f64 {
     1.0 - x
 }
 
-fn amplitude(params: &EnvelopeParams, state: &EnvelopeState, t: SampleSeq) -> f64 {
+fn amplitude(params: &EnvelopeParams, state: &EnvelopeState, t: SampleSeq, sample_rate: usize) -> f64 {
     match state {
         EnvelopeState::Initial => 0.0,
         EnvelopeState::TriggerOn {on} => {
-            let ms_since_on = sample_seq_duration_ms(*on, t);
+            let ms_since_on = sample_seq_duration_ms(*on, t, sample_rate);
 
             if ms_since_on < params.attack_ms {
                 // Currently in attack phase
@@ -49,7 +49,7 @@ fn amplitude(params: &EnvelopeParams, state: &EnvelopeState, t: SampleSeq) -> f6
             }
         }
         EnvelopeState::TriggerOff {off, off_amplitude} => {
-            let ms_since_off = sample_seq_duration_ms(*off, t);
+            let ms_since_off = sample_seq_duration_ms(*off, t, sample_rate);
             let release_amplitude = invert(clamp(1.0 / params.release_ms * ms_since_off));
 
             off_amplitude * release_amplitude
@@ -57,10 +57,27 @@ fn amplitude(params: &EnvelopeParams, state: &EnvelopeState, t: SampleSeq) -> f6
     }
 }
 
+impl Envelope {
+    fn release(&mut self, sample_seq: SampleSeq) {
+        if let EnvelopeState::TriggerOn { .. } = self.state {
+            self.state = EnvelopeState::TriggerOff {
+                off: sample_seq,
+                off_amplitude: amplitude(&self.params, &self.state, sample_seq, self.sample_rate)
+            };
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Envelope {
     params: EnvelopeParams,
     state: EnvelopeState,
+    // the note that triggered the envelope when triggered by events, only its
+    // note off releases it:
+    note: Option<u8>,
+    // peak amplitude, scaled by note velocity:
+    level: f64,
+    sample_rate: usize,
     inputs: Vec<Terminal>,
     outputs: Vec<Terminal>,
 }
@@ -70,11 +87,17 @@ impl ModuleT for Envelope {
     type Indication = ();
     type Event = ();
 
-    fn create(params: Self::Params, _: engine::ModuleCtx<Self>) -> (Self, Self::Indication) {
+    fn create(params: Self::Params, ctx: engine::ModuleCtx<Self>) -> (Self, Self::Indication) {
         (Self {
             params,
             state: EnvelopeState::Initial,
-            inputs: vec![LineType::Mono.unlabeled()],
+            note: None,
+            level: 1.0,
+            sample_rate: ctx.config().sample_rate,
+            inputs: vec![
+                LineType::Mono.labeled("Gate"),
+                LineType::Event.labeled("Events"),
+            ],
             outputs: vec![LineType::Mono.unlabeled()],
         }, ())
     }
@@ -89,7 +112,10 @@ impl ModuleT for Envelope {
     }
 
     fn run_tick(&mut self, t: u64, inputs: &[InputRef], outputs: &mut [OutputRef]) -> Option<Self::Indication> {
-        let input = inputs[0].expect_mono();
+        let gate = &inputs[0];
+        let input = gate.expect_mono();
+        let events = inputs[1].expect_events();
+        let mut next_event = 0;
         let output = outputs[0].expect_mono();
 
         let len = input.len();
@@ -97,23 +123,44 @@ impl ModuleT for Envelope {
             let sample_seq = t + i as u64;
 
             // First, process input
-            match self.state {
-                EnvelopeState::Initial | EnvelopeState::TriggerOff { .. } => {
-                    if input[i] == 1.0 {
+            while let Some(event) = events.get(next_event).filter(|event| event.offset <= i) {
+                next_event += 1;
+
+                match event.message {
+                    EventMessage::NoteOn { note, velocity } => {
+                        self.note = Some(note);
+                        self.level = velocity as f64 / 127.0;
                         self.state = EnvelopeState::TriggerOn { on: sample_seq };
                     }
+                    EventMessage::NoteOff { note } if self.note == Some(note) => {
+                        self.note = None;
+                        self.release(sample_seq);
+                    }
+                    _ => {}
                 }
-                EnvelopeState::TriggerOn {..} => {
-                    if input[i] == 0.0 {
-                        self.state = EnvelopeState::TriggerOff {
-                            off: sample_seq,
-                            off_amplitude: amplitude(&self.params, &self.state, sample_seq)
-                        };
+            }
+
+            // a disconnected gate would read as closed, releasing notes
+            // triggered by events
+            if gate.connected() {
+                match self.state {
+                    EnvelopeState::Initial | EnvelopeState::TriggerOff { .. } => {
+                        if input[i] == 1.0 {
+                            self.note = None;
+                            self.level = 1.0;
+                            self.state = EnvelopeState::TriggerOn { on: sample_seq };
+                        }
+                    }
+                    EnvelopeState::TriggerOn {..} => {
+                        if input[i] == 0.0 {
+                            self.release(sample_seq);
+                        }
                     }
                 }
             }
+
             // Then set output
-            output[i] = amplitude(&self.params, &self.state, sample_seq) as f32;
+            output[i] = (self.level * amplitude(&self.params, &self.state, sample_seq, self.sample_rate)) as f32;
         }
 
         None
@@ -127,3 +174,104 @@ impl ModuleT for Envelope {
         &self.outputs
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use mixlab_protocol::EnvelopeParams;
+
+    use crate::engine::{InputRef, Output, Sample, TimedEvent, EventMessage};
+    use crate::engine::harness::Harness;
+    use super::Envelope;
+
+    // at 44.1 kHz these are 88.2, 132.3 and 176.4 samples long:
+    fn params() -> EnvelopeParams {
+        EnvelopeParams {
+            attack_ms: 2.0,
+            decay_ms: 3.0,
+            sustain_amplitude: 0.5,
+            release_ms: 4.0,
+        }
+    }
+
+    fn tick(env: &mut Harness<Envelope>, inputs: &[InputRef], output: &mut Vec<Sample>) {
+        match &env.tick(inputs)[..] {
+            [Output::Mono(samples)] => output.extend(samples),
+            _ => panic!("expected one mono output"),
+        }
+    }
+
+    #[test]
+    fn attack_decay_sustain_release() {
+        let mut env = Harness::<Envelope>::new(params());
+
+        let gate_on = vec![1.0; env.samples_per_tick()];
+        let gate_off = vec![0.0; env.samples_per_tick()];
+
+        let none = env.disconnected();
+        let mut output = Vec::new();
+
+        for _ in 0..8 {
+            tick(&mut env, &[InputRef::Mono(&gate_on), none], &mut output);
+        }
+
+        let released = output.len();
+
+        for _ in 0..6 {
+            tick(&mut env, &[InputRef::Mono(&gate_off), none], &mut output);
+        }
+
+        // rises to full, falls to sustain and holds there:
+        assert_eq!(output[0], 0.0);
+        assert!(output[..89].windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(output[88] > 0.99);
+        assert!(output[89..221].windows(2).all(|pair| pair[0] >= pair[1]));
+        assert!(output[221..released].iter().all(|sample| *sample == 0.5));
+
+        // then releases to nothing:
+        assert!(output[released..].windows(2).all(|pair| pair[0] >= pair[1]));
+        assert!(output[released + 177..].iter().all(|sample| *sample == 0.0));
+
+        env.assert_fixture("envelope");
+    }
+
+    #[test]
+    fn triggered_by_note_events() {
+        let mut env = Harness::<Envelope>::new(params());
+
+        let none = env.disconnected();
+        let mut output = Vec::new();
+
+        let note_on = [TimedEvent { offset: 10, message: EventMessage::NoteOn { note: 64, velocity: 100 } }];
+        tick(&mut env, &[none, InputRef::Events(&note_on)], &mut output);
+
+        for _ in 0..7 {
+            tick(&mut env, &[none, none], &mut output);
+        }
+
+        // sustains at a level scaled by the note's velocity:
+        let sustain = (0.5 * 100.0 / 127.0) as Sample;
+
+        assert!(output[..10].iter().all(|sample| *sample == 0.0));
+        assert!(output[10..].iter().any(|sample| *sample > 0.75));
+        assert_eq!(output.last(), Some(&sustain));
+
+        // releases only on the note that triggered it:
+        let other_off = [TimedEvent { offset: 0, message: EventMessage::NoteOff { note: 65 } }];
+        tick(&mut env, &[none, InputRef::Events(&other_off)], &mut output);
+        assert_eq!(output.last(), Some(&sustain));
+
+        let released = output.len() + 20;
+
+        let note_off = [TimedEvent { offset: 20, message: EventMessage::NoteOff { note: 64 } }];
+        tick(&mut env, &[none, InputRef::Events(&note_off)], &mut output);
+
+        for _ in 0..5 {
+            tick(&mut env, &[none, none], &mut output);
+        }
+
+        assert_eq!(output[released - 1], sustain);
+        assert!(output[released + 177..].iter().all(|sample| *sample == 0.0));
+
+        env.assert_fixture("envelope_events");
+    }
+}
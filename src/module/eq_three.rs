@@ -2,7 +2,7 @@ use std::f64;
 
 use mixlab_protocol::EqThreeParams;
 
-use crate::engine::{self, InputRef, OutputRef, SAMPLE_RATE};
+use crate::engine::{self, InputRef, OutputRef, Smoothed};
 use crate::module::{ModuleT, LineType, Terminal};
 
 const FREQ_LO: f64 = 420.0;
@@ -21,6 +21,11 @@ pub struct EqThree {
     // sample history
     history: [f64; 3],
 
+    // band gains
+    gain_lo: Smoothed,
+    gain_mid: Smoothed,
+    gain_hi: Smoothed,
+
     inputs: Vec<Terminal>,
     outputs: Vec<Terminal>,
 }
@@ -30,15 +35,19 @@ impl ModuleT for EqThree {
     type Indication = ();
     type Event = ();
 
-    fn create(params: Self::Params, _: engine::ModuleCtx<Self>) -> (Self, Self::Indication) {
-        let lo = LowPass::new(FREQ_LO);
-        let hi = LowPass::new(FREQ_HI);
+    fn create(params: Self::Params, ctx: engine::ModuleCtx<Self>) -> (Self, Self::Indication) {
+        let sample_rate = ctx.config().sample_rate;
+        let lo = LowPass::new(FREQ_LO, sample_rate);
+        let hi = LowPass::new(FREQ_HI, sample_rate);
 
         let eq_three = Self {
-            params,
             lo,
             hi,
             history: [0.0; 3],
+            gain_lo: Smoothed::new(params.gain_lo.to_linear(), sample_rate),
+            gain_mid: Smoothed::new(params.gain_mid.to_linear(), sample_rate),
+            gain_hi: Smoothed::new(params.gain_hi.to_linear(), sample_rate),
+            params,
             inputs: vec![LineType::Mono.unlabeled()],
             outputs: vec![LineType::Mono.unlabeled()],
         };
@@ -51,6 +60,13 @@ impl ModuleT for EqThree {
     }
 
     fn update(&mut self, params: Self::Params) -> Option<Self::Indication> {
+        self.update_at(params, 0)
+    }
+
+    fn update_at(&mut self, params: Self::Params, offset: usize) -> Option<Self::Indication> {
+        self.gain_lo.set_at(params.gain_lo.to_linear(), offset);
+        self.gain_mid.set_at(params.gain_mid.to_linear(), offset);
+        self.gain_hi.set_at(params.gain_hi.to_linear(), offset);
         self.params = params;
         None
     }
@@ -59,9 +75,9 @@ impl ModuleT for EqThree {
         let input = inputs[0].expect_mono();
         let output = outputs[0].expect_mono();
 
-        let gain_lo = self.params.gain_lo.to_linear();
-        let gain_mid = self.params.gain_mid.to_linear();
-        let gain_hi = self.params.gain_hi.to_linear();
+        self.gain_lo.begin_tick();
+        self.gain_mid.begin_tick();
+        self.gain_hi.begin_tick();
 
         for (input, output) in input.iter().copied().zip(output.iter_mut()) {
             let sample = input as f64;
@@ -78,9 +94,9 @@ impl ModuleT for EqThree {
 
             // apply gain
 
-            let lo = lo * gain_lo;
-            let mid = mid * gain_mid;
-            let hi = hi * gain_hi;
+            let lo = lo * self.gain_lo.next();
+            let mid = mid * self.gain_mid.next();
+            let hi = hi * self.gain_hi.next();
 
             *output = (lo + mid + hi) as f32;
         }
@@ -104,14 +120,14 @@ struct LowPass {
 }
 
 impl LowPass {
-    pub fn new(freq: f64) -> Self {
+    pub fn new(freq: f64, sample_rate: usize) -> Self {
         let mut filter = LowPass { freq: 0.0, poles: [0.0, 0.0, 0.0, 0.0] };
-        filter.set_freq(freq);
+        filter.set_freq(freq, sample_rate);
         filter
     }
 
-    pub fn set_freq(&mut self, freq: f64) {
-        self.freq = 2.0 * f64::sin(f64::consts::PI * freq / (SAMPLE_RATE as f64));
+    pub fn set_freq(&mut self, freq: f64, sample_rate: usize) {
+        self.freq = 2.0 * f64::sin(f64::consts::PI * freq / (sample_rate as f64));
     }
 
     pub fn pump(&mut self, sample: f64) -> f64 {
@@ -126,8 +142,10 @@ impl LowPass {
 
 #[cfg(test)]
 mod tests {
-    use crate::module::{ModuleT, InputRef, OutputRef};
     use mixlab_protocol::{Decibel, EqThreeParams};
+
+    use crate::engine::{EngineConfig, InputRef, Output};
+    use crate::engine::harness::Harness;
     use super::EqThree;
 
     fn bytes_to_f32s(bytes: &[u8]) -> Vec<f32> {
@@ -149,20 +167,35 @@ mod tests {
 
     #[test]
     fn basic_smoke_test() {
-        let input = bytes_to_f32s(include_bytes!("../../fixtures/module/eq_three/chronos.f32.raw"));
+        let mut input = bytes_to_f32s(include_bytes!("../../fixtures/module/eq_three/chronos.f32.raw"));
+        let expected_output = bytes_to_f32s(include_bytes!("../../fixtures/module/eq_three/chronos-eq.f32.raw"));
 
-        let (mut eq, _) = EqThree::create(EqThreeParams {
+        let mut eq = Harness::<EqThree>::with_config(EqThreeParams {
             gain_lo: Decibel(4.0),
             gain_mid: Decibel(0.0),
             gain_hi: Decibel(4.0),
-        });
+        }, EngineConfig::default());
 
-        let mut output = vec![0.0; input.len()];
+        // pad out the last tick with silence:
+        let samples_per_tick = eq.samples_per_tick();
+        let len = input.len();
+        input.resize((len + samples_per_tick - 1) / samples_per_tick * samples_per_tick, 0.0);
 
-        eq.run_tick(0, &[InputRef::Mono(&input)], &mut [OutputRef::Mono(&mut output)]);
+        let mut output = Vec::with_capacity(input.len());
 
-        let expected_output = bytes_to_f32s(include_bytes!("../../fixtures/module/eq_three/chronos-eq.f32.raw"));
+        for chunk in input.chunks(samples_per_tick) {
+            match &eq.tick(&[InputRef::Mono(chunk)])[..] {
+                [Output::Mono(samples)] => output.extend_from_slice(samples),
+                _ => panic!("expected one mono output"),
+            }
+        }
 
-        assert!(output == expected_output);
+        output.truncate(len);
+
+        assert_eq!(output.len(), expected_output.len());
+
+        for (i, (actual, expected)) in output.iter().zip(&expected_output).enumerate() {
+            assert!((actual - expected).abs() <= 1e-5, "sample {} differs: expected {}, got {}", i, expected, actual);
+        }
     }
 }
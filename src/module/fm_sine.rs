@@ -2,12 +2,13 @@ use std::f64;
 
 use mixlab_protocol::{FmSineParams, LineType, Terminal};
 
-use crate::engine::{self, Sample, InputRef, OutputRef, SAMPLE_RATE, CHANNELS};
+use crate::engine::{self, Sample, InputRef, OutputRef, CHANNELS};
 use crate::module::ModuleT;
 
 #[derive(Debug)]
 pub struct FmSine {
     params: FmSineParams,
+    sample_rate: usize,
     inputs: Vec<Terminal>,
     outputs: Vec<Terminal>,
 }
@@ -17,9 +18,10 @@ impl ModuleT for FmSine {
     type Indication = ();
     type Event = ();
 
-    fn create(params: Self::Params, _: engine::ModuleCtx<Self>) -> (Self, Self::Indication) {
+    fn create(params: Self::Params, ctx: engine::ModuleCtx<Self>) -> (Self, Self::Indication) {
         (Self {
             params,
+            sample_rate: ctx.config().sample_rate,
             inputs: vec![LineType::Mono.unlabeled()],
             outputs: vec![LineType::Stereo.unlabeled()],
         }, ())
@@ -43,7 +45,7 @@ impl ModuleT for FmSine {
         let freq_mid = self.params.freq_lo + freq_amp;
 
         for i in 0..len {
-            let t = (t + i as u64) as f64 / SAMPLE_RATE as f64;
+            let t = (t + i as u64) as f64 / self.sample_rate as f64;
             let co = (freq_mid + freq_amp * input[i] as f64) * 2.0 * f64::consts::PI;
             let x = f64::sin(co * t);
 
@@ -63,3 +65,44 @@ impl ModuleT for FmSine {
         &self.outputs
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use mixlab_protocol::FmSineParams;
+
+    use crate::engine::{InputRef, Output, Sample};
+    use crate::engine::harness::{self, Harness};
+    use super::FmSine;
+
+    fn tick(fm: &mut Harness<FmSine>, modulator: &[Sample]) -> Vec<Sample> {
+        match &fm.tick(&[InputRef::Mono(modulator)])[..] {
+            [Output::Stereo(samples)] => samples.clone(),
+            _ => panic!("expected one stereo output"),
+        }
+    }
+
+    #[test]
+    fn modulates_frequency() {
+        let mut fm = Harness::<FmSine>::new(FmSineParams { freq_lo: 200.0, freq_hi: 600.0 });
+
+        for _ in 0..4 {
+            let modulator = fm.sine(30.0, 1.0);
+            let output = tick(&mut fm, &modulator);
+            assert!(output.chunks(2).all(|frame| frame[0] == frame[1] && frame[0].abs() <= 1.0));
+        }
+
+        // the modulator sweeps between the two frequencies, so at rest it's
+        // right in the middle of them, and at its extremes on either one:
+        for (modulator, freq) in &[(0.0, 400.0), (-1.0, 200.0), (1.0, 600.0)] {
+            let expected = harness::stereo(&fm.sine(*freq, 1.0));
+            let modulator = vec![*modulator; fm.samples_per_tick()];
+            let output = tick(&mut fm, &modulator);
+
+            for (i, (sample, expected)) in output.iter().zip(&expected).enumerate() {
+                assert!((sample - expected).abs() < 1e-5, "sample {}: expected {}, got {}", i, expected, sample);
+            }
+        }
+
+        fm.assert_fixture("fm_sine");
+    }
+}
@@ -0,0 +1,240 @@
+use mixlab_protocol::{GateParams, GateIndication, Decibel, LineType, Terminal, GATE_MAX_RATIO};
+
+use crate::engine::{self, Sample, InputRef, OutputRef, CHANNELS, level_db, time_coefficient};
+use crate::module::ModuleT;
+
+#[derive(Debug)]
+pub struct Gate {
+    params: GateParams,
+    sample_rate: usize,
+    open: bool,
+    // samples left before a gate below its closing level closes:
+    hold: usize,
+    gain: f64,
+    inputs: Vec<Terminal>,
+    outputs: Vec<Terminal>,
+}
+
+impl ModuleT for Gate {
+    type Params = GateParams;
+    type Indication = GateIndication;
+    type Event = ();
+
+    fn create(params: Self::Params, ctx: engine::ModuleCtx<Self>) -> (Self, Self::Indication) {
+        let line_type = line_type(&params);
+
+        (Self {
+            sample_rate: ctx.config().sample_rate,
+            open: false,
+            hold: 0,
+            gain: params.range.to_linear(),
+            inputs: vec![line_type.unlabeled()],
+            outputs: vec![line_type.unlabeled()],
+            params,
+        }, GateIndication { open: false })
+    }
+
+    fn params(&self) -> Self::Params {
+        self.params.clone()
+    }
+
+    fn update(&mut self, params: Self::Params) -> Option<Self::Indication> {
+        let line_type = line_type(&params);
+        self.inputs = vec![line_type.unlabeled()];
+        self.outputs = vec![line_type.unlabeled()];
+        self.params = params;
+        None
+    }
+
+    fn run_tick(&mut self, _t: u64, inputs: &[InputRef], outputs: &mut [OutputRef]) -> Option<Self::Indication> {
+        let (input, output, channels) = if self.params.stereo {
+            (inputs[0].expect_stereo(), outputs[0].expect_stereo(), CHANNELS)
+        } else {
+            (inputs[0].expect_mono(), outputs[0].expect_mono(), 1)
+        };
+
+        let open_level = self.params.threshold.0;
+        let close_level = open_level - self.params.hysteresis.0.max(0.0);
+        let hold = (self.params.hold_ms.max(0.0) * self.sample_rate as f64 / 1000.0) as usize;
+        let closed_gain = self.params.range.to_linear().min(1.0);
+        let expansion = closed_expansion(self.params.ratio);
+        let attack = time_coefficient(self.params.attack_ms, self.sample_rate);
+        let release = time_coefficient(self.params.release_ms, self.sample_rate);
+
+        let was_open = self.open;
+
+        for (frame, out) in input.chunks(channels).zip(output.chunks_mut(channels)) {
+            let level = level_db(frame);
+
+            if level >= open_level {
+                self.open = true;
+                self.hold = hold;
+            } else if self.open && level < close_level {
+                if self.hold > 0 {
+                    self.hold -= 1;
+                } else {
+                    self.open = false;
+                }
+            }
+
+            let target = if self.open {
+                1.0
+            } else {
+                match expansion {
+                    // each decibel below threshold comes out `slope` more
+                    // decibels down:
+                    Some(slope) => Decibel((level - open_level).min(0.0) * slope).to_linear().max(closed_gain),
+                    None => closed_gain,
+                }
+            };
+
+            let coefficient = if target > self.gain { attack } else { release };
+
+            self.gain = coefficient * self.gain + (1.0 - coefficient) * target;
+
+            for (out, sample) in out.iter_mut().zip(frame) {
+                *out = (*sample as f64 * self.gain) as Sample;
+            }
+        }
+
+        if self.open != was_open {
+            Some(GateIndication { open: self.open })
+        } else {
+            None
+        }
+    }
+
+    fn inputs(&self) -> &[Terminal] {
+        &self.inputs
+    }
+
+    fn outputs(&self) -> &[Terminal] {
+        &self.outputs
+    }
+}
+
+// additional gain reduction per decibel below threshold while closed, or None
+// to close fully
+fn closed_expansion(ratio: f64) -> Option<f64> {
+    if ratio >= GATE_MAX_RATIO {
+        None
+    } else {
+        Some(ratio.max(1.0) - 1.0)
+    }
+}
+
+fn line_type(params: &GateParams) -> LineType {
+    if params.stereo {
+        LineType::Stereo
+    } else {
+        LineType::Mono
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mixlab_protocol::{GateParams, Decibel, GATE_MAX_RATIO};
+
+    use crate::engine::{InputRef, Output, Sample};
+    use crate::engine::harness::{self, Harness};
+    use super::Gate;
+
+    #[test]
+    fn opens_holds_and_closes() {
+        let mut gate = Harness::<Gate>::new(GateParams {
+            threshold: Decibel(-30.0),
+            hysteresis: Decibel(6.0),
+            hold_ms: 2.0,
+            attack_ms: 0.5,
+            release_ms: 2.0,
+            range: Decibel(-60.0),
+            ratio: GATE_MAX_RATIO,
+            stereo: false,
+        });
+
+        // -40 dB hum stays below threshold:
+        let hum = gate.sine(50.0, 0.01);
+        gate.tick(&[InputRef::Mono(&hum)]);
+        assert!(!gate.module().open);
+
+        for _ in 0..4 {
+            let speech = gate.sine(440.0, 0.5);
+            gate.tick(&[InputRef::Mono(&speech)]);
+        }
+
+        assert!(gate.module().open);
+
+        // held open for a while after falling quiet, then closes:
+        let silence = vec![0.0; gate.samples_per_tick()];
+        gate.tick(&[InputRef::Mono(&silence)]);
+        assert!(gate.module().open);
+
+        for _ in 0..4 {
+            gate.tick(&[InputRef::Mono(&silence)]);
+        }
+
+        assert!(!gate.module().open);
+
+        gate.assert_fixture("gate");
+    }
+
+    #[test]
+    fn gates_stereo_lines() {
+        let mut gate = Harness::<Gate>::new(GateParams { stereo: true, ..GateParams::default() });
+
+        let speech = harness::stereo(&gate.sine(440.0, 0.5));
+
+        match &gate.tick(&[InputRef::Stereo(&speech)])[..] {
+            [Output::Stereo(samples)] => assert_eq!(samples.len(), speech.len()),
+            _ => panic!("expected one stereo output"),
+        }
+
+        assert!(gate.module().open);
+
+        gate.update(GateParams::default());
+
+        let speech = gate.sine(440.0, 0.5);
+        gate.tick(&[InputRef::Mono(&speech)]);
+
+        gate.assert_fixture("gate_stereo");
+    }
+
+    #[test]
+    fn expands_below_threshold() {
+        // instant attack and release, so gain follows the level exactly:
+        let params = |ratio| GateParams {
+            threshold: Decibel(-30.0),
+            attack_ms: 0.0,
+            release_ms: 0.0,
+            range: Decibel(-60.0),
+            ratio,
+            ..GateParams::default()
+        };
+
+        let gain = |gate: &mut Harness<Gate>, level: Sample| {
+            let input = vec![level; gate.samples_per_tick()];
+
+            match &gate.tick(&[InputRef::Mono(&input)])[..] {
+                [Output::Mono(output)] => Decibel::from_linear((output[0] / level) as f64).0,
+                _ => panic!("expected one mono output"),
+            }
+        };
+
+        // at 1:2, -40 dB is 10 dB below threshold so comes out 10 dB down,
+        // while -100 dB would come out 70 dB down but is held at range:
+        let mut gate = Harness::<Gate>::new(params(2.0));
+        assert!((gain(&mut gate, 0.01) + 10.0).abs() < 1e-3);
+        assert!((gain(&mut gate, 0.00001) + 60.0).abs() < 1e-3);
+        assert!(!gate.module().open);
+
+        // no expansion at 1:1:
+        gate.update(params(1.0));
+        assert!(gain(&mut gate, 0.01).abs() < 1e-3);
+
+        // and fully gated at the highest ratio:
+        gate.update(params(GATE_MAX_RATIO));
+        assert!((gain(&mut gate, 0.01) + 60.0).abs() < 1e-3);
+
+        gate.assert_fixture("gate_expander");
+    }
+}
@@ -9,7 +9,7 @@ use mixlab_codec::ffmpeg::{AvError, AvIoError, AvIoReader, IoReader, InputContai
 use mixlab_protocol::{MediaId, MediaSourceParams};
 use mixlab_util::time::{MediaTime, MediaDuration, TimeBase};
 
-use crate::engine::{InputRef, OutputRef, VideoFrame, ModuleCtx, SAMPLE_RATE, TICKS_PER_SECOND};
+use crate::engine::{InputRef, OutputRef, VideoFrame, ModuleCtx};
 use crate::module::{ModuleT, LineType, Terminal};
 use crate::project::media;
 use crate::project::ProjectBaseRef;
@@ -34,9 +34,11 @@ pub enum MediaSourceEvent {
 #[derive(Debug)]
 pub struct OpenMedia {
     media_id: MediaId,
-    rx: Receiver<Frame>,
+    rx: Receiver<Decoded>,
     epoch: Option<MediaTime>,
     video_buffer: VecDeque<Frame>,
+    // media loops, but has played to its end once the first pass is over:
+    played_through: bool,
 }
 
 impl ModuleT for MediaSource {
@@ -91,8 +93,9 @@ impl ModuleT for MediaSource {
     }
 
     fn run_tick(&mut self, t: u64, _: &[InputRef], outputs: &mut [OutputRef]) -> Option<Self::Indication> {
-        let start_of_frame = MediaTime::new(t as i64, SAMPLE_RATE as i64);
-        let end_of_frame = start_of_frame + MediaDuration::new(1, TICKS_PER_SECOND as i64);
+        let config = self.ctx.config();
+        let start_of_frame = MediaTime::new(t as i64, config.sample_rate as i64);
+        let end_of_frame = start_of_frame + MediaDuration::new(1, config.ticks_per_second as i64);
 
         if let Some(media) = &mut self.media {
             match media.rx.try_recv() {
@@ -100,7 +103,7 @@ impl ModuleT for MediaSource {
                 Err(TryRecvError::Disconnected) => {
                     eprintln!("media_source: decode thread died");
                 }
-                Ok(frame) => {
+                Ok(Decoded::Frame(frame)) => {
                     let epoch = *media.epoch.get_or_insert(start_of_frame);
 
                     media.video_buffer.push_back(Frame {
@@ -108,6 +111,9 @@ impl ModuleT for MediaSource {
                         frame: frame.frame,
                     });
                 }
+                Ok(Decoded::EndOfPass) => {
+                    media.played_through = true;
+                }
             }
 
             if let Some(frame) = media.video_buffer.front() {
@@ -132,6 +138,10 @@ impl ModuleT for MediaSource {
     fn outputs(&self)-> &[Terminal] {
         &self.outputs
     }
+
+    fn finished(&self) -> Option<bool> {
+        self.media.as_ref().map(|media| media.played_through && media.video_buffer.is_empty())
+    }
 }
 
 async fn open_media(project: ProjectBaseRef, media_id: MediaId) -> Option<OpenMedia> {
@@ -147,6 +157,7 @@ async fn open_media(project: ProjectBaseRef, media_id: MediaId) -> Option<OpenMe
                 rx,
                 epoch: None,
                 video_buffer: VecDeque::new(),
+                played_through: false,
             })
         }
         Ok(None) => None,
@@ -163,6 +174,13 @@ struct Frame {
     frame: video::Frame,
 }
 
+#[derive(Debug)]
+enum Decoded {
+    Frame(Frame),
+    // sent each time playback reaches the end of the media and loops:
+    EndOfPass,
+}
+
 #[derive(Debug, From)]
 enum DecodeError {
     CodecBuild(codec::BuildError),
@@ -182,7 +200,7 @@ impl From<AvIoError<ReadStream>> for DecodeError {
     }
 }
 
-fn run_decode_thread(stream: ReadStream, tx: SyncSender<Frame>) -> Result<(), DecodeError> {
+fn run_decode_thread(stream: ReadStream, tx: SyncSender<Decoded>) -> Result<(), DecodeError> {
     let container = InputContainer::open(AvIoReader::new(stream))?;
 
     for (idx, stream) in container.streams().iter().enumerate() {
@@ -209,6 +227,11 @@ fn run_decode_thread(stream: ReadStream, tx: SyncSender<Frame>) -> Result<(), De
     let mut iter_start = MediaTime::zero();
 
     while let Some(iter_end) = play_once(&mut play, iter_start)? {
+        if play.tx.send(Decoded::EndOfPass).is_err() {
+            // receiver disconnected
+            return Ok(());
+        }
+
         play.video_decode.flush_buffers();
         play.container.seek(MediaTime::zero())?;
         iter_start = iter_end;
@@ -222,7 +245,7 @@ struct PlaybackContext {
     video_decode: Decode<Video>,
     video_time_base: TimeBase,
     throttle: MediaThrottle,
-    tx: SyncSender<Frame>,
+    tx: SyncSender<Decoded>,
 }
 
 fn play_once(play: &mut PlaybackContext, iter_start: MediaTime) -> Result<Option<MediaTime>, DecodeError> {
@@ -272,7 +295,7 @@ fn play_once(play: &mut PlaybackContext, iter_start: MediaTime) -> Result<Option
                     },
                 };
 
-                match play.tx.send(frame) {
+                match play.tx.send(Decoded::Frame(frame)) {
                     Ok(()) => {}
                     Err(_) => {
                         // receiver disconnected
@@ -290,3 +313,56 @@ fn play_once(play: &mut PlaybackContext, iter_start: MediaTime) -> Result<Option
 
     Ok(Some(iter_end.ok_or(DecodeError::NoFrames)?))
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::sync::mpsc;
+
+    use mixlab_protocol::{MediaId, MediaSourceParams};
+
+    use crate::engine::Output;
+    use crate::engine::harness::Harness;
+    use crate::module::ModuleT;
+    use super::{MediaSource, OpenMedia, Decoded};
+
+    #[test]
+    fn blank_without_media() {
+        let mut source = Harness::<MediaSource>::new(MediaSourceParams::default());
+        source.idle(2);
+
+        // media which isn't in the library opens as nothing:
+        source.update(MediaSourceParams { media_id: Some(MediaId(1)) });
+        source.receive_event();
+        assert!(source.module().media.is_none());
+
+        match &source.tick(&[])[..] {
+            [Output::Video(frame)] => assert!(frame.is_none()),
+            _ => panic!("expected one video output"),
+        }
+
+        // without media there is no end to play to:
+        assert_eq!(source.module().finished(), None);
+    }
+
+    #[test]
+    fn finishes_after_first_pass() {
+        let mut source = Harness::<MediaSource>::new(MediaSourceParams::default());
+
+        let (tx, rx) = mpsc::sync_channel(2);
+        source.module().media = Some(OpenMedia {
+            media_id: MediaId(1),
+            rx,
+            epoch: None,
+            video_buffer: VecDeque::new(),
+            played_through: false,
+        });
+
+        source.tick(&[]);
+        assert_eq!(source.module().finished(), Some(false));
+
+        tx.send(Decoded::EndOfPass).unwrap();
+        source.tick(&[]);
+        assert_eq!(source.module().finished(), Some(true));
+    }
+}
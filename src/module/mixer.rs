@@ -1,12 +1,16 @@
-use mixlab_protocol::{MixerParams, LineType, Terminal};
+use std::mem;
 
-use crate::engine::{self, Sample, InputRef, OutputRef};
+use mixlab_protocol::{MixerParams, MixerChannelParams, LineType, Terminal};
+
+use crate::engine::{self, Sample, InputRef, OutputRef, Smoothed, CHANNELS};
 use crate::module::ModuleT;
 use crate::util;
 
 #[derive(Debug)]
 pub struct Mixer {
     params: MixerParams,
+    // smoothed fader * gain of each channel:
+    gains: Vec<Smoothed>,
     ctx: Option<engine::ModuleCtx<Self>>,
     inputs: Vec<Terminal>,
     outputs: Vec<Terminal>,
@@ -18,7 +22,12 @@ impl ModuleT for Mixer {
     type Event = ();
 
     fn create(params: Self::Params, ctx: engine::ModuleCtx<Self>) -> (Self, Self::Indication) {
+        let sample_rate = ctx.config().sample_rate;
+
         let mixer = Mixer {
+            gains: params.channels.iter()
+                .map(|channel| Smoothed::new(channel_gain(channel), sample_rate))
+                .collect(),
             inputs: params.channels.iter().enumerate().map(|(i, _)| {
                 LineType::Stereo.labeled(&(i+1).to_string())
             }).collect(),
@@ -38,8 +47,26 @@ impl ModuleT for Mixer {
     }
 
     fn update(&mut self, params: Self::Params) -> Option<Self::Indication> {
+        self.update_at(params, 0)
+    }
+
+    fn update_at(&mut self, params: Self::Params, offset: usize) -> Option<Self::Indication> {
+        // channels which remain ramp to their new gain from where they were:
+        let mut gains = mem::take(&mut self.gains);
+
         let (new, _) = Self::create(params, self.ctx.take().unwrap());
         *self = new;
+
+        gains.truncate(self.params.channels.len());
+
+        for (gain, channel) in gains.iter_mut().zip(&self.params.channels) {
+            gain.set_at(channel_gain(channel), offset);
+        }
+
+        let added = self.gains.split_off(gains.len());
+        gains.extend(added);
+        self.gains = gains;
+
         None
     }
 
@@ -54,11 +81,18 @@ impl ModuleT for Mixer {
         util::zero(master);
         util::zero(cue);
 
-        for (ch, channel) in self.params.channels.iter().enumerate() {
+        for (ch, (channel, gain)) in self.params.channels.iter().zip(self.gains.iter_mut()).enumerate() {
             let input = inputs[ch].expect_stereo();
-            let channel_gain = channel.fader * channel.gain.to_linear();
+
+            gain.begin_tick();
+
+            let mut channel_gain = 0.0;
 
             for i in 0..len {
+                if i % CHANNELS == 0 {
+                    channel_gain = gain.next();
+                }
+
                 master[i] += (input[i] as f64 * channel_gain) as Sample;
 
                 if channel.cue {
@@ -78,3 +112,89 @@ impl ModuleT for Mixer {
         &self.outputs
     }
 }
+
+fn channel_gain(channel: &MixerChannelParams) -> f64 {
+    channel.fader * channel.gain.to_linear()
+}
+
+#[cfg(test)]
+mod tests {
+    use mixlab_protocol::{MixerParams, MixerChannelParams, Decibel};
+
+    use crate::engine::{InputRef, Output, Sample};
+    use crate::engine::harness::{self, Harness};
+    use super::Mixer;
+
+    // returns the master and cue outputs
+    fn tick(mixer: &mut Harness<Mixer>, inputs: &[&[Sample]]) -> (Vec<Sample>, Vec<Sample>) {
+        let inputs = inputs.iter()
+            .map(|input| InputRef::Stereo(input))
+            .collect::<Vec<_>>();
+
+        match &mixer.tick(&inputs)[..] {
+            [Output::Stereo(master), Output::Stereo(cue)] => (master.clone(), cue.clone()),
+            _ => panic!("expected master and cue outputs"),
+        }
+    }
+
+    fn assert_mix(actual: &[Sample], inputs: &[(&[Sample], f64)]) {
+        for (i, actual) in actual.iter().enumerate() {
+            let expected = inputs.iter()
+                .map(|(input, gain)| input[i] as f64 * gain)
+                .sum::<f64>();
+
+            assert!((*actual as f64 - expected).abs() < 1e-6, "sample {}: expected {}, got {}", i, expected, actual);
+        }
+    }
+
+    #[test]
+    fn mixes_and_cues_channels() {
+        let mut params = MixerParams::with_channels(2);
+        params.channels[0].fader = 1.0;
+        params.channels[1].fader = 0.5;
+        params.channels[1].cue = true;
+
+        let mut mixer = Harness::<Mixer>::new(params.clone());
+
+        for _ in 0..2 {
+            let a = harness::stereo(&mixer.sine(220.0, 0.5));
+            let b = harness::stereo(&mixer.sine(330.0, 0.5));
+            let (master, cue) = tick(&mut mixer, &[&a, &b]);
+
+            assert_mix(&master, &[(&a, 1.0), (&b, 0.5)]);
+            assert_eq!(cue, b);
+        }
+
+        // fade the first channel down while boosting the second, then add a
+        // third channel which should start at its own gain:
+        params.channels[0].fader = 0.0;
+        params.channels[1].gain = Decibel(6.0);
+        params.channels.push(MixerChannelParams { fader: 1.0, ..Default::default() });
+        mixer.update(params);
+
+        let silence = vec![0.0; mixer.samples_per_tick() * 2];
+        let c = harness::stereo(&mixer.sine(440.0, 0.5));
+        let (master, cue) = tick(&mut mixer, &[&silence, &silence, &c]);
+
+        assert_mix(&master, &[(&c, 1.0)]);
+        assert_eq!(cue, silence);
+
+        // the faders ramp over 10ms, or 441 samples:
+        for _ in 0..11 {
+            let a = harness::stereo(&mixer.sine(220.0, 0.5));
+            let b = harness::stereo(&mixer.sine(330.0, 0.5));
+            let c = harness::stereo(&mixer.sine(440.0, 0.5));
+            tick(&mut mixer, &[&a, &b, &c]);
+        }
+
+        let a = harness::stereo(&mixer.sine(220.0, 0.5));
+        let b = harness::stereo(&mixer.sine(330.0, 0.5));
+        let c = harness::stereo(&mixer.sine(440.0, 0.5));
+        let (master, cue) = tick(&mut mixer, &[&a, &b, &c]);
+
+        assert_mix(&master, &[(&b, 0.5 * Decibel(6.0).to_linear()), (&c, 1.0)]);
+        assert_eq!(cue, b);
+
+        mixer.assert_fixture("mixer");
+    }
+}
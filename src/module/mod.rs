@@ -4,7 +4,9 @@ use mixlab_protocol::{Terminal, LineType};
 
 use crate::engine::{InputRef, OutputRef, ModuleCtx};
 
-pub trait ModuleT: Any + Sized {
+// modules must be Send as independent modules are run in parallel on the
+// engine's worker pool:
+pub trait ModuleT: Any + Send + Sized {
     type Params;
     type Indication;
     type Event: Send;
@@ -13,9 +15,19 @@ pub trait ModuleT: Any + Sized {
     fn params(&self) -> Self::Params;
     fn receive_event(&mut self, _: Self::Event) {}
     fn update(&mut self, new_params: Self::Params) -> Option<Self::Indication>;
+    // params changes are timed to take effect `offset` samples into the next
+    // tick. modules which care (eg. to ramp gain from the right sample) can
+    // override this, others apply the change from the start of the tick:
+    fn update_at(&mut self, new_params: Self::Params, _offset: usize) -> Option<Self::Indication> {
+        self.update(new_params)
+    }
     fn run_tick(&mut self, t: u64, inputs: &[InputRef], outputs: &mut [OutputRef]) -> Option<Self::Indication>;
     fn inputs(&self) -> &[Terminal];
     fn outputs(&self) -> &[Terminal];
+    // modules playing something with an end, such as a media file, report
+    // whether they have reached it, so that offline renders can stop there.
+    // None for modules which play indefinitely:
+    fn finished(&self) -> Option<bool> { None }
 }
 
 macro_rules! gen_modules {
@@ -29,19 +41,29 @@ macro_rules! enumerate_modules {
     (then $cb:ident!) => {
         $cb!{
             amplifier::Amplifier,
+            compressor::Compressor,
+            delay::Delay,
+            downmix::Downmix,
             envelope::Envelope,
             eq_three::EqThree,
             fm_sine::FmSine,
+            gate::Gate,
             mixer::Mixer,
             monitor::Monitor,
             oscillator::Oscillator,
             output_device::OutputDevice,
+            parametric_eq::ParametricEq,
+            patch_input::PatchInput,
+            patch_output::PatchOutput,
             plotter::Plotter,
+            reverb::Reverb,
             stereo_panner::StereoPanner,
             stereo_splitter::StereoSplitter,
             stream_input::StreamInput,
             stream_output::StreamOutput,
+            sub_patch::SubPatch,
             trigger::Trigger,
+            upmix::Upmix,
             video_mixer::VideoMixer,
             media_source::MediaSource,
         }
@@ -14,7 +14,8 @@ use mixlab_mux::mp4::{Mp4Params, TrackData, AdtsFrame, AvcFrame};
 use mixlab_protocol::{LineType, Terminal, MonitorIndication, MonitorTransportPacket};
 use mixlab_util::time::MediaTime;
 
-use crate::engine::{self, InputRef, OutputRef, SAMPLE_RATE};
+use crate::engine::{self, InputRef, OutputRef};
+use crate::metrics::QueueGauge;
 use crate::module::ModuleT;
 use crate::video::encode::{EncodeStream, AudioCtx, AudioParams, VideoCtx, VideoParams, StreamSegment, Profile};
 
@@ -76,6 +77,7 @@ async fn send_packet(websocket: &mut WebSocket, packet: MonitorTransportPacket)
 #[derive(Debug)]
 pub struct Monitor {
     epoch: Option<MediaTime>,
+    sample_rate: usize,
     socket_id: Uuid,
     codec: AsyncCodec,
     inputs: Vec<Terminal>,
@@ -86,12 +88,14 @@ impl ModuleT for Monitor {
     type Indication = MonitorIndication;
     type Event = ();
 
-    fn create(_: Self::Params, _: engine::ModuleCtx<Self>) -> (Self, Self::Indication) {
+    fn create(_: Self::Params, ctx: engine::ModuleCtx<Self>) -> (Self, Self::Indication) {
         let socket_id = Uuid::new_v4();
-        let codec = AsyncCodec::start(socket_id);
+        let sample_rate = ctx.config().sample_rate;
+        let codec = AsyncCodec::start(socket_id, sample_rate);
 
         let module = Monitor {
             epoch: None,
+            sample_rate,
             socket_id,
             codec,
             inputs: vec![
@@ -117,7 +121,7 @@ impl ModuleT for Monitor {
             _ => unreachable!()
         };
 
-        let absolute_timestamp = MediaTime::new(time as i64, SAMPLE_RATE as i64);
+        let absolute_timestamp = MediaTime::new(time as i64, self.sample_rate as i64);
         let epoch = *self.epoch.get_or_insert(absolute_timestamp);
         let timestamp = absolute_timestamp.remove_epoch(epoch);
 
@@ -147,15 +151,22 @@ impl ModuleT for Monitor {
 #[derive(Debug)]
 struct AsyncCodec {
     codec_tx: mpsc::SyncSender<Tick>,
+    queue: QueueGauge,
 }
 
 impl AsyncCodec {
-    pub fn start(socket_id: Uuid) -> AsyncCodec {
+    pub fn start(socket_id: Uuid, sample_rate: usize) -> AsyncCodec {
         let (codec_tx, codec_rx) = mpsc::sync_channel(2);
-        thread::spawn(move || run_codec_thread(socket_id, codec_rx));
+        let queue = QueueGauge::register("monitor");
+
+        thread::spawn({
+            let queue = queue.clone();
+            move || run_codec_thread(socket_id, sample_rate, codec_rx, queue)
+        });
 
         AsyncCodec {
             codec_tx,
+            queue,
         }
     }
 
@@ -163,10 +174,14 @@ impl AsyncCodec {
         use mpsc::TrySendError;
 
         match self.codec_tx.try_send(tick) {
-            Ok(()) => Ok(()),
+            Ok(()) => {
+                self.queue.push();
+                Ok(())
+            }
             Err(TrySendError::Full(_)) => {
                 // codec thread lagging
                 println!("monitor: codec not keeping up, dropping tick");
+                self.queue.drop_item();
                 Ok(())
             }
             Err(TrySendError::Disconnected(_)) => {
@@ -182,17 +197,17 @@ struct Tick {
     video: Option<engine::VideoFrame>,
 }
 
-fn run_codec_thread(socket_id: Uuid, rx: mpsc::Receiver<Tick>) {
+fn run_codec_thread(socket_id: Uuid, sample_rate: usize, rx: mpsc::Receiver<Tick>, queue: QueueGauge) {
     // create encoders
     let audio_ctx = AudioCtx::new(AudioParams {
         bit_rate: aac::BitRate::VbrVeryHigh,
-        sample_rate: SAMPLE_RATE,
+        sample_rate,
         transport: aac::Transport::Adts,
     });
 
     let video_ctx = VideoCtx::new(VideoParams {
         picture: PictureSettings::yuv420p(MONITOR_WIDTH, MONITOR_HEIGHT),
-        time_base: SAMPLE_RATE,
+        time_base: sample_rate,
         profile: Profile::Monitor,
     });
 
@@ -203,7 +218,8 @@ fn run_codec_thread(socket_id: Uuid, rx: mpsc::Receiver<Tick>) {
         dcr.write_to(&mut dcr_bytes);
 
         Mp4Params {
-            timescale: SAMPLE_RATE as u32,
+            timescale: sample_rate as u32,
+            sample_rate: sample_rate as u32,
             width: MONITOR_WIDTH as u32,
             height: MONITOR_HEIGHT as u32,
             dcr: Cow::Owned(dcr_bytes),
@@ -223,6 +239,8 @@ fn run_codec_thread(socket_id: Uuid, rx: mpsc::Receiver<Tick>) {
 
     // run codec
     while let Ok(tick) = rx.recv() {
+        queue.pop();
+
         encode.send_audio(&tick.audio);
 
         if let Some(video_frame) = tick.video {
@@ -249,3 +267,74 @@ fn run_codec_thread(socket_id: Uuid, rx: mpsc::Receiver<Tick>) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use crate::engine::InputRef;
+    use crate::engine::harness::{self, Harness};
+    use crate::video::encode::StreamSegment;
+    use super::{Monitor, SOCKETS, MONITOR_WIDTH, MONITOR_HEIGHT};
+
+    // the codec runs on its own thread, so poll for what it produces
+    fn wait_for<T>(mut f: impl FnMut() -> Option<T>) -> T {
+        let deadline = Instant::now() + Duration::from_secs(5);
+
+        loop {
+            if let Some(value) = f() {
+                return value;
+            }
+
+            assert!(Instant::now() < deadline, "timed out waiting for monitor codec");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    // the monitor's only observable output is the stream it encodes for its
+    // socket, which is named randomly, so there's no fixture for it
+    #[test]
+    fn streams_to_its_socket() {
+        let mut a = Harness::<Monitor>::new(());
+        let mut b = Harness::<Monitor>::new(());
+
+        assert_ne!(a.module().socket_id, b.module().socket_id);
+
+        let socket_id = a.module().socket_id;
+
+        let mut live = wait_for(|| {
+            let sockets = SOCKETS.lock().unwrap();
+            let stream = sockets.get(&socket_id)?;
+
+            assert_eq!(stream.params.sample_rate, 44100);
+            assert_eq!((stream.params.width, stream.params.height), (MONITOR_WIDTH as u32, MONITOR_HEIGHT as u32));
+
+            Some(stream.live.subscribe())
+        });
+
+        // a few aac frames' worth, paced so the codec doesn't drop ticks:
+        for _ in 0..100 {
+            let audio = harness::stereo(&a.sine(440.0, 0.5));
+            a.tick(&[InputRef::Video(None), InputRef::Stereo(&audio)]);
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        let (mut audio, mut video) = (false, false);
+
+        wait_for(|| {
+            while let Ok(segment) = live.try_recv() {
+                match segment {
+                    StreamSegment::Audio(_) => { audio = true; }
+                    // blank frames fill in for the missing video:
+                    StreamSegment::Video(segment) => {
+                        assert!(segment.frame.is_key_frame);
+                        video = true;
+                    }
+                }
+            }
+
+            if audio && video { Some(()) } else { None }
+        });
+    }
+}
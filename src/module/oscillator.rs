@@ -2,12 +2,13 @@ use std::f64;
 
 use mixlab_protocol::{OscillatorParams, Waveform, LineType, Terminal};
 
-use crate::engine::{self, InputRef, OutputRef, SAMPLE_RATE};
+use crate::engine::{self, InputRef, OutputRef};
 use crate::module::ModuleT;
 
 #[derive(Debug)]
 pub struct Oscillator {
     params: OscillatorParams,
+    sample_rate: usize,
     inputs: Vec<Terminal>,
     outputs: Vec<Terminal>,
 }
@@ -41,9 +42,10 @@ impl ModuleT for Oscillator {
     type Indication = ();
     type Event = ();
 
-    fn create(params: Self::Params, _: engine::ModuleCtx<Self>) -> (Self, Self::Indication) {
+    fn create(params: Self::Params, ctx: engine::ModuleCtx<Self>) -> (Self, Self::Indication) {
         (Self {
             params,
+            sample_rate: ctx.config().sample_rate,
             inputs: vec![],
             outputs: vec![
                 LineType::Mono.labeled("Mono"),
@@ -71,7 +73,7 @@ impl ModuleT for Oscillator {
         let len = mono.len();
 
         for i in 0..len {
-            let t0 = (t + i as u64) as f64 / SAMPLE_RATE as f64;
+            let t0 = (t + i as u64) as f64 / self.sample_rate as f64;
             let n = t0 * self.params.freq as f64;
 
             let sample: f32 = match &self.params.waveform {
@@ -99,3 +101,43 @@ impl ModuleT for Oscillator {
         &self.outputs
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use mixlab_protocol::{OscillatorParams, Waveform};
+
+    use crate::engine::Output;
+    use crate::engine::harness::{self, Harness};
+    use super::Oscillator;
+
+    #[test]
+    fn generates_each_waveform() {
+        let mut osc = Harness::<Oscillator>::new(OscillatorParams { freq: 440.0, waveform: Waveform::Sine });
+
+        for waveform in &[Waveform::Sine, Waveform::Square, Waveform::Saw, Waveform::Triangle, Waveform::On, Waveform::Off] {
+            osc.update(OscillatorParams { freq: 440.0, waveform: *waveform });
+
+            for _ in 0..2 {
+                let sine = osc.sine(440.0, 1.0);
+
+                let mono = match &osc.tick(&[])[..] {
+                    [Output::Mono(mono), Output::Stereo(stereo)] => {
+                        assert_eq!(stereo, &harness::stereo(mono));
+                        mono.clone()
+                    }
+                    _ => panic!("expected mono and stereo outputs"),
+                };
+
+                match waveform {
+                    Waveform::Sine => assert!(mono.iter().zip(&sine).all(|(sample, sine)| (sample - sine).abs() < 1e-6)),
+                    Waveform::Square => assert!(mono.iter().zip(&sine).all(|(sample, sine)| *sample == sine.signum())),
+                    Waveform::Saw | Waveform::Triangle => assert!(mono.iter().all(|sample| sample.abs() <= 1.0)),
+                    Waveform::On => assert!(mono.iter().all(|sample| *sample == 1.0)),
+                    Waveform::Off => assert!(mono.iter().all(|sample| *sample == 0.0)),
+                }
+            }
+        }
+
+        osc.assert_fixture("oscillator");
+    }
+}
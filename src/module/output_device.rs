@@ -2,6 +2,8 @@ use std::f32;
 use std::fmt::{self, Debug};
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::thread;
 use std::time::Instant;
 
 use cpal::traits::{HostTrait, DeviceTrait, StreamTrait};
@@ -15,7 +17,6 @@ use crate::util;
 
 pub struct OutputDevice {
     params: OutputDeviceParams,
-    host: cpal::Host,
     scratch: Vec<Sample>,
     stream: Option<OutputStream>,
     last_clip: Option<Instant>,
@@ -26,12 +27,102 @@ pub struct OutputDevice {
     outputs: Vec<Terminal>,
 }
 
+// cpal::Stream is not Send because some backends require that streams are
+// dropped on the thread that created them, while modules are created, run and
+// dropped on whichever engine thread gets to them. so each stream lives on a
+// thread of its own, and the module only holds the sending end of its buffer
 struct OutputStream {
     tx: Producer<f32>,
-    config: cpal::StreamConfig,
-    // this field is never used directly but must not be dropped for the
-    // stream to continue playing:
-    _stream: cpal::Stream,
+    channels: usize,
+    // the stream thread drops the stream and exits once this is dropped:
+    _stop: mpsc::Sender<()>,
+}
+
+impl OutputStream {
+    fn open(device_name: String, lag_flag: Arc<AtomicBool>) -> Option<OutputStream> {
+        let (open_tx, open_rx) = mpsc::sync_channel(1);
+        let (stop_tx, stop_rx) = mpsc::channel::<()>();
+
+        thread::spawn(move || {
+            let (stream, tx, channels) = match build_stream(&device_name, lag_flag) {
+                Some(opened) => opened,
+                None => {
+                    let _ = open_tx.send(None);
+                    return;
+                }
+            };
+
+            let _ = open_tx.send(Some((tx, channels)));
+
+            // nothing is ever sent, this just waits for the module to hang up:
+            let _ = stop_rx.recv();
+            drop(stream);
+        });
+
+        let (tx, channels) = open_rx.recv().ok()??;
+
+        Some(OutputStream { tx, channels, _stop: stop_tx })
+    }
+}
+
+fn build_stream(device_name: &str, lag_flag: Arc<AtomicBool>) -> Option<(cpal::Stream, Producer<f32>, usize)> {
+    let host = cpal::default_host();
+
+    let output_device = host.output_devices()
+        .ok()?
+        .find(|dev| dev.name().map(|name| name == device_name).unwrap_or(false))?;
+
+    let config = match output_device.default_output_config() {
+        Ok(config) => config.config(),
+        Err(e) => {
+            eprintln!("output_device: no default output config for {}: {:?}", device_name, e);
+            return None;
+        }
+    };
+
+    let (tx, mut rx) = RingBuffer::<f32>::new(65536).split();
+
+    let stream = output_device.build_output_stream(
+            &config,
+            {
+                let mut backoff_ticks = 0;
+                move |data: &mut [f32], _info| {
+                    // TOOD info param contains timestamp for sample block
+                    // consider how we might be able to use this
+
+                    if backoff_ticks > 0 {
+                        backoff_ticks -= 1;
+                        util::zero(data);
+                        return;
+                    }
+
+                    let bytes = rx.pop_slice(data);
+
+                    if bytes < data.len() {
+                        lag_flag.store(true, Ordering::Relaxed);
+                        backoff_ticks += 3;
+                        util::zero(&mut data[bytes..])
+                    }
+                }
+            },
+            |err| {
+                eprintln!("output stream error! {:?}", err);
+            });
+
+    let stream = match stream {
+        Ok(stream) => stream,
+        Err(e) => {
+            eprintln!("output_device: could not open {}: {:?}", device_name, e);
+            return None;
+        }
+    };
+
+    if let Err(e) = stream.play() {
+        eprintln!("output_device: could not play {}: {:?}", device_name, e);
+        return None;
+    }
+
+    Some((stream, tx, config.channels as usize))
 }
 
 impl Debug for OutputDevice {
@@ -71,7 +162,6 @@ impl ModuleT for OutputDevice {
 
         let device = OutputDevice {
             params,
-            host,
             scratch: Vec::new(),
             stream: None,
             last_clip: None,
@@ -93,59 +183,15 @@ impl ModuleT for OutputDevice {
         let OutputDeviceParams { device, left, right } = new_params;
 
         if self.params.device != device {
-            let output_device = self.host.output_devices()
-                .ok()
-                .and_then(|devices| {
-                    devices.into_iter().find(|dev| dev.name().map(|dev| Some(dev) == device).unwrap_or(false))
-                });
-
-            if let Some(output_device) = output_device {
-                let config = output_device.default_output_config()
-                    .expect("default_output_format");
-
-                let (tx, mut rx) = RingBuffer::<f32>::new(65536).split();
-
-                let stream = output_device.build_output_stream(
-                        &config.config(),
-                        {
-                            let lag_flag = self.lag_flag.clone();
-                            let mut backoff_ticks = 0;
-                            move |data: &mut [f32], _info| {
-                                // TOOD info param contains timestamp for sample block
-                                // consider how we might be able to use this
-
-                                if backoff_ticks > 0 {
-                                    backoff_ticks -= 1;
-                                    util::zero(data);
-                                    return;
-                                }
-
-                                let bytes = rx.pop_slice(data);
-
-                                if bytes < data.len() {
-                                    lag_flag.store(true, Ordering::Relaxed);
-                                    backoff_ticks += 3;
-                                    util::zero(&mut data[bytes..])
-                                }
-                            }
-                        },
-                        |err| {
-                            eprintln!("output stream error! {:?}", err);
-                        })
-                    .expect("build_output_stream");
-
-                stream.play().expect("stream.play");
-
-                let stream = OutputStream {
-                    tx,
-                    config: config.config(),
-                    _stream: stream,
-                };
-
-                self.params.device = device.clone();
-                self.stream = Some(stream);
-            } else {
-                self.stream = None;
+            // the old stream is closed before the new one is opened, in case
+            // they're on the same device:
+            self.stream = None;
+
+            self.stream = device.clone()
+                .and_then(|name| OutputStream::open(name, self.lag_flag.clone()));
+
+            if self.stream.is_some() {
+                self.params.device = device;
             }
         }
 
@@ -162,10 +208,10 @@ impl ModuleT for OutputDevice {
             // assign left and right channels, validating that they are within range:
 
             self.params.left = left.filter(|left|
-                *left < stream.config.channels as usize);
+                *left < stream.channels);
 
             self.params.right = right.filter(|right|
-                *right < stream.config.channels as usize);
+                *right < stream.channels);
         }
 
         None
@@ -177,7 +223,7 @@ impl ModuleT for OutputDevice {
         let mut clip = false;
 
         if let Some(stream) = &mut self.stream {
-            let output_channels = stream.config.channels as usize;
+            let output_channels = stream.channels;
             let samples_per_channel = input.len() / CHANNELS;
             let scratch_len = samples_per_channel * output_channels;
 
@@ -253,3 +299,52 @@ impl ModuleT for OutputDevice {
         &self.outputs
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+
+    use mixlab_protocol::{OutputDeviceParams, TemporalWarningStatus};
+
+    use crate::engine::InputRef;
+    use crate::engine::harness::{self, Harness};
+    use crate::module::ModuleT;
+    use super::OutputDevice;
+
+    // the devices listed depend on the machine running the tests, so these
+    // only run without a device assigned
+
+    #[test]
+    fn ignores_input_without_device() {
+        let mut output = Harness::<OutputDevice>::new(OutputDeviceParams { device: None, left: Some(0), right: Some(1) });
+
+        output.update(OutputDeviceParams { device: Some("no such device".to_owned()), left: Some(0), right: Some(1) });
+        assert_eq!(output.module().params().device, None);
+
+        for _ in 0..4 {
+            // well over full scale, but there's nothing to clip:
+            let input = harness::stereo(&output.sine(440.0, 2.0));
+            assert!(output.tick(&[InputRef::Stereo(&input)]).is_empty());
+            assert!(output.module().indication.clip.is_none());
+            assert!(output.indication().is_none());
+        }
+    }
+
+    #[test]
+    fn indicates_lag_reported_by_stream() {
+        let mut output = Harness::<OutputDevice>::new(OutputDeviceParams { device: None, left: Some(0), right: Some(1) });
+        output.idle(1);
+        assert!(output.indication().is_none());
+
+        // as the stream callback does when it runs out of samples:
+        output.module().lag_flag.store(true, Ordering::Relaxed);
+        output.idle(1);
+
+        let indication = output.indication().expect("lag indication");
+        assert_eq!(indication.lag, Some(TemporalWarningStatus::Active));
+
+        // and not again until the status changes:
+        output.idle(1);
+        assert!(output.indication().is_none());
+    }
+}
@@ -0,0 +1,387 @@
+use std::f64;
+
+use mixlab_protocol::{ParametricEqParams, ParametricEqIndication, EqBand, FilterType, Decibel, LineType, Terminal};
+
+use crate::engine::{self, Sample, InputRef, OutputRef, CHANNELS};
+use crate::module::ModuleT;
+
+// frequency response is indicated at this many points from 20 Hz to 20 kHz:
+const RESPONSE_POINTS: usize = 96;
+const RESPONSE_LO: f64 = 20.0;
+const RESPONSE_HI: f64 = 20000.0;
+
+const MIN_FREQ: f64 = 10.0;
+const MIN_Q: f64 = 0.05;
+
+#[derive(Debug)]
+pub struct ParametricEq {
+    params: ParametricEqParams,
+    sample_rate: usize,
+    bands: Vec<Band>,
+    inputs: Vec<Terminal>,
+    outputs: Vec<Terminal>,
+}
+
+#[derive(Debug)]
+struct Band {
+    coefficients: Coefficients,
+    filters: [Biquad; CHANNELS],
+    // the filter being faded out after the band changed, see run_tick:
+    fade: Option<Fade>,
+}
+
+#[derive(Debug)]
+struct Fade {
+    coefficients: Coefficients,
+    filters: [Biquad; CHANNELS],
+}
+
+impl ModuleT for ParametricEq {
+    type Params = ParametricEqParams;
+    type Indication = ParametricEqIndication;
+    type Event = ();
+
+    fn create(params: Self::Params, ctx: engine::ModuleCtx<Self>) -> (Self, Self::Indication) {
+        let mut eq = Self {
+            params: ParametricEqParams { bands: Vec::new() },
+            sample_rate: ctx.config().sample_rate,
+            bands: Vec::new(),
+            inputs: vec![LineType::Stereo.unlabeled()],
+            outputs: vec![LineType::Stereo.unlabeled()],
+        };
+
+        let indication = eq.set_params(params);
+        (eq, indication)
+    }
+
+    fn params(&self) -> Self::Params {
+        self.params.clone()
+    }
+
+    fn update(&mut self, params: Self::Params) -> Option<Self::Indication> {
+        if params == self.params {
+            return None;
+        }
+
+        Some(self.set_params(params))
+    }
+
+    fn run_tick(&mut self, _t: u64, inputs: &[InputRef], outputs: &mut [OutputRef]) -> Option<Self::Indication> {
+        let input = inputs[0].expect_stereo();
+        let output = outputs[0].expect_stereo();
+
+        // switching coefficients mid-signal can click, so a band which has
+        // changed crossfades from its old filter to the new over this tick:
+        let frames = input.len() / CHANNELS;
+
+        for (index, (frame, out)) in input.chunks(CHANNELS).zip(output.chunks_mut(CHANNELS)).enumerate() {
+            let mix = (index + 1) as f64 / frames as f64;
+
+            for (channel, (sample, out)) in frame.iter().zip(out.iter_mut()).enumerate() {
+                let mut sample = *sample as f64;
+
+                for band in self.bands.iter_mut() {
+                    let new = band.filters[channel].process(&band.coefficients, sample);
+
+                    sample = match &mut band.fade {
+                        Some(fade) => {
+                            let old = fade.filters[channel].process(&fade.coefficients, sample);
+                            old + (new - old) * mix
+                        }
+                        None => new,
+                    };
+                }
+
+                *out = sample as Sample;
+            }
+        }
+
+        for band in self.bands.iter_mut() {
+            band.fade = None;
+        }
+
+        None
+    }
+
+    fn inputs(&self) -> &[Terminal] {
+        &self.inputs
+    }
+
+    fn outputs(&self) -> &[Terminal] {
+        &self.outputs
+    }
+}
+
+impl ParametricEq {
+    fn set_params(&mut self, params: ParametricEqParams) -> ParametricEqIndication {
+        // bands keep their filter state across changes so that moving a
+        // control doesn't click, bands added start from silence:
+        self.bands.truncate(params.bands.len());
+
+        for (index, band) in params.bands.iter().enumerate() {
+            let coefficients = Coefficients::for_band(band, self.sample_rate);
+
+            match self.bands.get_mut(index) {
+                Some(existing) => {
+                    // several changes before the next tick fade from the
+                    // filter that was last heard:
+                    if existing.coefficients != coefficients && existing.fade.is_none() {
+                        existing.fade = Some(Fade {
+                            coefficients: existing.coefficients,
+                            filters: existing.filters,
+                        });
+                    }
+
+                    existing.coefficients = coefficients;
+                }
+                None => self.bands.push(Band { coefficients, filters: Default::default(), fade: None }),
+            }
+        }
+
+        self.params = params;
+        self.response()
+    }
+
+    fn response(&self) -> ParametricEqIndication {
+        let nyquist = self.sample_rate as f64 / 2.0;
+
+        let response = (0..RESPONSE_POINTS)
+            .map(|i| RESPONSE_LO * f64::powf(RESPONSE_HI / RESPONSE_LO, i as f64 / (RESPONSE_POINTS - 1) as f64))
+            .filter(|freq| *freq < nyquist)
+            .map(|freq| {
+                let magnitude = self.bands.iter()
+                    .map(|band| band.coefficients.magnitude(freq, self.sample_rate))
+                    .product::<f64>();
+
+                // notches can go all the way to zero, which has no level in
+                // decibels:
+                (freq, Decibel::from_linear(magnitude.max(1e-6)))
+            })
+            .collect();
+
+        ParametricEqIndication { response }
+    }
+}
+
+// biquad filter coefficients, normalised so that a0 is 1. see Robert
+// Bristow-Johnson's Audio EQ Cookbook
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Coefficients {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+}
+
+impl Coefficients {
+    pub fn for_band(band: &EqBand, sample_rate: usize) -> Self {
+        Self::new(band.filter, band.freq, band.q, band.gain, sample_rate)
+    }
+
+    pub fn new(filter: FilterType, freq: f64, q: f64, gain: Decibel, sample_rate: usize) -> Self {
+        let freq = freq.max(MIN_FREQ).min(sample_rate as f64 * 0.49);
+        let q = q.max(MIN_Q);
+
+        let w0 = 2.0 * f64::consts::PI * freq / sample_rate as f64;
+        let cos = w0.cos();
+        let alpha = w0.sin() / (2.0 * q);
+        let a = f64::powf(10.0, gain.0 / 40.0);
+        let shelf = 2.0 * a.sqrt() * alpha;
+
+        let (b0, b1, b2, a0, a1, a2) = match filter {
+            FilterType::Peak => (
+                1.0 + alpha * a,
+                -2.0 * cos,
+                1.0 - alpha * a,
+                1.0 + alpha / a,
+                -2.0 * cos,
+                1.0 - alpha / a,
+            ),
+            FilterType::LowShelf => (
+                a * ((a + 1.0) - (a - 1.0) * cos + shelf),
+                2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                a * ((a + 1.0) - (a - 1.0) * cos - shelf),
+                (a + 1.0) + (a - 1.0) * cos + shelf,
+                -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                (a + 1.0) + (a - 1.0) * cos - shelf,
+            ),
+            FilterType::HighShelf => (
+                a * ((a + 1.0) + (a - 1.0) * cos + shelf),
+                -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                a * ((a + 1.0) + (a - 1.0) * cos - shelf),
+                (a + 1.0) - (a - 1.0) * cos + shelf,
+                2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                (a + 1.0) - (a - 1.0) * cos - shelf,
+            ),
+            FilterType::LowPass => (
+                (1.0 - cos) / 2.0,
+                1.0 - cos,
+                (1.0 - cos) / 2.0,
+                1.0 + alpha,
+                -2.0 * cos,
+                1.0 - alpha,
+            ),
+            FilterType::HighPass => (
+                (1.0 + cos) / 2.0,
+                -(1.0 + cos),
+                (1.0 + cos) / 2.0,
+                1.0 + alpha,
+                -2.0 * cos,
+                1.0 - alpha,
+            ),
+            FilterType::Notch => (
+                1.0,
+                -2.0 * cos,
+                1.0,
+                1.0 + alpha,
+                -2.0 * cos,
+                1.0 - alpha,
+            ),
+        };
+
+        Coefficients {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+        }
+    }
+
+    // linear gain of the filter at a frequency
+    pub fn magnitude(&self, freq: f64, sample_rate: usize) -> f64 {
+        let w = 2.0 * f64::consts::PI * freq / sample_rate as f64;
+        let (cos1, sin1) = (w.cos(), w.sin());
+        let (cos2, sin2) = ((2.0 * w).cos(), (2.0 * w).sin());
+
+        let num_re = self.b0 + self.b1 * cos1 + self.b2 * cos2;
+        let num_im = -(self.b1 * sin1 + self.b2 * sin2);
+        let den_re = 1.0 + self.a1 * cos1 + self.a2 * cos2;
+        let den_im = -(self.a1 * sin1 + self.a2 * sin2);
+
+        ((num_re * num_re + num_im * num_im) / (den_re * den_re + den_im * den_im)).sqrt()
+    }
+}
+
+// filter state for one channel, in transposed direct form II
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Biquad {
+    z1: f64,
+    z2: f64,
+}
+
+impl Biquad {
+    pub fn process(&mut self, c: &Coefficients, x: f64) -> f64 {
+        let y = c.b0 * x + self.z1;
+        self.z1 = c.b1 * x - c.a1 * y + self.z2;
+        self.z2 = c.b2 * x - c.a2 * y;
+        y
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mixlab_protocol::{ParametricEqParams, EqBand, FilterType, Decibel};
+
+    use crate::engine::{InputRef, Output};
+    use crate::engine::harness::{self, Harness};
+    use super::{ParametricEq, Coefficients};
+
+    #[test]
+    fn filter_responses() {
+        let peak = Coefficients::new(FilterType::Peak, 1000.0, 1.0, Decibel(6.0), 44100);
+        assert!((Decibel::from_linear(peak.magnitude(1000.0, 44100)).0 - 6.0).abs() < 1e-6);
+        assert!(Decibel::from_linear(peak.magnitude(50.0, 44100)).0.abs() < 0.1);
+
+        let shelf = Coefficients::new(FilterType::LowShelf, 200.0, 0.707, Decibel(-12.0), 44100);
+        assert!((Decibel::from_linear(shelf.magnitude(20.0, 44100)).0 + 12.0).abs() < 0.1);
+        assert!(Decibel::from_linear(shelf.magnitude(10000.0, 44100)).0.abs() < 0.1);
+
+        let low_pass = Coefficients::new(FilterType::LowPass, 1000.0, 0.707, Decibel(0.0), 44100);
+        assert!(Decibel::from_linear(low_pass.magnitude(100.0, 44100)).0.abs() < 0.1);
+        assert!(Decibel::from_linear(low_pass.magnitude(10000.0, 44100)).0 < -36.0);
+
+        let notch = Coefficients::new(FilterType::Notch, 1000.0, 4.0, Decibel(0.0), 44100);
+        assert!(notch.magnitude(1000.0, 44100) < 1e-6);
+    }
+
+    #[test]
+    fn notches_out_a_tone() {
+        let mut eq = Harness::<ParametricEq>::new(ParametricEqParams {
+            bands: vec![
+                EqBand { filter: FilterType::Notch, freq: 1050.0, q: 2.0, gain: Decibel(0.0) },
+            ],
+        });
+
+        for _ in 0..100 {
+            let input = harness::stereo(&eq.sine(1050.0, 0.5));
+            eq.tick(&[InputRef::Stereo(&input)]);
+        }
+
+        let input = harness::stereo(&eq.sine(1050.0, 0.5));
+
+        match &eq.tick(&[InputRef::Stereo(&input)])[..] {
+            [Output::Stereo(samples)] => assert!(samples.iter().all(|sample| sample.abs() < 1e-3)),
+            _ => panic!("expected one stereo output"),
+        }
+
+        // unchanged params don't indicate again:
+        let params = eq.module().params.clone();
+        assert!(eq.update(params).is_none());
+
+        let mut params = ParametricEqParams::default();
+        params.bands[1].gain = Decibel(-6.0);
+        assert!(eq.update(params).is_some());
+
+        let input = harness::stereo(&eq.sine(1050.0, 0.5));
+        eq.tick(&[InputRef::Stereo(&input)]);
+
+        eq.assert_fixture("parametric_eq");
+    }
+
+    #[test]
+    fn crossfades_band_changes() {
+        let notch = EqBand { filter: FilterType::Notch, freq: 1050.0, q: 2.0, gain: Decibel(0.0) };
+        let mut eq = Harness::<ParametricEq>::new(ParametricEqParams { bands: vec![notch.clone()] });
+
+        for _ in 0..100 {
+            let input = harness::stereo(&eq.sine(1050.0, 0.5));
+            eq.tick(&[InputRef::Stereo(&input)]);
+        }
+
+        // a flat peak passes the tone, but fades in over the tick rather than
+        // jumping straight to it:
+        let flat = EqBand { filter: FilterType::Peak, ..notch };
+        assert!(eq.update(ParametricEqParams { bands: vec![flat] }).is_some());
+
+        let input = harness::stereo(&eq.sine(1050.0, 0.5));
+
+        match &eq.tick(&[InputRef::Stereo(&input)])[..] {
+            [Output::Stereo(samples)] => {
+                assert!(samples[0].abs() < 0.02);
+
+                let half = samples.len() / 2;
+                let peak = |samples: &[f32]| samples.iter().fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+                assert!(peak(&samples[..half]) < peak(&samples[half..]));
+            }
+            _ => panic!("expected one stereo output"),
+        }
+
+        for _ in 0..20 {
+            let input = harness::stereo(&eq.sine(1050.0, 0.5));
+            eq.tick(&[InputRef::Stereo(&input)]);
+        }
+
+        let input = harness::stereo(&eq.sine(1050.0, 0.5));
+
+        match &eq.tick(&[InputRef::Stereo(&input)])[..] {
+            [Output::Stereo(samples)] => {
+                for (sample, input) in samples.iter().zip(input.iter()) {
+                    assert!((sample - input).abs() < 1e-3);
+                }
+            }
+            _ => panic!("expected one stereo output"),
+        }
+    }
+}
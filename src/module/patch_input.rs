@@ -0,0 +1,70 @@
+use crate::engine::{self, InputRef, OutputRef};
+use crate::module::{ModuleT, Terminal};
+
+use mixlab_protocol::PatchTerminalParams;
+
+// Exposes an input of the enclosing sub-patch to the modules within it. The
+// sub-patch supplies its output buffer directly, so it has nothing to do when
+// run.
+#[derive(Debug)]
+pub struct PatchInput {
+    params: PatchTerminalParams,
+    outputs: Vec<Terminal>,
+}
+
+impl ModuleT for PatchInput {
+    type Params = PatchTerminalParams;
+    type Indication = ();
+    type Event = ();
+
+    fn create(params: Self::Params, _: engine::ModuleCtx<Self>) -> (Self, Self::Indication) {
+        (Self {
+            outputs: vec![params.terminal()],
+            params,
+        }, ())
+    }
+
+    fn params(&self) -> Self::Params {
+        self.params.clone()
+    }
+
+    fn update(&mut self, params: Self::Params) -> Option<Self::Indication> {
+        self.outputs = vec![params.terminal()];
+        self.params = params;
+        None
+    }
+
+    fn run_tick(&mut self, _t: u64, _: &[InputRef], _: &mut [OutputRef]) -> Option<Self::Indication> {
+        None
+    }
+
+    fn inputs(&self) -> &[Terminal] {
+        &[]
+    }
+
+    fn outputs(&self) -> &[Terminal] {
+        &self.outputs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mixlab_protocol::{PatchTerminalParams, LineType};
+
+    use crate::engine::harness::Harness;
+    use crate::module::ModuleT;
+    use super::PatchInput;
+
+    #[test]
+    fn terminal_follows_params() {
+        let mut module = Harness::<PatchInput>::new(PatchTerminalParams { label: String::new(), line_type: LineType::Stereo });
+        assert_eq!(module.module().outputs(), &[LineType::Stereo.unlabeled()]);
+        module.idle(1);
+
+        module.update(PatchTerminalParams { label: "Key".to_owned(), line_type: LineType::Mono });
+        assert_eq!(module.module().outputs(), &[LineType::Mono.labeled("Key")]);
+        module.idle(1);
+
+        module.assert_fixture("patch_input");
+    }
+}
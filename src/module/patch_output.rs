@@ -0,0 +1,69 @@
+use crate::engine::{self, InputRef, OutputRef};
+use crate::module::{ModuleT, Terminal};
+
+use mixlab_protocol::PatchTerminalParams;
+
+// Exposes an output of the enclosing sub-patch. The sub-patch reads whatever
+// is connected to this module's input after each tick.
+#[derive(Debug)]
+pub struct PatchOutput {
+    params: PatchTerminalParams,
+    inputs: Vec<Terminal>,
+}
+
+impl ModuleT for PatchOutput {
+    type Params = PatchTerminalParams;
+    type Indication = ();
+    type Event = ();
+
+    fn create(params: Self::Params, _: engine::ModuleCtx<Self>) -> (Self, Self::Indication) {
+        (Self {
+            inputs: vec![params.terminal()],
+            params,
+        }, ())
+    }
+
+    fn params(&self) -> Self::Params {
+        self.params.clone()
+    }
+
+    fn update(&mut self, params: Self::Params) -> Option<Self::Indication> {
+        self.inputs = vec![params.terminal()];
+        self.params = params;
+        None
+    }
+
+    fn run_tick(&mut self, _t: u64, _: &[InputRef], _: &mut [OutputRef]) -> Option<Self::Indication> {
+        None
+    }
+
+    fn inputs(&self) -> &[Terminal] {
+        &self.inputs
+    }
+
+    fn outputs(&self) -> &[Terminal] {
+        &[]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mixlab_protocol::{PatchTerminalParams, LineType};
+
+    use crate::engine::harness::Harness;
+    use crate::module::ModuleT;
+    use super::PatchOutput;
+
+    #[test]
+    fn terminal_follows_params() {
+        let mut module = Harness::<PatchOutput>::new(PatchTerminalParams { label: String::new(), line_type: LineType::Stereo });
+        assert_eq!(module.module().inputs(), &[LineType::Stereo.unlabeled()]);
+        module.idle(1);
+
+        module.update(PatchTerminalParams { label: "Key".to_owned(), line_type: LineType::Mono });
+        assert_eq!(module.module().inputs(), &[LineType::Mono.labeled("Key")]);
+        module.idle(1);
+
+        module.assert_fixture("patch_output");
+    }
+}
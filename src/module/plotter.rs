@@ -63,3 +63,40 @@ impl ModuleT for Plotter {
         &self.outputs
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::engine::InputRef;
+    use crate::engine::harness::Harness;
+    use super::Plotter;
+
+    #[test]
+    fn plots_every_sixth_tick() {
+        let mut plotter = Harness::<Plotter>::new(());
+
+        for tick in 1..=12 {
+            let left = plotter.sine(440.0, 0.5);
+            let right = plotter.sine(440.0, 0.25);
+
+            let input = left.iter().zip(&right)
+                .flat_map(|(l, r)| vec![*l, *r])
+                .collect::<Vec<_>>();
+
+            plotter.tick(&[InputRef::Stereo(&input)]);
+
+            match plotter.indication() {
+                Some(indication) => {
+                    assert_eq!(tick % 6, 0);
+                    assert_eq!(indication.inputs, vec![left, right]);
+                }
+                None => assert_ne!(tick % 6, 0),
+            }
+        }
+
+        // nothing to plot while disconnected:
+        plotter.idle(6);
+        assert!(plotter.indication().is_none());
+
+        plotter.assert_fixture("plotter");
+    }
+}
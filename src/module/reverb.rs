@@ -0,0 +1,271 @@
+use mixlab_protocol::{ReverbParams, LineType, Terminal, REVERB_MAX_PRE_DELAY_MS};
+
+use crate::engine::{self, Sample, InputRef, OutputRef, Smoothed, CHANNELS};
+use crate::module::ModuleT;
+
+// Jezar's Freeverb: a bank of parallel damped comb filters into a chain of
+// allpass filters for each channel, the right channel's tuned slightly apart
+// from the left's. delay lengths are in samples at 44.1 kHz, and scaled for
+// other sample rates
+const COMB_TUNING: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+const ALLPASS_TUNING: [usize; 4] = [556, 441, 341, 225];
+const STEREO_SPREAD: usize = 23;
+const TUNING_RATE: f64 = 44100.0;
+
+const INPUT_GAIN: f64 = 0.015;
+const WET_GAIN: f64 = 3.0;
+const ALLPASS_FEEDBACK: f64 = 0.5;
+
+// room size maps onto comb feedback within this range:
+const ROOM_OFFSET: f64 = 0.7;
+const ROOM_SCALE: f64 = 0.28;
+const DAMPING_SCALE: f64 = 0.4;
+
+#[derive(Debug)]
+pub struct Reverb {
+    params: ReverbParams,
+    sample_rate: usize,
+    mix: Smoothed,
+    // mono send into the tanks, delayed by the pre-delay:
+    pre_delay: Vec<f64>,
+    pre_delay_pos: usize,
+    tanks: [Tank; CHANNELS],
+    inputs: Vec<Terminal>,
+    outputs: Vec<Terminal>,
+}
+
+impl ModuleT for Reverb {
+    type Params = ReverbParams;
+    type Indication = ();
+    type Event = ();
+
+    fn create(params: Self::Params, ctx: engine::ModuleCtx<Self>) -> (Self, Self::Indication) {
+        let sample_rate = ctx.config().sample_rate;
+        let max_pre_delay = ms_to_samples(REVERB_MAX_PRE_DELAY_MS, sample_rate);
+
+        (Self {
+            mix: Smoothed::new(params.mix, sample_rate),
+            params,
+            sample_rate,
+            pre_delay: vec![0.0; max_pre_delay + 1],
+            pre_delay_pos: 0,
+            tanks: [Tank::new(0, sample_rate), Tank::new(STEREO_SPREAD, sample_rate)],
+            inputs: vec![LineType::Stereo.unlabeled()],
+            outputs: vec![LineType::Stereo.unlabeled()],
+        }, ())
+    }
+
+    fn params(&self) -> Self::Params {
+        self.params.clone()
+    }
+
+    fn update(&mut self, params: Self::Params) -> Option<Self::Indication> {
+        self.update_at(params, 0)
+    }
+
+    fn update_at(&mut self, params: Self::Params, offset: usize) -> Option<Self::Indication> {
+        self.mix.set_at(params.mix, offset);
+        self.params = params;
+        None
+    }
+
+    fn run_tick(&mut self, _t: u64, inputs: &[InputRef], outputs: &mut [OutputRef]) -> Option<Self::Indication> {
+        let input = inputs[0].expect_stereo();
+        let output = outputs[0].expect_stereo();
+
+        let feedback = self.params.room_size.max(0.0).min(1.0) * ROOM_SCALE + ROOM_OFFSET;
+        let damping = self.params.damping.max(0.0).min(1.0) * DAMPING_SCALE;
+
+        // width crossfeeds the two tails, down to the same tail on both
+        // channels at 0:
+        let width = self.params.width.max(0.0).min(1.0);
+        let direct = WET_GAIN * (width / 2.0 + 0.5);
+        let cross = WET_GAIN * ((1.0 - width) / 2.0);
+
+        let pre_delay = ms_to_samples(self.params.pre_delay_ms, self.sample_rate)
+            .min(self.pre_delay.len() - 1);
+
+        self.mix.begin_tick();
+
+        for (frame, out) in input.chunks(CHANNELS).zip(output.chunks_mut(CHANNELS)) {
+            let (left, right) = (frame[0] as f64, frame[1] as f64);
+
+            let len = self.pre_delay.len();
+            self.pre_delay[self.pre_delay_pos] = (left + right) * INPUT_GAIN;
+            let send = self.pre_delay[(self.pre_delay_pos + len - pre_delay) % len];
+            self.pre_delay_pos = (self.pre_delay_pos + 1) % len;
+
+            let tail_l = self.tanks[0].process(send, feedback, damping);
+            let tail_r = self.tanks[1].process(send, feedback, damping);
+
+            let wet_l = tail_l * direct + tail_r * cross;
+            let wet_r = tail_r * direct + tail_l * cross;
+
+            let mix = self.mix.next();
+            out[0] = (left * (1.0 - mix) + wet_l * mix) as Sample;
+            out[1] = (right * (1.0 - mix) + wet_r * mix) as Sample;
+        }
+
+        None
+    }
+
+    fn inputs(&self) -> &[Terminal] {
+        &self.inputs
+    }
+
+    fn outputs(&self) -> &[Terminal] {
+        &self.outputs
+    }
+}
+
+fn ms_to_samples(ms: f64, sample_rate: usize) -> usize {
+    (ms.max(0.0) * sample_rate as f64 / 1000.0) as usize
+}
+
+fn tuned(samples: usize, sample_rate: usize) -> usize {
+    ((samples as f64 * sample_rate as f64 / TUNING_RATE) as usize).max(1)
+}
+
+// the combs and allpasses for one channel
+#[derive(Debug)]
+struct Tank {
+    combs: Vec<Comb>,
+    allpasses: Vec<Allpass>,
+}
+
+impl Tank {
+    fn new(spread: usize, sample_rate: usize) -> Self {
+        Tank {
+            combs: COMB_TUNING.iter()
+                .map(|len| Comb::new(tuned(len + spread, sample_rate)))
+                .collect(),
+            allpasses: ALLPASS_TUNING.iter()
+                .map(|len| Allpass::new(tuned(len + spread, sample_rate)))
+                .collect(),
+        }
+    }
+
+    fn process(&mut self, input: f64, feedback: f64, damping: f64) -> f64 {
+        let combs = self.combs.iter_mut()
+            .map(|comb| comb.process(input, feedback, damping))
+            .sum::<f64>();
+
+        self.allpasses.iter_mut()
+            .fold(combs, |sample, allpass| allpass.process(sample))
+    }
+}
+
+// feedback comb with a one pole low pass in its loop, so that high
+// frequencies decay faster than low
+#[derive(Debug)]
+struct Comb {
+    buffer: Vec<f64>,
+    pos: usize,
+    store: f64,
+}
+
+impl Comb {
+    fn new(len: usize) -> Self {
+        Comb { buffer: vec![0.0; len], pos: 0, store: 0.0 }
+    }
+
+    fn process(&mut self, input: f64, feedback: f64, damping: f64) -> f64 {
+        let output = self.buffer[self.pos];
+        self.store = output * (1.0 - damping) + self.store * damping;
+        self.buffer[self.pos] = input + self.store * feedback;
+        self.pos = (self.pos + 1) % self.buffer.len();
+        output
+    }
+}
+
+#[derive(Debug)]
+struct Allpass {
+    buffer: Vec<f64>,
+    pos: usize,
+}
+
+impl Allpass {
+    fn new(len: usize) -> Self {
+        Allpass { buffer: vec![0.0; len], pos: 0 }
+    }
+
+    fn process(&mut self, input: f64) -> f64 {
+        let delayed = self.buffer[self.pos];
+        self.buffer[self.pos] = input + delayed * ALLPASS_FEEDBACK;
+        self.pos = (self.pos + 1) % self.buffer.len();
+        delayed - input
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mixlab_protocol::ReverbParams;
+
+    use crate::engine::{InputRef, Output, Sample};
+    use crate::engine::harness::Harness;
+    use super::{Reverb, COMB_TUNING};
+
+    // runs the reverb over an impulse, returning the left and right output
+    fn impulse(reverb: &mut Harness<Reverb>, ticks: usize) -> (Vec<Sample>, Vec<Sample>) {
+        let mut input = vec![0.0; reverb.samples_per_tick() * 2];
+        input[0] = 1.0;
+        input[1] = 1.0;
+
+        let silence = vec![0.0; input.len()];
+
+        let mut left = Vec::new();
+        let mut right = Vec::new();
+
+        for tick in 0..ticks {
+            let input = if tick == 0 { &input } else { &silence };
+
+            match &reverb.tick(&[InputRef::Stereo(input)])[..] {
+                [Output::Stereo(samples)] => {
+                    left.extend(samples.iter().step_by(2));
+                    right.extend(samples.iter().skip(1).step_by(2));
+                }
+                _ => panic!("expected one stereo output"),
+            }
+        }
+
+        (left, right)
+    }
+
+    #[test]
+    fn tail_follows_pre_delay() {
+        let mut reverb = Harness::<Reverb>::new(ReverbParams {
+            pre_delay_ms: 10.0,
+            mix: 1.0,
+            ..ReverbParams::default()
+        });
+
+        let (left, right) = impulse(&mut reverb, 80);
+
+        // nothing comes out until the pre-delay and shortest comb have
+        // passed, 441 + 1116 samples at 44.1 kHz:
+        let onset = 441 + COMB_TUNING[0];
+
+        assert!(left[..onset].iter().all(|sample| *sample == 0.0));
+        assert!(left[onset..].iter().any(|sample| *sample != 0.0));
+
+        // the right channel is tuned apart, so its tail differs:
+        assert!(left[onset..] != right[onset..]);
+
+        reverb.assert_fixture("reverb");
+    }
+
+    #[test]
+    fn narrows_to_mono() {
+        let mut reverb = Harness::<Reverb>::new(ReverbParams {
+            width: 0.0,
+            ..ReverbParams::default()
+        });
+
+        let (left, right) = impulse(&mut reverb, 80);
+
+        assert_eq!(left, right);
+        assert!(left[2000..].iter().any(|sample| *sample != 0.0));
+
+        reverb.assert_fixture("reverb_mono");
+    }
+}
@@ -48,3 +48,30 @@ impl ModuleT for StereoPanner {
         &self.outputs
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::engine::{InputRef, Output};
+    use crate::engine::harness::Harness;
+    use super::StereoPanner;
+
+    #[test]
+    fn interleaves_inputs() {
+        let mut panner = Harness::<StereoPanner>::new(());
+
+        for _ in 0..2 {
+            let left = panner.sine(440.0, 0.5);
+            let right = panner.sine(660.0, 0.25);
+
+            match &panner.tick(&[InputRef::Mono(&left), InputRef::Mono(&right)])[..] {
+                [Output::Stereo(output)] => {
+                    assert!(output.chunks(2).map(|frame| frame[0]).eq(left.iter().copied()));
+                    assert!(output.chunks(2).map(|frame| frame[1]).eq(right.iter().copied()));
+                }
+                _ => panic!("expected one stereo output"),
+            }
+        }
+
+        panner.assert_fixture("stereo_panner");
+    }
+}
@@ -54,3 +54,34 @@ impl ModuleT for StereoSplitter {
         &self.outputs
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::engine::{InputRef, Output};
+    use crate::engine::harness::Harness;
+    use super::StereoSplitter;
+
+    #[test]
+    fn splits_channels() {
+        let mut splitter = Harness::<StereoSplitter>::new(());
+
+        for _ in 0..2 {
+            let left = splitter.sine(440.0, 0.5);
+            let right = splitter.sine(660.0, 0.25);
+
+            let input = left.iter().zip(&right)
+                .flat_map(|(l, r)| vec![*l, *r])
+                .collect::<Vec<_>>();
+
+            match &splitter.tick(&[InputRef::Stereo(&input)])[..] {
+                [Output::Mono(l), Output::Mono(r)] => {
+                    assert_eq!(l, &left);
+                    assert_eq!(r, &right);
+                }
+                _ => panic!("expected two mono outputs"),
+            }
+        }
+
+        splitter.assert_fixture("stereo_splitter");
+    }
+}
@@ -3,7 +3,7 @@ use std::cmp;
 use mixlab_protocol::{StreamInputParams, LineType, Terminal, StreamProtocol};
 use mixlab_util::time::{MediaTime, MediaDuration};
 
-use crate::engine::{self, InputRef, OutputRef, Sample, VideoFrame, SAMPLE_RATE};
+use crate::engine::{self, InputRef, OutputRef, Sample, VideoFrame};
 use crate::icecast;
 use crate::module::ModuleT;
 use crate::rtmp;
@@ -13,8 +13,10 @@ use crate::util;
 #[derive(Debug)]
 pub struct StreamInput {
     params: StreamInputParams,
+    sample_rate: usize,
     recv: Option<SourceRecv>,
     source: Option<SourceTiming>,
+    resampler: Option<Resampler>,
     audio_frame: Option<Frame<AudioData>>,
     video_frame: Option<Frame<VideoData>>,
     inputs: Vec<Terminal>,
@@ -32,13 +34,15 @@ impl ModuleT for StreamInput {
     type Indication = ();
     type Event = ();
 
-    fn create(params: Self::Params, _: engine::ModuleCtx<Self>) -> (Self, Self::Indication) {
+    fn create(params: Self::Params, ctx: engine::ModuleCtx<Self>) -> (Self, Self::Indication) {
         let recv = listen_mountpoint(&params);
 
         let module = StreamInput {
             params,
+            sample_rate: ctx.config().sample_rate,
             recv,
             source: None,
+            resampler: None,
             audio_frame: None,
             video_frame: None,
             inputs: vec![],
@@ -70,14 +74,14 @@ impl ModuleT for StreamInput {
     }
 
     fn run_tick(&mut self, engine_time: u64, _: &[InputRef], outputs: &mut [OutputRef]) -> Option<Self::Indication> {
-        let engine_time = MediaTime::new(engine_time as i64, SAMPLE_RATE as i64);
+        let engine_time = MediaTime::new(engine_time as i64, self.sample_rate as i64);
 
         let (video_out, mut audio_out) = match outputs {
             [video, audio] => (video.expect_video(), audio.expect_stereo()),
             _ => unimplemented!(),
         };
 
-        let tick_duration = MediaDuration::new(audio_out.len() as i64 / 2, SAMPLE_RATE as i64);
+        let tick_duration = MediaDuration::new(audio_out.len() as i64 / 2, self.sample_rate as i64);
 
         let video_frame = self.video_frame.take()
             .or_else(|| {
@@ -97,6 +101,21 @@ impl ModuleT for StreamInput {
                     });
 
             if let Some(mut frame) = audio_frame {
+                if frame.data.sample_rate != self.sample_rate {
+                    // source sample rate does not match the engine's, convert
+                    // it. resampler state carries across frames from the same
+                    // source so that there are no discontinuities between them
+                    let source_rate = (frame.source_id, frame.data.sample_rate);
+
+                    if self.resampler.as_ref().map(|resampler| (resampler.source_id, resampler.from)) != Some(source_rate) {
+                        self.resampler = Some(Resampler::new(frame.source_id, frame.data.sample_rate, self.sample_rate));
+                    }
+
+                    let resampler = self.resampler.as_mut().unwrap();
+                    frame.data.samples = resampler.process(&frame.data.samples);
+                    frame.data.sample_rate = self.sample_rate;
+                }
+
                 if existing_source_id != Some(frame.source_id) {
                     // source changed
                     self.source = Some(SourceTiming {
@@ -105,16 +124,16 @@ impl ModuleT for StreamInput {
                     });
                 }
 
-                let len = cmp::min(audio_out.len(), frame.data.len());
+                let len = cmp::min(audio_out.len(), frame.data.samples.len());
 
                 for i in 0..len {
-                    audio_out[i] = convert_sample(frame.data[i]);
+                    audio_out[i] = convert_sample(frame.data.samples[i]);
                 }
 
                 audio_out = &mut audio_out[len..];
 
-                if len < frame.data.len() {
-                    frame.data.drain(0..len);
+                if len < frame.data.samples.len() {
+                    frame.data.samples.drain(0..len);
                     self.audio_frame = Some(frame);
                 }
             } else {
@@ -164,6 +183,71 @@ fn listen_mountpoint(params: &StreamInputParams) -> Option<SourceRecv> {
     }
 }
 
+// converts interleaved stereo from one sample rate to another by linear
+// interpolation
+#[derive(Debug)]
+struct Resampler {
+    source_id: SourceId,
+    from: usize,
+    to: usize,
+    // position of the next output frame in input frames, relative to the
+    // start of the next call to process. -1 is the last frame of the previous
+    // call, kept in `last` to interpolate from
+    position: f64,
+    last: [i16; 2],
+}
+
+impl Resampler {
+    fn new(source_id: SourceId, from: usize, to: usize) -> Self {
+        Resampler {
+            source_id,
+            from,
+            to,
+            position: 0.0,
+            last: [0; 2],
+        }
+    }
+
+    fn process(&mut self, samples: &[i16]) -> Vec<i16> {
+        let frames = samples.len() / 2;
+
+        if frames == 0 {
+            return Vec::new();
+        }
+
+        let step = self.from as f64 / self.to as f64;
+        let last = self.last;
+        let frame = |index: isize, channel: usize| -> f64 {
+            if index < 0 {
+                last[channel] as f64
+            } else {
+                samples[index as usize * 2 + channel] as f64
+            }
+        };
+
+        let mut out = Vec::with_capacity(((frames as f64 / step) as usize + 1) * 2);
+
+        while self.position < (frames - 1) as f64 {
+            let index = self.position.floor();
+            let fraction = self.position - index;
+            let index = index as isize;
+
+            for channel in 0..2 {
+                let a = frame(index, channel);
+                let b = frame(index + 1, channel);
+                out.push((a + (b - a) * fraction).round() as i16);
+            }
+
+            self.position += step;
+        }
+
+        self.position -= frames as f64;
+        self.last = [samples[(frames - 1) * 2], samples[(frames - 1) * 2 + 1]];
+
+        out
+    }
+}
+
 fn convert_sample(sample: i16) -> Sample {
     // i16::min_value is a greater absolute distance away from 0 than max_value
     // divide by it rather than max_value to prevent clipping
@@ -171,3 +255,83 @@ fn convert_sample(sample: i16) -> Sample {
 
     sample as Sample / divisor
 }
+
+#[cfg(test)]
+mod tests {
+    use mixlab_protocol::{StreamInputParams, StreamProtocol};
+    use mixlab_util::time::MediaTime;
+
+    use crate::engine::Output;
+    use crate::engine::harness::Harness;
+    use crate::source::{Registry, AudioData};
+    use super::StreamInput;
+
+    fn assert_silent(input: &mut Harness<StreamInput>) {
+        match &input.tick(&[])[..] {
+            [Output::Video(frame), Output::Stereo(audio)] => {
+                assert!(frame.is_none());
+                assert!(audio.iter().all(|sample| *sample == 0.0));
+            }
+            _ => panic!("expected video and audio outputs"),
+        }
+    }
+
+    #[test]
+    fn silent_without_source() {
+        let mut input = Harness::<StreamInput>::new(StreamInputParams::default());
+
+        for _ in 0..2 {
+            assert_silent(&mut input);
+        }
+
+        let params = StreamInputParams {
+            protocol: Some(StreamProtocol::Icecast),
+            mountpoint: Some("stream-input-test".to_owned()),
+        };
+
+        // listens on the mountpoint, which only one input can do at a time:
+        input.update(params.clone());
+        assert!(input.module().recv.is_some());
+
+        let mut other = Harness::<StreamInput>::new(params);
+        assert!(other.module().recv.is_none());
+
+        for _ in 0..2 {
+            assert_silent(&mut input);
+        }
+
+        input.assert_fixture("stream_input");
+    }
+
+    #[test]
+    fn resamples_other_rates() {
+        let mut input = Harness::<StreamInput>::new(StreamInputParams::default());
+
+        let registry = Registry::new();
+        input.module().recv = Some(registry.listen("resample-test").unwrap());
+        let mut send = registry.connect("resample-test").unwrap();
+
+        // a ramp at half the engine's sample rate, sent in two frames:
+        let ramp = (0..input.samples_per_tick() as i16)
+            .flat_map(|i| vec![i * 100, i * -100])
+            .collect::<Vec<_>>();
+
+        for (index, samples) in ramp.chunks(ramp.len() / 2).enumerate() {
+            let timestamp = MediaTime::new(index as i64 * samples.len() as i64 / 2, 22050);
+            let data = AudioData { sample_rate: 22050, samples: samples.to_vec() };
+            send.write_audio(timestamp, data).unwrap();
+        }
+
+        match &input.tick(&[])[..] {
+            [Output::Video(_), Output::Stereo(audio)] => {
+                // interpolated over the join between frames too:
+                for (i, frame) in audio.chunks(2).enumerate() {
+                    let expected = (i * 50) as f32 / 32768.0;
+                    assert!((frame[0] - expected).abs() < 1e-6);
+                    assert!((frame[1] + expected).abs() < 1e-6);
+                }
+            }
+            _ => panic!("expected video and audio outputs"),
+        }
+    }
+}
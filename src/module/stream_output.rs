@@ -13,7 +13,8 @@ use mixlab_codec::ffmpeg::PictureSettings;
 use mixlab_protocol::{StreamOutputParams, LineType, Terminal, StreamOutputIndication, StreamOutputLiveStatus};
 use mixlab_util::time::MediaTime;
 
-use crate::engine::{self, InputRef, OutputRef, SAMPLE_RATE};
+use crate::engine::{self, InputRef, OutputRef};
+use crate::metrics::QueueGauge;
 use crate::module::ModuleT;
 use crate::rtmp;
 use crate::rtmp::packet::{AudioPacket, VideoPacket, VideoFrameType, VideoPacketType};
@@ -26,6 +27,8 @@ const OUTPUT_HEIGHT: usize = 700;
 #[derive(Debug)]
 pub struct StreamOutput {
     params: StreamOutputParams,
+    runtime: runtime::Handle,
+    sample_rate: usize,
     connection: Connection,
     inputs: Vec<Terminal>,
     indication: StreamOutputIndication,
@@ -36,7 +39,7 @@ impl ModuleT for StreamOutput {
     type Indication = StreamOutputIndication;
     type Event = ();
 
-    fn create(params: Self::Params, _: engine::ModuleCtx<Self>) -> (Self, Self::Indication) {
+    fn create(params: Self::Params, ctx: engine::ModuleCtx<Self>) -> (Self, Self::Indication) {
         let indic = StreamOutputIndication {
             live: StreamOutputLiveStatus::Offline,
             error: false,
@@ -44,6 +47,8 @@ impl ModuleT for StreamOutput {
 
         let module = StreamOutput {
             params,
+            runtime: ctx.runtime(),
+            sample_rate: ctx.config().sample_rate,
             connection: Connection::Offline,
             inputs: vec![
                 LineType::Video.labeled("Video"),
@@ -85,10 +90,11 @@ impl ModuleT for StreamOutput {
                 let (completion_tx, completion_rx) = oneshot::channel();
 
                 // spawn task to connect to RTMP
-                tokio::spawn({
+                self.runtime.spawn({
                     let params = self.params.clone();
+                    let sample_rate = self.sample_rate;
                     async move {
-                        let _ = completion_tx.send(connect_rtmp(params.clone()).await);
+                        let _ = completion_tx.send(connect_rtmp(params.clone(), sample_rate).await);
                     }
                 });
 
@@ -110,7 +116,7 @@ impl ModuleT for StreamOutput {
             _ => unreachable!()
         };
 
-        let timestamp = MediaTime::new(engine_time as i64, SAMPLE_RATE as i64);
+        let timestamp = MediaTime::new(engine_time as i64, self.sample_rate as i64);
 
         let live = match &mut self.connection {
             Connection::Offline => {
@@ -124,7 +130,7 @@ impl ModuleT for StreamOutput {
 
                 match completion.try_recv() {
                     Ok(Ok(publish)) => {
-                        self.connection = Connection::Live(LiveOutputTask::start(timestamp, publish));
+                        self.connection = Connection::Live(LiveOutputTask::start(&self.runtime, timestamp, self.sample_rate, publish));
 
                         match &mut self.connection {
                             Connection::Live(live) => live,
@@ -185,7 +191,7 @@ enum RtmpConnectError {
     Client(client::Error),
 }
 
-async fn connect_rtmp(params: StreamOutputParams) -> Result<PublishClient, RtmpConnectError> {
+async fn connect_rtmp(params: StreamOutputParams, sample_rate: usize) -> Result<PublishClient, RtmpConnectError> {
     let url = url::Url::parse(&params.rtmp_url)?;
 
     if url.scheme() != "rtmp" {
@@ -216,7 +222,7 @@ async fn connect_rtmp(params: StreamOutputParams) -> Result<PublishClient, RtmpC
                 video_bitrate_kbps: None, //Some(2500),
                 audio_codec: Some("aac1".to_owned()),
                 audio_bitrate_kbps: Some(160),
-                audio_sample_rate: Some(SAMPLE_RATE as u32),
+                audio_sample_rate: Some(sample_rate as u32),
                 audio_channels: Some(2),
                 audio_is_stereo: Some(true),
                 encoder: Some("Mixlab".to_owned()),
@@ -280,6 +286,7 @@ impl Connection {
 #[derive(Debug)]
 struct LiveOutputTask {
     tx: mpsc::SyncSender<LiveOutputMsg>,
+    queue: QueueGauge,
 }
 
 enum LiveOutputMsg {
@@ -287,35 +294,43 @@ enum LiveOutputMsg {
 }
 
 impl LiveOutputTask {
-    pub fn start(epoch: MediaTime, publish: PublishClient) -> Self {
-        let runtime = runtime::Handle::current();
+    pub fn start(runtime: &runtime::Handle, epoch: MediaTime, sample_rate: usize, publish: PublishClient) -> Self {
+        let runtime = runtime.clone();
         let (tx, rx) = mpsc::sync_channel(100);
+        let queue = QueueGauge::register("stream_output");
 
-        thread::spawn(move || {
-            runtime.enter(move || {
-                let mut live = LiveOutput::start(epoch, publish);
+        thread::spawn({
+            let queue = queue.clone();
+            move || runtime.enter(move || {
+                let mut live = LiveOutput::start(epoch, sample_rate, publish);
 
                 while let Ok(msg) = rx.recv() {
+                    queue.pop();
+
                     match msg {
                         LiveOutputMsg::Tick { timestamp, audio, video } => {
                             live.tick(timestamp, audio, video);
                         }
                     }
                 }
-            });
+            })
         });
 
-        LiveOutputTask { tx }
+        LiveOutputTask { tx, queue }
     }
 
     pub fn send(&mut self, msg: LiveOutputMsg) -> Result<(), ()> {
         use mpsc::TrySendError;
 
         match self.tx.try_send(msg) {
-            Ok(()) => Ok(()),
+            Ok(()) => {
+                self.queue.push();
+                Ok(())
+            }
             Err(TrySendError::Full(_)) => {
                 // encoder thread is lagging what do? just drop for now
                 // TODO
+                self.queue.drop_item();
                 Ok(())
             }
             Err(TrySendError::Disconnected(_)) => {
@@ -333,10 +348,10 @@ struct LiveOutput {
 }
 
 impl LiveOutput {
-    pub fn start(epoch: MediaTime, mut publish: PublishClient) -> Self {
+    pub fn start(epoch: MediaTime, sample_rate: usize, mut publish: PublishClient) -> Self {
         let audio_ctx = AudioCtx::new(AudioParams {
             bit_rate: aac::BitRate::Cbr(160000),
-            sample_rate: SAMPLE_RATE,
+            sample_rate,
             transport: aac::Transport::Raw,
         });
 
@@ -346,7 +361,7 @@ impl LiveOutput {
 
         let video_ctx = VideoCtx::new(VideoParams {
             picture: PictureSettings::yuv420p(OUTPUT_WIDTH, OUTPUT_HEIGHT),
-            time_base: SAMPLE_RATE,
+            time_base: sample_rate,
             profile: Profile::Stream,
         });
 
@@ -405,3 +420,36 @@ impl LiveOutput {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use mixlab_protocol::{StreamOutputParams, StreamOutputLiveStatus};
+
+    use crate::engine::harness::Harness;
+    use crate::module::ModuleT;
+    use super::StreamOutput;
+
+    #[test]
+    fn offline_until_connected() {
+        let mut output = Harness::<StreamOutput>::new(StreamOutputParams::default());
+        output.idle(2);
+
+        let params = StreamOutputParams {
+            seq: 2,
+            rtmp_url: "rtmp://localhost/live".to_owned(),
+            ..Default::default()
+        };
+
+        output.update(params.clone());
+        assert_eq!(output.module().params(), params);
+
+        // updates older than the current params are ignored:
+        output.update(StreamOutputParams::default());
+        assert_eq!(output.module().params(), params);
+
+        // params without a connect_seq don't connect:
+        output.idle(2);
+        assert_eq!(output.module().indication.live, StreamOutputLiveStatus::Offline);
+        assert!(!output.module().indication.error);
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+
+use mixlab_protocol::{ModuleId, ModuleParams, InputId, OutputId, SubPatchParams, SubPatchIndication};
+
+use crate::engine::{self, InputRef, OutputRef, Output, NestedWorkspace};
+use crate::module::{ModuleT, Terminal};
+use crate::persist;
+
+#[derive(Debug)]
+pub struct SubPatch {
+    name: String,
+    patch: NestedWorkspace,
+    // params are read often (eg. on every save), so the persisted form of the
+    // patch is kept rather than rebuilt each time. it only changes on update
+    persisted: persist::Workspace,
+    // PatchInput and PatchOutput modules within the patch backing each of
+    // this module's terminals:
+    input_modules: Vec<ModuleId>,
+    output_modules: Vec<ModuleId>,
+    inputs: Vec<Terminal>,
+    outputs: Vec<Terminal>,
+}
+
+impl SubPatch {
+    fn refresh(&mut self) {
+        self.persisted = self.patch.to_persist();
+        self.refresh_terminals();
+    }
+
+    fn refresh_terminals(&mut self) {
+        self.input_modules.clear();
+        self.output_modules.clear();
+        self.inputs.clear();
+        self.outputs.clear();
+
+        for (module_id, module) in self.patch.modules() {
+            match module.params() {
+                ModuleParams::PatchInput(params) => {
+                    self.input_modules.push(module_id);
+                    self.inputs.push(params.terminal());
+                }
+                ModuleParams::PatchOutput(params) => {
+                    self.output_modules.push(module_id);
+                    self.outputs.push(params.terminal());
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn indication(&self) -> SubPatchIndication {
+        SubPatchIndication {
+            modules: self.patch.module_states(),
+            complete: true,
+        }
+    }
+}
+
+impl ModuleT for SubPatch {
+    type Params = SubPatchParams;
+    type Indication = SubPatchIndication;
+    type Event = ();
+
+    fn create(params: Self::Params, ctx: engine::ModuleCtx<Self>) -> (Self, Self::Indication) {
+        let mut module = SubPatch {
+            patch: NestedWorkspace::new(&params.patch, ctx.project()),
+            persisted: persist::Workspace::default(),
+            name: params.name,
+            input_modules: Vec::new(),
+            output_modules: Vec::new(),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+        };
+
+        module.refresh();
+
+        let indication = module.indication();
+        (module, indication)
+    }
+
+    fn params(&self) -> Self::Params {
+        SubPatchParams {
+            name: self.name.clone(),
+            patch: self.persisted.clone(),
+        }
+    }
+
+    fn update(&mut self, params: Self::Params) -> Option<Self::Indication> {
+        self.name = params.name;
+        self.patch.update(&params.patch);
+        self.refresh();
+        Some(self.indication())
+    }
+
+    fn run_tick(&mut self, t: u64, inputs: &[InputRef], outputs: &mut [OutputRef]) -> Option<Self::Indication> {
+        // feed our inputs in as the outputs of the patch's input modules
+        let external = self.input_modules.iter()
+            .zip(inputs)
+            .filter_map(|(module_id, input)| {
+                Some((OutputId(*module_id, 0), Output::from_input_ref(*input)?))
+            })
+            .collect::<HashMap<_, _>>();
+
+        let tick = self.patch.run_tick(t, &external);
+
+        for (module_id, output) in self.output_modules.iter().zip(outputs.iter_mut()) {
+            let buffer = self.patch.connection(InputId(*module_id, 0))
+                .and_then(|output_id| {
+                    external.get(&output_id)
+                        .or_else(|| tick.buffers.get(&output_id))
+                });
+
+            if let Some(buffer) = buffer {
+                output.copy_from(buffer);
+            }
+        }
+
+        if tick.indications.is_empty() {
+            None
+        } else {
+            // only the modules which changed, the rest are as last sent:
+            Some(SubPatchIndication {
+                modules: tick.indications.iter()
+                    .filter_map(|(module_id, _)| self.patch.module_state(*module_id))
+                    .collect(),
+                complete: false,
+            })
+        }
+    }
+
+    fn inputs(&self) -> &[Terminal] {
+        &self.inputs
+    }
+
+    fn outputs(&self) -> &[Terminal] {
+        &self.outputs
+    }
+
+    // media within the patch plays to its end as it would outside it
+    fn finished(&self) -> Option<bool> {
+        self.patch.finished()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use mixlab_protocol::{ModuleId, ModuleParams, OutputId, SubPatchParams, SavedWorkspace, SavedModule, PatchTerminalParams, AmplifierParams, LineType};
+
+    use crate::engine::{InputRef, Output};
+    use crate::engine::harness::{self, Harness};
+    use crate::module::ModuleT;
+    use super::SubPatch;
+
+    fn module(params: ModuleParams, inputs: Vec<Option<OutputId>>) -> SavedModule {
+        SavedModule {
+            params,
+            geometry: Default::default(),
+            inputs,
+            feedback: Vec::new(),
+            cv: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn runs_patch_between_terminals() {
+        let id = |id| ModuleId(NonZeroUsize::new(id).unwrap());
+
+        let terminal = |label: &str| PatchTerminalParams {
+            label: label.to_owned(),
+            line_type: LineType::Stereo,
+        };
+
+        // input -> amplifier -> output, with the amplifier's control input
+        // left disconnected:
+        let mut patch = SavedWorkspace::default();
+        patch.module_seq = 3;
+        patch.modules.insert(id(1), module(ModuleParams::PatchInput(terminal("In")), vec![]));
+        patch.modules.insert(id(2), module(ModuleParams::Amplifier(AmplifierParams { amplitude: 0.5, mod_depth: 0.0 }), vec![Some(OutputId(id(1), 0)), None]));
+        patch.modules.insert(id(3), module(ModuleParams::PatchOutput(terminal("Out")), vec![Some(OutputId(id(2), 0))]));
+
+        let mut sub_patch = Harness::<SubPatch>::new(SubPatchParams { name: "Half".to_owned(), patch });
+
+        assert_eq!(sub_patch.module().inputs().len(), 1);
+        assert_eq!(sub_patch.module().outputs().len(), 1);
+
+        for _ in 0..3 {
+            let input = harness::stereo(&sub_patch.sine(440.0, 0.5));
+
+            match &sub_patch.tick(&[InputRef::Stereo(&input)])[..] {
+                [Output::Stereo(output)] => {
+                    let expected = input.iter().map(|sample| sample * 0.5).collect::<Vec<_>>();
+                    assert_eq!(output, &expected);
+                }
+                _ => panic!("expected one stereo output"),
+            }
+        }
+
+        sub_patch.assert_fixture("sub_patch");
+    }
+
+    #[test]
+    fn indicates_only_changed_modules() {
+        let id = |id| ModuleId(NonZeroUsize::new(id).unwrap());
+
+        let terminal = PatchTerminalParams {
+            label: "In".to_owned(),
+            line_type: LineType::Stereo,
+        };
+
+        // the plotter indicates every 6th tick, the input never does:
+        let mut patch = SavedWorkspace::default();
+        patch.module_seq = 2;
+        patch.modules.insert(id(1), module(ModuleParams::PatchInput(terminal), vec![]));
+        patch.modules.insert(id(2), module(ModuleParams::Plotter(()), vec![Some(OutputId(id(1), 0))]));
+
+        let mut sub_patch = Harness::<SubPatch>::new(SubPatchParams { name: "Plot".to_owned(), patch });
+
+        for _ in 0..5 {
+            let input = harness::stereo(&sub_patch.sine(440.0, 0.5));
+            sub_patch.tick(&[InputRef::Stereo(&input)]);
+            assert!(sub_patch.indication().is_none());
+        }
+
+        let input = harness::stereo(&sub_patch.sine(440.0, 0.5));
+        sub_patch.tick(&[InputRef::Stereo(&input)]);
+
+        let indication = sub_patch.indication().expect("plotter indication");
+        assert!(!indication.complete);
+        assert_eq!(indication.modules.iter().map(|module| module.id).collect::<Vec<_>>(), vec![id(2)]);
+
+        // params come from the cached patch, which is rebuilt on update:
+        let mut params = sub_patch.module().params();
+        assert_eq!(params.patch.modules.len(), 2);
+
+        params.patch.modules.remove(&id(2));
+        sub_patch.update(params);
+        assert_eq!(sub_patch.module().params().patch.modules.len(), 1);
+    }
+}
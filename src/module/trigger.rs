@@ -1,11 +1,18 @@
 use mixlab_protocol::{GateState, LineType, Terminal};
 
-use crate::engine::{self, InputRef, OutputRef};
+use crate::engine::{self, InputRef, OutputRef, TimedEvent, EventMessage};
 use crate::module::ModuleT;
 
+// the gate is also sent as note on and off events for middle c at full
+// velocity, timed to the sample the gate changed at:
+const NOTE: u8 = 60;
+const VELOCITY: u8 = 127;
+
 #[derive(Debug)]
 pub struct Trigger {
     params: GateState,
+    // gate changes since the last tick:
+    pending: Vec<TimedEvent>,
     inputs: Vec<Terminal>,
     outputs: Vec<Terminal>,
 }
@@ -18,8 +25,12 @@ impl ModuleT for Trigger {
     fn create(params: Self::Params, _: engine::ModuleCtx<Self>) -> (Self, Self::Indication) {
         (Self {
             params,
+            pending: Vec::new(),
             inputs: vec![],
-            outputs: vec![LineType::Mono.unlabeled()]
+            outputs: vec![
+                LineType::Mono.labeled("Gate"),
+                LineType::Event.labeled("Events"),
+            ]
         }, ())
     }
 
@@ -28,11 +39,35 @@ impl ModuleT for Trigger {
     }
 
     fn update(&mut self, new_params: Self::Params) -> Option<Self::Indication> {
+        self.update_at(new_params, 0)
+    }
+
+    fn update_at(&mut self, new_params: Self::Params, offset: usize) -> Option<Self::Indication> {
+        if new_params != self.params {
+            let message = match new_params {
+                GateState::Open => EventMessage::NoteOn { note: NOTE, velocity: VELOCITY },
+                GateState::Closed => EventMessage::NoteOff { note: NOTE },
+            };
+
+            self.pending.push(TimedEvent { offset, message });
+        }
+
         self.params = new_params;
         None
     }
 
     fn run_tick(&mut self, _t: u64, _: &[InputRef], outputs: &mut [OutputRef]) -> Option<Self::Indication> {
+        let samples_per_tick = outputs[0].expect_mono().len();
+
+        // updates may arrive out of order of offset:
+        self.pending.sort_by_key(|event| event.offset);
+
+        let events = outputs[1].expect_events();
+        events.extend(self.pending.drain(..).map(|event| TimedEvent {
+            offset: event.offset.min(samples_per_tick - 1),
+            ..event
+        }));
+
         let output = outputs[0].expect_mono();
 
         let value = match self.params {
@@ -55,3 +90,41 @@ impl ModuleT for Trigger {
         &self.outputs
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use mixlab_protocol::GateState;
+
+    use crate::engine::{Output, TimedEvent, EventMessage};
+    use crate::engine::harness::Harness;
+    use super::{Trigger, NOTE};
+
+    #[test]
+    fn outputs_gate_state() {
+        let mut trigger = Harness::<Trigger>::new(GateState::Closed);
+        trigger.idle(1);
+
+        trigger.update_at(GateState::Open, 7);
+
+        match &trigger.tick(&[])[..] {
+            [Output::Mono(samples), Output::Events(events)] => {
+                assert!(samples.iter().all(|sample| *sample == 1.0));
+                assert_eq!(events, &[TimedEvent { offset: 7, message: EventMessage::NoteOn { note: NOTE, velocity: 127 } }]);
+            }
+            _ => panic!("expected mono and event outputs"),
+        }
+
+        // no change, no event:
+        trigger.update(GateState::Open);
+
+        match &trigger.tick(&[])[..] {
+            [_, Output::Events(events)] => assert!(events.is_empty()),
+            _ => panic!("expected mono and event outputs"),
+        }
+
+        trigger.update(GateState::Closed);
+        trigger.idle(1);
+
+        trigger.assert_fixture("trigger");
+    }
+}
@@ -0,0 +1,157 @@
+use mixlab_protocol::{UpmixParams, Speaker, LineType, Terminal};
+
+use crate::engine::{self, Sample, InputRef, OutputRef, Smoothed, CHANNELS};
+use crate::module::ModuleT;
+
+#[derive(Debug)]
+pub struct Upmix {
+    params: UpmixParams,
+    centre: Smoothed,
+    surround: Smoothed,
+    inputs: Vec<Terminal>,
+    outputs: Vec<Terminal>,
+}
+
+impl ModuleT for Upmix {
+    type Params = UpmixParams;
+    type Indication = ();
+    type Event = ();
+
+    fn create(params: Self::Params, ctx: engine::ModuleCtx<Self>) -> (Self, Self::Indication) {
+        let sample_rate = ctx.config().sample_rate;
+
+        (Self {
+            centre: Smoothed::new(params.centre, sample_rate),
+            surround: Smoothed::new(params.surround, sample_rate),
+            inputs: vec![LineType::Stereo.unlabeled()],
+            outputs: vec![params.layout.line_type().unlabeled()],
+            params,
+        }, ())
+    }
+
+    fn params(&self) -> Self::Params {
+        self.params.clone()
+    }
+
+    fn update(&mut self, params: Self::Params) -> Option<Self::Indication> {
+        self.update_at(params, 0)
+    }
+
+    fn update_at(&mut self, params: Self::Params, offset: usize) -> Option<Self::Indication> {
+        self.centre.set_at(params.centre, offset);
+        self.surround.set_at(params.surround, offset);
+        self.outputs = vec![params.layout.line_type().unlabeled()];
+        self.params = params;
+        None
+    }
+
+    fn run_tick(&mut self, _t: u64, inputs: &[InputRef], outputs: &mut [OutputRef]) -> Option<Self::Indication> {
+        let speakers = self.params.layout.speakers();
+        let input = inputs[0].expect_stereo();
+        let output = outputs[0].expect_multi(speakers.len());
+
+        self.centre.begin_tick();
+        self.surround.begin_tick();
+
+        for (frame, out) in input.chunks(CHANNELS).zip(output.chunks_mut(speakers.len())) {
+            let left = frame[0] as f64;
+            let right = frame[1] as f64;
+
+            let centre = self.centre.next() * (left + right) / 2.0;
+            let surround = self.surround.next();
+
+            for (speaker, out) in speakers.iter().zip(out.iter_mut()) {
+                let sample = match speaker {
+                    Speaker::Left => left,
+                    Speaker::Right => right,
+                    Speaker::Centre => centre,
+                    Speaker::Lfe => 0.0,
+                    Speaker::SurroundLeft | Speaker::RearLeft => surround * left,
+                    Speaker::SurroundRight | Speaker::RearRight => surround * right,
+                };
+
+                *out = sample as Sample;
+            }
+        }
+
+        None
+    }
+
+    fn inputs(&self) -> &[Terminal] {
+        &self.inputs
+    }
+
+    fn outputs(&self) -> &[Terminal] {
+        &self.outputs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mixlab_protocol::{ChannelLayout, UpmixParams};
+
+    use crate::engine::{InputRef, Output, Sample};
+    use crate::engine::harness::{self, Harness};
+    use super::Upmix;
+
+    fn tick(upmix: &mut Harness<Upmix>, input: &[Sample]) -> (usize, Vec<Sample>) {
+        match &upmix.tick(&[InputRef::Stereo(input)])[..] {
+            [Output::Multi(channels, samples)] => (*channels, samples.clone()),
+            _ => panic!("expected one multi-channel output"),
+        }
+    }
+
+    fn interleave(left: &[Sample], right: &[Sample]) -> Vec<Sample> {
+        left.iter().zip(right)
+            .flat_map(|(l, r)| vec![*l, *r])
+            .collect()
+    }
+
+    #[test]
+    fn spreads_stereo_over_layout() {
+        let mut upmix = Harness::<Upmix>::new(UpmixParams {
+            layout: ChannelLayout::Surround51,
+            centre: 0.5,
+            surround: 0.25,
+        });
+
+        for _ in 0..2 {
+            let input = interleave(&upmix.sine(440.0, 0.5), &upmix.sine(660.0, 0.5));
+            let (channels, output) = tick(&mut upmix, &input);
+            assert_eq!(channels, 6);
+
+            for (input, frame) in input.chunks(2).zip(output.chunks(6)) {
+                let (left, right) = (input[0] as f64, input[1] as f64);
+
+                let expected = [left, right, 0.5 * (left + right) / 2.0, 0.0, 0.25 * left, 0.25 * right];
+
+                for (sample, expected) in frame.iter().zip(&expected) {
+                    assert!((*sample as f64 - expected).abs() < 1e-6);
+                }
+            }
+        }
+
+        upmix.update(UpmixParams { layout: ChannelLayout::Surround71, centre: 1.0, surround: 0.0 });
+
+        for _ in 0..2 {
+            let input = interleave(&upmix.sine(440.0, 0.5), &upmix.sine(660.0, 0.5));
+            let (channels, output) = tick(&mut upmix, &input);
+            assert_eq!(channels, 8);
+
+            // the fronts pass straight through, there's nothing for the lfe:
+            for (input, frame) in input.chunks(2).zip(output.chunks(8)) {
+                assert_eq!(frame[..2], input[..]);
+                assert_eq!(frame[3], 0.0);
+            }
+        }
+
+        let input = harness::stereo(&upmix.sine(440.0, 0.5));
+        upmix.update(UpmixParams { layout: ChannelLayout::Quad, centre: 1.0, surround: 1.0 });
+
+        let (channels, output) = tick(&mut upmix, &input);
+        assert_eq!(channels, 4);
+        assert_eq!(output.len(), input.len() * 2);
+
+        upmix.assert_fixture("upmix");
+    }
+}
@@ -5,7 +5,7 @@ use mixlab_codec::ffmpeg::{AvFrame, PictureSettings, PixelFormat};
 use mixlab_protocol::{VideoMixerParams, LineType, Terminal, VIDEO_MIXER_CHANNELS};
 use mixlab_util::time::{MediaTime, MediaDuration};
 
-use crate::engine::{self, InputRef, OutputRef, SAMPLE_RATE, TICKS_PER_SECOND};
+use crate::engine::{self, EngineConfig, InputRef, OutputRef};
 use crate::module::ModuleT;
 use crate::video;
 use crate::video::encode::DynamicScaler;
@@ -13,6 +13,7 @@ use crate::video::encode::DynamicScaler;
 #[derive(Debug)]
 pub struct VideoMixer {
     params: VideoMixerParams,
+    config: EngineConfig,
     inputs: Vec<Terminal>,
     outputs: Vec<Terminal>,
     channels: Vec<Channel>,
@@ -36,9 +37,10 @@ impl ModuleT for VideoMixer {
     type Indication = ();
     type Event = ();
 
-    fn create(params: Self::Params, _: engine::ModuleCtx<Self>) -> (Self, Self::Indication) {
+    fn create(params: Self::Params, ctx: engine::ModuleCtx<Self>) -> (Self, Self::Indication) {
         let mixer = VideoMixer {
             params,
+            config: ctx.config(),
             inputs: (0..VIDEO_MIXER_CHANNELS).map(|i|
                 LineType::Video.labeled(&(i + 1).to_string())
             ).collect(),
@@ -89,7 +91,7 @@ impl ModuleT for VideoMixer {
                 .cloned();
         }
 
-        let absolute_timestamp = MediaTime::new(t as i64, SAMPLE_RATE as i64);
+        let absolute_timestamp = MediaTime::new(t as i64, self.config.sample_rate as i64);
 
         // expire stored frames
         for channel in &mut self.channels {
@@ -241,7 +243,7 @@ impl ModuleT for VideoMixer {
         *out = Some(engine::VideoFrame {
             data: video::Frame {
                 decoded: output_frame,
-                duration_hint: MediaDuration::new(1, TICKS_PER_SECOND as i64), // TODO this assumes 1 output frame per tick
+                duration_hint: MediaDuration::new(1, self.config.ticks_per_second as i64), // TODO this assumes 1 output frame per tick
             },
             tick_offset: MediaDuration::new(0, 1),
         });
@@ -295,3 +297,50 @@ fn unify_picture_settings(a: PictureSettings, b: PictureSettings) -> PictureSett
         pixel_format: pixfmt,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use mixlab_codec::ffmpeg::{AvFrame, PictureSettings, PixelFormat};
+    use mixlab_protocol::VideoMixerParams;
+    use mixlab_util::time::MediaDuration;
+
+    use crate::engine::{InputRef, Output, VideoFrame};
+    use crate::engine::harness::Harness;
+    use crate::video;
+    use super::VideoMixer;
+
+    fn is_some(output: &Output) -> bool {
+        match output {
+            Output::Video(frame) => frame.is_some(),
+            _ => panic!("expected video output"),
+        }
+    }
+
+    #[test]
+    fn holds_frames_for_their_duration() {
+        let mut mixer = Harness::<VideoMixer>::new(VideoMixerParams { a: Some(0), b: Some(1), fader: 1.0 });
+
+        let frame = VideoFrame {
+            data: video::Frame {
+                decoded: AvFrame::blank(&PictureSettings {
+                    width: 64,
+                    height: 32,
+                    pixel_format: PixelFormat::yuv420p(),
+                }),
+                duration_hint: MediaDuration::new(3, 1050),
+            },
+            tick_offset: MediaDuration::new(0, 1),
+        };
+
+        let none = mixer.disconnected();
+        let outputs = mixer.tick(&[InputRef::Video(Some(&frame)), none, none, none]);
+        assert_eq!(outputs.iter().map(is_some).collect::<Vec<_>>(), vec![true, true, false]);
+
+        // channel outputs only pass through live frames, but the mix holds
+        // the last frame until it expires after three ticks:
+        for expect_mix in &[true, true, false] {
+            let outputs = mixer.tick(&[none, none, none, none]);
+            assert_eq!(outputs.iter().map(is_some).collect::<Vec<_>>(), vec![*expect_mix, false, false]);
+        }
+    }
+}
@@ -0,0 +1,484 @@
+mod packet;
+
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::net::SocketAddr;
+use std::num::NonZeroUsize;
+
+use derive_more::From;
+use tokio::net::UdpSocket;
+use tokio::net::udp::SendHalf;
+use tokio::sync::broadcast;
+
+use mixlab_protocol::{ModuleId, ModuleParams, Indication, ServerUpdate, WorkspaceState, WorkspaceMessage, WorkspaceOp, ClientSequence, StreamOutputLiveStatus, TemporalWarningStatus};
+
+use crate::engine::{EngineEvent, EngineError, EngineSession};
+use crate::project::ProjectHandle;
+use crate::util::Sequence;
+
+use packet::{Packet, Message, Arg};
+
+// OSC server for external control surfaces. Listens for messages on UDP:
+//
+//   /module/<id>/<param> <number>  sets a numeric param of a module, named as
+//                                  in scenes and cv bindings (eg. amplitude,
+//                                  channel1.fader)
+//   /subscribe [port]              sends status to the sender, or to the given
+//                                  port on the sender's host
+//   /unsubscribe [port]            stops sending status
+//
+// Subscribers are sent the current value of every address on subscribing,
+// then each change as it happens:
+//
+//   /module/<id>/<param> f         numeric params
+//   /module/<id>/level f...        plotter peak level, one per input
+//   /module/<id>/gain_reduction f  compressor gain reduction in decibels
+//   /module/<id>/open i            gate open
+//   /module/<id>/clip i            output device clipping
+//   /module/<id>/live s            stream output status: offline, connecting or live
+//   /module/<id>/error i           stream output error
+//   /module/<id>/fault s           module faulted, with the fault message
+//
+// Bundles are applied as soon as they arrive, regardless of their time tag.
+
+const MAX_PACKET: usize = 65536;
+
+#[derive(From, Debug)]
+pub enum OscError {
+    Io(io::Error),
+    Engine(EngineError),
+}
+
+struct Server {
+    socket: SendHalf,
+    subscribers: HashSet<SocketAddr>,
+    modules: HashMap<ModuleId, ModuleParams>,
+    // the most recent args sent to each address, so that only changes are
+    // sent on, and new subscribers can be caught up:
+    sent: HashMap<String, Vec<Arg>>,
+}
+
+enum Event {
+    Receive(io::Result<(usize, SocketAddr)>),
+    Engine(Result<EngineEvent, broadcast::RecvError>),
+}
+
+pub async fn run(addr: SocketAddr, project: ProjectHandle) -> Result<(), OscError> {
+    let socket = UdpSocket::bind(addr).await?;
+
+    println!("OSC server listening on udp://{}", socket.local_addr()?);
+
+    serve(socket, project).await
+}
+
+async fn serve(socket: UdpSocket, project: ProjectHandle) -> Result<(), OscError> {
+    let (mut recv, send) = socket.split();
+
+    let mut server = Server {
+        socket: send,
+        subscribers: HashSet::new(),
+        modules: HashMap::new(),
+        sent: HashMap::new(),
+    };
+
+    let mut buf = vec![0; MAX_PACKET];
+
+    loop {
+        let (state, mut events, session) = project.connect_engine().await?;
+        let mut sequence = Sequence::new();
+
+        server.load(state).await;
+
+        loop {
+            let event = tokio::select! {
+                result = recv.recv_from(&mut buf) => Event::Receive(result),
+                event = events.recv() => Event::Engine(event),
+            };
+
+            match event {
+                Event::Receive(Ok((len, from))) => {
+                    match Packet::parse(&buf[..len]) {
+                        Ok(packet) => {
+                            let mut messages = Vec::new();
+                            flatten(packet, &mut messages);
+
+                            for message in messages {
+                                server.receive(message, from, &session, &mut sequence).await;
+                            }
+                        }
+                        Err(e) => {
+                            eprintln!("osc: bad packet from {}: {:?}", from, e);
+                        }
+                    }
+                }
+                Event::Receive(Err(e)) => {
+                    // some platforms report ICMP port unreachable from a
+                    // previous send as an error here, so carry on
+                    eprintln!("osc: recv error: {:?}", e);
+                }
+                Event::Engine(Ok(EngineEvent::ServerUpdate(update))) => {
+                    server.update(update).await;
+                }
+                Event::Engine(Ok(EngineEvent::Sync(_))) => {}
+                Event::Engine(Err(broadcast::RecvError::Lagged(_))) => {
+                    // missed updates, reconnect to resync with the engine
+                    break;
+                }
+                Event::Engine(Err(broadcast::RecvError::Closed)) => {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+fn flatten(packet: Packet, messages: &mut Vec<Message>) {
+    match packet {
+        Packet::Message(message) => messages.push(message),
+        Packet::Bundle(bundle) => {
+            for element in bundle.elements {
+                flatten(element, messages);
+            }
+        }
+    }
+}
+
+impl Server {
+    async fn load(&mut self, state: WorkspaceState) {
+        let module_ids = state.modules.iter()
+            .map(|(id, _)| *id)
+            .collect::<HashSet<_>>();
+
+        // forget about anything deleted while we were out of sync:
+        let stale = self.modules.keys()
+            .filter(|id| !module_ids.contains(id))
+            .copied()
+            .collect::<Vec<_>>();
+
+        for module_id in stale {
+            self.delete_module(module_id);
+        }
+
+        for (module_id, params) in state.modules {
+            self.update_params(module_id, params).await;
+        }
+
+        for (module_id, indication) in state.indications {
+            self.update_indication(module_id, &indication).await;
+        }
+    }
+
+    async fn receive(&mut self, message: Message, from: SocketAddr, session: &EngineSession, sequence: &mut Sequence) {
+        match message.address.as_str() {
+            "/subscribe" => {
+                let subscriber = subscriber_addr(&message, from);
+
+                self.subscribers.insert(subscriber);
+
+                // surfaces resubscribe when they restart, so catch them up
+                // even if they were already subscribed
+                let messages = self.sent.iter()
+                    .map(|(address, args)| Message::new(address.clone(), args.clone()))
+                    .collect::<Vec<_>>();
+
+                for message in messages {
+                    self.send_to(message, subscriber).await;
+                }
+            }
+            "/unsubscribe" => {
+                self.subscribers.remove(&subscriber_addr(&message, from));
+            }
+            address => {
+                // control surfaces send all sorts of things we have no use
+                // for, so anything unrecognised is quietly ignored
+                let (module_id, param) = match parse_module_address(address) {
+                    Some(target) => target,
+                    None => { return; }
+                };
+
+                let value = match message.args.first().and_then(Arg::as_f64) {
+                    Some(value) => value,
+                    None => { return; }
+                };
+
+                let params = match self.modules.get_mut(&module_id) {
+                    Some(params) => params,
+                    None => { return; }
+                };
+
+                let mut new_params = params.clone();
+
+                if !new_params.set_numeric_param(param, value) {
+                    return;
+                }
+
+                let msg = WorkspaceMessage {
+                    sequence: ClientSequence(sequence.next()),
+                    op: WorkspaceOp::UpdateModuleParams(module_id, new_params.clone()),
+                };
+
+                match session.update(msg) {
+                    Ok(()) => {
+                        // later messages in the same bundle or before the
+                        // engine's update arrives build on this one
+                        *params = new_params;
+                    }
+                    Err(EngineError::Busy) => {
+                        // drop it, the surface will send another value soon
+                        // enough if the control is still moving
+                    }
+                    Err(EngineError::Stopped) => {
+                        eprintln!("osc: engine stopped");
+                    }
+                }
+            }
+        }
+    }
+
+    async fn update(&mut self, update: ServerUpdate) {
+        match update {
+            ServerUpdate::CreateModule { id, params, indication, .. } => {
+                self.update_params(id, params).await;
+                self.update_indication(id, &indication).await;
+            }
+            ServerUpdate::UpdateModuleParams(id, params) => {
+                self.update_params(id, params).await;
+            }
+            ServerUpdate::UpdateModuleIndication(id, indication) => {
+                self.update_indication(id, &indication).await;
+            }
+            ServerUpdate::DeleteModule(id) => {
+                self.delete_module(id);
+            }
+            _ => {}
+        }
+    }
+
+    async fn update_params(&mut self, module_id: ModuleId, params: ModuleParams) {
+        for (name, value) in params.numeric_params() {
+            self.publish(Message::new(module_address(module_id, &name), vec![Arg::Float(value as f32)])).await;
+        }
+
+        self.modules.insert(module_id, params);
+    }
+
+    async fn update_indication(&mut self, module_id: ModuleId, indication: &Indication) {
+        for message in indication_messages(module_id, indication) {
+            self.publish(message).await;
+        }
+    }
+
+    fn delete_module(&mut self, module_id: ModuleId) {
+        self.modules.remove(&module_id);
+
+        let prefix = module_address(module_id, "");
+        self.sent.retain(|address, _| !address.starts_with(&prefix));
+    }
+
+    async fn publish(&mut self, message: Message) {
+        if self.sent.get(&message.address) == Some(&message.args) {
+            return;
+        }
+
+        self.sent.insert(message.address.clone(), message.args.clone());
+
+        let bytes = message.into_bytes();
+
+        let subscribers = self.subscribers.iter().copied().collect::<Vec<_>>();
+
+        for subscriber in subscribers {
+            if let Err(e) = self.socket.send_to(&bytes, &subscriber).await {
+                eprintln!("osc: send to {} failed: {:?}", subscriber, e);
+            }
+        }
+    }
+
+    async fn send_to(&mut self, message: Message, addr: SocketAddr) {
+        if let Err(e) = self.socket.send_to(&message.into_bytes(), &addr).await {
+            eprintln!("osc: send to {} failed: {:?}", addr, e);
+        }
+    }
+}
+
+fn subscriber_addr(message: &Message, from: SocketAddr) -> SocketAddr {
+    let port = message.args.first()
+        .and_then(Arg::as_f64)
+        .filter(|port| *port > 0.0 && *port <= u16::max_value() as f64)
+        .map(|port| port as u16)
+        .unwrap_or(from.port());
+
+    SocketAddr::new(from.ip(), port)
+}
+
+fn module_address(module_id: ModuleId, name: &str) -> String {
+    format!("/module/{}/{}", module_id.0, name)
+}
+
+fn parse_module_address(address: &str) -> Option<(ModuleId, &str)> {
+    let mut parts = address.strip_prefix("/module/")?.splitn(2, '/');
+    let module_id = parts.next()?.parse::<NonZeroUsize>().ok()?;
+    let param = parts.next().filter(|param| !param.is_empty())?;
+    Some((ModuleId(module_id), param))
+}
+
+fn indication_messages(module_id: ModuleId, indication: &Indication) -> Vec<Message> {
+    let message = |name: &str, args| Message::new(module_address(module_id, name), args);
+
+    match indication {
+        Indication::Plotter(indication) => {
+            let levels = indication.inputs.iter()
+                .map(|samples| samples.iter().fold(0.0f32, |peak, sample| peak.max(sample.abs())))
+                .map(Arg::Float)
+                .collect();
+
+            vec![message("level", levels)]
+        }
+        Indication::Compressor(indication) => {
+            vec![message("gain_reduction", vec![Arg::Float(indication.gain_reduction.0 as f32)])]
+        }
+        Indication::Gate(indication) => {
+            vec![message("open", vec![Arg::Int(indication.open as i32)])]
+        }
+        Indication::OutputDevice(indication) => {
+            let clip = matches!(indication.clip, Some(TemporalWarningStatus::Active));
+            vec![message("clip", vec![Arg::Int(clip as i32)])]
+        }
+        Indication::StreamOutput(indication) => {
+            let live = match indication.live {
+                StreamOutputLiveStatus::Offline => "offline",
+                StreamOutputLiveStatus::Connecting => "connecting",
+                StreamOutputLiveStatus::Live => "live",
+            };
+
+            vec![
+                message("live", vec![Arg::String(live.to_owned())]),
+                message("error", vec![Arg::Int(indication.error as i32)]),
+            ]
+        }
+        Indication::Fault(fault) => {
+            vec![message("fault", vec![Arg::String(fault.message.clone())])]
+        }
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::net::UdpSocket as StdUdpSocket;
+    use std::time::Duration;
+
+    use tokio::task;
+
+    use mixlab_protocol::{AmplifierParams, WindowGeometry};
+
+    use crate::engine::{ConfigOverride, EngineEvents};
+    use crate::project;
+    use super::*;
+
+    // blocks with a timeout so that a lost message fails the test rather than
+    // hanging it
+    fn receive(socket: &StdUdpSocket) -> Message {
+        let mut buf = vec![0; MAX_PACKET];
+
+        let len = task::block_in_place(|| socket.recv(&mut buf))
+            .expect("receive from osc server");
+
+        match Packet::parse(&buf[..len]).expect("parse packet") {
+            Packet::Message(message) => message,
+            Packet::Bundle(_) => panic!("server sent a bundle"),
+        }
+    }
+
+    async fn next_update(events: &mut EngineEvents) -> ServerUpdate {
+        loop {
+            match events.recv().await.expect("engine event") {
+                EngineEvent::ServerUpdate(update) => { return update; }
+                EngineEvent::Sync(_) => {}
+            }
+        }
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn sets_params_and_echoes_them() {
+        let path = env::temp_dir().join(format!("mixlab-osc-{}", std::process::id()));
+        let project = project::open_or_create(path.clone(), ConfigOverride::default()).await
+            .expect("open project");
+
+        let (_, mut events, session) = project.connect_engine().await.expect("connect engine");
+
+        let params = ModuleParams::Amplifier(AmplifierParams { amplitude: 1.0, mod_depth: 0.0 });
+
+        session.update(WorkspaceMessage {
+            sequence: ClientSequence(Sequence::new().next()),
+            op: WorkspaceOp::CreateModule(params, WindowGeometry::default()),
+        }).expect("create module");
+
+        let module_id = match next_update(&mut events).await {
+            ServerUpdate::CreateModule { id, .. } => id,
+            update => panic!("expected module to be created, got {:?}", update),
+        };
+
+        let socket = UdpSocket::bind("127.0.0.1:0").await.expect("bind osc server");
+        let server_addr = socket.local_addr().expect("osc server address");
+        tokio::spawn(serve(socket, project.clone()));
+
+        let client = StdUdpSocket::bind("127.0.0.1:0").expect("bind client");
+        client.set_read_timeout(Some(Duration::from_secs(5))).expect("set read timeout");
+        client.connect(server_addr).expect("connect client");
+
+        let address = module_address(module_id, "amplitude");
+
+        let send = |message: Message| {
+            client.send(&message.into_bytes()).expect("send to osc server");
+        };
+
+        send(Message::new("/subscribe", vec![]));
+
+        // caught up with the current value on subscribing:
+        loop {
+            let message = receive(&client);
+
+            if message.address == address {
+                assert_eq!(message.args, vec![Arg::Float(1.0)]);
+                break;
+            }
+        }
+
+        send(Message::new(address.clone(), vec![Arg::Float(0.5)]));
+
+        match next_update(&mut events).await {
+            ServerUpdate::UpdateModuleParams(id, ModuleParams::Amplifier(params)) => {
+                assert_eq!(id, module_id);
+                assert_eq!(params.amplitude, 0.5);
+            }
+            update => panic!("expected amplifier params to be updated, got {:?}", update),
+        }
+
+        loop {
+            let message = receive(&client);
+
+            if message.address == address {
+                assert_eq!(message.args, vec![Arg::Float(0.5)]);
+                break;
+            }
+        }
+
+        let mut database = path.clone();
+        database.set_extension("mixlab");
+        let _ = fs::remove_file(database);
+    }
+
+    #[test]
+    fn parses_module_addresses() {
+        let id = ModuleId(NonZeroUsize::new(12).unwrap());
+
+        assert_eq!(parse_module_address("/module/12/channel1.fader"), Some((id, "channel1.fader")));
+        assert_eq!(parse_module_address(&module_address(id, "amplitude")), Some((id, "amplitude")));
+        assert_eq!(parse_module_address("/module/12/"), None);
+        assert_eq!(parse_module_address("/module/0/amplitude"), None);
+        assert_eq!(parse_module_address("/module/x/amplitude"), None);
+        assert_eq!(parse_module_address("/ping"), None);
+    }
+}
@@ -0,0 +1,370 @@
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+// See http://opensoundcontrol.org/spec-1_0 for reference. All values are big
+// endian, and strings and blobs are padded with zeroes to a multiple of four
+// bytes.
+
+const BUNDLE_TAG: &[u8] = b"#bundle\0";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Packet {
+    Message(Message),
+    Bundle(Bundle),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub address: String,
+    pub args: Vec<Arg>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bundle {
+    pub timetag: u64,
+    pub elements: Vec<Packet>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Arg {
+    Int(i32),
+    Float(f32),
+    String(String),
+    Blob(Vec<u8>),
+    Long(i64),
+    Double(f64),
+    True,
+    False,
+    Nil,
+}
+
+#[derive(Debug)]
+pub enum PacketError {
+    Eof,
+    BadString,
+    BadAddress,
+    BadTypeTag,
+    UnknownType(char),
+    BadElementSize(i32),
+}
+
+impl Arg {
+    // controllers send whichever numeric type suits them, booleans are taken
+    // as 0 or 1
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Arg::Int(value) => Some(*value as f64),
+            Arg::Float(value) => Some(*value as f64),
+            Arg::Long(value) => Some(*value as f64),
+            Arg::Double(value) => Some(*value),
+            Arg::True => Some(1.0),
+            Arg::False => Some(0.0),
+            Arg::String(_) |
+            Arg::Blob(_) |
+            Arg::Nil => None,
+        }
+    }
+
+    fn type_tag(&self) -> char {
+        match self {
+            Arg::Int(_) => 'i',
+            Arg::Float(_) => 'f',
+            Arg::String(_) => 's',
+            Arg::Blob(_) => 'b',
+            Arg::Long(_) => 'h',
+            Arg::Double(_) => 'd',
+            Arg::True => 'T',
+            Arg::False => 'F',
+            Arg::Nil => 'N',
+        }
+    }
+}
+
+impl Packet {
+    pub fn parse(bytes: &[u8]) -> Result<Packet, PacketError> {
+        if bytes.starts_with(BUNDLE_TAG) {
+            Bundle::parse(&bytes[BUNDLE_TAG.len()..]).map(Packet::Bundle)
+        } else {
+            Message::parse(bytes).map(Packet::Message)
+        }
+    }
+
+    pub fn write_to(&self, out: &mut BytesMut) {
+        match self {
+            Packet::Message(message) => message.write_to(out),
+            Packet::Bundle(bundle) => bundle.write_to(out),
+        }
+    }
+
+    pub fn into_bytes(self) -> Bytes {
+        let mut out = BytesMut::new();
+        self.write_to(&mut out);
+        out.freeze()
+    }
+}
+
+impl Message {
+    pub fn new(address: impl Into<String>, args: Vec<Arg>) -> Self {
+        Message { address: address.into(), args }
+    }
+
+    fn parse(mut bytes: &[u8]) -> Result<Message, PacketError> {
+        let address = read_string(&mut bytes)?;
+
+        if !address.starts_with('/') {
+            return Err(PacketError::BadAddress);
+        }
+
+        // very old implementations omit the type tag string when there are
+        // no arguments
+        if !bytes.has_remaining() {
+            return Ok(Message { address, args: Vec::new() });
+        }
+
+        let type_tags = read_string(&mut bytes)?;
+
+        let mut type_tags = type_tags.chars();
+
+        if type_tags.next() != Some(',') {
+            return Err(PacketError::BadTypeTag);
+        }
+
+        let mut args = Vec::new();
+
+        for tag in type_tags {
+            let arg = match tag {
+                'i' => Arg::Int(read_i32(&mut bytes)?),
+                'f' => Arg::Float(f32::from_bits(read_i32(&mut bytes)? as u32)),
+                's' => Arg::String(read_string(&mut bytes)?),
+                'b' => Arg::Blob(read_blob(&mut bytes)?),
+                'h' => Arg::Long(read_i64(&mut bytes)?),
+                'd' => Arg::Double(f64::from_bits(read_i64(&mut bytes)? as u64)),
+                'T' => Arg::True,
+                'F' => Arg::False,
+                'N' => Arg::Nil,
+                tag => { return Err(PacketError::UnknownType(tag)); }
+            };
+
+            args.push(arg);
+        }
+
+        Ok(Message { address, args })
+    }
+
+    fn write_to(&self, out: &mut BytesMut) {
+        write_string(out, &self.address);
+
+        let type_tags = Some(',')
+            .into_iter()
+            .chain(self.args.iter().map(Arg::type_tag))
+            .collect::<String>();
+
+        write_string(out, &type_tags);
+
+        for arg in &self.args {
+            match arg {
+                Arg::Int(value) => out.put_i32(*value),
+                Arg::Float(value) => out.put_u32(value.to_bits()),
+                Arg::String(value) => write_string(out, value),
+                Arg::Blob(value) => write_blob(out, value),
+                Arg::Long(value) => out.put_i64(*value),
+                Arg::Double(value) => out.put_u64(value.to_bits()),
+                Arg::True |
+                Arg::False |
+                Arg::Nil => {}
+            }
+        }
+    }
+
+    pub fn into_bytes(self) -> Bytes {
+        Packet::Message(self).into_bytes()
+    }
+}
+
+impl Bundle {
+    fn parse(mut bytes: &[u8]) -> Result<Bundle, PacketError> {
+        let timetag = read_i64(&mut bytes)? as u64;
+
+        let mut elements = Vec::new();
+
+        while bytes.has_remaining() {
+            let size = read_i32(&mut bytes)?;
+
+            if size <= 0 || size % 4 != 0 || size as usize > bytes.remaining() {
+                return Err(PacketError::BadElementSize(size));
+            }
+
+            let size = size as usize;
+            elements.push(Packet::parse(&bytes[..size])?);
+            bytes.advance(size);
+        }
+
+        Ok(Bundle { timetag, elements })
+    }
+
+    fn write_to(&self, out: &mut BytesMut) {
+        out.put_slice(BUNDLE_TAG);
+        out.put_u64(self.timetag);
+
+        for element in &self.elements {
+            let mut buf = BytesMut::new();
+            element.write_to(&mut buf);
+
+            out.put_i32(buf.len() as i32);
+            out.put_slice(&buf);
+        }
+    }
+}
+
+fn padded_len(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+fn need(bytes: &[u8], len: usize) -> Result<(), PacketError> {
+    if bytes.remaining() < len {
+        Err(PacketError::Eof)
+    } else {
+        Ok(())
+    }
+}
+
+fn read_i32(bytes: &mut &[u8]) -> Result<i32, PacketError> {
+    need(bytes, 4)?;
+    Ok(bytes.get_i32())
+}
+
+fn read_i64(bytes: &mut &[u8]) -> Result<i64, PacketError> {
+    need(bytes, 8)?;
+    Ok(bytes.get_i64())
+}
+
+fn read_string(bytes: &mut &[u8]) -> Result<String, PacketError> {
+    let len = bytes.iter()
+        .position(|byte| *byte == 0)
+        .ok_or(PacketError::Eof)?;
+
+    let string = std::str::from_utf8(&bytes[..len])
+        .map_err(|_| PacketError::BadString)?
+        .to_owned();
+
+    // there is always at least one terminating zero, but the padding may be
+    // cut short at the end of a packet
+    let padded = padded_len(len + 1).min(bytes.remaining());
+    bytes.advance(padded);
+
+    Ok(string)
+}
+
+fn read_blob(bytes: &mut &[u8]) -> Result<Vec<u8>, PacketError> {
+    let len = read_i32(bytes)?;
+
+    if len < 0 {
+        return Err(PacketError::BadElementSize(len));
+    }
+
+    let len = len as usize;
+    need(bytes, len)?;
+
+    let blob = bytes[..len].to_vec();
+    bytes.advance(padded_len(len).min(bytes.remaining()));
+
+    Ok(blob)
+}
+
+fn write_string(out: &mut BytesMut, string: &str) {
+    out.put_slice(string.as_bytes());
+    out.put_slice(&[0; 4][..padded_len(string.len() + 1) - string.len()]);
+}
+
+fn write_blob(out: &mut BytesMut, blob: &[u8]) {
+    out.put_i32(blob.len() as i32);
+    out.put_slice(blob);
+    out.put_slice(&[0; 4][..padded_len(blob.len()) - blob.len()]);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::UdpSocket;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn parses_spec_example() {
+        let bytes = [
+            0x2f, 0x6f, 0x73, 0x63, 0x69, 0x6c, 0x6c, 0x61, // /oscilla
+            0x74, 0x6f, 0x72, 0x2f, 0x34, 0x2f, 0x66, 0x72, // tor/4/fr
+            0x65, 0x71, 0x75, 0x65, 0x6e, 0x63, 0x79, 0x00, // equency
+            0x2c, 0x66, 0x00, 0x00,                         // ,f
+            0x43, 0xdc, 0x00, 0x00,                         // 440.0
+        ];
+
+        let expected = Message::new("/oscillator/4/frequency", vec![Arg::Float(440.0)]);
+
+        assert_eq!(Packet::parse(&bytes).unwrap(), Packet::Message(expected.clone()));
+        assert_eq!(&expected.into_bytes()[..], &bytes[..]);
+    }
+
+    #[test]
+    fn round_trips_all_arg_types() {
+        let packet = Packet::Message(Message::new("/module/1/freq", vec![
+            Arg::Int(-3),
+            Arg::Float(0.25),
+            Arg::String("four".to_owned()),
+            Arg::Blob(vec![1, 2, 3, 4, 5]),
+            Arg::Long(1 << 40),
+            Arg::Double(-0.125),
+            Arg::True,
+            Arg::False,
+            Arg::Nil,
+        ]));
+
+        let bytes = packet.clone().into_bytes();
+
+        assert_eq!(bytes.len() % 4, 0);
+        assert_eq!(Packet::parse(&bytes).unwrap(), packet);
+    }
+
+    #[test]
+    fn round_trips_nested_bundles() {
+        let packet = Packet::Bundle(Bundle {
+            timetag: 1,
+            elements: vec![
+                Packet::Message(Message::new("/a", vec![Arg::Int(1)])),
+                Packet::Bundle(Bundle {
+                    timetag: 2,
+                    elements: vec![Packet::Message(Message::new("/b", vec![]))],
+                }),
+            ],
+        });
+
+        let bytes = packet.clone().into_bytes();
+
+        assert_eq!(Packet::parse(&bytes).unwrap(), packet);
+    }
+
+    #[test]
+    fn rejects_truncated_packets() {
+        let bytes = Message::new("/module/1/freq", vec![Arg::Double(1.0)]).into_bytes();
+
+        for len in 0..bytes.len() {
+            assert!(Packet::parse(&bytes[..len]).is_err(), "parsed truncated packet of {} bytes", len);
+        }
+    }
+
+    #[test]
+    fn round_trips_over_udp() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+
+        let message = Message::new("/module/2/channel1.fader", vec![Arg::Float(0.5)]);
+        sender.send_to(&message.clone().into_bytes(), receiver.local_addr().unwrap()).unwrap();
+
+        let mut buf = [0; 1536];
+        let (len, from) = receiver.recv_from(&mut buf).unwrap();
+
+        assert_eq!(from, sender.local_addr().unwrap());
+        assert_eq!(Packet::parse(&buf[..len]).unwrap(), Packet::Message(message));
+    }
+}
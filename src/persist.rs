@@ -1,20 +1,17 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
-use serde::{Serialize, Deserialize};
+use mixlab_protocol::{ModuleId, AutomationLane, Scene, MidiBinding};
 
-use mixlab_protocol::{ModuleId, ModuleParams, OutputId, WindowGeometry};
+// the persisted workspace shape is shared with sub-patches, which carry a
+// nested workspace in their module params:
+pub use mixlab_protocol::{SavedWorkspace as Workspace, SavedModule as Module};
 
-use crate::util::Sequence;
+// automation lanes are kept apart from the workspace as they grow large and
+// change on a different schedule:
+pub type Automation = HashMap<ModuleId, AutomationLane>;
 
-#[derive(Debug, Serialize, Deserialize, Clone, Default)]
-pub struct Workspace {
-    pub module_seq: Sequence,
-    pub modules: HashMap<ModuleId, Module>,
-}
+// named snapshots of module params, in name order:
+pub type Scenes = BTreeMap<String, Scene>;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct Module {
-    pub params: ModuleParams,
-    pub geometry: WindowGeometry,
-    pub inputs: Vec<Option<OutputId>>,
-}
+// bindings of MIDI controls to module params, in the order they were made:
+pub type MidiBindings = Vec<MidiBinding>;
@@ -1,10 +1,12 @@
 use std::fmt::{self, Debug};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use derive_more::From;
 use futures::stream::{Stream, StreamExt};
 use rusqlite::{self, Connection, OptionalExtension};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
 use tokio::sync::watch;
 use tokio::{io, task, runtime};
 
@@ -12,7 +14,7 @@ use mixlab_protocol as protocol;
 use mixlab_protocol::{WorkspaceState, PerformanceInfo};
 
 use crate::db;
-use crate::engine::{self, EngineHandle, EngineEvents, EngineError, EngineSession, WorkspaceEmbryo};
+use crate::engine::{self, EngineHandle, EngineEvents, EngineError, EngineSession, WorkspaceEmbryo, EngineConfig, ConfigError, ConfigOverride, Transport, TransportRef, Automation, Scenes, MidiBindings};
 use crate::persist;
 
 pub mod stream;
@@ -33,6 +35,17 @@ pub struct ProjectBase {
     // in the blocking context and pass it as an Arc rather than a reference
     database: Arc<std::sync::Mutex<Connection>>,
 
+    // fixed for the lifetime of the open project, changing it requires a
+    // restart:
+    config: EngineConfig,
+
+    // musical clock driven by the engine and read by modules:
+    transport: TransportRef,
+
+    // captured when the project is opened. modules spawn their async tasks
+    // onto it, as they run on threads outside of the runtime:
+    runtime: runtime::Handle,
+
     notify: NotifyTx,
 }
 
@@ -49,7 +62,12 @@ pub enum OpenError {
     Io(io::Error),
     Json(serde_json::Error),
     Database(rusqlite::Error),
+    Config(ConfigError),
     NotDirectory,
+    NotFound,
+    // the project was last saved by a different version of mixlab. projects
+    // are only migrated when opened by the server, never by a render
+    OutdatedSchema,
 }
 
 impl ProjectBase {
@@ -66,39 +84,76 @@ impl ProjectBase {
     }
 
     async fn attach(path: PathBuf, notify: NotifyTx) -> Result<Self, rusqlite::Error> {
-        let mut sqlite_path = path.clone();
-        sqlite_path.set_extension("mixlab");
-        let database = db::attach(sqlite_path).await?;
+        let database = db::attach(database_path(&path)).await?;
+        Ok(Self::new(path, database, notify))
+    }
+
+    fn new(path: PathBuf, database: Connection, notify: NotifyTx) -> Self {
+        let config = EngineConfig::default();
 
-        Ok(ProjectBase {
+        ProjectBase {
             path,
             database: Arc::new(std::sync::Mutex::new(database)),
+            config,
+            transport: Transport::new(Default::default(), config.sample_rate),
+            runtime: runtime::Handle::current(),
+            notify,
+        }
+    }
+
+    // a project with an empty in-memory database and nothing on disk, for
+    // running modules in tests
+    #[cfg(test)]
+    pub fn stub(config: EngineConfig, runtime: runtime::Handle) -> ProjectBaseRef {
+        let database = db::attach_in_memory().expect("attach in-memory database");
+        let (notify, _) = notify();
+
+        Arc::new(ProjectBase {
+            path: PathBuf::from("stub"),
+            database: Arc::new(std::sync::Mutex::new(database)),
+            config,
+            transport: Transport::new(Default::default(), config.sample_rate),
+            runtime,
             notify,
         })
     }
 
-    async fn read_workspace(&self) -> Result<persist::Workspace, OpenError> {
-        let serialized = self.with_database(|conn| -> Result<Option<Vec<u8>>, rusqlite::Error> {
-            conn.query_row("SELECT serialized FROM workspace WHERE rowid = 1", rusqlite::NO_PARAMS,
+    pub fn config(&self) -> EngineConfig {
+        self.config
+    }
+
+    pub fn transport(&self) -> TransportRef {
+        self.transport.clone()
+    }
+
+    pub fn runtime(&self) -> runtime::Handle {
+        self.runtime.clone()
+    }
+
+    // config, the workspace and the like are each kept as a single json
+    // document in row 1 of their own table
+    async fn read_json_row<T: DeserializeOwned + Default>(&self, table: &'static str) -> Result<T, OpenError> {
+        let serialized = self.with_database(move |conn| -> Result<Option<Vec<u8>>, rusqlite::Error> {
+            conn.query_row(&format!("SELECT serialized FROM {} WHERE rowid = 1", table), rusqlite::NO_PARAMS,
                 |row| row.get(0)).optional()
         }).await?;
 
-        let workspace = match serialized {
+        let value = match serialized {
             Some(serialized) => serde_json::from_slice(&serialized)?,
-            None => persist::Workspace::default(),
+            None => T::default(),
         };
 
-        Ok(workspace)
+        Ok(value)
     }
 
-    async fn write_workspace(&self, workspace: &persist::Workspace) -> Result<(), rusqlite::Error> {
-        let serialized = serde_json::to_vec(workspace).expect("serde_json::to_vec");
+    async fn write_json_row<T: Serialize>(&self, table: &'static str, value: &T) -> Result<(), rusqlite::Error> {
+        let serialized = serde_json::to_vec(value).expect("serde_json::to_vec");
 
         self.with_database(move |conn| -> Result<(), rusqlite::Error> {
-            conn.execute(r"
-                    INSERT INTO workspace (rowid, serialized) VALUES (1, ?)
+            conn.execute(&format!(r"
+                    INSERT INTO {} (rowid, serialized) VALUES (1, ?)
                     ON CONFLICT (rowid) DO UPDATE SET serialized = excluded.serialized
-                ",
+                ", table),
                 &[serialized])?;
 
             Ok(())
@@ -106,30 +161,81 @@ impl ProjectBase {
     }
 }
 
-pub async fn open_or_create(path: PathBuf) -> Result<ProjectHandle, OpenError> {
+fn database_path(path: &Path) -> PathBuf {
+    let mut sqlite_path = path.to_owned();
+    sqlite_path.set_extension("mixlab");
+    sqlite_path
+}
+
+// a project opened for rendering. no engine is started, and nothing is written
+// back to the project, not even config overrides
+pub struct OfflineProject {
+    pub base: ProjectBaseRef,
+    pub workspace: persist::Workspace,
+    pub automation: persist::Automation,
+}
+
+pub async fn open_offline(path: PathBuf, config_override: ConfigOverride) -> Result<OfflineProject, OpenError> {
+    if !database_path(&path).exists() {
+        return Err(OpenError::NotFound);
+    }
+
+    let database = db::attach_read_only(database_path(&path)).await?
+        .ok_or(OpenError::OutdatedSchema)?;
+
+    let (notify_tx, _) = notify();
+    let mut base = ProjectBase::new(path, database, notify_tx);
+
+    let stored_config = base.read_json_row::<EngineConfig>("config").await?;
+    base.config = config_override.apply(stored_config).validate()?;
+
+    let workspace = base.read_json_row::<persist::Workspace>("workspace").await?;
+
+    base.transport = Transport::new(workspace.tempo.unwrap_or_default(), base.config.sample_rate);
+
+    let automation = base.read_json_row::<persist::Automation>("automation").await?;
+
+    Ok(OfflineProject {
+        base: Arc::new(base),
+        workspace,
+        automation,
+    })
+}
+
+pub async fn open_or_create(path: PathBuf, config_override: ConfigOverride) -> Result<ProjectHandle, OpenError> {
     let (notify_tx, notify_rx) = notify();
-    let base = ProjectBase::attach(path, notify_tx).await?;
-    let workspace = base.read_workspace().await?;
+    let mut base = ProjectBase::attach(path, notify_tx).await?;
+
+    let stored_config = base.read_json_row::<EngineConfig>("config").await?;
+    let config = config_override.apply(stored_config).validate()?;
+
+    if !config_override.is_empty() {
+        base.write_json_row("config", &config).await?;
+    }
+
+    base.config = config;
+
+    let workspace = base.read_json_row::<persist::Workspace>("workspace").await?;
+
+    base.transport = Transport::new(workspace.tempo.unwrap_or_default(), config.sample_rate);
+
+    let automation = base.read_json_row::<persist::Automation>("automation").await?;
+    let scenes = base.read_json_row::<persist::Scenes>("scenes").await?;
+    let midi = base.read_json_row::<persist::MidiBindings>("midi_bindings").await?;
 
     let base = Arc::new(base);
 
     // start engine update thread
-    let (embryo, mut persist_rx) = WorkspaceEmbryo::new(workspace);
-    let engine = engine::start(runtime::Handle::current(), embryo, base.clone());
-
-    task::spawn({
-        let base = base.clone();
-        async move {
-            while let Some(workspace) = persist_rx.recv().await {
-                match base.write_workspace(&workspace).await {
-                    Ok(()) => {}
-                    Err(e) => {
-                        eprintln!("project: could not persist workspace: {:?}", e);
-                    }
-                }
-            }
-        }
-    });
+    let (embryo, persist_rx) = WorkspaceEmbryo::new(workspace);
+    let (automation, automation_persist_rx) = Automation::new(automation);
+    let (scenes, scenes_persist_rx) = Scenes::new(scenes);
+    let (midi, midi_persist_rx) = MidiBindings::new(midi);
+    let engine = engine::start(embryo, automation, scenes, midi, base.clone());
+
+    persist_changes(base.clone(), "workspace", persist_rx);
+    persist_changes(base.clone(), "automation", automation_persist_rx);
+    persist_changes(base.clone(), "scenes", scenes_persist_rx);
+    persist_changes(base.clone(), "midi_bindings", midi_persist_rx);
 
     Ok(ProjectHandle {
         base,
@@ -138,11 +244,32 @@ pub async fn open_or_create(path: PathBuf) -> Result<ProjectHandle, OpenError> {
     })
 }
 
+// writes each new version the engine publishes back to its table
+fn persist_changes<T>(base: ProjectBaseRef, table: &'static str, mut rx: watch::Receiver<T>)
+    where T: Serialize + Clone + Send + Sync + 'static
+{
+    task::spawn(async move {
+        while let Some(value) = rx.recv().await {
+            match base.write_json_row(table, &value).await {
+                Ok(()) => {}
+                Err(e) => {
+                    eprintln!("project: could not persist {}: {:?}", table, e);
+                }
+            }
+        }
+    });
+}
+
 impl ProjectHandle {
     pub async fn connect_engine(&self) -> Result<(WorkspaceState, EngineEvents, EngineSession), EngineError> {
         self.engine.connect().await
     }
 
+    // the engine's most recent performance report, if it has made one yet
+    pub fn performance_info(&self) -> Option<Arc<PerformanceInfo>> {
+        self.engine.latest_performance_info()
+    }
+
     pub fn notifications(&self) -> impl Stream<Item = Notification> {
         let perf_info = self.engine.performance_info().map(Notification::PerformanceInfo);
         let media = self.notify.media.clone().map(|()| Notification::MediaLibrary);
@@ -185,3 +312,91 @@ pub fn notify() -> (NotifyTx, NotifyRx) {
 
     (tx, rx)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::num::NonZeroUsize;
+    use std::thread;
+    use std::time::Duration;
+
+    use tokio::sync::broadcast::RecvError;
+
+    use mixlab_protocol::{ModuleId, ModuleParams, AmplifierParams, MidiBinding, MidiControl, MidiControlKind, WorkspaceMessage, WorkspaceOp, ClientSequence};
+
+    use crate::engine::{EngineConfig, ConfigOverride, EngineEvent, OpClock};
+    use crate::persist;
+
+    #[tokio::test(threaded_scheduler)]
+    async fn persists_config_overrides() {
+        let path = env::temp_dir().join(format!("mixlab-project-{}", std::process::id()));
+
+        let overrides = ConfigOverride { sample_rate: Some(48000), ticks_per_second: Some(100) };
+        super::open_or_create(path.clone(), overrides).await
+            .expect("open project");
+
+        // opened again without overrides, the project keeps its 48 kHz config:
+        let project = super::open_offline(path.clone(), ConfigOverride::default()).await
+            .expect("open project offline");
+
+        assert_eq!(project.base.config(), EngineConfig { sample_rate: 48000, ticks_per_second: 100 });
+        assert_eq!(project.base.config().samples_per_tick(), 480);
+
+        let _ = fs::remove_file(super::database_path(&path));
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn persists_midi_bindings() {
+        let path = env::temp_dir().join(format!("mixlab-midi-{}", std::process::id()));
+
+        let project = super::open_or_create(path.clone(), ConfigOverride::default()).await
+            .expect("open project");
+
+        let (_, mut events, session) = project.connect_engine().await.expect("connect to engine");
+
+        let amplifier = ModuleParams::Amplifier(AmplifierParams { amplitude: 1.0, mod_depth: 0.0 });
+        let control = MidiControl { device: "nanoKONTROL".to_owned(), channel: 0, kind: MidiControlKind::Cc(7) };
+        let binding = MidiBinding { control, module: ModuleId(NonZeroUsize::new(1).unwrap()), param: "amplitude".to_owned(), min: 0.0, max: 1.0 };
+
+        let ops = vec![WorkspaceOp::CreateModule(amplifier, Default::default()), WorkspaceOp::BindMidi(binding.clone())];
+        let sequence = |n: usize| ClientSequence(NonZeroUsize::new(n).unwrap());
+        let last = sequence(ops.len());
+
+        for (n, op) in ops.into_iter().enumerate() {
+            session.update(WorkspaceMessage { sequence: sequence(n + 1), op }).expect("send op");
+        }
+
+        loop {
+            match events.recv().await {
+                Ok(EngineEvent::Sync(OpClock(_, sequence))) if sequence == last => break,
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => panic!("engine stopped"),
+            }
+        }
+
+        // bindings are written back in the background:
+        let mut persisted = persist::MidiBindings::new();
+
+        for _ in 0..100 {
+            persisted = project.base.read_json_row("midi_bindings").await.expect("read midi bindings");
+
+            if !persisted.is_empty() {
+                break;
+            }
+
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        assert_eq!(persisted, vec![binding.clone()]);
+
+        // and loaded along with the rest of the project:
+        let reopened = super::open_or_create(path.clone(), ConfigOverride::default()).await
+            .expect("reopen project");
+
+        let (state, _, _) = reopened.connect_engine().await.expect("connect to engine");
+        assert_eq!(state.midi, vec![binding]);
+
+        let _ = fs::remove_file(super::database_path(&path));
+    }
+}
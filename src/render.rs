@@ -0,0 +1,651 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::iter;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use byteorder::{LittleEndian, WriteBytesExt};
+use derive_more::From;
+use fdk_aac::enc as aac;
+use structopt::StructOpt;
+use tokio::task;
+
+use mixlab_codec::ffmpeg::PictureSettings;
+use mixlab_mux::mp4::{Mp4Mux, Mp4Params, TrackData, AdtsFrame};
+use mixlab_protocol::{ModuleId, InputId, OutputId, LineType, ChannelLayout};
+use mixlab_util::time::MediaTime;
+
+use crate::engine::{self, ConfigOverride, OfflineEngine, Output, Sample, CHANNELS};
+use crate::module::downmix::downmix_frame;
+use crate::project::{self, OpenError};
+use crate::video::encode::{EncodeStream, AudioCtx, AudioParams, VideoCtx, VideoParams, StreamSegment, Profile};
+
+const VIDEO_WIDTH: usize = 1280;
+const VIDEO_HEIGHT: usize = 720;
+
+#[derive(StructOpt)]
+pub struct RenderOpts {
+    /// longest to render for, eg. 60s, 1500ms, 2m. renders stop sooner once
+    /// media in the project has played to its end
+    #[structopt(long, parse(try_from_str = parse_duration))]
+    duration: Duration,
+    /// .wav for audio only, .mp4 for audio and video
+    #[structopt(short, long)]
+    output: PathBuf,
+    /// id of the module whose inputs are rendered. defaults to the first
+    /// terminal module with anything connected to it
+    #[structopt(long)]
+    terminal: Option<NonZeroUsize>,
+    /// render at a different sample rate to the project's, leaving the
+    /// project itself unchanged
+    #[structopt(long)]
+    sample_rate: Option<usize>,
+    /// render at a different tick rate to the project's, leaving the project
+    /// itself unchanged
+    #[structopt(long)]
+    tick_rate: Option<usize>,
+    /// project to render
+    project_path: PathBuf,
+}
+
+#[derive(From, Debug)]
+pub enum RenderError {
+    Open(OpenError),
+    Io(io::Error),
+    UnsupportedFormat,
+    #[from(ignore)]
+    NoSuchModule(ModuleId),
+    #[from(ignore)]
+    NothingConnected(ModuleId),
+    NoTerminal,
+    NoAudio,
+}
+
+enum Format {
+    Wav,
+    Mp4,
+}
+
+impl Format {
+    fn from_path(path: &Path) -> Option<Format> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+
+        match extension.as_str() {
+            "wav" => Some(Format::Wav),
+            "mp4" => Some(Format::Mp4),
+            _ => None,
+        }
+    }
+}
+
+pub async fn run(opts: RenderOpts) -> Result<(), RenderError> {
+    let format = Format::from_path(&opts.output)
+        .ok_or(RenderError::UnsupportedFormat)?;
+
+    let config_override = ConfigOverride {
+        sample_rate: opts.sample_rate,
+        ticks_per_second: opts.tick_rate,
+    };
+
+    let project = project::open_offline(opts.project_path, config_override).await?;
+
+    // the render is cpu bound, but modules may spawn async tasks of their own
+    // which need the rest of the runtime to keep going:
+    task::block_in_place(|| {
+        let mut engine = OfflineEngine::new(&project.workspace, project.base.clone())
+            .with_automation(project.automation);
+
+        let terminal = select_terminal(&engine, opts.terminal.map(ModuleId))?;
+
+        let mut sink = match format {
+            Format::Wav => {
+                let (line_type, output) = terminal.audio.ok_or(RenderError::NoAudio)?;
+                Sink::Wav(WavSink::create(&opts.output, line_type, output, engine.config().sample_rate)?)
+            }
+            Format::Mp4 => {
+                Sink::Mp4(Mp4Sink::create(&opts.output, terminal.audio, terminal.video, engine.config().sample_rate)?)
+            }
+        };
+
+        let samples_per_tick = engine.config().samples_per_tick();
+        let mut result = Ok(());
+
+        engine.play();
+
+        let max_ticks = engine.ticks_for(opts.duration);
+
+        let ticks = engine.run(max_ticks, |tick, mut output| {
+            if result.is_ok() {
+                result = sink.write_tick(tick * samples_per_tick as u64, samples_per_tick, &mut output.buffers);
+            }
+        });
+
+        result?;
+        sink.finish()?;
+
+        eprintln!("render: rendered {} ticks to {}", ticks, opts.output.display());
+
+        Ok(())
+    })
+}
+
+struct Terminal {
+    audio: Option<(LineType, OutputId)>,
+    video: Option<OutputId>,
+}
+
+fn select_terminal(engine: &OfflineEngine, module_id: Option<ModuleId>) -> Result<Terminal, RenderError> {
+    let connected = |module_id: ModuleId| {
+        let inputs = engine.inputs(module_id).unwrap_or(&[]);
+
+        let connected = inputs.iter().enumerate()
+            .filter_map(|(index, terminal)| {
+                let output = engine.connection(InputId(module_id, index))?;
+                Some((terminal.line_type(), output))
+            })
+            .collect::<Vec<_>>();
+
+        let audio = connected.iter().copied().find(|(line_type, _)| {
+            match line_type {
+                LineType::Mono | LineType::Stereo | LineType::Multi(_) => true,
+                LineType::Video | LineType::Event => false,
+            }
+        });
+
+        let video = connected.iter().copied().find(|(line_type, _)| {
+            match line_type {
+                LineType::Video => true,
+                LineType::Mono | LineType::Stereo | LineType::Multi(_) | LineType::Event => false,
+            }
+        });
+
+        Terminal {
+            audio,
+            video: video.map(|(_, output)| output),
+        }
+    };
+
+    let is_connected = |terminal: &Terminal| terminal.audio.is_some() || terminal.video.is_some();
+
+    match module_id {
+        Some(module_id) => {
+            if engine.inputs(module_id).is_none() {
+                return Err(RenderError::NoSuchModule(module_id));
+            }
+
+            Some(connected(module_id))
+                .filter(is_connected)
+                .ok_or(RenderError::NothingConnected(module_id))
+        }
+        None => {
+            engine.terminal_modules().into_iter()
+                .map(connected)
+                .find(is_connected)
+                .ok_or(RenderError::NoTerminal)
+        }
+    }
+}
+
+enum Sink {
+    Wav(WavSink),
+    Mp4(Mp4Sink),
+}
+
+impl Sink {
+    // t is the sample timestamp of the start of the tick
+    fn write_tick(&mut self, t: u64, samples_per_tick: usize, buffers: &mut HashMap<OutputId, Output>) -> Result<(), io::Error> {
+        match self {
+            Sink::Wav(wav) => {
+                let samples = match buffers.remove(&wav.output) {
+                    Some(Output::Mono(samples)) |
+                    Some(Output::Stereo(samples)) |
+                    Some(Output::Multi(_, samples)) => samples,
+                    _ => vec![0.0; samples_per_tick * wav.channels as usize],
+                };
+
+                wav.write(&samples)
+            }
+            Sink::Mp4(mp4) => {
+                let audio = match mp4.audio.and_then(|output| buffers.remove(&output)) {
+                    Some(Output::Stereo(samples)) => samples,
+                    Some(Output::Mono(samples)) => {
+                        samples.iter()
+                            .flat_map(|sample| iter::repeat(*sample).take(CHANNELS))
+                            .collect()
+                    }
+                    Some(Output::Multi(channels, samples)) => downmix(channels, &samples),
+                    _ => vec![0.0; samples_per_tick * CHANNELS],
+                };
+
+                let video = match mp4.video.and_then(|output| buffers.remove(&output)) {
+                    Some(Output::Video(frame)) => frame,
+                    _ => None,
+                };
+
+                mp4.write(t, &audio, video)
+            }
+        }
+    }
+
+    fn finish(self) -> Result<(), io::Error> {
+        match self {
+            Sink::Wav(wav) => wav.finish(),
+            Sink::Mp4(mp4) => mp4.finish(),
+        }
+    }
+}
+
+// 32 bit float PCM, so that renders are an exact record of the engine's output
+struct WavSink {
+    file: BufWriter<File>,
+    output: OutputId,
+    channels: u16,
+    header_len: u32,
+    data_len: u32,
+}
+
+const WAV_FORMAT_IEEE_FLOAT: u16 = 3;
+const WAV_FORMAT_EXTENSIBLE: u16 = 0xfffe;
+// KSDATAFORMAT_SUBTYPE_IEEE_FLOAT, the format of extensible wav files:
+const WAV_SUBFORMAT_IEEE_FLOAT: [u8; 16] = [
+    0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00,
+    0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71,
+];
+
+// speaker positions of each channel, for more channels than plain wav files
+// can describe. layouts match ffmpeg's of the same name
+fn wav_channel_mask(channels: u16) -> u32 {
+    const FRONT_LEFT: u32 = 0x1;
+    const FRONT_RIGHT: u32 = 0x2;
+    const FRONT_CENTRE: u32 = 0x4;
+    const LFE: u32 = 0x8;
+    const BACK_LEFT: u32 = 0x10;
+    const BACK_RIGHT: u32 = 0x20;
+    const SIDE_LEFT: u32 = 0x200;
+    const SIDE_RIGHT: u32 = 0x400;
+
+    match ChannelLayout::from_channels(channels as usize) {
+        Some(ChannelLayout::Quad) => FRONT_LEFT | FRONT_RIGHT | BACK_LEFT | BACK_RIGHT,
+        Some(ChannelLayout::Surround51) => FRONT_LEFT | FRONT_RIGHT | FRONT_CENTRE | LFE | SIDE_LEFT | SIDE_RIGHT,
+        Some(ChannelLayout::Surround71) => FRONT_LEFT | FRONT_RIGHT | FRONT_CENTRE | LFE | BACK_LEFT | BACK_RIGHT | SIDE_LEFT | SIDE_RIGHT,
+        // other channel counts are left without positions
+        None => 0,
+    }
+}
+
+impl WavSink {
+    fn create(path: &Path, line_type: LineType, output: OutputId, sample_rate: usize) -> Result<Self, io::Error> {
+        let channels = match line_type {
+            LineType::Mono => 1,
+            LineType::Stereo => CHANNELS as u16,
+            LineType::Multi(channels) => channels as u16,
+            LineType::Video | LineType::Event => unreachable!("{:?} line is not audio", line_type),
+        };
+
+        // files of more than two channels carry their speaker positions
+        let extensible = channels > 2;
+        let fmt_len = if extensible { 40 } else { 18 };
+
+        let mut wav = WavSink {
+            file: BufWriter::new(File::create(path)?),
+            output,
+            channels,
+            // RIFF, fmt, fact and data chunk headers:
+            header_len: 12 + 8 + fmt_len + 12 + 8,
+            data_len: 0,
+        };
+
+        let sample_rate = sample_rate as u32;
+        let block_align = channels * 4;
+
+        // sizes are filled in once the render is finished
+        wav.file.write_all(b"RIFF")?;
+        wav.file.write_u32::<LittleEndian>(0)?;
+        wav.file.write_all(b"WAVE")?;
+
+        wav.file.write_all(b"fmt ")?;
+        wav.file.write_u32::<LittleEndian>(fmt_len)?;
+        wav.file.write_u16::<LittleEndian>(if extensible { WAV_FORMAT_EXTENSIBLE } else { WAV_FORMAT_IEEE_FLOAT })?;
+        wav.file.write_u16::<LittleEndian>(channels)?;
+        wav.file.write_u32::<LittleEndian>(sample_rate)?;
+        wav.file.write_u32::<LittleEndian>(sample_rate * block_align as u32)?;
+        wav.file.write_u16::<LittleEndian>(block_align)?;
+        wav.file.write_u16::<LittleEndian>(32)?;
+
+        if extensible {
+            wav.file.write_u16::<LittleEndian>(22)?;
+            wav.file.write_u16::<LittleEndian>(32)?;
+            wav.file.write_u32::<LittleEndian>(wav_channel_mask(channels))?;
+            wav.file.write_all(&WAV_SUBFORMAT_IEEE_FLOAT)?;
+        } else {
+            wav.file.write_u16::<LittleEndian>(0)?;
+        }
+
+        // float files must say how many sample frames they hold
+        wav.file.write_all(b"fact")?;
+        wav.file.write_u32::<LittleEndian>(4)?;
+        wav.file.write_u32::<LittleEndian>(0)?;
+
+        wav.file.write_all(b"data")?;
+        wav.file.write_u32::<LittleEndian>(0)?;
+
+        Ok(wav)
+    }
+
+    // the RIFF chunk size must fit in 32 bits too, which limits wav files to
+    // just under 4 GiB
+    fn max_data_len(&self) -> u32 {
+        u32::MAX - (self.header_len - 8)
+    }
+
+    fn write(&mut self, samples: &[Sample]) -> Result<(), io::Error> {
+        let max_data_len = self.max_data_len();
+
+        let data_len = u32::try_from(samples.len() * 4).ok()
+            .and_then(|len| self.data_len.checked_add(len))
+            .filter(|len| *len <= max_data_len)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::Other,
+                "render is too long for a wav file, try a shorter duration or mp4"))?;
+
+        for sample in samples {
+            self.file.write_f32::<LittleEndian>(*sample)?;
+        }
+
+        self.data_len = data_len;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<(), io::Error> {
+        self.file.seek(SeekFrom::Start(4))?;
+        self.file.write_u32::<LittleEndian>(self.header_len - 8 + self.data_len)?;
+
+        // sample frames in the fact chunk, then the data chunk size:
+        self.file.seek(SeekFrom::Start(u64::from(self.header_len) - 12))?;
+        self.file.write_u32::<LittleEndian>(self.data_len / (u32::from(self.channels) * 4))?;
+
+        self.file.seek(SeekFrom::Start(u64::from(self.header_len) - 4))?;
+        self.file.write_u32::<LittleEndian>(self.data_len)?;
+
+        self.file.flush()
+    }
+}
+
+// fragmented mp4, as the monitor module produces
+struct Mp4Sink {
+    file: BufWriter<File>,
+    audio: Option<OutputId>,
+    video: Option<OutputId>,
+    sample_rate: usize,
+    encode: EncodeStream,
+    mux: Mp4Mux,
+}
+
+impl Mp4Sink {
+    fn create(path: &Path, audio: Option<(LineType, OutputId)>, video: Option<OutputId>, sample_rate: usize) -> Result<Self, io::Error> {
+        let audio_ctx = AudioCtx::new(AudioParams {
+            bit_rate: aac::BitRate::VbrVeryHigh,
+            sample_rate,
+            transport: aac::Transport::Adts,
+        });
+
+        let video_ctx = VideoCtx::new(VideoParams {
+            picture: PictureSettings::yuv420p(VIDEO_WIDTH, VIDEO_HEIGHT),
+            time_base: sample_rate,
+            profile: Profile::Render,
+        });
+
+        let mut dcr = Vec::new();
+        video_ctx.decoder_configuration_record().write_to(&mut dcr);
+
+        let (mux, init) = Mp4Mux::new(Mp4Params {
+            timescale: sample_rate as u32,
+            sample_rate: sample_rate as u32,
+            width: VIDEO_WIDTH as u32,
+            height: VIDEO_HEIGHT as u32,
+            dcr: Cow::Owned(dcr),
+        });
+
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(&init)?;
+
+        Ok(Mp4Sink {
+            file,
+            audio: audio.map(|(_, output)| output),
+            video,
+            sample_rate,
+            encode: EncodeStream::new(audio_ctx, video_ctx),
+            mux,
+        })
+    }
+
+    fn write(&mut self, t: u64, audio: &[Sample], video: Option<engine::VideoFrame>) -> Result<(), io::Error> {
+        let timestamp = MediaTime::new(t as i64, self.sample_rate as i64);
+
+        self.encode.send_audio(audio);
+
+        if let Some(video_frame) = video {
+            let frame_timestamp = timestamp + video_frame.tick_offset;
+            let frame = video_frame.data.decoded.clone();
+
+            self.encode.send_video(frame_timestamp, video_frame.data.duration_hint, frame);
+        }
+
+        self.encode.barrier(timestamp);
+
+        while let Some(segment) = self.encode.recv_segment() {
+            self.write_segment(segment)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_segment(&mut self, segment: StreamSegment) -> Result<(), io::Error> {
+        let (duration, track_data) = match segment {
+            StreamSegment::Audio(audio) => (audio.duration, TrackData::Audio(AdtsFrame(audio.frame))),
+            StreamSegment::Video(video) => (video.duration, TrackData::Video(video.frame)),
+        };
+
+        let fragment = self.mux.write_track(duration, &track_data);
+        self.file.write_all(&fragment)
+    }
+
+    fn finish(mut self) -> Result<(), io::Error> {
+        for segment in self.encode.drain_segments() {
+            self.write_segment(segment)?;
+        }
+
+        self.file.flush()
+    }
+}
+
+// mp4 renders are always stereo. layouts we know are folded down properly,
+// anything else keeps its first two channels
+fn downmix(channels: usize, samples: &[Sample]) -> Vec<Sample> {
+    match ChannelLayout::from_channels(channels) {
+        Some(layout) => {
+            samples.chunks(channels)
+                .flat_map(|frame| {
+                    let (left, right) = downmix_frame(layout, frame);
+                    vec![left as Sample, right as Sample]
+                })
+                .collect()
+        }
+        None => {
+            samples.chunks(channels)
+                .flat_map(|frame| vec![frame[0], *frame.get(1).unwrap_or(&frame[0])])
+                .collect()
+        }
+    }
+}
+
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+
+    let split = s.find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+
+    let (value, unit) = s.split_at(split);
+
+    let value = value.parse::<f64>()
+        .map_err(|_| format!("invalid duration: {}", s))?;
+
+    let seconds = match unit {
+        "ms" => value / 1000.0,
+        "" | "s" => value,
+        "m" => value * 60.0,
+        "h" => value * 3600.0,
+        _ => return Err(format!("unknown duration unit: {}", unit)),
+    };
+
+    if !seconds.is_finite() || seconds <= 0.0 {
+        return Err(format!("duration must be positive: {}", s));
+    }
+
+    Ok(Duration::from_secs_f64(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::num::NonZeroUsize;
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
+
+    use mixlab_protocol::{ModuleId, OutputId, LineType};
+
+    use super::{parse_duration, Format, WavSink, WAV_FORMAT_IEEE_FLOAT, WAV_FORMAT_EXTENSIBLE, WAV_SUBFORMAT_IEEE_FLOAT};
+
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("mixlab-render-{}-{}.wav", std::process::id(), name))
+    }
+
+    fn output() -> OutputId {
+        OutputId(ModuleId(NonZeroUsize::new(1).unwrap()), 0)
+    }
+
+    fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+        let mut word = [0; 2];
+        word.copy_from_slice(&bytes[offset..offset + 2]);
+        u16::from_le_bytes(word)
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        let mut word = [0; 4];
+        word.copy_from_slice(&bytes[offset..offset + 4]);
+        u32::from_le_bytes(word)
+    }
+
+    // renders samples to a wav file, returning its contents
+    fn render_wav(name: &str, line_type: LineType, samples: &[f32]) -> Vec<u8> {
+        let path = temp_path(name);
+
+        let mut wav = WavSink::create(&path, line_type, output(), 44100).unwrap();
+        wav.write(samples).unwrap();
+        wav.finish().unwrap();
+
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("1500ms"), Ok(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("60s"), Ok(Duration::from_secs(60)));
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("2m"), Ok(Duration::from_secs(120)));
+        assert_eq!(parse_duration("0.5h"), Ok(Duration::from_secs(1800)));
+
+        assert!(parse_duration("0s").is_err());
+        assert!(parse_duration("10 parsecs").is_err());
+        assert!(parse_duration("s").is_err());
+    }
+
+    #[test]
+    fn picks_format_from_extension() {
+        assert!(matches!(Format::from_path(Path::new("out.WAV")), Some(Format::Wav)));
+        assert!(matches!(Format::from_path(Path::new("out.mp4")), Some(Format::Mp4)));
+        assert!(Format::from_path(Path::new("out.ogg")).is_none());
+        assert!(Format::from_path(Path::new("out")).is_none());
+    }
+
+    #[test]
+    fn writes_wav_header_sizes() {
+        let bytes = render_wav("header", LineType::Stereo, &[0.0, 0.5, -0.5, 1.0]);
+
+        assert_eq!(bytes.len(), 58 + 16);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(u32_at(&bytes, 4), 58 - 8 + 16);
+
+        assert_eq!(&bytes[12..16], b"fmt ");
+        assert_eq!(u32_at(&bytes, 16), 18);
+        assert_eq!(u16_at(&bytes, 20), WAV_FORMAT_IEEE_FLOAT);
+        assert_eq!(u16_at(&bytes, 22), 2);
+        assert_eq!(u32_at(&bytes, 24), 44100);
+        assert_eq!(u16_at(&bytes, 36), 0);
+
+        // float data needs a fact chunk, counting sample frames:
+        assert_eq!(&bytes[38..42], b"fact");
+        assert_eq!(u32_at(&bytes, 42), 4);
+        assert_eq!(u32_at(&bytes, 46), 2);
+
+        assert_eq!(&bytes[50..54], b"data");
+        assert_eq!(u32_at(&bytes, 54), 16);
+        assert_eq!(&bytes[62..66], &0.5f32.to_le_bytes());
+    }
+
+    #[test]
+    fn writes_speaker_positions_of_multi_channel_wav() {
+        let bytes = render_wav("surround", LineType::Multi(6), &[0.25; 12]);
+
+        assert_eq!(bytes.len(), 80 + 48);
+        assert_eq!(u32_at(&bytes, 4), 80 - 8 + 48);
+
+        assert_eq!(u32_at(&bytes, 16), 40);
+        assert_eq!(u16_at(&bytes, 20), WAV_FORMAT_EXTENSIBLE);
+        assert_eq!(u16_at(&bytes, 22), 6);
+        assert_eq!(u16_at(&bytes, 32), 24);
+        assert_eq!(u16_at(&bytes, 36), 22);
+        assert_eq!(u16_at(&bytes, 38), 32);
+        // front left, right and centre, lfe, side left and right:
+        assert_eq!(u32_at(&bytes, 40), 0x60f);
+        assert_eq!(&bytes[44..60], &WAV_SUBFORMAT_IEEE_FLOAT);
+
+        assert_eq!(&bytes[60..64], b"fact");
+        assert_eq!(u32_at(&bytes, 68), 2);
+
+        assert_eq!(&bytes[72..76], b"data");
+        assert_eq!(u32_at(&bytes, 76), 48);
+    }
+
+    #[test]
+    fn leaves_unknown_layouts_unpositioned() {
+        let bytes = render_wav("three", LineType::Multi(3), &[0.0; 3]);
+        assert_eq!(u16_at(&bytes, 20), WAV_FORMAT_EXTENSIBLE);
+        assert_eq!(u32_at(&bytes, 40), 0);
+    }
+
+    #[test]
+    fn refuses_to_overflow_wav_sizes() {
+        let path = temp_path("overflow");
+
+        let mut wav = WavSink::create(&path, LineType::Mono, output(), 44100).unwrap();
+
+        // pretend almost 4 GiB has been written already:
+        let max_data_len = u32::MAX - (wav.header_len - 8);
+        assert_eq!(wav.max_data_len(), max_data_len);
+
+        wav.data_len = max_data_len - 4;
+        assert!(wav.write(&[0.0]).is_ok());
+        assert!(wav.write(&[0.0]).is_err());
+        assert_eq!(wav.data_len, max_data_len);
+
+        drop(wav);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -2,6 +2,7 @@ use std::borrow::Cow;
 use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::sync::atomic::Ordering;
 
 use bytes::Buf;
 use derive_more::From;
@@ -18,15 +19,28 @@ use warp::ws::{self, Ws, WebSocket};
 
 use mixlab_protocol::{ClientMessage, ServerMessage};
 
-use crate::engine::EngineEvent;
+use crate::engine::{EngineEvent, ConfigOverride};
 use crate::listen::{self, Disambiguation};
 use crate::project::{self, ProjectHandle, Notification};
-use crate::{icecast, module, rtmp};
+use crate::{api, icecast, metrics, module, osc, rtmp};
 
 #[derive(StructOpt)]
 pub struct RunOpts {
+    /// address to serve the web UI and API on
     #[structopt(short, long, default_value = "127.0.0.1:8000")]
     listen: SocketAddr,
+    /// sample rate to run the project at. saved with the project, so this
+    /// and all future runs use it
+    #[structopt(long)]
+    sample_rate: Option<usize>,
+    /// engine ticks per second. saved with the project, so this and all
+    /// future runs use it
+    #[structopt(long)]
+    tick_rate: Option<usize>,
+    /// address to listen for OSC control messages on, eg. 0.0.0.0:9000
+    #[structopt(long)]
+    osc: Option<SocketAddr>,
+    /// project to open, created if it doesn't exist yet
     workspace_path: PathBuf,
 }
 
@@ -45,11 +59,26 @@ impl Server {
 }
 
 pub async fn run(opts: RunOpts) {
-    let project = project::open_or_create(opts.workspace_path).await
+    let config_override = ConfigOverride {
+        sample_rate: opts.sample_rate,
+        ticks_per_second: opts.tick_rate,
+    };
+
+    let project = project::open_or_create(opts.workspace_path, config_override).await
         .expect("create_or_open_project");
 
     let server = Arc::new(Server::new(project));
 
+    if let Some(osc_addr) = opts.osc {
+        let project = server.project.clone();
+
+        tokio::spawn(async move {
+            if let Err(e) = osc::run(osc_addr, project).await {
+                eprintln!("osc: {:?}", e);
+            }
+        });
+    }
+
     let index = warp::path::end()
         .map(index);
 
@@ -81,6 +110,21 @@ pub async fn run(opts: RunOpts) {
             }
         });
 
+    let metrics = warp::get()
+        .and(warp::path!("metrics"))
+        .map({
+            let server = server.clone();
+            move || {
+                let body = server.project.performance_info()
+                    .map(|info| metrics::render(&info))
+                    .unwrap_or_default();
+
+                content("text/plain; version=0.0.4", body)
+            }
+        });
+
+    let api = api::routes(server.project.clone());
+
     let monitor_socket = warp::get()
         .and(warp::path!("_monitor" / Uuid))
         .and(warp::ws())
@@ -120,6 +164,8 @@ pub async fn run(opts: RunOpts) {
         .or(websocket)
         .or(monitor_socket)
         .or(media_upload)
+        .or(metrics)
+        .or(api)
         .with(warp::log("mixlab-http"));
 
     let warp = warp::serve(routes);
@@ -250,11 +296,14 @@ async fn session(websocket: WebSocket, server: ServerRef) {
             }
             Event::Engine(Err(broadcast::RecvError::Lagged(skipped))) => {
                 println!("disconnecting client: lagged {} messages behind", skipped);
+                metrics::LAGGED_CLIENTS.fetch_add(1, Ordering::Relaxed);
+                metrics::LAGGED_MESSAGES.fetch_add(skipped, Ordering::Relaxed);
                 return;
             }
             Event::Engine(Err(broadcast::RecvError::Closed)) => {
                 // TODO we should tell the user that the engine has stopped
-                unimplemented!()
+                println!("disconnecting client: engine has stopped");
+                return;
             }
             Event::Engine(Ok(event)) => {
                 // sequence is only applicable if it belongs to this session:
@@ -60,7 +60,13 @@ pub struct SourceSend {
     tx: Option<TxPair>,
 }
 
-pub type AudioData = Vec<i16>;
+#[derive(Debug)]
+pub struct AudioData {
+    pub sample_rate: usize,
+    // interleaved stereo
+    pub samples: Vec<i16>,
+}
+
 pub type VideoData = video::Frame;
 
 #[derive(Debug)]
@@ -17,6 +17,15 @@ impl Sequence {
         Sequence(0)
     }
 
+    // resumes a sequence which last handed out `last`
+    pub fn resume(last: usize) -> Self {
+        Sequence(last)
+    }
+
+    pub fn last(&self) -> usize {
+        self.0
+    }
+
     pub fn next(&mut self) -> NonZeroUsize {
         self.0 += 1;
         NonZeroUsize::new(self.0).unwrap()
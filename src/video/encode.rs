@@ -121,6 +121,29 @@ impl EncodeStream {
             self.video_segments.pop_front().map(StreamSegment::Video)
         }
     }
+
+    // takes all remaining segments in decode order, for when the stream has
+    // come to an end
+    pub fn drain_segments(&mut self) -> Vec<StreamSegment> {
+        let mut segments = Vec::new();
+
+        loop {
+            let audio_first = match (self.audio_segments.front(), self.video_segments.front()) {
+                (Some(audio), Some(video)) => audio.decode_timestamp < video.decode_timestamp,
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (None, None) => { break; }
+            };
+
+            if audio_first {
+                segments.extend(self.audio_segments.pop_front().map(StreamSegment::Audio));
+            } else {
+                segments.extend(self.video_segments.pop_front().map(StreamSegment::Video));
+            }
+        }
+
+        segments
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -238,6 +261,7 @@ pub struct VideoParams {
 pub enum Profile {
     Monitor,
     Stream,
+    Render,
 }
 
 impl VideoCtx {
@@ -255,18 +279,24 @@ impl VideoCtx {
                 // cannot use constant bitrate in zero latency mode apparently:
                 Profile::Monitor => RateControl::ConstantQuality { crf: 30 },
                 Profile::Stream => RateControl::ConstantBitRate { bitrate: 1_500_000 },
+                Profile::Render => RateControl::ConstantQuality { crf: 23 },
             },
             preset: match params.profile {
                 Profile::Monitor => Preset::Veryfast,
                 Profile::Stream => Preset::Slow,
+                Profile::Render => Preset::Medium,
             },
             tune: match params.profile {
                 Profile::Monitor => Some(Tune::Zerolatency),
                 Profile::Stream => Some(Tune::Film),
+                // frames held back for lookahead would be lost at the end of
+                // the render:
+                Profile::Render => Some(Tune::Zerolatency),
             },
             gop_size: match params.profile {
                 Profile::Monitor => Some(1), // every frame is key frame
                 Profile::Stream => Some(60),
+                Profile::Render => Some(60),
             },
         };
 